@@ -0,0 +1,94 @@
+use fixed::types::I80F48;
+
+use crate::value::calc_value;
+
+/// A balance resolved against its bank and oracle, everything its health contribution depends on.
+///
+/// Prices are expected to be biased already, low for assets and high for liabilities, and the
+/// weights to match the requirement type being checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BalanceHealth {
+    pub asset_amount: I80F48,
+    pub liability_amount: I80F48,
+    pub asset_price: I80F48,
+    pub liability_price: I80F48,
+    pub asset_weight: I80F48,
+    pub liability_weight: I80F48,
+    pub mint_decimals: u8,
+}
+
+impl BalanceHealth {
+    /// Return (weighted asset value, weighted liability value) of the balance.
+    pub fn weighted_values(&self) -> Option<(I80F48, I80F48)> {
+        let assets = calc_value(
+            self.asset_amount,
+            self.asset_price,
+            self.mint_decimals,
+            Some(self.asset_weight),
+        )?;
+        let liabilities = calc_value(
+            self.liability_amount,
+            self.liability_price,
+            self.mint_decimals,
+            Some(self.liability_weight),
+        )?;
+
+        Some((assets, liabilities))
+    }
+}
+
+/// Sum the weighted values of `balances`, return (total assets, total liabilities).
+pub fn calc_health_components(
+    balances: impl IntoIterator<Item = BalanceHealth>,
+) -> Option<(I80F48, I80F48)> {
+    balances.into_iter().try_fold(
+        (I80F48::ZERO, I80F48::ZERO),
+        |(total_assets, total_liabilities), balance| {
+            let (assets, liabilities) = balance.weighted_values()?;
+
+            Some((
+                total_assets.checked_add(assets)?,
+                total_liabilities.checked_add(liabilities)?,
+            ))
+        },
+    )
+}
+
+/// Health of an account, its weighted assets minus its weighted liabilities.
+pub fn calc_health(balances: impl IntoIterator<Item = BalanceHealth>) -> Option<I80F48> {
+    let (assets, liabilities) = calc_health_components(balances)?;
+
+    assets.checked_sub(liabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use fixed_macro::types::I80F48;
+
+    use super::*;
+
+    #[test]
+    fn health_of_mixed_balances() {
+        let sol = BalanceHealth {
+            asset_amount: I80F48!(10_000_000_000),
+            asset_price: I80F48!(10),
+            asset_weight: I80F48!(0.5),
+            mint_decimals: 9,
+            ..Default::default()
+        };
+        let usdc = BalanceHealth {
+            liability_amount: I80F48!(50_000_000),
+            liability_price: I80F48!(1),
+            liability_weight: I80F48!(1.25),
+            mint_decimals: 6,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            calc_health_components([sol, usdc]),
+            Some((I80F48!(50), I80F48!(62.5)))
+        );
+        assert_eq!(calc_health([sol, usdc]), Some(I80F48!(-12.5)));
+        assert_eq!(calc_health([]), Some(I80F48::ZERO));
+    }
+}
@@ -0,0 +1,226 @@
+use fixed::types::I80F48;
+use fixed_macro::types::I80F48;
+
+pub const SECONDS_PER_YEAR: I80F48 = I80F48!(31_536_000);
+
+/// Fees charged to borrowers on top of the base interest rate, as APRs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InterestRateFees {
+    pub protocol_ir_fee: I80F48,
+    pub insurance_ir_fee: I80F48,
+    pub protocol_fixed_fee_apr: I80F48,
+    pub insurance_fee_fixed_apr: I80F48,
+}
+
+/// Piecewise linear interest rate function.
+/// The curves approaches the `plateau_ir` as the utilization ratio approaches the `optimal_ur`,
+/// once the utilization ratio exceeds the `optimal_ur`, the curve passes through the `kinks`
+/// (utilization ratio, interest rate) and approaches the `max_ir`.
+///
+/// Kinks at or below the `optimal_ur` are skipped, which can only happen mid-ramp.
+///
+/// To be clear we don't particularly appreciate the piecewise linear nature of this "curve", but it is what it is.
+#[inline]
+pub fn interest_rate_curve(
+    ur: I80F48,
+    optimal_ur: I80F48,
+    plateau_ir: I80F48,
+    max_ir: I80F48,
+    kinks: impl IntoIterator<Item = (I80F48, I80F48)>,
+) -> Option<I80F48> {
+    let kinks = kinks
+        .into_iter()
+        .filter(|(kink_ur, _)| *kink_ur > optimal_ur && *kink_ur < I80F48::ONE);
+
+    let mut points = core::iter::once((optimal_ur, plateau_ir))
+        .chain(kinks)
+        .chain(core::iter::once((I80F48::ONE, max_ir)))
+        .peekable();
+    let mut start = (I80F48::ZERO, I80F48::ZERO);
+
+    while let Some(end) = points.next() {
+        // The last segment also covers a utilization ratio above 1
+        if ur <= end.0 || points.peek().is_none() {
+            return (ur - start.0)
+                .checked_div(end.0 - start.0)?
+                .checked_mul(end.1 - start.1)?
+                .checked_add(start.1);
+        }
+
+        start = end;
+    }
+
+    None
+}
+
+/// Split the `base_rate` of the curve into the rates paid by borrowers and to depositors.
+///
+/// Return (`lending_rate`, `borrowing_rate`, `group_fees_apr`, `insurance_fees_apr`)
+///
+/// The lending rate is scaled by the `utilization_ratio` so depositors receive exactly the base
+/// interest borrowers pay. With a `max_borrow_apr_cap` the base rate is lowered until the
+/// borrowing rate fits under the cap, so lenders and fee rates give up their share of the excess
+/// in proportion. Zero means no cap.
+pub fn calc_interest_rate(
+    base_rate: I80F48,
+    utilization_ratio: I80F48,
+    fees: &InterestRateFees,
+    max_borrow_apr_cap: I80F48,
+) -> Option<(I80F48, I80F48, I80F48, I80F48)> {
+    let rate_fee = fees.protocol_ir_fee + fees.insurance_ir_fee;
+    let total_fixed_fee_apr = fees.protocol_fixed_fee_apr + fees.insurance_fee_fixed_apr;
+
+    let base_rate = if max_borrow_apr_cap == I80F48::ZERO {
+        base_rate
+    } else {
+        let max_base_rate = max_borrow_apr_cap
+            .checked_sub(total_fixed_fee_apr)?
+            .checked_div(I80F48::ONE.checked_add(rate_fee)?)?
+            .max(I80F48::ZERO);
+
+        base_rate.min(max_base_rate)
+    };
+
+    // Lending rate is adjusted for utilization ratio to symmetrize payments between borrowers and depositors.
+    let lending_rate = base_rate.checked_mul(utilization_ratio)?;
+
+    // Borrowing rate is adjusted for fees.
+    // borrowing_rate = base_rate + base_rate * rate_fee + total_fixed_fee_apr
+    let borrowing_rate = base_rate
+        .checked_mul(I80F48::ONE.checked_add(rate_fee)?)?
+        .checked_add(total_fixed_fee_apr)?;
+
+    let group_fees_apr =
+        calc_fee_rate(base_rate, fees.protocol_ir_fee, fees.protocol_fixed_fee_apr)?;
+
+    let insurance_fees_apr = calc_fee_rate(
+        base_rate,
+        fees.insurance_ir_fee,
+        fees.insurance_fee_fixed_apr,
+    )?;
+
+    assert!(lending_rate >= I80F48::ZERO);
+    assert!(borrowing_rate >= I80F48::ZERO);
+    assert!(group_fees_apr >= I80F48::ZERO);
+    assert!(insurance_fees_apr >= I80F48::ZERO);
+
+    Some((
+        lending_rate,
+        borrowing_rate,
+        group_fees_apr,
+        insurance_fees_apr,
+    ))
+}
+
+/// Calculates the fee rate for a given base rate and fees specified.
+/// The returned rate is only the fee rate without the base rate.
+///
+/// Used for calculating the fees charged to the borrowers.
+pub fn calc_fee_rate(base_rate: I80F48, rate_fees: I80F48, fixed_fees: I80F48) -> Option<I80F48> {
+    base_rate.checked_mul(rate_fees)?.checked_add(fixed_fees)
+}
+
+/// Calculates the accrued interest payment per period `time_delta` in a principal value `value` for interest rate (in APR) `arp`.
+/// Result is the new principal value.
+pub fn calc_accrued_interest_payment_per_period(
+    apr: I80F48,
+    time_delta: u64,
+    value: I80F48,
+) -> Option<I80F48> {
+    let ir_per_period = apr
+        .checked_mul(time_delta.into())?
+        .checked_div(SECONDS_PER_YEAR)?;
+
+    let new_value = value.checked_mul(I80F48::ONE.checked_add(ir_per_period)?)?;
+
+    Some(new_value)
+}
+
+/// Calculates the interest payment for a given period `time_delta` in a principal value `value` for interest rate (in APR) `arp`.
+/// Result is the interest payment.
+pub fn calc_interest_payment_for_period(
+    apr: I80F48,
+    time_delta: u64,
+    value: I80F48,
+) -> Option<I80F48> {
+    let interest_payment = value
+        .checked_mul(apr)?
+        .checked_mul(time_delta.into())?
+        .checked_div(SECONDS_PER_YEAR)?;
+
+    Some(interest_payment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(left: I80F48, right: I80F48) {
+        assert!(
+            (left - right).abs() < I80F48!(0.000001),
+            "{left} != {right}"
+        );
+    }
+
+    #[test]
+    fn curve_through_kinks() {
+        let curve = |ur| {
+            interest_rate_curve(
+                ur,
+                I80F48!(0.5),
+                I80F48!(0.1),
+                I80F48!(3),
+                [
+                    (I80F48!(0.8), I80F48!(1)),
+                    // Below the optimal utilization rate, skipped
+                    (I80F48!(0.4), I80F48!(2)),
+                ],
+            )
+            .unwrap()
+        };
+
+        assert_close(curve(I80F48::ZERO), I80F48::ZERO);
+        assert_close(curve(I80F48!(0.25)), I80F48!(0.05));
+        assert_close(curve(I80F48!(0.5)), I80F48!(0.1));
+        assert_close(curve(I80F48!(0.8)), I80F48!(1));
+        assert_close(curve(I80F48!(0.9)), I80F48!(2));
+        assert_close(curve(I80F48::ONE), I80F48!(3));
+        assert_close(curve(I80F48!(1.1)), I80F48!(4));
+    }
+
+    #[test]
+    fn interest_rate_with_fees_and_cap() {
+        let fees = InterestRateFees {
+            protocol_ir_fee: I80F48!(0.1),
+            insurance_ir_fee: I80F48!(0.1),
+            protocol_fixed_fee_apr: I80F48!(0.01),
+            insurance_fee_fixed_apr: I80F48!(0.01),
+        };
+
+        let (lending_rate, borrowing_rate, group_fees_apr, insurance_fees_apr) =
+            calc_interest_rate(I80F48!(0.5), I80F48!(0.5), &fees, I80F48::ZERO).unwrap();
+        assert_close(lending_rate, I80F48!(0.25));
+        assert_close(borrowing_rate, I80F48!(0.62));
+        assert_close(group_fees_apr, I80F48!(0.06));
+        assert_close(insurance_fees_apr, I80F48!(0.06));
+
+        // The base rate drops to (0.26 - 0.02) / 1.2 = 0.2 to fit the cap
+        let (lending_rate, borrowing_rate, _, _) =
+            calc_interest_rate(I80F48!(0.5), I80F48!(0.5), &fees, I80F48!(0.26)).unwrap();
+        assert_close(lending_rate, I80F48!(0.1));
+        assert_close(borrowing_rate, I80F48!(0.26));
+    }
+
+    #[test]
+    fn interest_payments() {
+        assert_close(
+            calc_interest_payment_for_period(I80F48!(0.5), 31_536_000, I80F48::ONE).unwrap(),
+            I80F48!(0.5),
+        );
+        assert_close(
+            calc_accrued_interest_payment_per_period(I80F48::ONE, 31_536_000, I80F48!(2)).unwrap(),
+            I80F48!(4),
+        );
+        assert!(calc_interest_payment_for_period(I80F48::MAX, 2, I80F48::ONE).is_none());
+    }
+}
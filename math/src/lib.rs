@@ -0,0 +1,17 @@
+//! Fixed point math of the marginfi program without any Solana or Anchor dependency, so clients
+//! compute exactly what the program does.
+//!
+//! Everything works on [`I80F48`] and returns `None` on overflow, the program maps that to its
+//! math error. The account types holding these values (`WrappedI80F48`, the bank and interest rate
+//! configs) stay in the program, they are part of its account layout and IDL.
+//!
+//! Browser bindings of the value and health math live in `math/wasm`.
+#![cfg_attr(not(test), no_std)]
+
+pub mod health;
+pub mod interest;
+pub mod shares;
+pub mod value;
+pub mod weights;
+
+pub use fixed::types::I80F48;
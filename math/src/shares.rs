@@ -0,0 +1,55 @@
+use fixed::types::I80F48;
+
+/// Token amount of `shares` at `share_value`.
+#[inline]
+pub fn shares_to_amount(shares: I80F48, share_value: I80F48) -> Option<I80F48> {
+    shares.checked_mul(share_value)
+}
+
+/// Shares worth `amount` at `share_value`.
+#[inline]
+pub fn amount_to_shares(amount: I80F48, share_value: I80F48) -> Option<I80F48> {
+    amount.checked_div(share_value)
+}
+
+/// Share value after a loss of `loss_amount` is spread over `total_shares`.
+pub fn calc_socialized_share_value(
+    total_shares: I80F48,
+    share_value: I80F48,
+    loss_amount: I80F48,
+) -> Option<I80F48> {
+    total_shares
+        .checked_mul(share_value)?
+        .checked_sub(loss_amount)?
+        .checked_div(total_shares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixed_macro::types::I80F48;
+
+    #[test]
+    fn share_conversion_round_trip() {
+        let share_value = I80F48!(1.25);
+
+        assert_eq!(
+            shares_to_amount(I80F48!(100), share_value).unwrap(),
+            I80F48!(125)
+        );
+        assert_eq!(
+            amount_to_shares(I80F48!(125), share_value).unwrap(),
+            I80F48!(100)
+        );
+        assert!(amount_to_shares(I80F48!(125), I80F48::ZERO).is_none());
+    }
+
+    #[test]
+    fn socialized_loss() {
+        assert_eq!(
+            calc_socialized_share_value(I80F48!(1_000), I80F48!(1.5), I80F48!(500)).unwrap(),
+            I80F48!(1)
+        );
+        assert!(calc_socialized_share_value(I80F48::ZERO, I80F48::ONE, I80F48::ONE).is_none());
+    }
+}
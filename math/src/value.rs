@@ -0,0 +1,139 @@
+use fixed::types::I80F48;
+use fixed_macro::types::I80F48;
+
+pub const MAX_EXP_10_I80F48: usize = 24;
+pub const EXP_10_I80F48: [I80F48; MAX_EXP_10_I80F48] = [
+    I80F48!(1),                        // 10^0
+    I80F48!(10),                       // 10^1
+    I80F48!(100),                      // 10^2
+    I80F48!(1000),                     // 10^3
+    I80F48!(10000),                    // 10^4
+    I80F48!(100000),                   // 10^5
+    I80F48!(1000000),                  // 10^6
+    I80F48!(10000000),                 // 10^7
+    I80F48!(100000000),                // 10^8
+    I80F48!(1000000000),               // 10^9
+    I80F48!(10000000000),              // 10^10
+    I80F48!(100000000000),             // 10^11
+    I80F48!(1000000000000),            // 10^12
+    I80F48!(10000000000000),           // 10^13
+    I80F48!(100000000000000),          // 10^14
+    I80F48!(1000000000000000),         // 10^15
+    I80F48!(10000000000000000),        // 10^16
+    I80F48!(100000000000000000),       // 10^17
+    I80F48!(1000000000000000000),      // 10^18
+    I80F48!(10000000000000000000),     // 10^19
+    I80F48!(100000000000000000000),    // 10^20
+    I80F48!(1000000000000000000000),   // 10^21
+    I80F48!(10000000000000000000000),  // 10^22
+    I80F48!(100000000000000000000000), // 10^23
+];
+
+/// Multiply `value` by `10^exponent` for any exponent, in steps that fit [`EXP_10_I80F48`].
+///
+/// Fails if the result overflows, values scaled below the I80F48 resolution (~3.6e-15) round to zero.
+pub fn scale_by_exponent(value: I80F48, exponent: i32) -> Option<I80F48> {
+    let mut scaled = value;
+    let mut remaining = exponent.unsigned_abs() as usize;
+
+    while remaining > 0 && scaled != I80F48::ZERO {
+        let step = remaining.min(MAX_EXP_10_I80F48 - 1);
+
+        scaled = if exponent < 0 {
+            scaled.checked_div(EXP_10_I80F48[step])
+        } else {
+            scaled.checked_mul(EXP_10_I80F48[step])
+        }?;
+
+        remaining -= step;
+    }
+
+    Some(scaled)
+}
+
+/// Calculate the value of an asset, given its quantity with a decimal exponent, and a price with a decimal exponent, and an optional weight.
+#[inline]
+pub fn calc_value(
+    amount: I80F48,
+    price: I80F48,
+    mint_decimals: u8,
+    weight: Option<I80F48>,
+) -> Option<I80F48> {
+    if amount == I80F48::ZERO {
+        return Some(I80F48::ZERO);
+    }
+
+    let weighted_asset_amount = match weight {
+        Some(weight) => amount.checked_mul(weight)?,
+        None => amount,
+    };
+
+    match weighted_asset_amount.checked_mul(price) {
+        Some(value) => scale_by_exponent(value, -(mint_decimals as i32)),
+        // Large balances of high decimal mints, normalize the amount first at some precision cost
+        None => {
+            scale_by_exponent(weighted_asset_amount, -(mint_decimals as i32))?.checked_mul(price)
+        }
+    }
+}
+
+/// Inverse of [`calc_value`] without a weight, the token amount worth `value` at `price`.
+#[inline]
+pub fn calc_amount(value: I80F48, price: I80F48, mint_decimals: u8) -> Option<I80F48> {
+    match scale_by_exponent(value, mint_decimals as i32) {
+        Some(scaled_value) => scaled_value.checked_div(price),
+        // Same as in `calc_value`, divide by the price before scaling
+        None => scale_by_exponent(value.checked_div(price)?, mint_decimals as i32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_by_exponent_extremes() {
+        assert_eq!(scale_by_exponent(I80F48!(123), 0).unwrap(), I80F48!(123));
+        assert_eq!(scale_by_exponent(I80F48!(2), 5).unwrap(), I80F48!(200_000));
+
+        // Beyond the lookup table, scaled in steps
+        assert_eq!(
+            scale_by_exponent(I80F48!(100_000_000_000_000_000_000_000), -30).unwrap(),
+            scale_by_exponent(I80F48!(1), -7).unwrap()
+        );
+
+        assert_eq!(
+            scale_by_exponent(I80F48!(1), i32::MIN).unwrap(),
+            I80F48::ZERO
+        );
+        assert!(scale_by_exponent(I80F48!(1), 24).is_none());
+        assert_eq!(
+            scale_by_exponent(I80F48::ZERO, i32::MAX).unwrap(),
+            I80F48::ZERO
+        );
+    }
+
+    #[test]
+    fn value_and_amount() {
+        // 1.5 tokens of a 6 decimals mint at $2, weighted at 0.5
+        assert_eq!(
+            calc_value(I80F48!(1_500_000), I80F48!(2), 6, Some(I80F48!(0.5))).unwrap(),
+            I80F48!(1.5)
+        );
+        assert_eq!(
+            calc_amount(I80F48!(3), I80F48!(2), 6).unwrap(),
+            I80F48!(1_500_000)
+        );
+
+        // Overflowing before scaling falls back to scaling first
+        let amount = I80F48::MAX / I80F48!(10);
+        assert_eq!(
+            calc_value(amount, I80F48!(100), 9, None).unwrap(),
+            scale_by_exponent(amount, -9).unwrap() * I80F48!(100)
+        );
+        assert_eq!(
+            calc_value(I80F48::ZERO, I80F48::MAX, 9, Some(I80F48::MAX)).unwrap(),
+            I80F48::ZERO
+        );
+    }
+}
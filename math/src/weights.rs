@@ -0,0 +1,34 @@
+use fixed::types::I80F48;
+
+/// Basis points in a weight of 1.0
+pub const WEIGHT_BPS_DENOMINATOR: u32 = 10_000;
+
+pub fn bps_to_weight(bps: u32) -> Option<I80F48> {
+    I80F48::from_num(bps).checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+}
+
+/// Inverse of [`bps_to_weight`], rounds to the nearest basis point.
+pub fn weight_to_bps(weight: I80F48) -> Option<u32> {
+    weight
+        .checked_mul(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))?
+        .checked_round()?
+        .checked_to_num()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixed_macro::types::I80F48;
+
+    #[test]
+    fn weight_bps_round_trip() {
+        assert!((bps_to_weight(6_500).unwrap() - I80F48!(0.65)).abs() < I80F48!(0.000001));
+        assert!((bps_to_weight(13_000).unwrap() - I80F48!(1.3)).abs() < I80F48!(0.000001));
+
+        for bps in [0, 1, 3_333, 8_000, 10_000, 25_000] {
+            assert_eq!(weight_to_bps(bps_to_weight(bps).unwrap()).unwrap(), bps);
+        }
+
+        assert!(weight_to_bps(-I80F48::ONE).is_none());
+    }
+}
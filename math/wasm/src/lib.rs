@@ -0,0 +1,104 @@
+//! wasm-bindgen bindings of the marginfi value and health math for browsers, build with
+//! `wasm-pack build math/wasm`.
+//!
+//! JS numbers are converted to and from [`I80F48`], so values that don't fit an `f64` lose
+//! precision on the way back, the on-chain checks stay authoritative.
+use marginfi_math::{health::BalanceHealth, I80F48};
+use wasm_bindgen::prelude::*;
+
+fn to_fixed(value: f64) -> Result<I80F48, JsValue> {
+    I80F48::checked_from_num(value).ok_or_else(|| JsValue::from_str("value out of range"))
+}
+
+fn math_error() -> JsValue {
+    JsValue::from_str("math error")
+}
+
+/// Value of `amount` native tokens at `price`, see [`marginfi_math::value::calc_value`].
+#[wasm_bindgen(js_name = calcValue)]
+pub fn calc_value(
+    amount: f64,
+    price: f64,
+    mint_decimals: u8,
+    weight: Option<f64>,
+) -> Result<f64, JsValue> {
+    let weight = weight.map(to_fixed).transpose()?;
+
+    marginfi_math::value::calc_value(to_fixed(amount)?, to_fixed(price)?, mint_decimals, weight)
+        .map(|value| value.to_num())
+        .ok_or_else(math_error)
+}
+
+/// Native token amount worth `value` at `price`, see [`marginfi_math::value::calc_amount`].
+#[wasm_bindgen(js_name = calcAmount)]
+pub fn calc_amount(value: f64, price: f64, mint_decimals: u8) -> Result<f64, JsValue> {
+    marginfi_math::value::calc_amount(to_fixed(value)?, to_fixed(price)?, mint_decimals)
+        .map(|amount| amount.to_num())
+        .ok_or_else(math_error)
+}
+
+/// Accumulates the weighted values of an account's balances, see [`marginfi_math::health`].
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct HealthCalculator {
+    assets: I80F48,
+    liabilities: I80F48,
+}
+
+#[wasm_bindgen]
+impl HealthCalculator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a balance, prices biased and weights picked for the requirement type by the caller.
+    #[wasm_bindgen(js_name = addBalance)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_balance(
+        &mut self,
+        asset_amount: f64,
+        liability_amount: f64,
+        asset_price: f64,
+        liability_price: f64,
+        asset_weight: f64,
+        liability_weight: f64,
+        mint_decimals: u8,
+    ) -> Result<(), JsValue> {
+        let (assets, liabilities) = BalanceHealth {
+            asset_amount: to_fixed(asset_amount)?,
+            liability_amount: to_fixed(liability_amount)?,
+            asset_price: to_fixed(asset_price)?,
+            liability_price: to_fixed(liability_price)?,
+            asset_weight: to_fixed(asset_weight)?,
+            liability_weight: to_fixed(liability_weight)?,
+            mint_decimals,
+        }
+        .weighted_values()
+        .ok_or_else(math_error)?;
+
+        self.assets = self.assets.checked_add(assets).ok_or_else(math_error)?;
+        self.liabilities = self
+            .liabilities
+            .checked_add(liabilities)
+            .ok_or_else(math_error)?;
+
+        Ok(())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn assets(&self) -> f64 {
+        self.assets.to_num()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn liabilities(&self) -> f64 {
+        self.liabilities.to_num()
+    }
+
+    /// Weighted assets minus weighted liabilities.
+    #[wasm_bindgen(getter)]
+    pub fn health(&self) -> f64 {
+        (self.assets - self.liabilities).to_num()
+    }
+}
@@ -0,0 +1,126 @@
+//! Host-side benchmarks for the interest, share and health math hot paths.
+//!
+//! Run with `cargo bench -p marginfi`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use fixed::types::I80F48;
+use fixed_macro::types::I80F48;
+use marginfi::state::{
+    marginfi_account::{calc_value, RequirementType},
+    marginfi_group::{Bank, BankConfig, InterestRateConfig},
+};
+#[cfg(not(feature = "client"))]
+use solana_program::pubkey::Pubkey;
+
+fn ir_config() -> InterestRateConfig {
+    InterestRateConfig {
+        optimal_utilization_rate: I80F48!(0.4).into(),
+        plateau_interest_rate: I80F48!(0.4).into(),
+        protocol_fixed_fee_apr: I80F48!(0.01).into(),
+        max_interest_rate: I80F48!(3).into(),
+        insurance_ir_fee: I80F48!(0.1).into(),
+        ..Default::default()
+    }
+}
+
+fn bank() -> Bank {
+    Bank {
+        asset_share_value: I80F48!(1.05).into(),
+        liability_share_value: I80F48!(1.1).into(),
+        total_liability_shares: I80F48!(207_112_621_602).into(),
+        total_asset_shares: I80F48!(10_000_000_000_000).into(),
+        config: BankConfig {
+            asset_weight_init: I80F48!(0.5).into(),
+            asset_weight_maint: I80F48!(0.75).into(),
+            liability_weight_init: I80F48!(1.5).into(),
+            liability_weight_maint: I80F48!(1.25).into(),
+            borrow_limit: u64::MAX,
+            deposit_limit: u64::MAX,
+            interest_rate_config: ir_config(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn bench_calc_interest_rate(c: &mut Criterion) {
+    let config = ir_config();
+
+    c.bench_function("calc_interest_rate below optimal", |b| {
+        b.iter(|| config.calc_interest_rate(black_box(I80F48!(0.2))))
+    });
+    c.bench_function("calc_interest_rate above optimal", |b| {
+        b.iter(|| config.calc_interest_rate(black_box(I80F48!(0.9))))
+    });
+}
+
+fn bench_accrue_interest(c: &mut Criterion) {
+    c.bench_function("accrue_interest 1h", |b| {
+        b.iter_batched(
+            bank,
+            |mut bank| {
+                bank.accrue_interest(
+                    black_box(3600),
+                    #[cfg(not(feature = "client"))]
+                    Pubkey::default(),
+                )
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_share_conversions(c: &mut Criterion) {
+    let bank = bank();
+
+    c.bench_function("asset shares <-> amount", |b| {
+        b.iter(|| {
+            let shares = bank.get_asset_shares(black_box(I80F48!(1_234_567_890)), false)?;
+            bank.get_asset_amount(shares, false)
+        })
+    });
+    c.bench_function("liability shares <-> amount", |b| {
+        b.iter(|| {
+            let shares = bank.get_liability_shares(black_box(I80F48!(1_234_567_890)))?;
+            bank.get_liability_amount(shares)
+        })
+    });
+}
+
+/// Approximates the per-balance work of the risk engine for a full (16 balance) account.
+fn bench_health_aggregation(c: &mut Criterion) {
+    let bank = bank();
+    let price = I80F48!(23.456789);
+
+    c.bench_function("health aggregation 16 balances", |b| {
+        b.iter(|| {
+            let mut assets = I80F48::ZERO;
+            let mut liabilities = I80F48::ZERO;
+
+            for i in 0..16u32 {
+                let shares = I80F48::from_num(1_000_000 * (i + 1));
+                let (asset_weight, liability_weight) =
+                    bank.config.get_weights(RequirementType::Initial);
+
+                if i % 2 == 0 {
+                    let amount = bank.get_asset_amount(shares, false)?;
+                    assets += calc_value(amount, black_box(price), 6, Some(asset_weight))?;
+                } else {
+                    let amount = bank.get_liability_amount(shares)?;
+                    liabilities += calc_value(amount, black_box(price), 9, Some(liability_weight))?;
+                }
+            }
+
+            Ok::<_, anchor_lang::error::Error>(assets - liabilities)
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_calc_interest_rate,
+    bench_accrue_interest,
+    bench_share_conversions,
+    bench_health_aggregation
+);
+criterion_main!(benches);
@@ -12,9 +12,23 @@ pub const LIQUIDITY_VAULT_SEED: &str = "liquidity_vault";
 pub const INSURANCE_VAULT_SEED: &str = "insurance_vault";
 pub const FEE_VAULT_SEED: &str = "fee_vault";
 
+/// Version of the on-chain data model.
+/// Bump together with a migration step in `MarginfiGroup::migrate` on every state layout change.
+pub const PROGRAM_VERSION: u64 = 3;
+/// Oldest group version instructions still operate on without a migration.
+pub const MIN_COMPATIBLE_PROGRAM_VERSION: u64 = 0;
+
 pub const EMISSIONS_AUTH_SEED: &str = "emissions_auth_seed";
 pub const EMISSIONS_TOKEN_ACCOUNT_SEED: &str = "emissions_token_account_seed";
 
+pub const HEALTH_SNAPSHOT_SEED: &str = "health_snapshot";
+
+pub const FEE_STATE_SEED: &str = "feestate";
+
+pub const BANK_REGISTRY_SEED: &str = "bank_registry";
+
+pub const LOOKUP_TABLE_AUTHORITY_SEED: &str = "lookup_table_auth";
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "devnet")] {
         pub const PYTH_ID: Pubkey = pubkey!("gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s");
@@ -37,12 +51,28 @@ cfg_if::cfg_if! {
 /// TODO: Make these variable per bank
 pub const LIQUIDATION_LIQUIDATOR_FEE: I80F48 = I80F48!(0.025);
 pub const LIQUIDATION_INSURANCE_FEE: I80F48 = I80F48!(0.025);
+/// Liquidator fee for liquidating an expired liability of an otherwise healthy account.
+pub const LIQUIDATION_MATURITY_LIQUIDATOR_FEE: I80F48 = I80F48!(0.01);
 
-pub const SECONDS_PER_YEAR: I80F48 = I80F48!(31_536_000);
+pub use marginfi_math::interest::SECONDS_PER_YEAR;
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Deposits moved into a bank's insured share class are locked for at least this long,
+/// so the cover can't be picked up right before a bankruptcy and dropped right after.
+pub const MIN_INSURED_DURATION: u64 = 7 * SECONDS_PER_DAY;
+
+/// Chainlink OCR2 store program, owner of the Chainlink data feed accounts.
+pub const CHAINLINK_STORE_ID: Pubkey = pubkey!("HEvSKofvBgfaexv23kMabbYqxasxU3mQ4ibBMEmJWHny");
 
 pub const MAX_PYTH_ORACLE_AGE: u64 = 60;
 pub const MAX_SWB_ORACLE_AGE: u64 = 3 * 60;
 
+/// Slots between proposing a new bank oracle and being able to accept it, ~1 hour.
+pub const ORACLE_ROTATION_DELAY_SLOTS: u64 = 9_000;
+
+/// Number of crank-recorded prices a bank keeps for its TWAP.
+pub const TWAP_OBSERVATIONS: usize = 6;
+
 /// Range that contains 95% price data distribution
 ///
 /// https://docs.pyth.network/price-feeds/best-practices#confidence-intervals
@@ -60,6 +90,37 @@ pub const MAX_ORACLE_KEYS: usize = 5;
 /// this is to account for any artifacts resulting from binary fraction arithemtic.
 pub const EMPTY_BALANCE_THRESHOLD: I80F48 = I80F48!(1);
 
+pub use marginfi_math::weights::WEIGHT_BPS_DENOMINATOR;
+
+/// Number of points a bank's interest rate curve can have above the optimal utilization rate.
+pub const MAX_INTEREST_RATE_KINKS: usize = 3;
+
+/// Floor of the plateau interest rate steered by the adaptive rate controller, 0.1% APR.
+pub const MIN_ADAPTIVE_PLATEAU_INTEREST_RATE: I80F48 = I80F48!(0.001);
+
+/// Number of banks a marginfi account can owe its referrer fees in at once.
+pub const MAX_REFERRAL_FEE_BANKS: usize = 4;
+
+/// Number of correlation buckets a group can define for portfolio margin.
+pub const MAX_CORRELATION_BUCKETS: usize = 8;
+
+/// Number of programs a group can allow to call user instructions through CPI.
+pub const MAX_CPI_ALLOWED_PROGRAMS: usize = 4;
+
+/// Number of banks a group's bank registry can hold.
+pub const MAX_REGISTRY_BANKS: usize = 256;
+
+/// Number of mints a group can whitelist for permissionless bank creation.
+pub const MAX_PERMISSIONLESS_MINTS: usize = 4;
+
+/// Liability weights forced on permissionless banks, their deposits don't count as collateral.
+pub const PERMISSIONLESS_LIABILITY_WEIGHT_INIT: I80F48 = I80F48!(1.5);
+pub const PERMISSIONLESS_LIABILITY_WEIGHT_MAINT: I80F48 = I80F48!(1.25);
+
+/// Upper bound for a correlation bucket offset. Offsetting too much of the haircut on hedged
+/// positions would let liquidations decrease the health of the liquidatee.
+pub const MAX_CORRELATION_OFFSET_BPS: u16 = 5_000;
+
 /// Any account with assets below this threshold is considered bankrupt.
 /// The account also needs to have more liabilities than assets.
 ///
@@ -72,41 +133,38 @@ pub const ZERO_AMOUNT_THRESHOLD: I80F48 = I80F48!(0.0001);
 pub const EMISSIONS_FLAG_BORROW_ACTIVE: u64 = 1 << 0;
 pub const EMISSIONS_FLAG_LENDING_ACTIVE: u64 = 1 << 1;
 pub const PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG: u64 = 1 << 2;
+/// Lets deposits into a bank with a fixed price oracle count as collateral.
+pub const FIXED_PRICE_COLLATERAL_FLAG: u64 = 1 << 3;
+/// Price maintenance health and liquidations with the EMA instead of the spot price,
+/// so short wicks don't liquidate accounts. Only Pyth oracles publish an EMA.
+pub const MAINTENANCE_EMA_PRICE_FLAG: u64 = 1 << 4;
+/// Set while the bank is paused by the oracle circuit breaker, cleared when it resumes
+/// or the admin sets the operational state.
+pub const ORACLE_PAUSED_FLAG: u64 = 1 << 5;
+/// Set by the oracle circuit breaker while the primary and fallback oracle diverge,
+/// blocks deposits and borrows.
+pub const ORACLE_DEVIATION_FLAG: u64 = 1 << 6;
+/// Set by `lending_pool_close_bank`, the bank stays paused and can't be configured again.
+pub const BANK_CLOSED_FLAG: u64 = 1 << 7;
+/// Set through `BankConfigOpt::freeze_config`, only the operational state can change afterwards.
+pub const BANK_CONFIG_FROZEN_FLAG: u64 = 1 << 8;
+
+/// Marginfi group flag, lets anyone add an isolated bank for a whitelisted mint.
+pub const PERMISSIONLESS_BANK_CREATION_FLAG: u64 = 1 << 0;
+/// Marginfi group flag, set once the group has a bank registry that every bank added or closed
+/// has to update.
+pub const BANK_REGISTRY_FLAG: u64 = 1 << 1;
 
 pub(crate) const EMISSION_FLAGS: u64 = EMISSIONS_FLAG_BORROW_ACTIVE | EMISSIONS_FLAG_LENDING_ACTIVE;
-pub(crate) const GROUP_FLAGS: u64 = PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG;
+pub(crate) const GROUP_FLAGS: u64 = PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG
+    | FIXED_PRICE_COLLATERAL_FLAG
+    | MAINTENANCE_EMA_PRICE_FLAG;
 
 /// Cutoff timestamp for balance last_update used in accounting collected emissions.
 /// Any balance updates before this timestamp are ignored, and current_timestamp is used instead.
 pub const MIN_EMISSIONS_START_TIME: u64 = 1681989983;
 
-pub const MAX_EXP_10_I80F48: usize = 24;
-pub const EXP_10_I80F48: [I80F48; MAX_EXP_10_I80F48] = [
-    I80F48!(1),                        // 10^0
-    I80F48!(10),                       // 10^1
-    I80F48!(100),                      // 10^2
-    I80F48!(1000),                     // 10^3
-    I80F48!(10000),                    // 10^4
-    I80F48!(100000),                   // 10^5
-    I80F48!(1000000),                  // 10^6
-    I80F48!(10000000),                 // 10^7
-    I80F48!(100000000),                // 10^8
-    I80F48!(1000000000),               // 10^9
-    I80F48!(10000000000),              // 10^10
-    I80F48!(100000000000),             // 10^11
-    I80F48!(1000000000000),            // 10^12
-    I80F48!(10000000000000),           // 10^13
-    I80F48!(100000000000000),          // 10^14
-    I80F48!(1000000000000000),         // 10^15
-    I80F48!(10000000000000000),        // 10^16
-    I80F48!(100000000000000000),       // 10^17
-    I80F48!(1000000000000000000),      // 10^18
-    I80F48!(10000000000000000000),     // 10^19
-    I80F48!(100000000000000000000),    // 10^20
-    I80F48!(1000000000000000000000),   // 10^21
-    I80F48!(10000000000000000000000),  // 10^22
-    I80F48!(100000000000000000000000), // 10^23
-];
+pub use marginfi_math::value::{EXP_10_I80F48, MAX_EXP_10_I80F48};
 
 pub const MAX_EXP_10: usize = 21;
 pub const EXP_10: [i128; MAX_EXP_10] = [
@@ -96,6 +96,64 @@ pub enum MarginfiError {
     IllegalAction,
     #[msg("Token22 Banks require mint account as first remaining account")] // 6047
     T22MintRequired,
+    #[msg("Group program version is not compatible, migration required")] // 6048
+    IncompatibleProgramVersion,
+    #[msg("Share amount outside of slippage tolerance")] // 6049
+    SharesSlippageExceeded,
+    #[msg("Invalid bank vault")] // 6050
+    InvalidBankVault,
+    #[msg("Action not allowed while the group is in recovery mode")] // 6051
+    GroupInRecoveryMode,
+    #[msg("Bank doesn't offer deposit insurance")] // 6052
+    DepositInsuranceNotOffered,
+    #[msg("Deposit is locked until its maturity")] // 6053
+    DepositLocked,
+    #[msg("Calling program is not allowed to CPI into this group")] // 6054
+    CpiCallerNotAllowed,
+    #[msg("Operation moved account health in an unexpected direction")] // 6055
+    HealthDeltaSanityCheckFailed,
+    #[msg("Liquidations are disabled during the bank's post-outage grace period")] // 6056
+    LiquidationGracePeriod,
+    #[msg("Refinance requires two distinct banks of the same mint")] // 6057
+    InvalidRefinance,
+    #[msg("Bank borrow volume cap for the current window exceeded")] // 6058
+    BorrowWindowCapExceeded,
+    #[msg("Health snapshot is stale")] // 6059
+    StaleHealthSnapshot,
+    #[msg("Bank oracles diverge, deposits and borrows are blocked")] // 6060
+    OracleDeviationExceeded,
+    #[msg("No oracle rotation is pending")] // 6061
+    OracleRotationNotPending,
+    #[msg("Oracle rotation delay has not elapsed")] // 6062
+    OracleRotationDelayNotElapsed,
+    #[msg("Fee collection accounts are incomplete or invalid")] // 6063
+    InvalidFeeCollectionAccounts,
+    #[msg("Account deposit capacity exceeded")] // 6064
+    AccountDepositCapacityExceeded,
+    #[msg("Bank is closed")] // 6065
+    BankClosed,
+    #[msg("Bank still has deposits, borrows or emissions")] // 6066
+    BankNotEmpty,
+    #[msg("Permissionless bank creation is disabled or the mint is not whitelisted")] // 6067
+    PermissionlessBankCreationNotAllowed,
+    #[msg("Bank config is frozen")] // 6068
+    BankConfigFrozen,
+    #[msg("Bank is killed")] // 6069
+    BankKilled,
+    #[msg("Bank is not killed")] // 6070
+    BankNotKilled,
+    #[msg("Bank registry account is required")] // 6071
+    BankRegistryMissing,
+    #[msg("Bank registry is full")] // 6072
+    BankRegistryFull,
+    #[msg("Invalid address lookup table")] // 6073
+    InvalidLookupTable,
+    #[msg("Borrow is below the bank's minimum borrow amount")] // 6074
+    BorrowBelowMinimum,
+    #[msg("Borrow would push utilization past the bank's ceiling")] // 6075
+    BorrowUtilizationExceeded,
+    #[msg("Borrow would dip into the bank's liquidity reserve")] // 6076
+    LiquidityReserveExceeded,
 }
 
 impl From<MarginfiError> for ProgramError {
@@ -1,4 +1,7 @@
-use crate::{prelude::*, state::marginfi_group::BankConfigOpt};
+use crate::{
+    prelude::*,
+    state::marginfi_group::{BankConfigOpt, BankVaultType},
+};
 use anchor_lang::prelude::*;
 
 // Event headers
@@ -30,6 +33,13 @@ pub struct MarginfiGroupConfigureEvent {
     pub config: GroupConfig,
 }
 
+#[event]
+pub struct MarginfiGroupMigrateEvent {
+    pub header: GroupEventHeader,
+    pub old_version: u64,
+    pub new_version: u64,
+}
+
 #[event]
 pub struct LendingPoolBankCreateEvent {
     pub header: GroupEventHeader,
@@ -45,6 +55,41 @@ pub struct LendingPoolBankConfigureEvent {
     pub config: BankConfigOpt,
 }
 
+#[event]
+pub struct LendingPoolBankOracleCircuitBreakerEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub paused: bool,
+    pub deviation_exceeded: bool,
+}
+
+#[event]
+pub struct LendingPoolBankMigrateVaultEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub vault_type: BankVaultType,
+    pub old_vault: Pubkey,
+    pub new_vault: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct LendingPoolBankCloseEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct LendingPoolBankKillEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub settlement_price: f64,
+}
+
 #[event]
 pub struct LendingPoolBankAccrueInterestEvent {
     pub header: GroupEventHeader,
@@ -55,6 +100,26 @@ pub struct LendingPoolBankAccrueInterestEvent {
     pub insurance_collected: f64,
 }
 
+#[event]
+pub struct LendingPoolBankAccrualBountyEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct LendingPoolBankLendingSubsidyEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// Moved from the lending subsidy reserve back into the fee vault
+    pub returned: bool,
+    pub reserve: f64,
+}
+
 #[event]
 pub struct LendingPoolBankCollectFeesEvent {
     pub header: GroupEventHeader,
@@ -64,6 +129,25 @@ pub struct LendingPoolBankCollectFeesEvent {
     pub group_fees_outstanding: f64,
     pub insurance_fees_collected: f64,
     pub insurance_fees_outstanding: f64,
+    pub program_fees_collected: f64,
+}
+
+#[event]
+pub struct LendingPoolBankWithdrawFeesEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct LendingPoolBankWithdrawInsuranceEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
 }
 
 #[event]
@@ -81,6 +165,8 @@ pub struct LendingPoolBankHandleBankruptcyEvent {
 #[event]
 pub struct MarginfiAccountCreateEvent {
     pub header: AccountEventHeader,
+    pub integrator_tag: [u8; 32],
+    pub referrer: Pubkey,
 }
 
 #[event]
@@ -108,6 +194,17 @@ pub struct LendingAccountBorrowEvent {
     pub amount: u64,
 }
 
+#[event]
+pub struct LendingAccountRefinanceEvent {
+    pub header: AccountEventHeader,
+    pub source_bank: Pubkey,
+    pub destination_bank: Pubkey,
+    pub mint: Pubkey,
+    pub repay_amount: u64,
+    pub borrow_amount: u64,
+    pub close_balance: bool,
+}
+
 #[event]
 pub struct LendingAccountWithdrawEvent {
     pub header: AccountEventHeader,
@@ -117,6 +214,23 @@ pub struct LendingAccountWithdrawEvent {
     pub close_balance: bool,
 }
 
+#[event]
+pub struct LendingAccountSettleKilledBalanceEvent {
+    pub header: AccountEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub repaid: u64,
+    pub withdrawn: u64,
+}
+
+#[event]
+pub struct LendingAccountClaimReferralFeesEvent {
+    pub header: AccountEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct LiquidationBalances {
     pub liquidatee_asset_balance: f64,
@@ -140,9 +254,102 @@ pub struct LendingAccountLiquidateEvent {
     pub post_balances: LiquidationBalances,
 }
 
+#[event]
+pub struct LendingAccountLiquidateToTokenAccountEvent {
+    pub header: GroupEventHeader,
+    pub liquidatee_marginfi_account: Pubkey,
+    pub liquidatee_marginfi_account_authority: Pubkey,
+    pub asset_bank: Pubkey,
+    pub asset_mint: Pubkey,
+    pub liability_bank: Pubkey,
+    pub liability_mint: Pubkey,
+    /// Collateral transferred to the liquidator
+    pub asset_amount: u64,
+    /// Liability paid by the liquidator
+    pub liability_amount: u64,
+    pub liquidatee_pre_health: f64,
+    pub liquidatee_post_health: f64,
+}
+
 #[event]
 pub struct MarginfiAccountTransferAccountAuthorityEvent {
     pub header: AccountEventHeader,
     pub old_account_authority: Pubkey,
     pub new_account_authority: Pubkey,
 }
+
+// Event decoding
+
+/// Prefix of the log line `emit!` produces for each event.
+#[cfg(any(feature = "test", feature = "client"))]
+pub const PROGRAM_DATA_LOG_PREFIX: &str = "Program data: ";
+
+/// Decode a single event of type `T` from the (base64 decoded) payload of a `Program data:` log line.
+///
+/// Returns `None` if the payload belongs to a different event.
+#[cfg(any(feature = "test", feature = "client"))]
+pub fn decode_event<T: anchor_lang::Event>(data: &[u8]) -> Option<T> {
+    if data.len() < 8 || data[..8] != T::DISCRIMINATOR {
+        return None;
+    }
+
+    T::deserialize(&mut &data[8..]).ok()
+}
+
+/// Decode all events of type `T` from transaction logs.
+///
+/// `decode_base64` is supplied by the caller so the program does not depend on a base64 crate.
+#[cfg(any(feature = "test", feature = "client"))]
+pub fn decode_events_from_logs<T, F>(logs: &[String], decode_base64: F) -> Vec<T>
+where
+    T: anchor_lang::Event,
+    F: Fn(&str) -> Option<Vec<u8>>,
+{
+    logs.iter()
+        .filter_map(|log| log.strip_prefix(PROGRAM_DATA_LOG_PREFIX))
+        .filter_map(|encoded| decode_base64(encoded))
+        .filter_map(|data| decode_event::<T>(&data))
+        .collect()
+}
+
+#[cfg(all(test, any(feature = "test", feature = "client")))]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    #[test]
+    fn decode_emitted_event_from_logs() {
+        let bank = Pubkey::new_unique();
+        let event = LendingPoolBankAccrueInterestEvent {
+            header: GroupEventHeader {
+                signer: None,
+                marginfi_group: Pubkey::new_unique(),
+            },
+            bank,
+            mint: Pubkey::new_unique(),
+            delta: 42,
+            fees_collected: 1.5,
+            insurance_collected: 0.5,
+        };
+
+        let logs = vec![
+            "Program log: Instruction: LendingPoolAccrueBankInterest".to_string(),
+            format!(
+                "{}{}",
+                PROGRAM_DATA_LOG_PREFIX,
+                STANDARD.encode(anchor_lang::Event::data(&event))
+            ),
+        ];
+
+        let decoded: Vec<LendingPoolBankAccrueInterestEvent> =
+            decode_events_from_logs(&logs, |s| STANDARD.decode(s).ok());
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].bank, bank);
+        assert_eq!(decoded[0].delta, 42);
+        assert_eq!(decoded[0].fees_collected, 1.5);
+
+        let other: Vec<MarginfiGroupCreateEvent> =
+            decode_events_from_logs(&logs, |s| STANDARD.decode(s).ok());
+        assert!(other.is_empty());
+    }
+}
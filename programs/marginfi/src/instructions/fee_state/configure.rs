@@ -0,0 +1,38 @@
+use crate::{constants::FEE_STATE_SEED, prelude::*, state::fee_state::FeeState};
+use anchor_lang::prelude::*;
+
+/// Change the program wide fee state, see [`FeeState::configure`].
+pub fn configure_fee_state(
+    ctx: Context<ConfigureFeeState>,
+    global_fee_admin: Option<Pubkey>,
+    global_fee_wallet: Option<Pubkey>,
+    program_fee_bps: Option<u16>,
+) -> MarginfiResult {
+    let mut fee_state = ctx.accounts.fee_state.load_mut()?;
+
+    fee_state.configure(global_fee_admin, global_fee_wallet, program_fee_bps)?;
+
+    msg!(
+        "Fee state: admin {}, wallet {}, program fee {} bps",
+        fee_state.global_fee_admin,
+        fee_state.global_fee_wallet,
+        fee_state.program_fee_bps
+    );
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ConfigureFeeState<'info> {
+    pub global_fee_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [FEE_STATE_SEED.as_bytes()],
+        bump = fee_state.load()?.bump,
+        has_one = global_fee_admin @ MarginfiError::Unauthorized,
+        constraint = fee_state.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub fee_state: AccountLoader<'info, FeeState>,
+}
@@ -0,0 +1,56 @@
+use crate::{
+    constants::{FEE_STATE_SEED, PROGRAM_VERSION},
+    prelude::*,
+    program::Marginfi,
+    state::fee_state::FeeState,
+};
+use anchor_lang::prelude::*;
+
+/// Create the program wide fee state. Only the program's upgrade authority can do this,
+/// so the first caller can't take over the program fee.
+pub fn initialize_fee_state(
+    ctx: Context<InitFeeState>,
+    global_fee_admin: Pubkey,
+    global_fee_wallet: Pubkey,
+    program_fee_bps: u16,
+) -> MarginfiResult {
+    let mut fee_state = ctx.accounts.fee_state.load_init()?;
+
+    fee_state.bump = ctx.bumps.fee_state;
+    fee_state.program_version = PROGRAM_VERSION;
+    fee_state.configure(
+        Some(global_fee_admin),
+        Some(global_fee_wallet),
+        Some(program_fee_bps),
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitFeeState<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<FeeState>(),
+        seeds = [FEE_STATE_SEED.as_bytes()],
+        bump,
+    )]
+    pub fee_state: AccountLoader<'info, FeeState>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key()),
+    )]
+    pub program: Program<'info, Marginfi>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(payer.key())
+            @ MarginfiError::Unauthorized,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
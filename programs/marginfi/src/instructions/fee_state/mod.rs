@@ -0,0 +1,5 @@
+mod configure;
+mod initialize;
+
+pub use configure::*;
+pub use initialize::*;
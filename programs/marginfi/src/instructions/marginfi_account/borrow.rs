@@ -1,11 +1,14 @@
+#[cfg(feature = "sanity")]
+use crate::state::marginfi_account::HealthDelta;
 use crate::{
     bank_signer, check,
-    constants::{LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED},
+    constants::LIQUIDITY_VAULT_AUTHORITY_SEED,
     events::{AccountEventHeader, LendingAccountBorrowEvent},
+    math_error,
     prelude::{MarginfiError, MarginfiGroup, MarginfiResult},
     state::{
         marginfi_account::{BankAccountWrapper, MarginfiAccount, RiskEngine, DISABLED_FLAG},
-        marginfi_group::{Bank, BankVaultType},
+        marginfi_group::{Bank, BankVaultType, WrappedI80F48},
     },
     utils,
 };
@@ -21,10 +24,26 @@ use solana_program::{clock::Clock, sysvar::Sysvar};
 /// 5. Verify that the user account is in a healthy state
 ///
 /// Will error if there is an existing asset <=> withdrawing is not allowed.
+///
+/// If `max_liability_shares` is set, will error if more liability shares are minted,
+/// e.g. because interest accrued between quoting and execution.
+///
+/// Will error if the bank's borrow volume cap for the current window is exceeded,
+/// if the borrow leaves the bank above its utilization ceiling, or if it leaves less than the
+/// bank's liquidity reserve in the liquidity vault.
+///
+/// With the `sanity` feature, the borrow is asserted not to increase account health.
 pub fn lending_account_borrow<'info>(
     mut ctx: Context<'_, '_, 'info, 'info, LendingAccountBorrow<'info>>,
     amount: u64,
+    max_liability_shares: Option<WrappedI80F48>,
 ) -> MarginfiResult {
+    ctx.accounts
+        .marginfi_group
+        .load()?
+        .check_cpi_caller(ctx.remaining_accounts)?;
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+
     let LendingAccountBorrow {
         marginfi_account: marginfi_account_loader,
         destination_token_account,
@@ -54,6 +73,9 @@ pub fn lending_account_borrow<'info>(
         bank_loader.key(),
     )?;
 
+    #[cfg(feature = "sanity")]
+    let health_before = RiskEngine::get_sanity_health(&marginfi_account, ctx.remaining_accounts)?;
+
     {
         let mut bank = bank_loader.load_mut()?;
 
@@ -62,7 +84,8 @@ pub fn lending_account_borrow<'info>(
         let mut bank_account = BankAccountWrapper::find_or_create(
             &bank_loader.key(),
             &mut bank,
-            &mut marginfi_account.lending_account,
+            &mut marginfi_account,
+            referral_fee_bps,
         )?;
 
         // User needs to borrow amount + fee to receive amount
@@ -78,7 +101,26 @@ pub fn lending_account_borrow<'info>(
             .transpose()?
             .unwrap_or(amount);
 
-        bank_account.borrow(I80F48::from_num(amount_pre_fee))?;
+        let liability_shares_before: I80F48 = bank_account.balance.liability_shares.into();
+
+        borrow_within_limits(
+            &mut bank_account,
+            amount_pre_fee,
+            bank_liquidity_vault.amount,
+            clock.slot,
+        )?;
+
+        if let Some(max_liability_shares) = max_liability_shares {
+            let liability_shares_out = I80F48::from(bank_account.balance.liability_shares)
+                .checked_sub(liability_shares_before)
+                .ok_or_else(math_error!())?;
+
+            check!(
+                liability_shares_out <= I80F48::from(max_liability_shares),
+                MarginfiError::SharesSlippageExceeded
+            );
+        }
+
         bank_account.withdraw_spl_transfer(
             amount_pre_fee,
             bank_liquidity_vault.to_account_info(),
@@ -109,13 +151,49 @@ pub fn lending_account_borrow<'info>(
 
     // Check account health, if below threshold fail transaction
     // Assuming `ctx.remaining_accounts` holds only oracle accounts
-    RiskEngine::check_account_init_health(&marginfi_account, ctx.remaining_accounts)?;
+    RiskEngine::check_account_init_health(
+        &marginfi_account,
+        ctx.remaining_accounts,
+        &*ctx.accounts.marginfi_group.load()?,
+    )?;
+
+    #[cfg(feature = "sanity")]
+    HealthDelta::NonIncreasing.check(
+        health_before,
+        RiskEngine::get_sanity_health(&marginfi_account, ctx.remaining_accounts)?,
+    )?;
+
+    Ok(())
+}
+
+/// Record a liability increase of `amount` in `bank_account` and check it against the bank's
+/// borrow volume cap, utilization ceiling and liquidity reserve. `liquidity_vault_amount` is the
+/// vault balance before `amount` is transferred out of it.
+pub(crate) fn borrow_within_limits(
+    bank_account: &mut BankAccountWrapper,
+    amount: u64,
+    liquidity_vault_amount: u64,
+    current_slot: u64,
+) -> MarginfiResult {
+    bank_account.borrow(I80F48::from_num(amount))?;
+
+    bank_account
+        .bank
+        .record_borrow_volume(amount, current_slot)?;
+    bank_account.bank.check_borrow_utilization()?;
+    bank_account
+        .bank
+        .check_liquidity_reserve(liquidity_vault_amount.saturating_sub(amount))?;
 
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct LendingAccountBorrow<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -151,11 +229,7 @@ pub struct LendingAccountBorrow<'info> {
 
     #[account(
         mut,
-        seeds = [
-            LIQUIDITY_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load() ?.liquidity_vault_bump,
+        address = bank.load()?.liquidity_vault,
     )]
     pub bank_liquidity_vault: InterfaceAccount<'info, TokenAccount>,
 
@@ -1,6 +1,10 @@
 use anchor_lang::prelude::*;
 
-use crate::{check, state::marginfi_account::MarginfiAccount, MarginfiError, MarginfiResult};
+use crate::{
+    check,
+    state::{marginfi_account::MarginfiAccount, marginfi_group::MarginfiGroup},
+    MarginfiError, MarginfiResult,
+};
 
 pub fn close_account(ctx: Context<MarginfiAccountClose>) -> MarginfiResult {
     let marginfi_account = &ctx.accounts.marginfi_account.load()?;
@@ -16,6 +20,13 @@ pub fn close_account(ctx: Context<MarginfiAccountClose>) -> MarginfiResult {
 
 #[derive(Accounts)]
 pub struct MarginfiAccountClose<'info> {
+    #[account(
+        address = marginfi_account.load()?.group,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
     #[account(mut, close = fee_payer)]
     pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
     #[account(address = marginfi_account.load()?.authority)]
@@ -10,6 +10,7 @@ use crate::{
 };
 
 pub fn lending_account_close_balance(ctx: Context<LendingAccountCloseBalance>) -> MarginfiResult {
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
     let LendingAccountCloseBalance {
         marginfi_account,
         bank: bank_loader,
@@ -33,7 +34,8 @@ pub fn lending_account_close_balance(ctx: Context<LendingAccountCloseBalance>) -
     let mut bank_account = BankAccountWrapper::find(
         &bank_loader.key(),
         &mut bank,
-        &mut marginfi_account.lending_account,
+        &mut marginfi_account,
+        referral_fee_bps,
     )?;
 
     bank_account.close_balance()?;
@@ -43,6 +45,10 @@ pub fn lending_account_close_balance(ctx: Context<LendingAccountCloseBalance>) -
 
 #[derive(Accounts)]
 pub struct LendingAccountCloseBalance<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
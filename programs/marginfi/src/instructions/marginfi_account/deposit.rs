@@ -1,11 +1,13 @@
+#[cfg(feature = "sanity")]
+use crate::state::marginfi_account::{HealthDelta, RiskEngine};
 use crate::{
     check,
-    constants::LIQUIDITY_VAULT_SEED,
     events::{AccountEventHeader, LendingAccountDepositEvent},
+    math_error,
     prelude::*,
     state::{
         marginfi_account::{BankAccountWrapper, MarginfiAccount, DISABLED_FLAG},
-        marginfi_group::Bank,
+        marginfi_group::{Bank, WrappedI80F48},
     },
     utils,
 };
@@ -21,10 +23,23 @@ use solana_program::sysvar::Sysvar;
 /// 4. Transfer funds from the signer's token account to the bank's liquidity vault
 ///
 /// Will error if there is an existing liability <=> repaying is not allowed.
+///
+/// If `min_shares_out` is set, will error if fewer asset shares are minted,
+/// e.g. because interest accrued between quoting and execution.
+///
+/// With the `sanity` feature, remaining accounts also carry the bank and oracle of every balance
+/// and the deposit is asserted not to decrease account health.
 pub fn lending_account_deposit<'info>(
     mut ctx: Context<'_, '_, 'info, 'info, LendingAccountDeposit<'info>>,
     amount: u64,
+    min_shares_out: Option<WrappedI80F48>,
 ) -> MarginfiResult {
+    ctx.accounts
+        .marginfi_group
+        .load()?
+        .check_cpi_caller(ctx.remaining_accounts)?;
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+
     let LendingAccountDeposit {
         marginfi_account: marginfi_account_loader,
         signer,
@@ -41,6 +56,17 @@ pub fn lending_account_deposit<'info>(
         token_program.key,
     )?;
 
+    #[cfg(feature = "sanity")]
+    let health_before = {
+        bank_loader.load_mut()?.accrue_interest(
+            clock.unix_timestamp,
+            #[cfg(not(feature = "client"))]
+            bank_loader.key(),
+        )?;
+
+        RiskEngine::get_sanity_health(&*marginfi_account_loader.load()?, ctx.remaining_accounts)?
+    };
+
     let mut bank = bank_loader.load_mut()?;
     let mut marginfi_account = marginfi_account_loader.load_mut()?;
 
@@ -58,11 +84,25 @@ pub fn lending_account_deposit<'info>(
     let mut bank_account = BankAccountWrapper::find_or_create(
         &bank_loader.key(),
         &mut bank,
-        &mut marginfi_account.lending_account,
+        &mut marginfi_account,
+        referral_fee_bps,
     )?;
 
+    let asset_shares_before: I80F48 = bank_account.balance.asset_shares.into();
+
     bank_account.deposit(I80F48::from_num(amount))?;
 
+    if let Some(min_shares_out) = min_shares_out {
+        let asset_shares_out = I80F48::from(bank_account.balance.asset_shares)
+            .checked_sub(asset_shares_before)
+            .ok_or_else(math_error!())?;
+
+        check!(
+            asset_shares_out >= I80F48::from(min_shares_out),
+            MarginfiError::SharesSlippageExceeded
+        );
+    }
+
     let amount_pre_fee = maybe_bank_mint
         .as_ref()
         .map(|mint| {
@@ -93,11 +133,24 @@ pub fn lending_account_deposit<'info>(
         amount,
     });
 
+    #[cfg(feature = "sanity")]
+    {
+        drop(bank);
+        HealthDelta::NonDecreasing.check(
+            health_before,
+            RiskEngine::get_sanity_health(&marginfi_account, ctx.remaining_accounts)?,
+        )?;
+    }
+
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct LendingAccountDeposit<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -124,11 +177,7 @@ pub struct LendingAccountDeposit<'info> {
     /// CHECK: Seed constraint check
     #[account(
         mut,
-        seeds = [
-            LIQUIDITY_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.liquidity_vault_bump,
+        address = bank.load()?.liquidity_vault,
     )]
     pub bank_liquidity_vault: AccountInfo<'info>,
 
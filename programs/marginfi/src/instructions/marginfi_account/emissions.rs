@@ -17,6 +17,7 @@ use crate::{
 pub fn lending_account_withdraw_emissions<'info>(
     ctx: Context<'_, '_, 'info, 'info, LendingAccountWithdrawEmissions<'info>>,
 ) -> MarginfiResult {
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
     let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
 
     check!(
@@ -29,7 +30,8 @@ pub fn lending_account_withdraw_emissions<'info>(
     let mut balance = BankAccountWrapper::find(
         ctx.accounts.bank.to_account_info().key,
         &mut bank,
-        &mut marginfi_account.lending_account,
+        &mut marginfi_account,
+        referral_fee_bps,
     )?;
 
     // Settle emissions
@@ -66,6 +68,10 @@ pub fn lending_account_withdraw_emissions<'info>(
 
 #[derive(Accounts)]
 pub struct LendingAccountWithdrawEmissions<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -121,13 +127,15 @@ pub struct LendingAccountWithdrawEmissions<'info> {
 pub fn lending_account_settle_emissions(
     ctx: Context<LendingAccountSettleEmissions>,
 ) -> MarginfiResult {
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
     let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
     let mut bank = ctx.accounts.bank.load_mut()?;
 
     let mut balance = BankAccountWrapper::find(
         ctx.accounts.bank.to_account_info().key,
         &mut bank,
-        &mut marginfi_account.lending_account,
+        &mut marginfi_account,
+        referral_fee_bps,
     )?;
 
     balance.claim_emissions(Clock::get()?.unix_timestamp.try_into().unwrap())?;
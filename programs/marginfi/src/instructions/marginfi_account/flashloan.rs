@@ -28,6 +28,13 @@ pub fn lending_account_start_flashloan(
 
 #[derive(Accounts)]
 pub struct LendingAccountStartFlashloan<'info> {
+    #[account(
+        address = marginfi_account.load()?.group,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
     #[account(mut)]
     pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
     #[account(address = marginfi_account.load()?.authority)]
@@ -131,13 +138,23 @@ pub fn lending_account_end_flashloan<'info>(
 
     let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
 
+    // An end without a matching start can't be interleaved with another flashloan's end
+    check!(
+        marginfi_account.get_flag(IN_FLASHLOAN_FLAG),
+        MarginfiError::IllegalFlashloan,
+        "Account is not in a flashloan"
+    );
+
     marginfi_account.unset_flag(IN_FLASHLOAN_FLAG);
 
-    RiskEngine::check_account_init_health(&marginfi_account, ctx.remaining_accounts)?;
+    // The flashloan end ix has no group account, hedged positions get no portfolio margin offset
+    RiskEngine::check_flashloan_end_health(&marginfi_account, ctx.remaining_accounts)?;
 
     Ok(())
 }
 
+/// No group account: ending requires the account to be in a flashloan, and starting one is
+/// already gated on the group's program version.
 #[derive(Accounts)]
 pub struct LendingAccountEndFlashloan<'info> {
     #[account(mut)]
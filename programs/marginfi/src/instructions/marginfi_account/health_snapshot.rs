@@ -0,0 +1,111 @@
+use crate::{
+    constants::HEALTH_SNAPSHOT_SEED,
+    prelude::*,
+    state::{
+        health_snapshot::HealthSnapshot,
+        marginfi_account::{MarginfiAccount, RiskEngine, RiskRequirementType},
+    },
+};
+use anchor_lang::prelude::*;
+use solana_program::{clock::Clock, sysvar::Sysvar};
+
+/// Create the health snapshot PDA of a marginfi account, opting it in to crank refreshes.
+pub fn initialize_health_snapshot(
+    ctx: Context<MarginfiAccountInitHealthSnapshot>,
+) -> MarginfiResult {
+    let mut health_snapshot = ctx.accounts.health_snapshot.load_init()?;
+
+    health_snapshot.marginfi_account = ctx.accounts.marginfi_account.key();
+    health_snapshot.bump = ctx.bumps.health_snapshot;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiAccountInitHealthSnapshot<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        constraint = marginfi_account.load()?.group == marginfi_group.key(),
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    #[account(address = marginfi_account.load()?.authority)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        space = 8 + std::mem::size_of::<HealthSnapshot>(),
+        seeds = [
+            HEALTH_SNAPSHOT_SEED.as_bytes(),
+            marginfi_account.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub health_snapshot: AccountLoader<'info, HealthSnapshot>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Run the risk engine on a marginfi account and store the result in its health snapshot.
+///
+/// Permissionless, `ctx.remaining_accounts` holds the bank and oracle of every active balance,
+/// the same as for a health check.
+pub fn refresh_health_snapshot<'info>(
+    ctx: Context<'_, '_, 'info, 'info, MarginfiAccountRefreshHealthSnapshot<'info>>,
+) -> MarginfiResult {
+    let marginfi_account = ctx.accounts.marginfi_account.load()?;
+    let marginfi_group = ctx.accounts.marginfi_group.load()?;
+    let clock = Clock::get()?;
+
+    let risk_engine =
+        RiskEngine::new(&marginfi_account, ctx.remaining_accounts)?.with_group(&marginfi_group);
+
+    let (asset_value_init, liability_value_init) =
+        risk_engine.get_account_health_components(RiskRequirementType::Initial)?;
+    let (asset_value_maint, liability_value_maint) =
+        risk_engine.get_account_health_components(RiskRequirementType::Maintenance)?;
+
+    let mut health_snapshot = ctx.accounts.health_snapshot.load_mut()?;
+
+    health_snapshot.asset_value_init = asset_value_init.into();
+    health_snapshot.liability_value_init = liability_value_init.into();
+    health_snapshot.asset_value_maint = asset_value_maint.into();
+    health_snapshot.liability_value_maint = liability_value_maint.into();
+    health_snapshot.last_update_slot = clock.slot;
+    health_snapshot.last_update = clock.unix_timestamp;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiAccountRefreshHealthSnapshot<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        constraint = marginfi_account.load()?.group == marginfi_group.key(),
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            HEALTH_SNAPSHOT_SEED.as_bytes(),
+            marginfi_account.key().as_ref(),
+        ],
+        bump = health_snapshot.load()?.bump,
+    )]
+    pub health_snapshot: AccountLoader<'info, HealthSnapshot>,
+}
@@ -6,7 +6,15 @@ use crate::{
 use anchor_lang::prelude::*;
 use solana_program::sysvar::Sysvar;
 
-pub fn initialize_account(ctx: Context<MarginfiAccountInitialize>) -> MarginfiResult {
+/// Create a marginfi account, optionally tagged with `integrator_tag` so the integrator
+/// that opened it can attribute volume to it, see [`MarginfiAccount::integrator_tag`].
+/// A `referrer` earns a share of the group fees the account's borrows generate,
+/// see [`MarginfiAccount::referrer`].
+pub fn initialize_account(
+    ctx: Context<MarginfiAccountInitialize>,
+    integrator_tag: Option<[u8; 32]>,
+    referrer: Option<Pubkey>,
+) -> MarginfiResult {
     let MarginfiAccountInitialize {
         authority,
         marginfi_group,
@@ -16,7 +24,12 @@ pub fn initialize_account(ctx: Context<MarginfiAccountInitialize>) -> MarginfiRe
 
     let mut marginfi_account = marginfi_account_loader.load_init()?;
 
-    marginfi_account.initialize(marginfi_group.key(), authority.key());
+    marginfi_account.initialize(
+        marginfi_group.key(),
+        authority.key(),
+        integrator_tag.unwrap_or_default(),
+        referrer.unwrap_or_default(),
+    );
 
     emit!(MarginfiAccountCreateEvent {
         header: AccountEventHeader {
@@ -24,7 +37,9 @@ pub fn initialize_account(ctx: Context<MarginfiAccountInitialize>) -> MarginfiRe
             marginfi_account: marginfi_account_loader.key(),
             marginfi_account_authority: marginfi_account.authority,
             marginfi_group: marginfi_account.group,
-        }
+        },
+        integrator_tag: marginfi_account.integrator_tag,
+        referrer: marginfi_account.referrer,
     });
 
     Ok(())
@@ -32,6 +47,10 @@ pub fn initialize_account(ctx: Context<MarginfiAccountInitialize>) -> MarginfiRe
 
 #[derive(Accounts)]
 pub struct MarginfiAccountInitialize<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
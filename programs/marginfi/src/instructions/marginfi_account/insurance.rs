@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    check,
+    prelude::*,
+    state::{
+        marginfi_account::{BankAccountWrapper, MarginfiAccount, DISABLED_FLAG},
+        marginfi_group::Bank,
+    },
+};
+
+/// Opt a deposit in or out of the bank's deposit insurance.
+///
+/// Insured deposits pay `insurance_premium_bps` of their yield to the insurance vault,
+/// in exchange they only absorb socialized bad debt once uninsured deposits are wiped out.
+/// Insured deposits are locked for at least `MIN_INSURED_DURATION`.
+pub fn lending_account_set_insured(
+    ctx: Context<LendingAccountSetInsured>,
+    insured: bool,
+) -> MarginfiResult {
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+    let LendingAccountSetInsured {
+        marginfi_account,
+        bank: bank_loader,
+        ..
+    } = ctx.accounts;
+
+    let mut marginfi_account = marginfi_account.load_mut()?;
+    let mut bank = bank_loader.load_mut()?;
+
+    check!(
+        !marginfi_account.get_flag(DISABLED_FLAG),
+        MarginfiError::AccountDisabled
+    );
+
+    let current_timestamp = Clock::get()?.unix_timestamp;
+
+    bank.accrue_interest(
+        current_timestamp,
+        #[cfg(not(feature = "client"))]
+        bank_loader.key(),
+    )?;
+
+    let mut bank_account = BankAccountWrapper::find(
+        &bank_loader.key(),
+        &mut bank,
+        &mut marginfi_account,
+        referral_fee_bps,
+    )?;
+
+    bank_account.set_insured(insured, current_timestamp as u64)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountSetInsured<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = marginfi_account.load()?.group == marginfi_group.key(),
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    #[account(
+        address = marginfi_account.load()?.authority,
+    )]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
@@ -1,13 +1,13 @@
 use crate::constants::{
-    INSURANCE_VAULT_SEED, LIQUIDATION_INSURANCE_FEE, LIQUIDATION_LIQUIDATOR_FEE,
+    LIQUIDATION_INSURANCE_FEE, LIQUIDATION_LIQUIDATOR_FEE, LIQUIDATION_MATURITY_LIQUIDATOR_FEE,
 };
 use crate::events::{AccountEventHeader, LendingAccountLiquidateEvent, LiquidationBalances};
-use crate::state::marginfi_account::{calc_amount, calc_value, RiskEngine};
+use crate::state::marginfi_account::{calc_amount, calc_value, RequirementType, RiskEngine};
 use crate::state::marginfi_group::{Bank, BankVaultType};
-use crate::state::price::{OraclePriceFeedAdapter, OraclePriceType, PriceAdapter, PriceBias};
+use crate::state::price::{OraclePriceFeedAdapter, PriceAdapter, PriceBias};
 use crate::{
     bank_signer,
-    constants::{LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED},
+    constants::LIQUIDITY_VAULT_AUTHORITY_SEED,
     state::marginfi_account::{BankAccountWrapper, MarginfiAccount},
 };
 use crate::{check, debug, prelude::*, utils};
@@ -55,6 +55,16 @@ use solana_program::sysvar::Sysvar;
 /// `q_ll = q_a * p_a * (1 - f_l) / p_l`
 /// `q_lf = q_a * p_a * (1 - (f_l + f_i)) / p_l`
 ///
+/// Loan maturity:
+/// If the liability bank has a max borrow duration and the liquidatee's liability is older than it,
+/// the account can be liquidated regardless of its health. If the account is still healthy the
+/// liquidator fee is reduced to `LIQUIDATION_MATURITY_LIQUIDATOR_FEE`, the liability may be paid off
+/// in full, and the account can't be left below the maintenance requirement.
+///
+/// Outage grace period:
+/// For `liquidation_grace_period` seconds after either bank leaves the `Paused` state, the liquidator
+/// fee is capped at the bank's `liquidation_grace_fee_bps`, or liquidations are rejected if that is zero.
+///
 /// Risk model
 ///
 /// Assumptions:
@@ -70,10 +80,15 @@ use solana_program::sysvar::Sysvar;
 /// [
 ///    liab_mint_ai (if token2022 mint),
 ///    asset_oracle_ai,
+///    asset_fallback_oracle_ai (optional),
 ///    liab_oracle_ai,
+///    liab_fallback_oracle_ai (optional),
 ///    liquidator_observation_ais...,
 ///    liquidatee_observation_ais...,
 ///  ]
+///
+/// Observation accounts are looked up by bank, so a bank both accounts have a balance in
+/// only needs to be passed once.
 
 pub fn lending_account_liquidate<'info>(
     mut ctx: Context<'_, '_, 'info, 'info, LendingAccountLiquidate<'info>>,
@@ -91,6 +106,7 @@ pub fn lending_account_liquidate<'info>(
         "Asset and liability bank cannot be the same"
     );
 
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
     let LendingAccountLiquidate {
         liquidator_marginfi_account: liquidator_marginfi_account_loader,
         liquidatee_marginfi_account: liquidatee_marginfi_account_loader,
@@ -107,28 +123,18 @@ pub fn lending_account_liquidate<'info>(
         &*ctx.accounts.liab_bank.load()?,
         ctx.accounts.token_program.key,
     )?;
-    {
-        ctx.accounts.asset_bank.load_mut()?.accrue_interest(
-            current_timestamp,
-            #[cfg(not(feature = "client"))]
-            ctx.accounts.asset_bank.key(),
-        )?;
-        ctx.accounts.liab_bank.load_mut()?.accrue_interest(
-            current_timestamp,
-            #[cfg(not(feature = "client"))]
-            ctx.accounts.liab_bank.key(),
-        )?;
-    }
-    let init_liquidatee_remaining_len = liquidatee_marginfi_account.get_remaining_accounts_len();
-    let pre_liquidation_health = {
-        let liquidatee_accounts_starting_pos =
-            ctx.remaining_accounts.len() - init_liquidatee_remaining_len;
-        let liquidatee_remaining_accounts =
-            &ctx.remaining_accounts[liquidatee_accounts_starting_pos..];
-
-        RiskEngine::new(&liquidatee_marginfi_account, liquidatee_remaining_accounts)?
-            .check_pre_liquidation_condition_and_get_account_health(&ctx.accounts.liab_bank.key())?
-    };
+
+    // Banks are looked up by key, so both accounts' health checks can use all of the accounts
+    let liquidatee_remaining_accounts = ctx.remaining_accounts;
+
+    let terms = LiquidationTerms::start(
+        &*ctx.accounts.marginfi_group.load()?,
+        &ctx.accounts.asset_bank,
+        &ctx.accounts.liab_bank,
+        &mut liquidatee_marginfi_account,
+        liquidatee_remaining_accounts,
+        current_timestamp,
+    )?;
 
     // ##Accounting changes##
 
@@ -136,73 +142,28 @@ pub fn lending_account_liquidate<'info>(
         let asset_amount = I80F48::from_num(asset_amount);
 
         let mut asset_bank = ctx.accounts.asset_bank.load_mut()?;
-        let asset_price = {
-            let oracle_ais = &ctx.remaining_accounts[0..1];
-            let asset_pf = OraclePriceFeedAdapter::try_from_bank_config(
-                &asset_bank.config,
-                oracle_ais,
-                &clock,
-            )?;
-            asset_pf.get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low))?
-        };
-
         let mut liab_bank = ctx.accounts.liab_bank.load_mut()?;
-        let liab_price = {
-            let oracle_ais = &ctx.remaining_accounts[1..2];
-            let liab_pf = OraclePriceFeedAdapter::try_from_bank_config(
-                &liab_bank.config,
-                oracle_ais,
-                &clock,
-            )?;
-            liab_pf.get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::High))?
-        };
 
-        let final_discount = I80F48::ONE - (LIQUIDATION_INSURANCE_FEE + LIQUIDATION_LIQUIDATOR_FEE);
-        let liquidator_discount = I80F48::ONE - LIQUIDATION_LIQUIDATOR_FEE;
-
-        // Quantity of liability to be paid off by liquidator
-        let liab_amount_liquidator = calc_amount(
-            calc_value(
-                asset_amount,
-                asset_price,
-                asset_bank.mint_decimals,
-                Some(liquidator_discount),
-            )?,
-            liab_price,
-            liab_bank.mint_decimals,
+        let LiquidationAmounts {
+            liab_amount_liquidator,
+            liab_amount_final,
+            insurance_fee_to_transfer,
+            insurance_fee_dust,
+        } = terms.calc_amounts(
+            &asset_bank,
+            &liab_bank,
+            ctx.remaining_accounts,
+            &clock,
+            asset_amount,
         )?;
 
-        // Quantity of liability to be received by liquidatee
-        let liab_amount_final = calc_amount(
-            calc_value(
-                asset_amount,
-                asset_price,
-                asset_bank.mint_decimals,
-                Some(final_discount),
-            )?,
-            liab_price,
-            liab_bank.mint_decimals,
-        )?;
-
-        // Insurance fund fee
-        let insurance_fund_fee = liab_amount_liquidator - liab_amount_final;
-
-        assert!(
-            insurance_fund_fee >= I80F48::ZERO,
-            "Insurance fund fee cannot be negative"
-        );
-
-        debug!(
-            "liab_quantity_liq: {}, liab_q_final: {}, asset_amount: {}, insurance_fund_fee: {}",
-            liab_amount_liquidator, liab_amount_final, asset_amount, insurance_fund_fee
-        );
-
         // Liquidator pays off liability
         let (liquidator_liability_pre_balance, liquidator_liability_post_balance) = {
             let mut bank_account = BankAccountWrapper::find_or_create(
                 &ctx.accounts.liab_bank.key(),
                 &mut liab_bank,
-                &mut liquidator_marginfi_account.lending_account,
+                &mut liquidator_marginfi_account,
+                referral_fee_bps,
             )?;
 
             let pre_balance = bank_account
@@ -223,20 +184,23 @@ pub fn lending_account_liquidate<'info>(
             let mut bank_account = BankAccountWrapper::find(
                 &ctx.accounts.asset_bank.key(),
                 &mut asset_bank,
-                &mut liquidatee_marginfi_account.lending_account,
+                &mut liquidatee_marginfi_account,
+                referral_fee_bps,
             )?;
 
-            let pre_balance = bank_account
-                .bank
-                .get_asset_amount(bank_account.balance.asset_shares.into())?;
+            let pre_balance = bank_account.bank.get_asset_amount(
+                bank_account.balance.asset_shares.into(),
+                bank_account.balance.insured,
+            )?;
 
             bank_account
-                .withdraw(asset_amount)
+                .withdraw_in_liquidation(asset_amount)
                 .map_err(|_| MarginfiError::IllegalLiquidation)?;
 
-            let post_balance = bank_account
-                .bank
-                .get_asset_amount(bank_account.balance.asset_shares.into())?;
+            let post_balance = bank_account.bank.get_asset_amount(
+                bank_account.balance.asset_shares.into(),
+                bank_account.balance.insured,
+            )?;
 
             (pre_balance, post_balance)
         };
@@ -246,29 +210,25 @@ pub fn lending_account_liquidate<'info>(
             let mut bank_account = BankAccountWrapper::find_or_create(
                 &ctx.accounts.asset_bank.key(),
                 &mut asset_bank,
-                &mut liquidator_marginfi_account.lending_account,
+                &mut liquidator_marginfi_account,
+                referral_fee_bps,
             )?;
 
-            let pre_balance = bank_account
-                .bank
-                .get_asset_amount(bank_account.balance.asset_shares.into())?;
+            let pre_balance = bank_account.bank.get_asset_amount(
+                bank_account.balance.asset_shares.into(),
+                bank_account.balance.insured,
+            )?;
 
             bank_account.increase_balance_in_liquidation(asset_amount)?;
 
-            let post_balance = bank_account
-                .bank
-                .get_asset_amount(bank_account.balance.asset_shares.into())?;
+            let post_balance = bank_account.bank.get_asset_amount(
+                bank_account.balance.asset_shares.into(),
+                bank_account.balance.insured,
+            )?;
 
             (pre_balance, post_balance)
         };
 
-        let (insurance_fee_to_transfer, insurance_fee_dust) = (
-            insurance_fund_fee
-                .checked_to_num::<u64>()
-                .ok_or(MarginfiError::MathError)?,
-            insurance_fund_fee.frac(),
-        );
-
         let (liquidatee_liability_pre_balance, liquidatee_liability_post_balance) = {
             // Liquidatee receives liability payment
             let liab_bank_liquidity_authority_bump = liab_bank.liquidity_vault_authority_bump;
@@ -276,7 +236,8 @@ pub fn lending_account_liquidate<'info>(
             let mut liquidatee_liab_bank_account = BankAccountWrapper::find_or_create(
                 &ctx.accounts.liab_bank.key(),
                 &mut liab_bank,
-                &mut liquidatee_marginfi_account.lending_account,
+                &mut liquidatee_marginfi_account,
+                referral_fee_bps,
             )?;
 
             let liquidatee_liability_pre_balance =
@@ -284,7 +245,7 @@ pub fn lending_account_liquidate<'info>(
                     liquidatee_liab_bank_account.balance.liability_shares.into(),
                 )?;
 
-            liquidatee_liab_bank_account.increase_balance(liab_amount_final)?;
+            liquidatee_liab_bank_account.repay_in_liquidation(liab_amount_final)?;
 
             let liquidatee_liability_post_balance =
                 liquidatee_liab_bank_account.bank.get_liability_amount(
@@ -340,27 +301,19 @@ pub fn lending_account_liquidate<'info>(
 
     // ## Risk checks ##
 
-    let liquidatee_accounts_starting_pos =
-        ctx.remaining_accounts.len() - init_liquidatee_remaining_len;
-    let liquidator_accounts_starting_pos =
-        liquidatee_accounts_starting_pos - liquidator_marginfi_account.get_remaining_accounts_len();
-
-    let liquidatee_remaining_accounts = &ctx.remaining_accounts[liquidatee_accounts_starting_pos..];
-    let liquidator_remaining_accounts =
-        &ctx.remaining_accounts[liquidator_accounts_starting_pos..liquidatee_accounts_starting_pos];
-
     // Verify liquidatee liquidation post health
-    let post_liquidation_health =
-        RiskEngine::new(&liquidatee_marginfi_account, liquidatee_remaining_accounts)?
-            .check_post_liquidation_condition_and_get_account_health(
-                &ctx.accounts.liab_bank.key(),
-                pre_liquidation_health,
-            )?;
+    let post_liquidation_health = terms.check_post_liquidation(
+        &*ctx.accounts.marginfi_group.load()?,
+        &ctx.accounts.liab_bank.key(),
+        &liquidatee_marginfi_account,
+        liquidatee_remaining_accounts,
+    )?;
 
     // Verify liquidator account health
     RiskEngine::check_account_init_health(
         &liquidator_marginfi_account,
-        liquidator_remaining_accounts,
+        ctx.remaining_accounts,
+        &*ctx.accounts.marginfi_group.load()?,
     )?;
 
     emit!(LendingAccountLiquidateEvent {
@@ -376,7 +329,7 @@ pub fn lending_account_liquidate<'info>(
         asset_mint: ctx.accounts.asset_bank.load_mut()?.mint,
         liability_bank: ctx.accounts.liab_bank.key(),
         liability_mint: ctx.accounts.liab_bank.load_mut()?.mint,
-        liquidatee_pre_health: pre_liquidation_health.to_num::<f64>(),
+        liquidatee_pre_health: terms.pre_liquidation_health.to_num::<f64>(),
         liquidatee_post_health: post_liquidation_health.to_num::<f64>(),
         pre_balances,
         post_balances,
@@ -385,8 +338,232 @@ pub fn lending_account_liquidate<'info>(
     Ok(())
 }
 
+/// Liquidatee health before a liquidation and the liquidator fee it is charged at.
+///
+/// Shared by [`lending_account_liquidate`] and [`lending_account_liquidate_to_token_account`],
+/// which only differ in how the liquidator pays and receives.
+///
+/// [`lending_account_liquidate_to_token_account`]: crate::instructions::lending_account_liquidate_to_token_account
+pub(crate) struct LiquidationTerms {
+    pub pre_liquidation_health: I80F48,
+    /// Set for liquidatees in equity maintenance mode, see
+    /// [`RiskEngine::get_equity_mode_liquidation_health`].
+    pub pre_liquidation_weighted_health: Option<I80F48>,
+    pub liquidator_fee: I80F48,
+}
+
+/// Liability amounts of a liquidation, see the liquidation math on [`lending_account_liquidate`].
+pub(crate) struct LiquidationAmounts {
+    /// `q_ll`, paid by the liquidator.
+    pub liab_amount_liquidator: I80F48,
+    /// `q_lf`, received by the liquidatee.
+    pub liab_amount_final: I80F48,
+    /// Whole tokens of the insurance fee `q_ll - q_lf`, transferred to the insurance vault.
+    pub insurance_fee_to_transfer: u64,
+    /// Rest of the insurance fee, added to the outstanding insurance fees.
+    pub insurance_fee_dust: I80F48,
+}
+
+impl LiquidationTerms {
+    /// Accrue interest on both banks, check that the liquidatee can be liquidated for its
+    /// `liab_bank` liability and pick the liquidator fee.
+    pub fn start<'info>(
+        marginfi_group: &MarginfiGroup,
+        asset_bank: &AccountLoader<'info, Bank>,
+        liab_bank: &AccountLoader<'info, Bank>,
+        liquidatee_marginfi_account: &mut MarginfiAccount,
+        liquidatee_remaining_ais: &'info [AccountInfo<'info>],
+        current_timestamp: i64,
+    ) -> MarginfiResult<Self> {
+        for bank in [asset_bank, liab_bank] {
+            bank.load_mut()?.accrue_interest(
+                current_timestamp,
+                #[cfg(not(feature = "client"))]
+                bank.key(),
+            )?;
+        }
+
+        let liability_expired = {
+            let liab_bank_ref = liab_bank.load()?;
+
+            liquidatee_marginfi_account
+                .lending_account
+                .get_balance(&liab_bank.key())
+                .is_some_and(|balance| {
+                    liab_bank_ref
+                        .config
+                        .is_liability_expired(balance.side_opened_at, current_timestamp)
+                })
+        };
+
+        let risk_engine = RiskEngine::new(liquidatee_marginfi_account, liquidatee_remaining_ais)?
+            .with_group(marginfi_group);
+        let pre_liquidation_health = risk_engine
+            .check_pre_liquidation_condition_and_get_account_health(
+                &liab_bank.key(),
+                liability_expired,
+            )?;
+        let pre_liquidation_weighted_health = risk_engine.get_equity_mode_liquidation_health()?;
+
+        // Healthy accounts are only liquidatable for an expired liability, at a reduced fee
+        let mut liquidator_fee = if pre_liquidation_health > I80F48::ZERO {
+            LIQUIDATION_MATURITY_LIQUIDATOR_FEE
+        } else {
+            LIQUIDATION_LIQUIDATOR_FEE
+        };
+
+        // Banks coming back from an outage cap the liquidator fee for a while
+        let grace_fee = [
+            asset_bank
+                .load()?
+                .get_liquidation_grace_fee(current_timestamp),
+            liab_bank
+                .load()?
+                .get_liquidation_grace_fee(current_timestamp),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        if let Some(grace_fee) = grace_fee {
+            check!(
+                grace_fee > I80F48::ZERO,
+                MarginfiError::LiquidationGracePeriod
+            );
+
+            liquidator_fee = liquidator_fee.min(grace_fee);
+        }
+
+        Ok(Self {
+            pre_liquidation_health,
+            pre_liquidation_weighted_health,
+            liquidator_fee,
+        })
+    }
+
+    /// Price `asset_amount` of collateral in the liability, `oracle_ais` starts with the asset and
+    /// liability bank oracles in that order, each optionally followed by the bank's fallback oracle.
+    pub fn calc_amounts(
+        &self,
+        asset_bank: &Bank,
+        liab_bank: &Bank,
+        oracle_ais: &[AccountInfo],
+        clock: &Clock,
+        asset_amount: I80F48,
+    ) -> MarginfiResult<LiquidationAmounts> {
+        check!(
+            !oracle_ais.is_empty(),
+            MarginfiError::MissingPythOrBankAccount
+        );
+        let (asset_oracle_ais, oracle_ais) = oracle_ais.split_at(
+            OraclePriceFeedAdapter::get_oracle_ais_len(&asset_bank.config, oracle_ais),
+        );
+        check!(
+            !oracle_ais.is_empty(),
+            MarginfiError::MissingPythOrBankAccount
+        );
+        let liab_oracle_ais = &oracle_ais
+            [..OraclePriceFeedAdapter::get_oracle_ais_len(&liab_bank.config, oracle_ais)];
+
+        let asset_price = {
+            let asset_pf = OraclePriceFeedAdapter::try_from_bank_config(
+                &asset_bank.config,
+                asset_oracle_ais,
+                clock,
+            )?;
+            asset_pf.get_price_of_type(
+                asset_bank.get_oracle_price_type(RequirementType::Maintenance),
+                Some(PriceBias::Low),
+            )?
+        };
+
+        let liab_price = {
+            let liab_pf = OraclePriceFeedAdapter::try_from_bank_config(
+                &liab_bank.config,
+                liab_oracle_ais,
+                clock,
+            )?;
+            liab_pf.get_price_of_type(
+                liab_bank.get_oracle_price_type(RequirementType::Maintenance),
+                Some(PriceBias::High),
+            )?
+        };
+
+        let final_discount = I80F48::ONE - (LIQUIDATION_INSURANCE_FEE + self.liquidator_fee);
+        let liquidator_discount = I80F48::ONE - self.liquidator_fee;
+
+        // Quantity of liability to be paid off by liquidator
+        let liab_amount_liquidator = calc_amount(
+            calc_value(
+                asset_amount,
+                asset_price,
+                asset_bank.mint_decimals,
+                Some(liquidator_discount),
+            )?,
+            liab_price,
+            liab_bank.mint_decimals,
+        )?;
+
+        // Quantity of liability to be received by liquidatee
+        let liab_amount_final = calc_amount(
+            calc_value(
+                asset_amount,
+                asset_price,
+                asset_bank.mint_decimals,
+                Some(final_discount),
+            )?,
+            liab_price,
+            liab_bank.mint_decimals,
+        )?;
+
+        // Insurance fund fee
+        let insurance_fund_fee = liab_amount_liquidator - liab_amount_final;
+
+        assert!(
+            insurance_fund_fee >= I80F48::ZERO,
+            "Insurance fund fee cannot be negative"
+        );
+
+        debug!(
+            "liab_quantity_liq: {}, liab_q_final: {}, asset_amount: {}, insurance_fund_fee: {}",
+            liab_amount_liquidator, liab_amount_final, asset_amount, insurance_fund_fee
+        );
+
+        Ok(LiquidationAmounts {
+            liab_amount_liquidator,
+            liab_amount_final,
+            insurance_fee_to_transfer: insurance_fund_fee
+                .checked_to_num::<u64>()
+                .ok_or(MarginfiError::MathError)?,
+            insurance_fee_dust: insurance_fund_fee.frac(),
+        })
+    }
+
+    /// Verify the liquidatee's health after the liquidation, see
+    /// [`RiskEngine::check_post_liquidation_condition_and_get_account_health`].
+    pub fn check_post_liquidation<'info>(
+        &self,
+        marginfi_group: &MarginfiGroup,
+        liab_bank_pk: &Pubkey,
+        liquidatee_marginfi_account: &MarginfiAccount,
+        liquidatee_remaining_ais: &'info [AccountInfo<'info>],
+    ) -> MarginfiResult<I80F48> {
+        RiskEngine::new(liquidatee_marginfi_account, liquidatee_remaining_ais)?
+            .with_group(marginfi_group)
+            .check_post_liquidation_condition_and_get_account_health(
+                liab_bank_pk,
+                self.pre_liquidation_health,
+                self.pre_liquidation_weighted_health,
+            )
+    }
+}
+
 #[derive(Accounts)]
 pub struct LendingAccountLiquidate<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -432,22 +609,14 @@ pub struct LendingAccountLiquidate<'info> {
     /// CHECK: Seed constraint
     #[account(
         mut,
-        seeds = [
-            LIQUIDITY_VAULT_SEED.as_bytes(),
-            liab_bank.key().as_ref(),
-        ],
-        bump = liab_bank.load()?.liquidity_vault_bump
+        address = liab_bank.load()?.liquidity_vault,
     )]
     pub bank_liquidity_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: Seed constraint
     #[account(
         mut,
-        seeds = [
-            INSURANCE_VAULT_SEED.as_bytes(),
-            liab_bank.key().as_ref(),
-        ],
-        bump = liab_bank.load()?.insurance_vault_bump
+        address = liab_bank.load()?.insurance_vault,
     )]
     pub bank_insurance_vault: AccountInfo<'info>,
 
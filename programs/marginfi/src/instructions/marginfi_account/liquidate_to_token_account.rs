@@ -0,0 +1,313 @@
+use super::liquidate::{LiquidationAmounts, LiquidationTerms};
+use crate::events::{GroupEventHeader, LendingAccountLiquidateToTokenAccountEvent};
+use crate::state::marginfi_group::{Bank, BankVaultType};
+use crate::{
+    bank_signer,
+    constants::LIQUIDITY_VAULT_AUTHORITY_SEED,
+    state::marginfi_account::{BankAccountWrapper, MarginfiAccount},
+};
+use crate::{check, math_error, prelude::*, utils};
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::TokenInterface;
+use fixed::types::I80F48;
+use solana_program::clock::Clock;
+use solana_program::sysvar::Sysvar;
+
+/// Liquidate an unhealthy marginfi account without a liquidator marginfi account.
+///
+/// Same math and risk checks as [`crate::instructions::lending_account_liquidate`], but the liquidator
+/// pays the discounted liability from their token account and the seized collateral is transferred
+/// directly from the asset bank's liquidity vault to the destination token account.
+///
+/// The liability payment is rounded up to the next token, the rounding surplus goes to the insurance fund.
+///
+/// Expected remaining account schema
+/// [
+///    liab_mint_ai (if token2022 mint),
+///    asset_mint_ai (if token2022 mint),
+///    asset_oracle_ai,
+///    asset_fallback_oracle_ai (optional),
+///    liab_oracle_ai,
+///    liab_fallback_oracle_ai (optional),
+///    liquidatee_observation_ais...,
+///  ]
+pub fn lending_account_liquidate_to_token_account<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingAccountLiquidateToTokenAccount<'info>>,
+    asset_amount: u64,
+) -> MarginfiResult {
+    check!(
+        asset_amount > 0,
+        MarginfiError::IllegalLiquidation,
+        "Asset amount must be positive"
+    );
+
+    check!(
+        ctx.accounts.asset_bank.key() != ctx.accounts.liab_bank.key(),
+        MarginfiError::IllegalLiquidation,
+        "Asset and liability bank cannot be the same"
+    );
+
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+    let mut liquidatee_marginfi_account = ctx.accounts.liquidatee_marginfi_account.load_mut()?;
+    let clock = Clock::get()?;
+    let current_timestamp = clock.unix_timestamp;
+
+    let maybe_liab_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*ctx.accounts.liab_bank.load()?,
+        ctx.accounts.liab_token_program.key,
+    )?;
+    let maybe_asset_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*ctx.accounts.asset_bank.load()?,
+        ctx.accounts.asset_token_program.key,
+    )?;
+
+    // Banks are looked up by key, the liquidation oracles in front don't get in the way
+    let liquidatee_remaining_accounts = ctx.remaining_accounts;
+
+    let terms = LiquidationTerms::start(
+        &*ctx.accounts.marginfi_group.load()?,
+        &ctx.accounts.asset_bank,
+        &ctx.accounts.liab_bank,
+        &mut liquidatee_marginfi_account,
+        liquidatee_remaining_accounts,
+        current_timestamp,
+    )?;
+
+    // ##Accounting changes##
+
+    let liab_amount_to_transfer = {
+        let mut asset_bank = ctx.accounts.asset_bank.load_mut()?;
+        let mut liab_bank = ctx.accounts.liab_bank.load_mut()?;
+
+        let asset_quantity = I80F48::from_num(asset_amount);
+
+        let LiquidationAmounts {
+            liab_amount_liquidator,
+            liab_amount_final,
+            insurance_fee_to_transfer,
+            insurance_fee_dust,
+        } = terms.calc_amounts(
+            &asset_bank,
+            &liab_bank,
+            ctx.remaining_accounts,
+            &clock,
+            asset_quantity,
+        )?;
+
+        let liab_amount_to_transfer: u64 = liab_amount_liquidator
+            .checked_ceil()
+            .ok_or_else(math_error!())?
+            .checked_to_num()
+            .ok_or_else(math_error!())?;
+
+        // Liquidatee pays off `asset_quantity` amount of collateral, transferred to the liquidator
+        {
+            let mut bank_account = BankAccountWrapper::find(
+                &ctx.accounts.asset_bank.key(),
+                &mut asset_bank,
+                &mut liquidatee_marginfi_account,
+                referral_fee_bps,
+            )?;
+
+            bank_account
+                .withdraw_in_liquidation(asset_quantity)
+                .map_err(|_| MarginfiError::IllegalLiquidation)?;
+
+            bank_account.withdraw_spl_transfer(
+                asset_amount,
+                ctx.accounts.asset_bank_liquidity_vault.to_account_info(),
+                ctx.accounts
+                    .destination_asset_token_account
+                    .to_account_info(),
+                ctx.accounts
+                    .asset_bank_liquidity_vault_authority
+                    .to_account_info(),
+                maybe_asset_bank_mint.as_ref(),
+                ctx.accounts.asset_token_program.to_account_info(),
+                bank_signer!(
+                    BankVaultType::Liquidity,
+                    ctx.accounts.asset_bank.key(),
+                    bank_account.bank.liquidity_vault_authority_bump
+                ),
+                ctx.remaining_accounts,
+            )?;
+        }
+
+        // Liquidatee receives liability payment
+        {
+            let mut bank_account = BankAccountWrapper::find_or_create(
+                &ctx.accounts.liab_bank.key(),
+                &mut liab_bank,
+                &mut liquidatee_marginfi_account,
+                referral_fee_bps,
+            )?;
+
+            bank_account.repay_in_liquidation(liab_amount_final)?;
+
+            let liab_amount_pre_fee = maybe_liab_bank_mint
+                .as_ref()
+                .map(|mint| {
+                    utils::calculate_pre_fee_spl_deposit_amount(
+                        mint.to_account_info(),
+                        liab_amount_to_transfer,
+                        clock.epoch,
+                    )
+                })
+                .transpose()?
+                .unwrap_or(liab_amount_to_transfer);
+
+            // ## SPL transfer ##
+            // Liquidator pays off liability
+            bank_account.deposit_spl_transfer(
+                liab_amount_pre_fee,
+                ctx.accounts.signer_liab_token_account.to_account_info(),
+                ctx.accounts.liab_bank_liquidity_vault.to_account_info(),
+                ctx.accounts.signer.to_account_info(),
+                maybe_liab_bank_mint.as_ref(),
+                ctx.accounts.liab_token_program.to_account_info(),
+                ctx.remaining_accounts,
+            )?;
+
+            // Insurance fund receives fee
+            bank_account.withdraw_spl_transfer(
+                insurance_fee_to_transfer,
+                ctx.accounts.liab_bank_liquidity_vault.to_account_info(),
+                ctx.accounts.liab_bank_insurance_vault.to_account_info(),
+                ctx.accounts
+                    .liab_bank_liquidity_vault_authority
+                    .to_account_info(),
+                maybe_liab_bank_mint.as_ref(),
+                ctx.accounts.liab_token_program.to_account_info(),
+                bank_signer!(
+                    BankVaultType::Liquidity,
+                    ctx.accounts.liab_bank.key(),
+                    bank_account.bank.liquidity_vault_authority_bump
+                ),
+                ctx.remaining_accounts,
+            )?;
+        }
+
+        liab_bank.collected_insurance_fees_outstanding =
+            I80F48::from(liab_bank.collected_insurance_fees_outstanding)
+                .checked_add(I80F48::from_num(liab_amount_to_transfer) - liab_amount_liquidator)
+                .ok_or(MarginfiError::MathError)?
+                .checked_add(insurance_fee_dust)
+                .ok_or(MarginfiError::MathError)?
+                .into();
+
+        liab_amount_to_transfer
+    };
+
+    // ## Risk checks ##
+
+    // Verify liquidatee liquidation post health
+    let post_liquidation_health = terms.check_post_liquidation(
+        &*ctx.accounts.marginfi_group.load()?,
+        &ctx.accounts.liab_bank.key(),
+        &liquidatee_marginfi_account,
+        liquidatee_remaining_accounts,
+    )?;
+
+    emit!(LendingAccountLiquidateToTokenAccountEvent {
+        header: GroupEventHeader {
+            signer: Some(ctx.accounts.signer.key()),
+            marginfi_group: ctx.accounts.marginfi_group.key(),
+        },
+        liquidatee_marginfi_account: ctx.accounts.liquidatee_marginfi_account.key(),
+        liquidatee_marginfi_account_authority: liquidatee_marginfi_account.authority,
+        asset_bank: ctx.accounts.asset_bank.key(),
+        asset_mint: ctx.accounts.asset_bank.load()?.mint,
+        liability_bank: ctx.accounts.liab_bank.key(),
+        liability_mint: ctx.accounts.liab_bank.load()?.mint,
+        asset_amount,
+        liability_amount: liab_amount_to_transfer,
+        liquidatee_pre_health: terms.pre_liquidation_health.to_num::<f64>(),
+        liquidatee_post_health: post_liquidation_health.to_num::<f64>(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountLiquidateToTokenAccount<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = asset_bank.load()?.group == marginfi_group.key()
+    )]
+    pub asset_bank: AccountLoader<'info, Bank>,
+
+    #[account(
+        mut,
+        constraint = liab_bank.load()?.group == marginfi_group.key()
+    )]
+    pub liab_bank: AccountLoader<'info, Bank>,
+
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = liquidatee_marginfi_account.load()?.group == marginfi_group.key()
+    )]
+    pub liquidatee_marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    /// CHECK: Token mint/authority are checked at transfer
+    #[account(mut)]
+    pub signer_liab_token_account: AccountInfo<'info>,
+
+    /// CHECK: Token mint is checked at transfer
+    #[account(mut)]
+    pub destination_asset_token_account: AccountInfo<'info>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            asset_bank.key().as_ref(),
+        ],
+        bump = asset_bank.load()?.liquidity_vault_authority_bump
+    )]
+    pub asset_bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    /// CHECK: Checked against the bank
+    #[account(
+        mut,
+        address = asset_bank.load()?.liquidity_vault,
+    )]
+    pub asset_bank_liquidity_vault: AccountInfo<'info>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            liab_bank.key().as_ref(),
+        ],
+        bump = liab_bank.load()?.liquidity_vault_authority_bump
+    )]
+    pub liab_bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    /// CHECK: Checked against the bank
+    #[account(
+        mut,
+        address = liab_bank.load()?.liquidity_vault,
+    )]
+    pub liab_bank_liquidity_vault: AccountInfo<'info>,
+
+    /// CHECK: Checked against the bank
+    #[account(
+        mut,
+        address = liab_bank.load()?.insurance_vault,
+    )]
+    pub liab_bank_insurance_vault: AccountInfo<'info>,
+
+    pub asset_token_program: Interface<'info, TokenInterface>,
+
+    pub liab_token_program: Interface<'info, TokenInterface>,
+}
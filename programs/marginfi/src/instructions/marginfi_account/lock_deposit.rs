@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    check,
+    prelude::*,
+    state::{
+        marginfi_account::{BankAccountWrapper, MarginfiAccount, DISABLED_FLAG},
+        marginfi_group::Bank,
+    },
+};
+
+/// Lock a deposit for `lock_days`.
+///
+/// A locked deposit can't be withdrawn before the lock matures, except by a liquidator.
+/// In exchange it gets the bank's `locked_asset_weight_boost_bps` on its initial weight
+/// and `locked_emissions_boost_bps` on its emissions.
+pub fn lending_account_lock_deposit(
+    ctx: Context<LendingAccountLockDeposit>,
+    lock_days: u16,
+) -> MarginfiResult {
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+    let LendingAccountLockDeposit {
+        marginfi_account,
+        bank: bank_loader,
+        ..
+    } = ctx.accounts;
+
+    let mut marginfi_account = marginfi_account.load_mut()?;
+    let mut bank = bank_loader.load_mut()?;
+
+    check!(
+        !marginfi_account.get_flag(DISABLED_FLAG),
+        MarginfiError::AccountDisabled
+    );
+
+    let current_timestamp = Clock::get()?.unix_timestamp;
+
+    bank.accrue_interest(
+        current_timestamp,
+        #[cfg(not(feature = "client"))]
+        bank_loader.key(),
+    )?;
+
+    let mut bank_account = BankAccountWrapper::find(
+        &bank_loader.key(),
+        &mut bank,
+        &mut marginfi_account,
+        referral_fee_bps,
+    )?;
+
+    bank_account.lock(lock_days, current_timestamp as u64)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountLockDeposit<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = marginfi_account.load()?.group == marginfi_group.key(),
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    #[account(
+        address = marginfi_account.load()?.authority,
+    )]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
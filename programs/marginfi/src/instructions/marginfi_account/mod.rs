@@ -4,9 +4,16 @@ mod close_balance;
 mod deposit;
 mod emissions;
 mod flashloan;
+mod health_snapshot;
 mod initialize;
+mod insurance;
 mod liquidate;
+mod liquidate_to_token_account;
+mod lock_deposit;
+mod referral;
+mod refinance;
 mod repay;
+mod settle_killed_balance;
 mod transfer_authority;
 mod withdraw;
 
@@ -16,8 +23,15 @@ pub use close_balance::*;
 pub use deposit::*;
 pub use emissions::*;
 pub use flashloan::*;
+pub use health_snapshot::*;
 pub use initialize::*;
+pub use insurance::*;
 pub use liquidate::*;
+pub use liquidate_to_token_account::*;
+pub use lock_deposit::*;
+pub use referral::*;
+pub use refinance::*;
 pub use repay::*;
+pub use settle_killed_balance::*;
 pub use transfer_authority::*;
 pub use withdraw::*;
@@ -0,0 +1,128 @@
+use crate::{
+    bank_signer, check,
+    constants::LIQUIDITY_VAULT_AUTHORITY_SEED,
+    events::{AccountEventHeader, LendingAccountClaimReferralFeesEvent},
+    prelude::{MarginfiError, MarginfiGroup, MarginfiResult},
+    state::{
+        marginfi_account::{BankAccountWrapper, MarginfiAccount},
+        marginfi_group::{Bank, BankVaultType},
+    },
+    utils,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use fixed::types::I80F48;
+
+/// Transfer the referral fees a marginfi account owes its referrer in a bank, as far as the
+/// liquidity vault allows. Fees are accrued whenever the account's liability in the bank changes,
+/// see [`BankAccountWrapper::settle_group_fees`].
+///
+/// Referral fees of a killed bank are forfeited to its depositors. A closed bank keeps them in its
+/// liquidity vault until claimed.
+pub fn lending_account_claim_referral_fees<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingAccountClaimReferralFees<'info>>,
+) -> MarginfiResult {
+    let LendingAccountClaimReferralFees {
+        marginfi_account: marginfi_account_loader,
+        referrer,
+        bank: bank_loader,
+        liquidity_vault_authority,
+        liquidity_vault,
+        destination_token_account,
+        token_program,
+        ..
+    } = ctx.accounts;
+
+    let mut marginfi_account = marginfi_account_loader.load_mut()?;
+    let mut bank = bank_loader.load_mut()?;
+    let maybe_bank_mint =
+        utils::maybe_take_bank_mint(&mut ctx.remaining_accounts, &bank, token_program.key)?;
+
+    check!(!bank.is_killed(), MarginfiError::BankKilled);
+
+    let amount = marginfi_account.take_referral_fees(&bank_loader.key(), liquidity_vault.amount)?;
+
+    if amount == 0 {
+        return Ok(());
+    }
+
+    bank.pay_referral_fees(I80F48::from_num(amount))?;
+
+    bank.withdraw_spl_transfer(
+        amount,
+        liquidity_vault.to_account_info(),
+        destination_token_account.to_account_info(),
+        liquidity_vault_authority.to_account_info(),
+        maybe_bank_mint.as_ref(),
+        token_program.to_account_info(),
+        bank_signer!(
+            BankVaultType::Liquidity,
+            bank_loader.key(),
+            bank.liquidity_vault_authority_bump
+        ),
+        ctx.remaining_accounts,
+    )?;
+
+    emit!(LendingAccountClaimReferralFeesEvent {
+        header: AccountEventHeader {
+            signer: Some(referrer.key()),
+            marginfi_account: marginfi_account_loader.key(),
+            marginfi_account_authority: marginfi_account.authority,
+            marginfi_group: marginfi_account.group,
+        },
+        bank: bank_loader.key(),
+        mint: bank.mint,
+        amount,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountClaimReferralFees<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = marginfi_account.load()?.group == marginfi_group.key(),
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    #[account(
+        constraint = marginfi_account.load()?.referrer == referrer.key()
+            @ MarginfiError::Unauthorized,
+    )]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.liquidity_vault_authority_bump,
+    )]
+    pub liquidity_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        address = bank.load()?.liquidity_vault,
+    )]
+    pub liquidity_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(mut)]
+    pub destination_token_account: AccountInfo<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -0,0 +1,229 @@
+use super::borrow::borrow_within_limits;
+use crate::{
+    bank_signer, check,
+    constants::LIQUIDITY_VAULT_AUTHORITY_SEED,
+    events::{AccountEventHeader, LendingAccountRefinanceEvent},
+    prelude::{MarginfiError, MarginfiGroup, MarginfiResult},
+    state::{
+        marginfi_account::{BankAccountWrapper, MarginfiAccount, RiskEngine, DISABLED_FLAG},
+        marginfi_group::{Bank, BankVaultType},
+    },
+    utils,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use fixed::types::I80F48;
+use solana_program::{clock::Clock, sysvar::Sysvar};
+
+/// Move a liability between two banks of the same mint without repaying out of pocket.
+///
+/// 1. Accrue interest on both banks
+/// 2. Record liability decrease of `amount` in the source bank account
+/// 3. Create the user's bank account in the destination bank if it does not exist yet
+/// 4. Record liability increase in the destination bank account, subject to the same limits as
+///    a borrow
+/// 5. Transfer funds from the destination bank's liquidity vault to the source bank's liquidity vault
+/// 6. Verify that the user account is in a healthy state
+///
+/// If `repay_all` is set, the whole source liability is moved and `amount` is ignored.
+/// For Token-2022 mints with a transfer fee, the destination liability includes the fee.
+pub fn lending_account_refinance<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingAccountRefinance<'info>>,
+    amount: u64,
+    repay_all: Option<bool>,
+) -> MarginfiResult {
+    ctx.accounts
+        .marginfi_group
+        .load()?
+        .check_cpi_caller(ctx.remaining_accounts)?;
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+
+    let LendingAccountRefinance {
+        marginfi_account: marginfi_account_loader,
+        source_bank: source_bank_loader,
+        destination_bank: destination_bank_loader,
+        source_bank_liquidity_vault,
+        destination_bank_liquidity_vault_authority,
+        destination_bank_liquidity_vault,
+        token_program,
+        ..
+    } = ctx.accounts;
+    let clock = Clock::get()?;
+    let maybe_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*destination_bank_loader.load()?,
+        token_program.key,
+    )?;
+
+    let repay_all = repay_all.unwrap_or(false);
+    let mut marginfi_account = marginfi_account_loader.load_mut()?;
+
+    check!(
+        !marginfi_account.get_flag(DISABLED_FLAG),
+        MarginfiError::AccountDisabled
+    );
+
+    source_bank_loader.load_mut()?.accrue_interest(
+        clock.unix_timestamp,
+        #[cfg(not(feature = "client"))]
+        source_bank_loader.key(),
+    )?;
+    destination_bank_loader.load_mut()?.accrue_interest(
+        clock.unix_timestamp,
+        #[cfg(not(feature = "client"))]
+        destination_bank_loader.key(),
+    )?;
+
+    let repay_amount = {
+        let mut source_bank = source_bank_loader.load_mut()?;
+
+        let mut source_bank_account = BankAccountWrapper::find(
+            &source_bank_loader.key(),
+            &mut source_bank,
+            &mut marginfi_account,
+            referral_fee_bps,
+        )?;
+
+        if repay_all {
+            source_bank_account.repay_all()?
+        } else {
+            source_bank_account.repay(I80F48::from_num(amount))?;
+
+            amount
+        }
+    };
+
+    {
+        let mut destination_bank = destination_bank_loader.load_mut()?;
+
+        let liquidity_vault_authority_bump = destination_bank.liquidity_vault_authority_bump;
+
+        let mut destination_bank_account = BankAccountWrapper::find_or_create(
+            &destination_bank_loader.key(),
+            &mut destination_bank,
+            &mut marginfi_account,
+            referral_fee_bps,
+        )?;
+
+        // Source vault needs to receive the full repay amount
+        let borrow_amount = maybe_bank_mint
+            .as_ref()
+            .map(|mint| {
+                utils::calculate_pre_fee_spl_deposit_amount(
+                    mint.to_account_info(),
+                    repay_amount,
+                    clock.epoch,
+                )
+            })
+            .transpose()?
+            .unwrap_or(repay_amount);
+
+        borrow_within_limits(
+            &mut destination_bank_account,
+            borrow_amount,
+            destination_bank_liquidity_vault.amount,
+            clock.slot,
+        )?;
+
+        destination_bank_account.withdraw_spl_transfer(
+            borrow_amount,
+            destination_bank_liquidity_vault.to_account_info(),
+            source_bank_liquidity_vault.to_account_info(),
+            destination_bank_liquidity_vault_authority.to_account_info(),
+            maybe_bank_mint.as_ref(),
+            token_program.to_account_info(),
+            bank_signer!(
+                BankVaultType::Liquidity,
+                destination_bank_loader.key(),
+                liquidity_vault_authority_bump
+            ),
+            ctx.remaining_accounts,
+        )?;
+
+        emit!(LendingAccountRefinanceEvent {
+            header: AccountEventHeader {
+                signer: Some(ctx.accounts.signer.key()),
+                marginfi_account: marginfi_account_loader.key(),
+                marginfi_account_authority: marginfi_account.authority,
+                marginfi_group: marginfi_account.group,
+            },
+            source_bank: source_bank_loader.key(),
+            destination_bank: destination_bank_loader.key(),
+            mint: destination_bank.mint,
+            repay_amount,
+            borrow_amount,
+            close_balance: repay_all,
+        });
+    }
+
+    // Check account health, if below threshold fail transaction
+    // Assuming `ctx.remaining_accounts` holds only oracle accounts
+    RiskEngine::check_account_init_health(
+        &marginfi_account,
+        ctx.remaining_accounts,
+        &*ctx.accounts.marginfi_group.load()?,
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountRefinance<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = marginfi_account.load()?.group == marginfi_group.key(),
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    #[account(
+        address = marginfi_account.load()?.authority,
+    )]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = source_bank.load()?.group == marginfi_group.key(),
+    )]
+    pub source_bank: AccountLoader<'info, Bank>,
+
+    #[account(
+        mut,
+        constraint = destination_bank.load()?.group == marginfi_group.key(),
+        constraint = destination_bank.key() != source_bank.key()
+            @ MarginfiError::InvalidRefinance,
+        constraint = destination_bank.load()?.mint == source_bank.load()?.mint
+            @ MarginfiError::InvalidRefinance,
+    )]
+    pub destination_bank: AccountLoader<'info, Bank>,
+
+    #[account(
+        mut,
+        address = source_bank.load()?.liquidity_vault,
+    )]
+    pub source_bank_liquidity_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Seed constraint check
+    #[account(
+        mut,
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            destination_bank.key().as_ref(),
+        ],
+        bump = destination_bank.load()?.liquidity_vault_authority_bump,
+    )]
+    pub destination_bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        address = destination_bank.load()?.liquidity_vault,
+    )]
+    pub destination_bank_liquidity_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -1,6 +1,7 @@
+#[cfg(feature = "sanity")]
+use crate::state::marginfi_account::{HealthDelta, RiskEngine};
 use crate::{
     check,
-    constants::LIQUIDITY_VAULT_SEED,
     events::{AccountEventHeader, LendingAccountRepayEvent},
     prelude::{MarginfiError, MarginfiGroup, MarginfiResult},
     state::{
@@ -20,11 +21,20 @@ use solana_program::{clock::Clock, sysvar::Sysvar};
 /// 4. Transfer funds from the signer's token account to the bank's liquidity vault
 ///
 /// Will error if there is no existing liability <=> depositing is not allowed.
+///
+/// With the `sanity` feature, remaining accounts also carry the bank and oracle of every balance
+/// and the repay is asserted not to decrease account health.
 pub fn lending_account_repay<'info>(
     mut ctx: Context<'_, '_, 'info, 'info, LendingAccountRepay<'info>>,
     amount: u64,
     repay_all: Option<bool>,
 ) -> MarginfiResult {
+    ctx.accounts
+        .marginfi_group
+        .load()?
+        .check_cpi_caller(ctx.remaining_accounts)?;
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+
     let LendingAccountRepay {
         marginfi_account: marginfi_account_loader,
         signer,
@@ -42,6 +52,18 @@ pub fn lending_account_repay<'info>(
     )?;
 
     let repay_all = repay_all.unwrap_or(false);
+
+    #[cfg(feature = "sanity")]
+    let health_before = {
+        bank_loader.load_mut()?.accrue_interest(
+            clock.unix_timestamp,
+            #[cfg(not(feature = "client"))]
+            bank_loader.key(),
+        )?;
+
+        RiskEngine::get_sanity_health(&*marginfi_account_loader.load()?, ctx.remaining_accounts)?
+    };
+
     let mut bank = bank_loader.load_mut()?;
     let mut marginfi_account = marginfi_account_loader.load_mut()?;
 
@@ -59,7 +81,8 @@ pub fn lending_account_repay<'info>(
     let mut bank_account = BankAccountWrapper::find(
         &bank_loader.key(),
         &mut bank,
-        &mut marginfi_account.lending_account,
+        &mut marginfi_account,
+        referral_fee_bps,
     )?;
 
     let repay_amount_post_fee = if repay_all {
@@ -105,11 +128,24 @@ pub fn lending_account_repay<'info>(
         close_balance: repay_all,
     });
 
+    #[cfg(feature = "sanity")]
+    {
+        drop(bank);
+        HealthDelta::NonDecreasing.check(
+            health_before,
+            RiskEngine::get_sanity_health(&marginfi_account, ctx.remaining_accounts)?,
+        )?;
+    }
+
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct LendingAccountRepay<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -136,11 +172,7 @@ pub struct LendingAccountRepay<'info> {
     /// CHECK: Seed constraint check
     #[account(
         mut,
-        seeds = [
-            LIQUIDITY_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.liquidity_vault_bump,
+        address = bank.load()?.liquidity_vault,
     )]
     pub bank_liquidity_vault: AccountInfo<'info>,
 
@@ -0,0 +1,177 @@
+use crate::{
+    bank_signer, check,
+    constants::LIQUIDITY_VAULT_AUTHORITY_SEED,
+    events::{AccountEventHeader, LendingAccountSettleKilledBalanceEvent},
+    prelude::*,
+    state::{
+        marginfi_account::{
+            BankAccountWrapper, KilledBalanceSettlement, MarginfiAccount, DISABLED_FLAG,
+        },
+        marginfi_group::{Bank, BankVaultType},
+    },
+    utils,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use solana_program::{clock::Clock, sysvar::Sysvar};
+
+/// Settle the signer's balance in a killed bank, see `Bank::kill`.
+///
+/// A liability is repaid in full from `signer_token_account`. A deposit is paid out to
+/// `signer_token_account` pro-rata from what is left in the liquidity vault, the rest of it
+/// is written off. The balance is closed either way.
+///
+/// The killed bank no longer counts towards account health, so no oracles are needed.
+pub fn lending_account_settle_killed_balance<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingAccountSettleKilledBalance<'info>>,
+) -> MarginfiResult {
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+    let LendingAccountSettleKilledBalance {
+        marginfi_account: marginfi_account_loader,
+        signer,
+        signer_token_account,
+        bank_liquidity_vault_authority,
+        bank_liquidity_vault,
+        token_program,
+        bank: bank_loader,
+        ..
+    } = ctx.accounts;
+    let clock = Clock::get()?;
+
+    let mut marginfi_account = marginfi_account_loader.load_mut()?;
+
+    check!(
+        !marginfi_account.get_flag(DISABLED_FLAG),
+        MarginfiError::AccountDisabled
+    );
+
+    let maybe_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*bank_loader.load()?,
+        token_program.key,
+    )?;
+
+    let mut bank = bank_loader.load_mut()?;
+    let liquidity_vault_authority_bump = bank.liquidity_vault_authority_bump;
+
+    let mut bank_account = BankAccountWrapper::find(
+        &bank_loader.key(),
+        &mut bank,
+        &mut marginfi_account,
+        referral_fee_bps,
+    )?;
+
+    let settlement =
+        bank_account.settle_killed(bank_liquidity_vault.amount, clock.unix_timestamp)?;
+
+    let (repaid, withdrawn) = match settlement {
+        KilledBalanceSettlement::Repay(amount) => {
+            let amount_pre_fee = maybe_bank_mint
+                .as_ref()
+                .map(|mint| {
+                    utils::calculate_pre_fee_spl_deposit_amount(
+                        mint.to_account_info(),
+                        amount,
+                        clock.epoch,
+                    )
+                })
+                .transpose()?
+                .unwrap_or(amount);
+
+            bank_account.deposit_spl_transfer(
+                amount_pre_fee,
+                signer_token_account.to_account_info(),
+                bank_liquidity_vault.to_account_info(),
+                signer.to_account_info(),
+                maybe_bank_mint.as_ref(),
+                token_program.to_account_info(),
+                ctx.remaining_accounts,
+            )?;
+
+            (amount_pre_fee, 0)
+        }
+        KilledBalanceSettlement::Withdraw(amount) => {
+            if amount > 0 {
+                bank_account.withdraw_spl_transfer(
+                    amount,
+                    bank_liquidity_vault.to_account_info(),
+                    signer_token_account.to_account_info(),
+                    bank_liquidity_vault_authority.to_account_info(),
+                    maybe_bank_mint.as_ref(),
+                    token_program.to_account_info(),
+                    bank_signer!(
+                        BankVaultType::Liquidity,
+                        bank_loader.key(),
+                        liquidity_vault_authority_bump
+                    ),
+                    ctx.remaining_accounts,
+                )?;
+            }
+
+            (0, amount)
+        }
+    };
+
+    emit!(LendingAccountSettleKilledBalanceEvent {
+        header: AccountEventHeader {
+            signer: Some(signer.key()),
+            marginfi_account: marginfi_account_loader.key(),
+            marginfi_account_authority: marginfi_account.authority,
+            marginfi_group: marginfi_account.group,
+        },
+        bank: bank_loader.key(),
+        mint: bank.mint,
+        repaid,
+        withdrawn,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountSettleKilledBalance<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = marginfi_account.load()?.group == marginfi_group.key(),
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    #[account(
+        address = marginfi_account.load()?.authority,
+    )]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    /// CHECK: Token mint/authority are checked at transfer
+    #[account(mut)]
+    pub signer_token_account: AccountInfo<'info>,
+
+    /// CHECK: Seed constraint check
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.liquidity_vault_authority_bump,
+    )]
+    pub bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        address = bank.load()?.liquidity_vault,
+    )]
+    pub bank_liquidity_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -21,11 +21,12 @@ pub struct MarginfiAccountSetAccountAuthority<'info> {
     #[account(mut)]
     pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
 
-    /// CHECK: The group is confirmed by the address macro
     #[account(
         address = marginfi_account.load()?.group,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
     )]
-    pub marginfi_group: AccountInfo<'info>,
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
         address = marginfi_account.load()?.authority,
@@ -1,11 +1,14 @@
+#[cfg(feature = "sanity")]
+use crate::state::marginfi_account::HealthDelta;
 use crate::{
     bank_signer, check,
-    constants::{LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED},
+    constants::LIQUIDITY_VAULT_AUTHORITY_SEED,
     events::{AccountEventHeader, LendingAccountWithdrawEvent},
+    math_error,
     prelude::*,
     state::{
         marginfi_account::{BankAccountWrapper, MarginfiAccount, RiskEngine, DISABLED_FLAG},
-        marginfi_group::{Bank, BankVaultType},
+        marginfi_group::{Bank, BankVaultType, WrappedI80F48},
     },
     utils,
 };
@@ -21,11 +24,23 @@ use solana_program::{clock::Clock, sysvar::Sysvar};
 /// 5. Verify that the user account is in a healthy state
 ///
 /// Will error if there is no existing asset <=> borrowing is not allowed.
+///
+/// If `max_shares_in` is set, will error if more asset shares are burned,
+/// e.g. because a loss was socialized between quoting and execution.
+///
+/// With the `sanity` feature, the withdraw is asserted not to increase account health.
 pub fn lending_account_withdraw<'info>(
     mut ctx: Context<'_, '_, 'info, 'info, LendingAccountWithdraw<'info>>,
     amount: u64,
     withdraw_all: Option<bool>,
+    max_shares_in: Option<WrappedI80F48>,
 ) -> MarginfiResult {
+    ctx.accounts
+        .marginfi_group
+        .load()?
+        .check_cpi_caller(ctx.remaining_accounts)?;
+    let referral_fee_bps = ctx.accounts.marginfi_group.load()?.referral_fee_bps;
+
     let LendingAccountWithdraw {
         marginfi_account: marginfi_account_loader,
         destination_token_account,
@@ -57,6 +72,9 @@ pub fn lending_account_withdraw<'info>(
         bank_loader.key(),
     )?;
 
+    #[cfg(feature = "sanity")]
+    let health_before = RiskEngine::get_sanity_health(&marginfi_account, ctx.remaining_accounts)?;
+
     {
         let mut bank = bank_loader.load_mut()?;
 
@@ -65,9 +83,12 @@ pub fn lending_account_withdraw<'info>(
         let mut bank_account = BankAccountWrapper::find(
             &bank_loader.key(),
             &mut bank,
-            &mut marginfi_account.lending_account,
+            &mut marginfi_account,
+            referral_fee_bps,
         )?;
 
+        let asset_shares_before: I80F48 = bank_account.balance.asset_shares.into();
+
         let amount_pre_fee = if withdraw_all {
             bank_account.withdraw_all()?
         } else {
@@ -88,6 +109,17 @@ pub fn lending_account_withdraw<'info>(
             amount_pre_fee
         };
 
+        if let Some(max_shares_in) = max_shares_in {
+            let asset_shares_in = asset_shares_before
+                .checked_sub(bank_account.balance.asset_shares.into())
+                .ok_or_else(math_error!())?;
+
+            check!(
+                asset_shares_in <= I80F48::from(max_shares_in),
+                MarginfiError::SharesSlippageExceeded
+            );
+        }
+
         bank_account.withdraw_spl_transfer(
             amount_pre_fee,
             bank_liquidity_vault.to_account_info(),
@@ -119,13 +151,27 @@ pub fn lending_account_withdraw<'info>(
 
     // Check account health, if below threshold fail transaction
     // Assuming `ctx.remaining_accounts` holds only oracle accounts
-    RiskEngine::check_account_init_health(&marginfi_account, ctx.remaining_accounts)?;
+    RiskEngine::check_account_init_health(
+        &marginfi_account,
+        ctx.remaining_accounts,
+        &*ctx.accounts.marginfi_group.load()?,
+    )?;
+
+    #[cfg(feature = "sanity")]
+    HealthDelta::NonIncreasing.check(
+        health_before,
+        RiskEngine::get_sanity_health(&marginfi_account, ctx.remaining_accounts)?,
+    )?;
 
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct LendingAccountWithdraw<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -161,11 +207,7 @@ pub struct LendingAccountWithdraw<'info> {
 
     #[account(
         mut,
-        seeds = [
-            LIQUIDITY_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.liquidity_vault_bump,
+        address = bank.load()?.liquidity_vault,
     )]
     pub bank_liquidity_vault: InterfaceAccount<'info, TokenAccount>,
 
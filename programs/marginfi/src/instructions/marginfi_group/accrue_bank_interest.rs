@@ -1,26 +1,331 @@
+use super::collect_bank_fees::{collect_bank_fees, BankFeeCollectionAccounts};
 use crate::{
-    state::marginfi_group::{Bank, MarginfiGroup},
-    MarginfiResult,
+    bank_signer, check,
+    constants::{FEE_STATE_SEED, FEE_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED},
+    events::{
+        GroupEventHeader, LendingPoolBankAccrualBountyEvent, LendingPoolBankLendingSubsidyEvent,
+    },
+    math_error,
+    prelude::MarginfiError,
+    state::{
+        fee_state::FeeState,
+        marginfi_group::{Bank, BankVaultType, MarginfiGroup},
+    },
+    utils, MarginfiResult,
 };
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use fixed::types::I80F48;
+use std::cmp::min;
 
-pub fn lending_pool_accrue_bank_interest(
-    ctx: Context<LendingPoolAccrueBankInterest>,
+/// Accrue interest on the bank. When the vault accounts are provided, the lending subsidy reserve
+/// is first moved back into the fee vault if the bank's lending rate floor was removed, and the
+/// fees accrued so far are moved out of the liquidity vault. Otherwise fees stay outstanding on
+/// the bank until collected (fees can also be collected through `lending_pool_collect_bank_fees`).
+///
+/// The reserve is only ever funded by the group admin, see `lending_pool_fund_lending_subsidy`.
+pub fn lending_pool_accrue_bank_interest<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingPoolAccrueBankInterest<'info>>,
 ) -> MarginfiResult {
     let clock = Clock::get()?;
-    let mut bank = ctx.accounts.bank.load_mut()?;
+    let LendingPoolAccrueBankInterest {
+        marginfi_group,
+        bank: bank_loader,
+        liquidity_vault_authority,
+        liquidity_vault,
+        insurance_vault,
+        fee_vault,
+        fee_state,
+        program_fee_token_account,
+        token_program,
+    } = ctx.accounts;
+
+    let mut bank = bank_loader.load_mut()?;
+
+    match (
+        liquidity_vault_authority,
+        liquidity_vault,
+        insurance_vault,
+        fee_vault,
+        fee_state,
+        program_fee_token_account,
+        token_program,
+    ) {
+        (
+            Some(liquidity_vault_authority),
+            Some(liquidity_vault),
+            Some(insurance_vault),
+            Some(fee_vault),
+            Some(fee_state),
+            Some(program_fee_token_account),
+            Some(token_program),
+        ) => {
+            let fee_state = fee_state.load()?;
+
+            check!(
+                program_fee_token_account.owner == fee_state.global_fee_wallet
+                    && program_fee_token_account.mint == liquidity_vault.mint,
+                MarginfiError::InvalidFeeCollectionAccounts
+            );
+
+            let maybe_bank_mint =
+                utils::maybe_take_bank_mint(&mut ctx.remaining_accounts, &bank, token_program.key)?;
+
+            // A killed bank's liquidity vault belongs to its depositors.
+            let reserve = I80F48::from(bank.lending_subsidy_reserve);
+            let floor_set =
+                I80F48::from(bank.config.interest_rate_config.min_lending_apr) > I80F48::ZERO;
+            let amount = if bank.is_killed() || floor_set {
+                0
+            } else {
+                min(
+                    reserve
+                        .int()
+                        .checked_to_num::<u64>()
+                        .ok_or_else(math_error!())?,
+                    liquidity_vault.amount,
+                )
+            };
+
+            if amount > 0 {
+                bank.withdraw_spl_transfer(
+                    amount,
+                    liquidity_vault.to_account_info(),
+                    fee_vault.to_account_info(),
+                    liquidity_vault_authority.to_account_info(),
+                    maybe_bank_mint.as_ref(),
+                    token_program.to_account_info(),
+                    bank_signer!(
+                        BankVaultType::Liquidity,
+                        bank_loader.key(),
+                        bank.liquidity_vault_authority_bump
+                    ),
+                    ctx.remaining_accounts,
+                )?;
+                liquidity_vault.reload()?;
+
+                bank.lending_subsidy_reserve = reserve
+                    .checked_sub(I80F48::from_num(amount))
+                    .ok_or_else(math_error!())?
+                    .into();
+
+                emit!(LendingPoolBankLendingSubsidyEvent {
+                    header: GroupEventHeader {
+                        marginfi_group: marginfi_group.key(),
+                        signer: None,
+                    },
+                    bank: bank_loader.key(),
+                    mint: bank.mint,
+                    amount,
+                    returned: true,
+                    reserve: I80F48::from(bank.lending_subsidy_reserve).to_num::<f64>(),
+                });
+            }
+
+            bank.accrue_interest(
+                clock.unix_timestamp,
+                #[cfg(not(feature = "client"))]
+                bank_loader.key(),
+            )?;
+
+            collect_bank_fees(
+                &mut bank,
+                bank_loader.key(),
+                marginfi_group.key(),
+                BankFeeCollectionAccounts {
+                    liquidity_vault_authority: liquidity_vault_authority.to_account_info(),
+                    liquidity_vault,
+                    insurance_vault: insurance_vault.to_account_info(),
+                    fee_vault: fee_vault.to_account_info(),
+                    program_fee_token_account: Some(program_fee_token_account.to_account_info()),
+                    token_program: token_program.to_account_info(),
+                },
+                Some(&fee_state),
+                maybe_bank_mint.as_ref(),
+                ctx.remaining_accounts,
+            )?;
+        }
+        (None, None, None, None, None, None, None) => {
+            bank.accrue_interest(
+                clock.unix_timestamp,
+                #[cfg(not(feature = "client"))]
+                bank_loader.key(),
+            )?;
+        }
+        _ => return err!(MarginfiError::InvalidFeeCollectionAccounts),
+    }
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolAccrueBankInterest<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    // The accounts below are optional, fees are only collected when all of them are provided.
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.liquidity_vault_authority_bump
+    )]
+    pub liquidity_vault_authority: Option<AccountInfo<'info>>,
+
+    #[account(
+        mut,
+        address = bank.load()?.liquidity_vault,
+    )]
+    pub liquidity_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        mut,
+        address = bank.load()?.insurance_vault,
+    )]
+    pub insurance_vault: Option<AccountInfo<'info>>,
+
+    #[account(
+        mut,
+        address = bank.load()?.fee_vault,
+    )]
+    pub fee_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [FEE_STATE_SEED.as_bytes()],
+        bump = fee_state.load()?.bump,
+    )]
+    pub fee_state: Option<AccountLoader<'info, FeeState>>,
+
+    /// Token account of the program fee wallet for the bank's mint, checked in the handler.
+    #[account(mut)]
+    pub program_fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+/// Accrue interest on every bank passed in the remaining accounts, so a crank can keep all
+/// banks of a group fresh in a few transactions. All banks must belong to `marginfi_group`.
+pub fn lending_pool_accrue_interest_many<'info>(
+    ctx: Context<'_, '_, 'info, 'info, LendingPoolAccrueInterestMany<'info>>,
+) -> MarginfiResult {
+    let clock = Clock::get()?;
+    let marginfi_group = ctx.accounts.marginfi_group.key();
+
+    check!(
+        !ctx.remaining_accounts.is_empty(),
+        MarginfiError::MissingBankAccount
+    );
+
+    for bank_ai in ctx.remaining_accounts.iter() {
+        let bank_loader = AccountLoader::<Bank>::try_from(bank_ai)?;
+        let mut bank = bank_loader.load_mut()?;
+
+        check!(
+            bank.group == marginfi_group,
+            MarginfiError::InvalidBankAccount
+        );
+
+        bank.accrue_interest(
+            clock.unix_timestamp,
+            #[cfg(not(feature = "client"))]
+            bank_loader.key(),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolAccrueInterestMany<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+}
+
+/// Accrue interest and, if the bank went without an accrual for longer than its
+/// `accrual_bounty_min_age`, pay the bounty from the fee vault to `dst_token_account`.
+/// The bounty is capped at the fee vault balance so the accrual itself never fails on it.
+pub fn lending_pool_crank_bank_interest<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingPoolCrankBankInterest<'info>>,
+) -> MarginfiResult {
+    let LendingPoolCrankBankInterest {
+        marginfi_group,
+        bank: bank_loader,
+        fee_vault,
+        fee_vault_authority,
+        dst_token_account,
+        token_program,
+    } = ctx.accounts;
+
+    let clock = Clock::get()?;
+    let mut bank = bank_loader.load_mut()?;
+    let maybe_bank_mint =
+        utils::maybe_take_bank_mint(&mut ctx.remaining_accounts, &bank, token_program.key)?;
+
+    let bounty = min(
+        bank.get_accrual_bounty(clock.unix_timestamp),
+        fee_vault.amount,
+    );
 
     bank.accrue_interest(
         clock.unix_timestamp,
         #[cfg(not(feature = "client"))]
-        ctx.accounts.bank.key(),
+        bank_loader.key(),
+    )?;
+
+    if bounty == 0 {
+        return Ok(());
+    }
+
+    bank.withdraw_spl_transfer(
+        bounty,
+        fee_vault.to_account_info(),
+        dst_token_account.to_account_info(),
+        fee_vault_authority.to_account_info(),
+        maybe_bank_mint.as_ref(),
+        token_program.to_account_info(),
+        bank_signer!(
+            BankVaultType::Fee,
+            bank_loader.key(),
+            bank.fee_vault_authority_bump
+        ),
+        ctx.remaining_accounts,
     )?;
 
+    emit!(LendingPoolBankAccrualBountyEvent {
+        header: GroupEventHeader {
+            marginfi_group: marginfi_group.key(),
+            signer: None,
+        },
+        bank: bank_loader.key(),
+        mint: bank.mint,
+        destination: dst_token_account.key(),
+        amount: bounty,
+    });
+
     Ok(())
 }
 
 #[derive(Accounts)]
-pub struct LendingPoolAccrueBankInterest<'info> {
+pub struct LendingPoolCrankBankInterest<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -28,4 +333,27 @@ pub struct LendingPoolAccrueBankInterest<'info> {
         constraint = bank.load()?.group == marginfi_group.key(),
     )]
     pub bank: AccountLoader<'info, Bank>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        mut,
+        address = bank.load()?.fee_vault,
+    )]
+    pub fee_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            FEE_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.fee_vault_authority_bump
+    )]
+    pub fee_vault_authority: AccountInfo<'info>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(mut)]
+    pub dst_token_account: AccountInfo<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
 }
@@ -4,10 +4,15 @@ use crate::{
         INSURANCE_VAULT_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED,
     },
     events::{GroupEventHeader, LendingPoolBankCreateEvent},
-    state::marginfi_group::{Bank, BankConfig, BankConfigCompact, MarginfiGroup},
+    prelude::MarginfiError,
+    state::{
+        bank_registry::BankRegistry,
+        marginfi_group::{Bank, BankConfig, BankConfigCompact, MarginfiGroup},
+        price::OracleSetup,
+    },
     MarginfiResult,
 };
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, system_program};
 use anchor_spl::token_interface::*;
 
 /// Add a bank to the lending pool
@@ -25,6 +30,7 @@ pub fn lending_pool_add_bank(
         insurance_vault,
         fee_vault,
         bank: bank_loader,
+        bank_registry,
         ..
     } = ctx.accounts;
 
@@ -54,8 +60,15 @@ pub fn lending_pool_add_bank(
         fee_vault_authority_bump,
     );
 
+    let marginfi_group = ctx.accounts.marginfi_group.load()?;
+
     bank.config.validate()?;
-    bank.config.validate_oracle_setup(ctx.remaining_accounts)?;
+    bank.config.validate_oracle_setup(
+        ctx.remaining_accounts,
+        &marginfi_group.get_pyth_program_id(),
+    )?;
+
+    BankRegistry::register(&marginfi_group, bank_registry.as_ref(), bank_loader.key())?;
 
     emit!(LendingPoolBankCreateEvent {
         header: GroupEventHeader {
@@ -72,6 +85,10 @@ pub fn lending_pool_add_bank(
 #[derive(Accounts)]
 #[instruction(bank_config: BankConfigCompact)]
 pub struct LendingPoolAddBank<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -164,6 +181,13 @@ pub struct LendingPoolAddBank<'info> {
     pub rent: Sysvar<'info, Rent>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+
+    /// Required once the group has a bank registry, see [`MarginfiGroup::has_bank_registry`].
+    #[account(
+        mut,
+        constraint = bank_registry.load()?.group == marginfi_group.key(),
+    )]
+    pub bank_registry: Option<AccountLoader<'info, BankRegistry>>,
 }
 
 /// A copy of lending_pool_add_bank but with an additional bank seed provided.
@@ -181,6 +205,7 @@ pub fn lending_pool_add_bank_with_seed(
         insurance_vault,
         fee_vault,
         bank: bank_loader,
+        bank_registry,
         ..
     } = ctx.accounts;
 
@@ -210,8 +235,15 @@ pub fn lending_pool_add_bank_with_seed(
         fee_vault_authority_bump,
     );
 
+    let marginfi_group = ctx.accounts.marginfi_group.load()?;
+
     bank.config.validate()?;
-    bank.config.validate_oracle_setup(ctx.remaining_accounts)?;
+    bank.config.validate_oracle_setup(
+        ctx.remaining_accounts,
+        &marginfi_group.get_pyth_program_id(),
+    )?;
+
+    BankRegistry::register(&marginfi_group, bank_registry.as_ref(), bank_loader.key())?;
 
     emit!(LendingPoolBankCreateEvent {
         header: GroupEventHeader {
@@ -232,6 +264,10 @@ pub fn lending_pool_add_bank_with_seed(
 #[derive(Accounts)]
 #[instruction(bank_config: BankConfigCompact, bank_seed: u64)]
 pub struct LendingPoolAddBankWithSeed<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -330,4 +366,220 @@ pub struct LendingPoolAddBankWithSeed<'info> {
     pub rent: Sysvar<'info, Rent>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+
+    /// Required once the group has a bank registry, see [`MarginfiGroup::has_bank_registry`].
+    #[account(
+        mut,
+        constraint = bank_registry.load()?.group == marginfi_group.key(),
+    )]
+    pub bank_registry: Option<AccountLoader<'info, BankRegistry>>,
+}
+
+/// Add a bank for a whitelisted mint without the admin, see
+/// [`MarginfiGroup::check_permissionless_bank_mint`].
+///
+/// The creator pays the group's creation fee to its fee wallet and only chooses the oracle,
+/// the rest of the config comes from [`BankConfig::permissionless`].
+pub fn lending_pool_add_bank_permissionless(
+    ctx: Context<LendingPoolAddBankPermissionless>,
+    oracle_setup: OracleSetup,
+    oracle_key: Pubkey,
+    _bank_seed: u64,
+) -> MarginfiResult {
+    let LendingPoolAddBankPermissionless {
+        marginfi_group: marginfi_group_loader,
+        fee_payer,
+        fee_wallet,
+        bank_mint,
+        liquidity_vault,
+        insurance_vault,
+        fee_vault,
+        bank: bank_loader,
+        bank_registry,
+        system_program: system_program_account,
+        ..
+    } = ctx.accounts;
+
+    let marginfi_group = marginfi_group_loader.load()?;
+
+    marginfi_group.check_permissionless_bank_mint(&bank_mint.key())?;
+
+    if marginfi_group.permissionless_bank_creation_fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program_account.to_account_info(),
+                system_program::Transfer {
+                    from: fee_payer.to_account_info(),
+                    to: fee_wallet.to_account_info(),
+                },
+            ),
+            marginfi_group.permissionless_bank_creation_fee,
+        )?;
+    }
+
+    let mut bank = bank_loader.load_init()?;
+
+    let liquidity_vault_bump = ctx.bumps.liquidity_vault;
+    let liquidity_vault_authority_bump = ctx.bumps.liquidity_vault_authority;
+    let insurance_vault_bump = ctx.bumps.insurance_vault;
+    let insurance_vault_authority_bump = ctx.bumps.insurance_vault_authority;
+    let fee_vault_bump = ctx.bumps.fee_vault;
+    let fee_vault_authority_bump = ctx.bumps.fee_vault_authority;
+
+    *bank = Bank::new(
+        marginfi_group_loader.key(),
+        BankConfig::permissionless(
+            oracle_setup,
+            oracle_key,
+            bank_mint.decimals,
+            &marginfi_group,
+        ),
+        bank_mint.key(),
+        bank_mint.decimals,
+        liquidity_vault.key(),
+        insurance_vault.key(),
+        fee_vault.key(),
+        Clock::get().unwrap().unix_timestamp,
+        liquidity_vault_bump,
+        liquidity_vault_authority_bump,
+        insurance_vault_bump,
+        insurance_vault_authority_bump,
+        fee_vault_bump,
+        fee_vault_authority_bump,
+    );
+
+    bank.config.validate()?;
+    bank.config.validate_oracle_setup(
+        ctx.remaining_accounts,
+        &marginfi_group.get_pyth_program_id(),
+    )?;
+
+    BankRegistry::register(&marginfi_group, bank_registry.as_ref(), bank_loader.key())?;
+
+    emit!(LendingPoolBankCreateEvent {
+        header: GroupEventHeader {
+            marginfi_group: marginfi_group_loader.key(),
+            signer: Some(fee_payer.key())
+        },
+        bank: bank_loader.key(),
+        mint: bank_mint.key(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(oracle_setup: OracleSetup, oracle_key: Pubkey, bank_seed: u64)]
+pub struct LendingPoolAddBankPermissionless<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    /// Pays the creation fee and the rent of the new accounts.
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    /// CHECK: Receives the creation fee, must be the group's fee wallet
+    #[account(
+        mut,
+        address = marginfi_group.load()?.fee_wallet,
+    )]
+    pub fee_wallet: AccountInfo<'info>,
+
+    pub bank_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<Bank>(),
+        payer = fee_payer,
+        seeds = [
+            marginfi_group.key().as_ref(),
+            bank_mint.key().as_ref(),
+            &bank_seed.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump
+    )]
+    pub liquidity_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        token::mint = bank_mint,
+        token::authority = liquidity_vault_authority,
+        seeds = [
+            LIQUIDITY_VAULT_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub liquidity_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            INSURANCE_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump
+    )]
+    pub insurance_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        token::mint = bank_mint,
+        token::authority = insurance_vault_authority,
+        seeds = [
+            INSURANCE_VAULT_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub insurance_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            FEE_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump
+    )]
+    pub fee_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        token::mint = bank_mint,
+        token::authority = fee_vault_authority,
+        seeds = [
+            FEE_VAULT_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+
+    /// Required once the group has a bank registry, see [`MarginfiGroup::has_bank_registry`].
+    #[account(
+        mut,
+        constraint = bank_registry.load()?.group == marginfi_group.key(),
+    )]
+    pub bank_registry: Option<AccountLoader<'info, BankRegistry>>,
 }
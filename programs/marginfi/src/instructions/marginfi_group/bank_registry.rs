@@ -0,0 +1,106 @@
+use crate::{
+    check,
+    constants::{BANK_CLOSED_FLAG, BANK_REGISTRY_FLAG, BANK_REGISTRY_SEED},
+    prelude::*,
+    state::{bank_registry::BankRegistry, marginfi_group::Bank},
+};
+use anchor_lang::prelude::*;
+
+/// Create the bank registry PDA of a group. From then on every bank added or closed has to
+/// pass it, banks that already exist are added through `lending_pool_register_banks`.
+///
+/// Admin only
+pub fn initialize_bank_registry(ctx: Context<MarginfiGroupInitBankRegistry>) -> MarginfiResult {
+    let mut bank_registry = ctx.accounts.bank_registry.load_init()?;
+    bank_registry.group = ctx.accounts.marginfi_group.key();
+    bank_registry.bump = ctx.bumps.bank_registry;
+
+    ctx.accounts.marginfi_group.load_mut()?.flags |= BANK_REGISTRY_FLAG;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiGroupInitBankRegistry<'info> {
+    #[account(
+        mut,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        space = 8 + std::mem::size_of::<BankRegistry>(),
+        seeds = [
+            BANK_REGISTRY_SEED.as_bytes(),
+            marginfi_group.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub bank_registry: AccountLoader<'info, BankRegistry>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Add banks created before the group's registry to it.
+///
+/// `ctx.remaining_accounts` holds the banks, they have to belong to the group
+/// and can't be closed or registered already.
+///
+/// Admin only
+pub fn lending_pool_register_banks<'info>(
+    ctx: Context<'_, '_, 'info, 'info, LendingPoolRegisterBanks<'info>>,
+) -> MarginfiResult {
+    let mut bank_registry = ctx.accounts.bank_registry.load_mut()?;
+
+    for bank_ai in ctx.remaining_accounts {
+        let bank_loader = AccountLoader::<Bank>::try_from(bank_ai)?;
+        let bank = bank_loader.load()?;
+
+        check!(
+            bank.group == ctx.accounts.marginfi_group.key(),
+            MarginfiError::InvalidBankAccount
+        );
+        check!(!bank.get_flag(BANK_CLOSED_FLAG), MarginfiError::BankClosed);
+
+        bank_registry.add(bank_ai.key())?;
+    }
+
+    msg!("Bank registry holds {} banks", bank_registry.bank_count);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolRegisterBanks<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            BANK_REGISTRY_SEED.as_bytes(),
+            marginfi_group.key().as_ref(),
+        ],
+        bump = bank_registry.load()?.bump,
+    )]
+    pub bank_registry: AccountLoader<'info, BankRegistry>,
+}
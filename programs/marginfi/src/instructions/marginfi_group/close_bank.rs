@@ -0,0 +1,197 @@
+use crate::{
+    bank_signer,
+    constants::{
+        FEE_VAULT_AUTHORITY_SEED, INSURANCE_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED,
+    },
+    events::{GroupEventHeader, LendingPoolBankCloseEvent},
+    prelude::MarginfiError,
+    state::{
+        bank_registry::BankRegistry,
+        marginfi_group::{Bank, BankVaultType, MarginfiGroup},
+    },
+    utils, MarginfiResult,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{close_account, CloseAccount, TokenAccount, TokenInterface};
+
+/// Retire a bank without deposits or borrows.
+///
+/// Whatever is left in the vaults (uncollected fees, insurance, rounding dust) is moved to
+/// `dst_token_account`, then the three vaults are closed and their rent goes to the admin.
+/// Unclaimed referral fees are the exception: they stay in the liquidity vault, which is then
+/// left open so referrers can still claim them, see [`Bank::get_referral_fee_escrow`].
+/// The bank account itself is kept and marked closed, so balances that still point at it can be
+/// closed and the bank can't be configured, reopened or initialized again.
+///
+/// Token-2022 vaults holding withheld transfer fees have to be harvested first.
+///
+/// Admin only
+pub fn lending_pool_close_bank<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingPoolCloseBank<'info>>,
+) -> MarginfiResult {
+    let LendingPoolCloseBank {
+        marginfi_group,
+        admin,
+        bank: bank_loader,
+        liquidity_vault_authority,
+        liquidity_vault,
+        insurance_vault_authority,
+        insurance_vault,
+        fee_vault_authority,
+        fee_vault,
+        dst_token_account,
+        token_program,
+        bank_registry,
+    } = ctx.accounts;
+
+    let bank_pk = bank_loader.key();
+    let mut bank = bank_loader.load_mut()?;
+    let maybe_bank_mint =
+        utils::maybe_take_bank_mint(&mut ctx.remaining_accounts, &bank, token_program.key)?;
+
+    bank.close()?;
+    let referral_fee_escrow = bank.get_referral_fee_escrow()?;
+
+    BankRegistry::unregister(&*marginfi_group.load()?, bank_registry.as_ref(), &bank_pk)?;
+
+    for (vault_type, vault, vault_authority) in [
+        (
+            BankVaultType::Liquidity,
+            liquidity_vault,
+            liquidity_vault_authority,
+        ),
+        (
+            BankVaultType::Insurance,
+            insurance_vault,
+            insurance_vault_authority,
+        ),
+        (BankVaultType::Fee, fee_vault, fee_vault_authority),
+    ] {
+        let (_, authority_bump) = bank.get_vault_and_authority_bump(vault_type);
+        let escrow = match vault_type {
+            BankVaultType::Liquidity => referral_fee_escrow.min(vault.amount),
+            _ => 0,
+        };
+
+        if vault.amount > escrow {
+            bank.withdraw_spl_transfer(
+                vault.amount - escrow,
+                vault.to_account_info(),
+                dst_token_account.to_account_info(),
+                vault_authority.to_account_info(),
+                maybe_bank_mint.as_ref(),
+                token_program.to_account_info(),
+                bank_signer!(vault_type, bank_pk, authority_bump),
+                ctx.remaining_accounts,
+            )?;
+        }
+
+        if escrow > 0 {
+            continue;
+        }
+
+        close_account(CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            CloseAccount {
+                account: vault.to_account_info(),
+                destination: admin.to_account_info(),
+                authority: vault_authority.to_account_info(),
+            },
+            bank_signer!(vault_type, bank_pk, authority_bump),
+        ))?;
+    }
+
+    emit!(LendingPoolBankCloseEvent {
+        header: GroupEventHeader {
+            marginfi_group: marginfi_group.key(),
+            signer: Some(admin.key()),
+        },
+        bank: bank_pk,
+        mint: bank.mint,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolCloseBank<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    /// Receives the vault rent.
+    #[account(
+        mut,
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.liquidity_vault_authority_bump
+    )]
+    pub liquidity_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        address = bank.load()?.liquidity_vault,
+    )]
+    pub liquidity_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            INSURANCE_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.insurance_vault_authority_bump
+    )]
+    pub insurance_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        address = bank.load()?.insurance_vault,
+    )]
+    pub insurance_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            FEE_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.fee_vault_authority_bump
+    )]
+    pub fee_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        address = bank.load()?.fee_vault,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Receives the remaining vault balances, the token program checks the mint
+    #[account(mut)]
+    pub dst_token_account: AccountInfo<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Required once the group has a bank registry, see [`MarginfiGroup::has_bank_registry`].
+    #[account(
+        mut,
+        constraint = bank_registry.load()?.group == marginfi_group.key(),
+    )]
+    pub bank_registry: Option<AccountLoader<'info, BankRegistry>>,
+}
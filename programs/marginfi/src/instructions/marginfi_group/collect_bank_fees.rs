@@ -1,20 +1,29 @@
-use crate::constants::{FEE_VAULT_AUTHORITY_SEED, INSURANCE_VAULT_AUTHORITY_SEED};
-use crate::events::{GroupEventHeader, LendingPoolBankCollectFeesEvent};
+use crate::constants::{FEE_STATE_SEED, FEE_VAULT_AUTHORITY_SEED, INSURANCE_VAULT_AUTHORITY_SEED};
+use crate::events::{
+    GroupEventHeader, LendingPoolBankCollectFeesEvent, LendingPoolBankLendingSubsidyEvent,
+    LendingPoolBankWithdrawFeesEvent, LendingPoolBankWithdrawInsuranceEvent,
+};
 use crate::utils;
 use crate::{
-    bank_signer,
-    constants::{
-        FEE_VAULT_SEED, INSURANCE_VAULT_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED,
-    },
+    bank_signer, check,
+    constants::LIQUIDITY_VAULT_AUTHORITY_SEED,
     math_error,
-    state::marginfi_group::{Bank, BankVaultType, MarginfiGroup},
+    prelude::MarginfiError,
+    state::{
+        fee_state::FeeState,
+        marginfi_group::{Bank, BankVaultType, MarginfiGroup},
+    },
     MarginfiResult,
 };
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use fixed::types::I80F48;
 use std::cmp::min;
 
+/// Move the outstanding fees of a bank out of its liquidity vault, see [`collect_bank_fees`].
+///
+/// The program fee is only skimmed when `fee_state` and `program_fee_token_account` are passed,
+/// so fees can still be collected with the old account list before the fee state exists.
 pub fn lending_pool_collect_bank_fees<'info>(
     mut ctx: Context<'_, '_, 'info, 'info, LendingPoolCollectBankFees<'info>>,
 ) -> MarginfiResult {
@@ -24,6 +33,8 @@ pub fn lending_pool_collect_bank_fees<'info>(
         fee_vault,
         token_program,
         liquidity_vault,
+        fee_state,
+        program_fee_token_account,
         ..
     } = ctx.accounts;
 
@@ -31,7 +42,83 @@ pub fn lending_pool_collect_bank_fees<'info>(
     let maybe_bank_mint =
         utils::maybe_take_bank_mint(&mut ctx.remaining_accounts, &bank, token_program.key)?;
 
-    let mut available_liquidity = I80F48::from_num(liquidity_vault.amount);
+    let fee_state = match (fee_state.as_ref(), program_fee_token_account.as_ref()) {
+        (Some(fee_state), Some(program_fee_token_account)) => {
+            let fee_state = fee_state.load()?;
+
+            check!(
+                program_fee_token_account.owner == fee_state.global_fee_wallet
+                    && program_fee_token_account.mint == liquidity_vault.mint,
+                MarginfiError::InvalidFeeCollectionAccounts
+            );
+
+            Some(*fee_state)
+        }
+        (None, None) => None,
+        _ => return err!(MarginfiError::InvalidFeeCollectionAccounts),
+    };
+
+    collect_bank_fees(
+        &mut bank,
+        ctx.accounts.bank.key(),
+        ctx.accounts.marginfi_group.key(),
+        BankFeeCollectionAccounts {
+            liquidity_vault_authority: liquidity_vault_authority.to_account_info(),
+            liquidity_vault,
+            insurance_vault: insurance_vault.to_account_info(),
+            fee_vault: fee_vault.to_account_info(),
+            program_fee_token_account: program_fee_token_account
+                .as_ref()
+                .map(|account| account.to_account_info()),
+            token_program: token_program.to_account_info(),
+        },
+        fee_state.as_ref(),
+        maybe_bank_mint.as_ref(),
+        ctx.remaining_accounts,
+    )
+}
+
+/// Token accounts needed to move outstanding fees out of a bank's liquidity vault.
+pub(crate) struct BankFeeCollectionAccounts<'a, 'info> {
+    pub liquidity_vault_authority: AccountInfo<'info>,
+    pub liquidity_vault: &'a InterfaceAccount<'info, TokenAccount>,
+    pub insurance_vault: AccountInfo<'info>,
+    pub fee_vault: AccountInfo<'info>,
+    /// Only needed along with the fee state, see [`collect_bank_fees`].
+    pub program_fee_token_account: Option<AccountInfo<'info>>,
+    pub token_program: AccountInfo<'info>,
+}
+
+/// Transfer the outstanding insurance and group fees of `bank` from the liquidity vault to the
+/// insurance and fee vaults, as far as the vault's liquidity allows. The program fee is skimmed
+/// from the group fees when `fee_state` is given. Whatever can't be transferred stays outstanding
+/// on the bank.
+///
+/// The referral fees owed by the bank stay in the liquidity vault, and group fees gone negative
+/// by sharing fees that were already collected are repaid first, see [`Bank::share_group_fees`].
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn collect_bank_fees<'info>(
+    bank: &mut Bank,
+    bank_pk: Pubkey,
+    marginfi_group_pk: Pubkey,
+    accounts: BankFeeCollectionAccounts<'_, 'info>,
+    fee_state: Option<&FeeState>,
+    maybe_bank_mint: Option<&InterfaceAccount<'info, Mint>>,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> MarginfiResult {
+    let BankFeeCollectionAccounts {
+        liquidity_vault_authority,
+        liquidity_vault,
+        insurance_vault,
+        fee_vault,
+        program_fee_token_account,
+        token_program,
+    } = accounts;
+
+    let mut available_liquidity = I80F48::from_num(liquidity_vault.amount)
+        .checked_sub(bank.referral_fees_outstanding.into())
+        .ok_or_else(math_error!())?
+        .max(I80F48::ZERO);
 
     let (insurance_fee_transfer_amount, new_outstanding_insurance_fees) = {
         let outstanding = I80F48::from(bank.collected_insurance_fees_outstanding);
@@ -53,7 +140,7 @@ pub fn lending_pool_collect_bank_fees<'info>(
 
     let (group_fee_transfer_amount, new_outstanding_group_fees) = {
         let outstanding = I80F48::from(bank.collected_group_fees_outstanding);
-        let transfer_amount = min(outstanding, available_liquidity).int();
+        let transfer_amount = min(outstanding.max(I80F48::ZERO), available_liquidity).int();
 
         (
             transfer_amount.int(),
@@ -71,21 +158,53 @@ pub fn lending_pool_collect_bank_fees<'info>(
 
     bank.collected_group_fees_outstanding = new_outstanding_group_fees.into();
 
+    // The program fee is skimmed from the group fees being collected
+    let program_fee_transfer_amount = match (fee_state, program_fee_token_account) {
+        (Some(fee_state), Some(program_fee_token_account)) => {
+            let program_fee_transfer_amount =
+                fee_state.calc_program_fees(group_fee_transfer_amount)?;
+
+            bank.withdraw_spl_transfer(
+                program_fee_transfer_amount
+                    .checked_to_num()
+                    .ok_or_else(math_error!())?,
+                liquidity_vault.to_account_info(),
+                program_fee_token_account,
+                liquidity_vault_authority.clone(),
+                maybe_bank_mint,
+                token_program.clone(),
+                bank_signer!(
+                    BankVaultType::Liquidity,
+                    bank_pk,
+                    bank.liquidity_vault_authority_bump
+                ),
+                remaining_accounts,
+            )?;
+
+            program_fee_transfer_amount
+        }
+        (None, None) => I80F48::ZERO,
+        _ => return err!(MarginfiError::InvalidFeeCollectionAccounts),
+    };
+    let group_fee_transfer_amount = group_fee_transfer_amount
+        .checked_sub(program_fee_transfer_amount)
+        .ok_or_else(math_error!())?;
+
     bank.withdraw_spl_transfer(
         group_fee_transfer_amount
             .checked_to_num()
             .ok_or_else(math_error!())?,
         liquidity_vault.to_account_info(),
-        fee_vault.to_account_info(),
-        liquidity_vault_authority.to_account_info(),
-        maybe_bank_mint.as_ref(),
-        token_program.to_account_info(),
+        fee_vault.clone(),
+        liquidity_vault_authority.clone(),
+        maybe_bank_mint,
+        token_program.clone(),
         bank_signer!(
             BankVaultType::Liquidity,
-            ctx.accounts.bank.key(),
+            bank_pk,
             bank.liquidity_vault_authority_bump
         ),
-        ctx.remaining_accounts,
+        remaining_accounts,
     )?;
 
     bank.withdraw_spl_transfer(
@@ -93,29 +212,30 @@ pub fn lending_pool_collect_bank_fees<'info>(
             .checked_to_num()
             .ok_or_else(math_error!())?,
         liquidity_vault.to_account_info(),
-        insurance_vault.to_account_info(),
-        liquidity_vault_authority.to_account_info(),
-        maybe_bank_mint.as_ref(),
-        token_program.to_account_info(),
+        insurance_vault.clone(),
+        liquidity_vault_authority.clone(),
+        maybe_bank_mint,
+        token_program.clone(),
         bank_signer!(
             BankVaultType::Liquidity,
-            ctx.accounts.bank.key(),
+            bank_pk,
             bank.liquidity_vault_authority_bump
         ),
-        ctx.remaining_accounts,
+        remaining_accounts,
     )?;
 
     emit!(LendingPoolBankCollectFeesEvent {
         header: GroupEventHeader {
-            marginfi_group: ctx.accounts.marginfi_group.key(),
+            marginfi_group: marginfi_group_pk,
             signer: None
         },
-        bank: ctx.accounts.bank.key(),
+        bank: bank_pk,
         mint: liquidity_vault.mint,
         insurance_fees_collected: insurance_fee_transfer_amount.to_num::<f64>(),
         insurance_fees_outstanding: new_outstanding_insurance_fees.to_num::<f64>(),
         group_fees_collected: group_fee_transfer_amount.to_num::<f64>(),
         group_fees_outstanding: new_outstanding_group_fees.to_num::<f64>(),
+        program_fees_collected: program_fee_transfer_amount.to_num::<f64>(),
     });
 
     Ok(())
@@ -123,6 +243,10 @@ pub fn lending_pool_collect_bank_fees<'info>(
 
 #[derive(Accounts)]
 pub struct LendingPoolCollectBankFees<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -144,37 +268,36 @@ pub struct LendingPoolCollectBankFees<'info> {
     /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
     #[account(
         mut,
-        seeds = [
-            LIQUIDITY_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.liquidity_vault_bump
+        address = bank.load()?.liquidity_vault,
     )]
     pub liquidity_vault: InterfaceAccount<'info, TokenAccount>,
 
     /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
     #[account(
         mut,
-        seeds = [
-            INSURANCE_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.insurance_vault_bump
+        address = bank.load()?.insurance_vault,
     )]
     pub insurance_vault: AccountInfo<'info>,
 
     /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
     #[account(
         mut,
-        seeds = [
-            FEE_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.fee_vault_bump
+        address = bank.load()?.fee_vault,
     )]
     pub fee_vault: AccountInfo<'info>,
 
     pub token_program: Interface<'info, TokenInterface>,
+
+    // The accounts below are optional, the program fee is only skimmed when both are provided.
+    #[account(
+        seeds = [FEE_STATE_SEED.as_bytes()],
+        bump = fee_state.load()?.bump,
+    )]
+    pub fee_state: Option<AccountLoader<'info, FeeState>>,
+
+    /// Token account of the program fee wallet for the bank's mint, checked in the handler.
+    #[account(mut)]
+    pub program_fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
 }
 
 pub fn lending_pool_withdraw_fees<'info>(
@@ -182,12 +305,13 @@ pub fn lending_pool_withdraw_fees<'info>(
     amount: u64,
 ) -> MarginfiResult {
     let LendingPoolWithdrawFees {
+        marginfi_group,
         bank: bank_loader,
+        admin,
         fee_vault,
         fee_vault_authority,
         dst_token_account,
         token_program,
-        ..
     } = ctx.accounts;
 
     let bank = bank_loader.load()?;
@@ -209,11 +333,26 @@ pub fn lending_pool_withdraw_fees<'info>(
         ctx.remaining_accounts,
     )?;
 
+    emit!(LendingPoolBankWithdrawFeesEvent {
+        header: GroupEventHeader {
+            marginfi_group: marginfi_group.key(),
+            signer: Some(admin.key()),
+        },
+        bank: bank_loader.key(),
+        mint: bank.mint,
+        destination: dst_token_account.key(),
+        amount,
+    });
+
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct LendingPoolWithdrawFees<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -229,11 +368,7 @@ pub struct LendingPoolWithdrawFees<'info> {
     /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
     #[account(
         mut,
-        seeds = [
-            FEE_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.fee_vault_bump
+        address = bank.load()?.fee_vault,
     )]
     pub fee_vault: AccountInfo<'info>,
 
@@ -254,17 +389,132 @@ pub struct LendingPoolWithdrawFees<'info> {
     pub token_program: Interface<'info, TokenInterface>,
 }
 
+/// Move `amount` from the fee vault into the lending subsidy reserve, which pays depositors the
+/// shortfall below the bank's lending rate floor. Interest is accrued first, so the reserve only
+/// pays for the time after it was funded. See [`Bank::lending_subsidy_reserve`].
+pub fn lending_pool_fund_lending_subsidy<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingPoolFundLendingSubsidy<'info>>,
+    amount: u64,
+) -> MarginfiResult {
+    let LendingPoolFundLendingSubsidy {
+        marginfi_group,
+        bank: bank_loader,
+        admin,
+        liquidity_vault,
+        fee_vault,
+        fee_vault_authority,
+        token_program,
+    } = ctx.accounts;
+
+    let mut bank = bank_loader.load_mut()?;
+    let maybe_bank_mint =
+        utils::maybe_take_bank_mint(&mut ctx.remaining_accounts, &bank, token_program.key)?;
+
+    check!(!bank.is_killed(), MarginfiError::BankKilled);
+    check!(
+        I80F48::from(bank.config.interest_rate_config.min_lending_apr) > I80F48::ZERO,
+        MarginfiError::InvalidConfig
+    );
+
+    bank.accrue_interest(
+        Clock::get()?.unix_timestamp,
+        #[cfg(not(feature = "client"))]
+        bank_loader.key(),
+    )?;
+
+    bank.withdraw_spl_transfer(
+        amount,
+        fee_vault.to_account_info(),
+        liquidity_vault.to_account_info(),
+        fee_vault_authority.to_account_info(),
+        maybe_bank_mint.as_ref(),
+        token_program.to_account_info(),
+        bank_signer!(
+            BankVaultType::Fee,
+            bank_loader.key(),
+            bank.fee_vault_authority_bump
+        ),
+        ctx.remaining_accounts,
+    )?;
+
+    bank.lending_subsidy_reserve = I80F48::from(bank.lending_subsidy_reserve)
+        .checked_add(I80F48::from_num(amount))
+        .ok_or_else(math_error!())?
+        .into();
+
+    emit!(LendingPoolBankLendingSubsidyEvent {
+        header: GroupEventHeader {
+            marginfi_group: marginfi_group.key(),
+            signer: Some(admin.key()),
+        },
+        bank: bank_loader.key(),
+        mint: bank.mint,
+        amount,
+        returned: false,
+        reserve: I80F48::from(bank.lending_subsidy_reserve).to_num::<f64>(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolFundLendingSubsidy<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        mut,
+        address = bank.load()?.liquidity_vault,
+    )]
+    pub liquidity_vault: AccountInfo<'info>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        mut,
+        address = bank.load()?.fee_vault,
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            FEE_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.fee_vault_authority_bump
+    )]
+    pub fee_vault_authority: AccountInfo<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
 pub fn lending_pool_withdraw_insurance<'info>(
     mut ctx: Context<'_, '_, 'info, 'info, LendingPoolWithdrawInsurance<'info>>,
     amount: u64,
 ) -> MarginfiResult {
     let LendingPoolWithdrawInsurance {
+        marginfi_group,
         bank: bank_loader,
+        signer,
         insurance_vault,
         insurance_vault_authority,
         dst_token_account,
         token_program,
-        ..
     } = ctx.accounts;
 
     let bank = bank_loader.load()?;
@@ -286,11 +536,26 @@ pub fn lending_pool_withdraw_insurance<'info>(
         ctx.remaining_accounts,
     )?;
 
+    emit!(LendingPoolBankWithdrawInsuranceEvent {
+        header: GroupEventHeader {
+            marginfi_group: marginfi_group.key(),
+            signer: Some(signer.key()),
+        },
+        bank: bank_loader.key(),
+        mint: bank.mint,
+        destination: dst_token_account.key(),
+        amount,
+    });
+
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct LendingPoolWithdrawInsurance<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -299,18 +564,15 @@ pub struct LendingPoolWithdrawInsurance<'info> {
     pub bank: AccountLoader<'info, Bank>,
 
     #[account(
-        address = marginfi_group.load()?.admin,
+        constraint = marginfi_group.load()?.is_admin_or_insurance_admin(signer.key)
+            @ MarginfiError::Unauthorized,
     )]
-    pub admin: Signer<'info>,
+    pub signer: Signer<'info>,
 
     /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
     #[account(
         mut,
-        seeds = [
-            INSURANCE_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.insurance_vault_bump
+        address = bank.load()?.insurance_vault,
     )]
     pub insurance_vault: AccountInfo<'info>,
 
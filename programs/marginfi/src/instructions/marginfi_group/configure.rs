@@ -5,7 +5,7 @@ use crate::state::marginfi_account::{
     MarginfiAccount, FLASHLOAN_ENABLED_FLAG, TRANSFER_AUTHORITY_ALLOWED_FLAG,
 };
 use crate::{
-    state::marginfi_group::{GroupConfig, MarginfiGroup},
+    state::marginfi_group::{GroupConfig, MarginfiGroup, WrappedI80F48},
     MarginfiResult,
 };
 use anchor_lang::prelude::*;
@@ -31,7 +31,11 @@ pub fn configure(ctx: Context<MarginfiGroupConfigure>, config: GroupConfig) -> M
 
 #[derive(Accounts)]
 pub struct MarginfiGroupConfigure<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -70,7 +74,11 @@ pub fn set_account_flag(ctx: Context<SetAccountFlag>, flag: u64) -> MarginfiResu
 
 #[derive(Accounts)]
 pub struct SetAccountFlag<'info> {
-    #[account(address = marginfi_account.load()?.group)]
+    #[account(
+        address = marginfi_account.load()?.group,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(mut)]
@@ -93,7 +101,74 @@ pub fn unset_account_flag(ctx: Context<UnsetAccountFlag>, flag: u64) -> Marginfi
 
 #[derive(Accounts)]
 pub struct UnsetAccountFlag<'info> {
-    #[account(address = marginfi_account.load()?.group)]
+    #[account(
+        address = marginfi_account.load()?.group,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(mut)]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    /// Admin only
+    #[account(address = marginfi_group.load()?.admin)]
+    pub admin: Signer<'info>,
+}
+
+/// Enable equity maintenance mode for an account with the given buffer, or disable it with `None`.
+///
+/// Admin only, co-signed by the account authority since the buffer can make the account
+/// liquidatable.
+pub fn set_account_equity_maintenance(
+    ctx: Context<SetAccountEquityMaintenance>,
+    buffer: Option<WrappedI80F48>,
+) -> MarginfiResult {
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
+
+    marginfi_account.set_equity_maintenance(buffer.map(Into::into))?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetAccountEquityMaintenance<'info> {
+    #[account(
+        address = marginfi_account.load()?.group,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(mut)]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    /// Admin only
+    #[account(address = marginfi_group.load()?.admin)]
+    pub admin: Signer<'info>,
+
+    #[account(address = marginfi_account.load()?.authority)]
+    pub authority: Signer<'info>,
+}
+
+/// Set the fee tier discount of an account, in bps of the group fees charged on its borrows.
+///
+/// Admin only
+pub fn set_account_fee_tier(ctx: Context<SetAccountFeeTier>, discount_bps: u16) -> MarginfiResult {
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
+
+    marginfi_account.set_fee_tier(discount_bps)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetAccountFeeTier<'info> {
+    #[account(
+        address = marginfi_account.load()?.group,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(mut)]
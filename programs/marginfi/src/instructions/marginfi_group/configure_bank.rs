@@ -1,9 +1,13 @@
-use crate::constants::{EMISSIONS_AUTH_SEED, EMISSIONS_TOKEN_ACCOUNT_SEED};
-use crate::events::{GroupEventHeader, LendingPoolBankConfigureEvent};
+use crate::constants::{
+    BANK_CLOSED_FLAG, BANK_CONFIG_FROZEN_FLAG, EMISSIONS_AUTH_SEED, EMISSIONS_TOKEN_ACCOUNT_SEED,
+};
+use crate::events::{GroupEventHeader, LendingPoolBankConfigureEvent, LendingPoolBankKillEvent};
 use crate::prelude::MarginfiError;
 use crate::{check, math_error, utils};
 use crate::{
-    state::marginfi_group::{Bank, BankConfigOpt, MarginfiGroup},
+    state::marginfi_group::{
+        Bank, BankConfigOpt, BankOperationalState, MarginfiGroup, WrappedI80F48,
+    },
     MarginfiResult,
 };
 use anchor_lang::prelude::*;
@@ -17,10 +21,36 @@ pub fn lending_pool_configure_bank(
 ) -> MarginfiResult {
     let mut bank = ctx.accounts.bank.load_mut()?;
 
+    // Settle interest under the old curve before it starts ramping to the new one
+    if bank_config.interest_rate_config.is_some() {
+        bank.accrue_interest(
+            Clock::get()?.unix_timestamp,
+            #[cfg(not(feature = "client"))]
+            ctx.accounts.bank.key(),
+        )?;
+    }
+
+    let previous_state = bank.config.operational_state;
+
     bank.configure(&bank_config)?;
 
-    if bank_config.oracle.is_some() {
-        bank.config.validate_oracle_setup(ctx.remaining_accounts)?;
+    bank.update_unpaused_at(previous_state, Clock::get()?.unix_timestamp);
+
+    let pyth_program_id = ctx.accounts.marginfi_group.load()?.get_pyth_program_id();
+
+    // A new oracle only takes over after the rotation delay, see `lending_pool_accept_bank_oracle`
+    if let Some(oracle) = bank_config.oracle {
+        let mut pending_config = bank.config;
+        pending_config.oracle_setup = oracle.setup;
+        pending_config.oracle_keys = oracle.keys;
+        pending_config.validate_oracle_setup(ctx.remaining_accounts, &pyth_program_id)?;
+
+        bank.propose_oracle(oracle, Clock::get()?.slot);
+    }
+
+    if bank_config.fixed_price.is_some() {
+        bank.config
+            .validate_oracle_setup(ctx.remaining_accounts, &pyth_program_id)?;
     }
 
     emit!(LendingPoolBankConfigureEvent {
@@ -38,6 +68,72 @@ pub fn lending_pool_configure_bank(
 
 #[derive(Accounts)]
 pub struct LendingPoolConfigureBank<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
+
+/// Switch the bank to the oracle proposed through `lending_pool_configure_bank`, once
+/// `ORACLE_ROTATION_DELAY_SLOTS` have passed. Interest is settled first, so the switch
+/// doesn't reprice anything accrued under the old oracle.
+///
+/// Remaining accounts: the new oracle accounts, as in `lending_pool_configure_bank`.
+///
+/// Admin only
+pub fn lending_pool_accept_bank_oracle(
+    ctx: Context<LendingPoolAcceptBankOracle>,
+) -> MarginfiResult {
+    let mut bank = ctx.accounts.bank.load_mut()?;
+    let clock = Clock::get()?;
+
+    bank.accrue_interest(
+        clock.unix_timestamp,
+        #[cfg(not(feature = "client"))]
+        ctx.accounts.bank.key(),
+    )?;
+
+    let oracle = bank.accept_pending_oracle(clock.slot)?;
+
+    bank.config.validate_oracle_setup(
+        ctx.remaining_accounts,
+        &ctx.accounts.marginfi_group.load()?.get_pyth_program_id(),
+    )?;
+
+    emit!(LendingPoolBankConfigureEvent {
+        header: GroupEventHeader {
+            marginfi_group: ctx.accounts.marginfi_group.key(),
+            signer: Some(*ctx.accounts.admin.key)
+        },
+        bank: ctx.accounts.bank.key(),
+        mint: bank.mint,
+        config: BankConfigOpt {
+            oracle: Some(oracle),
+            ..Default::default()
+        },
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolAcceptBankOracle<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -52,6 +148,113 @@ pub struct LendingPoolConfigureBank<'info> {
     pub bank: AccountLoader<'info, Bank>,
 }
 
+/// Flip the operational state of a bank without touching any other parameter.
+///
+/// Admin or risk council
+pub fn lending_pool_set_bank_operational_state(
+    ctx: Context<LendingPoolSetBankOperationalState>,
+    operational_state: BankOperationalState,
+) -> MarginfiResult {
+    let mut bank = ctx.accounts.bank.load_mut()?;
+
+    let bank_config = BankConfigOpt {
+        operational_state: Some(operational_state),
+        ..Default::default()
+    };
+
+    let previous_state = bank.config.operational_state;
+
+    bank.configure(&bank_config)?;
+
+    bank.update_unpaused_at(previous_state, Clock::get()?.unix_timestamp);
+
+    emit!(LendingPoolBankConfigureEvent {
+        header: GroupEventHeader {
+            marginfi_group: ctx.accounts.marginfi_group.key(),
+            signer: Some(*ctx.accounts.signer.key)
+        },
+        bank: ctx.accounts.bank.key(),
+        mint: bank.mint,
+        config: bank_config,
+    });
+
+    Ok(())
+}
+
+/// Move a compromised bank to the terminal `Killed` state, freezing its balances at
+/// `settlement_price`. Deposits, borrows, withdrawals and liquidations stop, balances can only
+/// be settled through `lending_account_settle_killed_balance`.
+///
+/// Admin only
+pub fn lending_pool_kill_bank(
+    ctx: Context<LendingPoolKillBank>,
+    settlement_price: WrappedI80F48,
+) -> MarginfiResult {
+    let mut bank = ctx.accounts.bank.load_mut()?;
+    let settlement_price = I80F48::from(settlement_price);
+
+    bank.accrue_interest(
+        Clock::get()?.unix_timestamp,
+        #[cfg(not(feature = "client"))]
+        ctx.accounts.bank.key(),
+    )?;
+
+    bank.kill(settlement_price)?;
+
+    emit!(LendingPoolBankKillEvent {
+        header: GroupEventHeader {
+            marginfi_group: ctx.accounts.marginfi_group.key(),
+            signer: Some(*ctx.accounts.admin.key)
+        },
+        bank: ctx.accounts.bank.key(),
+        mint: bank.mint,
+        settlement_price: settlement_price.to_num::<f64>(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolKillBank<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolSetBankOperationalState<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        constraint = marginfi_group.load()?.is_admin_or_risk_council(signer.key)
+            @ MarginfiError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
+
 pub fn lending_pool_setup_emissions(
     ctx: Context<LendingPoolSetupEmissions>,
     emissions_flags: u64,
@@ -60,6 +263,7 @@ pub fn lending_pool_setup_emissions(
 ) -> MarginfiResult {
     let mut bank = ctx.accounts.bank.load_mut()?;
 
+    check!(!bank.get_flag(BANK_CLOSED_FLAG), MarginfiError::BankClosed);
     check!(
         bank.emissions_mint.eq(&Pubkey::default()),
         MarginfiError::EmissionsAlreadySetup
@@ -97,6 +301,10 @@ pub fn lending_pool_setup_emissions(
 
 #[derive(Accounts)]
 pub struct LendingPoolSetupEmissions<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
@@ -154,6 +362,7 @@ pub fn lending_pool_update_emissions_parameters(
 ) -> MarginfiResult {
     let mut bank = ctx.accounts.bank.load_mut()?;
 
+    check!(!bank.get_flag(BANK_CLOSED_FLAG), MarginfiError::BankClosed);
     check!(
         bank.emissions_mint.ne(&Pubkey::default()),
         MarginfiError::EmissionsUpdateError
@@ -166,7 +375,7 @@ pub fn lending_pool_update_emissions_parameters(
 
     if let Some(flags) = emissions_flags {
         msg!("Updating emissions flags to {:#010b}", flags);
-        bank.flags = flags;
+        bank.flags = flags | (bank.flags & BANK_CONFIG_FROZEN_FLAG);
     }
 
     if let Some(rate) = emissions_rate {
@@ -212,6 +421,10 @@ pub fn lending_pool_update_emissions_parameters(
 
 #[derive(Accounts)]
 pub struct LendingPoolUpdateEmissionsParameters<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     #[account(
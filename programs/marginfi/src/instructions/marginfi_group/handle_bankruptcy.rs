@@ -1,8 +1,8 @@
 use crate::{
     bank_signer, check,
     constants::{
-        INSURANCE_VAULT_AUTHORITY_SEED, INSURANCE_VAULT_SEED, LIQUIDITY_VAULT_SEED,
-        PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG, ZERO_AMOUNT_THRESHOLD,
+        INSURANCE_VAULT_AUTHORITY_SEED, PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG,
+        ZERO_AMOUNT_THRESHOLD,
     },
     debug,
     events::{AccountEventHeader, LendingPoolBankHandleBankruptcyEvent},
@@ -63,20 +63,23 @@ pub fn lending_pool_handle_bankruptcy<'info>(
         bank_loader.key(),
     )?;
 
-    let lending_account_balance = marginfi_account
-        .lending_account
-        .balances
-        .iter_mut()
-        .find(|balance| balance.active && balance.bank_pk == bank_loader.key());
-
-    check!(
-        lending_account_balance.is_some(),
-        MarginfiError::LendingAccountBalanceNotFound
-    );
-
-    let lending_account_balance = lending_account_balance.unwrap();
-
-    let bad_debt = bank.get_liability_amount(lending_account_balance.liability_shares.into())?;
+    let referral_fee_bps = marginfi_group_loader.load()?.referral_fee_bps;
+
+    // The fee tier rebate on the group fees still to be shared comes off the bad debt
+    let bad_debt = {
+        let mut bank_account = BankAccountWrapper::find(
+            &bank_loader.key(),
+            &mut bank,
+            &mut marginfi_account,
+            referral_fee_bps,
+        )
+        .map_err(|_| MarginfiError::LendingAccountBalanceNotFound)?;
+        bank_account.settle_group_fees()?;
+
+        bank_account
+            .bank
+            .get_liability_amount(bank_account.balance.liability_shares.into())?
+    };
 
     check!(
         bad_debt > ZERO_AMOUNT_THRESHOLD,
@@ -149,7 +152,8 @@ pub fn lending_pool_handle_bankruptcy<'info>(
     BankAccountWrapper::find_or_create(
         &bank_loader.key(),
         &mut bank,
-        &mut marginfi_account.lending_account,
+        &mut marginfi_account,
+        referral_fee_bps,
     )?
     .repay(bad_debt)?;
 
@@ -174,6 +178,10 @@ pub fn lending_pool_handle_bankruptcy<'info>(
 
 #[derive(Accounts)]
 pub struct LendingPoolHandleBankruptcy<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
     pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
 
     // #[account(address = marginfi_group.load()?.admin)]
@@ -194,21 +202,13 @@ pub struct LendingPoolHandleBankruptcy<'info> {
     /// CHECK: Seed constraint
     #[account(
         mut,
-        seeds = [
-            LIQUIDITY_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.liquidity_vault_bump
+        address = bank.load()?.liquidity_vault,
     )]
     pub liquidity_vault: AccountInfo<'info>,
 
     #[account(
         mut,
-        seeds = [
-            INSURANCE_VAULT_SEED.as_bytes(),
-            bank.key().as_ref(),
-        ],
-        bump = bank.load()?.insurance_vault_bump
+        address = bank.load()?.insurance_vault,
     )]
     pub insurance_vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
@@ -2,10 +2,13 @@ use crate::events::{GroupEventHeader, MarginfiGroupCreateEvent};
 use crate::{state::marginfi_group::MarginfiGroup, MarginfiResult};
 use anchor_lang::prelude::*;
 
-pub fn initialize_group(ctx: Context<MarginfiGroupInitialize>) -> MarginfiResult {
+pub fn initialize_group(
+    ctx: Context<MarginfiGroupInitialize>,
+    pyth_program_id: Option<Pubkey>,
+) -> MarginfiResult {
     let marginfi_group = &mut ctx.accounts.marginfi_group.load_init()?;
 
-    marginfi_group.set_initial_configuration(ctx.accounts.admin.key());
+    marginfi_group.set_initial_configuration(ctx.accounts.admin.key(), pyth_program_id);
 
     emit!(MarginfiGroupCreateEvent {
         header: GroupEventHeader {
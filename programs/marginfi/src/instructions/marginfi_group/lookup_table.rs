@@ -0,0 +1,229 @@
+use crate::{
+    check,
+    constants::{BANK_CLOSED_FLAG, LIQUIDITY_VAULT_AUTHORITY_SEED, LOOKUP_TABLE_AUTHORITY_SEED},
+    prelude::*,
+    state::{marginfi_group::Bank, price::OracleSetup},
+};
+use anchor_lang::prelude::*;
+use solana_program::{
+    address_lookup_table::{
+        self,
+        instruction::{create_lookup_table, extend_lookup_table},
+        state::AddressLookupTable,
+    },
+    program::{invoke, invoke_signed},
+};
+
+/// Create an address lookup table for the group, owned by the group's lookup table authority
+/// PDA so only this program can extend it. Replaces the table previously set on the group.
+///
+/// `lookup_table` is the address derived from the authority and `recent_slot`.
+///
+/// Admin only
+pub fn create_lookup_table_for_group(
+    ctx: Context<MarginfiGroupCreateLookupTable>,
+    recent_slot: u64,
+) -> MarginfiResult {
+    let (ix, lookup_table) = create_lookup_table(
+        ctx.accounts.lookup_table_authority.key(),
+        ctx.accounts.fee_payer.key(),
+        recent_slot,
+    );
+
+    check!(
+        lookup_table == ctx.accounts.lookup_table.key(),
+        MarginfiError::InvalidLookupTable
+    );
+
+    invoke(
+        &ix,
+        &[
+            ctx.accounts.lookup_table.to_account_info(),
+            ctx.accounts.lookup_table_authority.to_account_info(),
+            ctx.accounts.fee_payer.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ],
+    )?;
+
+    ctx.accounts.marginfi_group.load_mut()?.lookup_table = lookup_table;
+
+    msg!("Created lookup table {}", lookup_table);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiGroupCreateLookupTable<'info> {
+    #[account(
+        mut,
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    /// CHECK: Created by the address lookup table program, checked against the derived address
+    #[account(mut)]
+    pub lookup_table: AccountInfo<'info>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            LOOKUP_TABLE_AUTHORITY_SEED.as_bytes(),
+            marginfi_group.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub lookup_table_authority: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    /// CHECK: Address lookup table program
+    #[account(address = address_lookup_table::program::ID)]
+    pub address_lookup_table_program: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Add the keys transactions of the banks in `ctx.remaining_accounts` use to the group's
+/// lookup table: the group, each bank with its mint, vaults, liquidity vault authority and
+/// oracles. Keys already in the table are skipped, closed banks are ignored.
+///
+/// A lookup table holds at most 256 keys.
+///
+/// Admin only
+pub fn extend_lookup_table_for_group<'info>(
+    ctx: Context<'_, '_, 'info, 'info, MarginfiGroupExtendLookupTable<'info>>,
+) -> MarginfiResult {
+    let marginfi_group_pk = ctx.accounts.marginfi_group.key();
+
+    let mut addresses = {
+        let data = ctx.accounts.lookup_table.try_borrow_data()?;
+        let lookup_table = AddressLookupTable::deserialize(&data)
+            .map_err(|_| MarginfiError::InvalidLookupTable)?;
+
+        lookup_table.addresses.to_vec()
+    };
+    let existing = addresses.len();
+
+    let mut add = |key: Pubkey| {
+        if key != Pubkey::default() && !addresses.contains(&key) {
+            addresses.push(key);
+        }
+    };
+
+    add(marginfi_group_pk);
+
+    for bank_ai in ctx.remaining_accounts {
+        let bank_loader = AccountLoader::<Bank>::try_from(bank_ai)?;
+        let bank = bank_loader.load()?;
+
+        check!(
+            bank.group == marginfi_group_pk,
+            MarginfiError::InvalidBankAccount
+        );
+
+        if bank.get_flag(BANK_CLOSED_FLAG) {
+            continue;
+        }
+
+        add(bank_ai.key());
+        add(bank.mint);
+        add(bank.liquidity_vault);
+        add(bank.insurance_vault);
+        add(bank.fee_vault);
+        add(Pubkey::create_program_address(
+            &[
+                LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+                bank_ai.key.as_ref(),
+                &[bank.liquidity_vault_authority_bump],
+            ],
+            ctx.program_id,
+        )
+        .map_err(|_| MarginfiError::InvalidBankAccount)?);
+
+        // Fixed price oracles store the price in the key, not an account
+        if !matches!(bank.config.oracle_setup, OracleSetup::Fixed) {
+            for oracle_key in bank.config.oracle_keys {
+                add(oracle_key);
+            }
+        }
+    }
+
+    let new_addresses = addresses.split_off(existing);
+
+    if new_addresses.is_empty() {
+        return Ok(());
+    }
+
+    msg!("Adding {} keys to the lookup table", new_addresses.len());
+
+    let lookup_table_authority_bump = ctx.bumps.lookup_table_authority;
+
+    invoke_signed(
+        &extend_lookup_table(
+            ctx.accounts.lookup_table.key(),
+            ctx.accounts.lookup_table_authority.key(),
+            Some(ctx.accounts.fee_payer.key()),
+            new_addresses,
+        ),
+        &[
+            ctx.accounts.lookup_table.to_account_info(),
+            ctx.accounts.lookup_table_authority.to_account_info(),
+            ctx.accounts.fee_payer.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ],
+        &[&[
+            LOOKUP_TABLE_AUTHORITY_SEED.as_bytes(),
+            marginfi_group_pk.as_ref(),
+            &[lookup_table_authority_bump],
+        ]],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiGroupExtendLookupTable<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    /// CHECK: The group's lookup table
+    #[account(
+        mut,
+        address = marginfi_group.load()?.lookup_table,
+    )]
+    pub lookup_table: AccountInfo<'info>,
+
+    /// CHECK: ⋐ ͡⋄ ω ͡⋄ ⋑
+    #[account(
+        seeds = [
+            LOOKUP_TABLE_AUTHORITY_SEED.as_bytes(),
+            marginfi_group.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub lookup_table_authority: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    /// CHECK: Address lookup table program
+    #[account(address = address_lookup_table::program::ID)]
+    pub address_lookup_table_program: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,34 @@
+use crate::events::{GroupEventHeader, MarginfiGroupMigrateEvent};
+use crate::{state::marginfi_group::MarginfiGroup, MarginfiResult};
+use anchor_lang::prelude::*;
+
+/// Migrate the group data model to the current program version
+///
+/// Admin only
+pub fn migrate_group(ctx: Context<MarginfiGroupMigrate>) -> MarginfiResult {
+    let mut marginfi_group = ctx.accounts.marginfi_group.load_mut()?;
+
+    let old_version = marginfi_group.migrate()?;
+
+    emit!(MarginfiGroupMigrateEvent {
+        header: GroupEventHeader {
+            marginfi_group: ctx.accounts.marginfi_group.key(),
+            signer: Some(*ctx.accounts.admin.key)
+        },
+        old_version,
+        new_version: marginfi_group.program_version,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiGroupMigrate<'info> {
+    #[account(mut)]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+}
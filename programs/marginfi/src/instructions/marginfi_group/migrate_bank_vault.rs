@@ -0,0 +1,141 @@
+use crate::{
+    bank_signer, check,
+    constants::BANK_CLOSED_FLAG,
+    events::{GroupEventHeader, LendingPoolBankMigrateVaultEvent},
+    prelude::MarginfiError,
+    state::marginfi_group::{Bank, BankVaultType, MarginfiGroup},
+    utils, MarginfiResult,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+
+/// Move a bank vault to a new token account, e.g. a Token-2022 ATA of the vault authority.
+///
+/// The new vault has to be created beforehand (usually in the same transaction) and be owned by
+/// the existing vault authority PDA. The full balance of the current vault is moved over and the
+/// bank is pointed at the new vault, the emptied old vault is left in place.
+///
+/// Admin only
+pub fn lending_pool_migrate_bank_vault<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingPoolMigrateBankVault<'info>>,
+    vault_type: BankVaultType,
+) -> MarginfiResult {
+    let LendingPoolMigrateBankVault {
+        vault_authority,
+        current_vault,
+        new_vault,
+        token_program,
+        ..
+    } = ctx.accounts;
+
+    let bank_pk = ctx.accounts.bank.key();
+    let mut bank = ctx.accounts.bank.load_mut()?;
+    let maybe_bank_mint =
+        utils::maybe_take_bank_mint(&mut ctx.remaining_accounts, &bank, token_program.key)?;
+
+    check!(!bank.get_flag(BANK_CLOSED_FLAG), MarginfiError::BankClosed);
+
+    let (vault, authority_bump) = bank.get_vault_and_authority_bump(vault_type);
+
+    check!(
+        current_vault.key() == vault,
+        MarginfiError::InvalidBankVault,
+        "Current vault doesn't match the bank"
+    );
+
+    let expected_authority = Pubkey::create_program_address(
+        &[
+            vault_type.get_authority_seed(),
+            bank_pk.as_ref(),
+            &[authority_bump],
+        ],
+        &crate::ID,
+    )
+    .map_err(|_| MarginfiError::InvalidBankVault)?;
+
+    check!(
+        vault_authority.key() == expected_authority,
+        MarginfiError::InvalidBankVault,
+        "Vault authority doesn't match the bank"
+    );
+
+    check!(
+        new_vault.key() != vault
+            && new_vault.mint == bank.mint
+            && new_vault.owner == expected_authority
+            && new_vault.delegate.is_none()
+            && new_vault.close_authority.is_none(),
+        MarginfiError::InvalidBankVault,
+        "New vault must be a plain token account of the vault authority"
+    );
+
+    let amount = current_vault.amount;
+    let new_vault_amount_before = new_vault.amount;
+
+    bank.withdraw_spl_transfer(
+        amount,
+        current_vault.to_account_info(),
+        new_vault.to_account_info(),
+        vault_authority.to_account_info(),
+        maybe_bank_mint.as_ref(),
+        token_program.to_account_info(),
+        bank_signer!(vault_type, bank_pk, authority_bump),
+        ctx.remaining_accounts,
+    )?;
+
+    // Token-2022 transfer fees would leave the vault short of what the bank accounts for
+    new_vault.reload()?;
+    check!(
+        new_vault.amount.checked_sub(new_vault_amount_before) == Some(amount),
+        MarginfiError::InvalidBankVault,
+        "New vault didn't receive the full vault balance"
+    );
+
+    bank.set_vault(vault_type, new_vault.key());
+
+    emit!(LendingPoolBankMigrateVaultEvent {
+        header: GroupEventHeader {
+            marginfi_group: ctx.accounts.marginfi_group.key(),
+            signer: Some(*ctx.accounts.admin.key)
+        },
+        bank: bank_pk,
+        mint: bank.mint,
+        vault_type,
+        old_vault: vault,
+        new_vault: new_vault.key(),
+        amount,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolMigrateBankVault<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        address = marginfi_group.load()?.admin,
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    /// CHECK: Checked against the bank's stored authority bump
+    pub vault_authority: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub current_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub new_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -0,0 +1,63 @@
+use crate::{
+    constants::PROGRAM_VERSION,
+    state::{marginfi_group::WrappedI80F48, price::MockOracle},
+    MarginfiError, MarginfiResult,
+};
+use anchor_lang::prelude::*;
+
+/// Create a mock oracle owned by the signer, banks can use it with `OracleSetup::Mock`.
+pub fn mock_oracle_initialize(
+    ctx: Context<MockOracleInitialize>,
+    price: WrappedI80F48,
+    confidence: WrappedI80F48,
+) -> MarginfiResult {
+    let mut mock_oracle = ctx.accounts.mock_oracle.load_init()?;
+
+    mock_oracle.authority = ctx.accounts.authority.key();
+    mock_oracle.program_version = PROGRAM_VERSION;
+    mock_oracle.set_price(price.into(), confidence.into(), &Clock::get()?)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MockOracleInitialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MockOracle>(),
+    )]
+    pub mock_oracle: AccountLoader<'info, MockOracle>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Update the price of a mock oracle
+///
+/// Mock oracle authority only
+pub fn mock_oracle_set_price(
+    ctx: Context<MockOracleSetPrice>,
+    price: WrappedI80F48,
+    confidence: WrappedI80F48,
+) -> MarginfiResult {
+    ctx.accounts
+        .mock_oracle
+        .load_mut()?
+        .set_price(price.into(), confidence.into(), &Clock::get()?)
+}
+
+#[derive(Accounts)]
+pub struct MockOracleSetPrice<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        constraint = mock_oracle.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub mock_oracle: AccountLoader<'info, MockOracle>,
+
+    pub authority: Signer<'info>,
+}
@@ -1,15 +1,31 @@
 mod accrue_bank_interest;
 mod add_pool;
+mod bank_registry;
+mod close_bank;
 mod collect_bank_fees;
 mod configure;
 mod configure_bank;
 mod handle_bankruptcy;
 mod initialize;
+mod lookup_table;
+mod migrate;
+mod migrate_bank_vault;
+#[cfg(feature = "mock-oracle")]
+mod mock_oracle;
+mod oracle_circuit_breaker;
 
 pub use accrue_bank_interest::*;
 pub use add_pool::*;
+pub use bank_registry::*;
+pub use close_bank::*;
 pub use collect_bank_fees::*;
 pub use configure::*;
 pub use configure_bank::*;
 pub use handle_bankruptcy::*;
 pub use initialize::*;
+pub use lookup_table::*;
+pub use migrate::*;
+pub use migrate_bank_vault::*;
+#[cfg(feature = "mock-oracle")]
+pub use mock_oracle::*;
+pub use oracle_circuit_breaker::*;
@@ -0,0 +1,74 @@
+use crate::{
+    events::{GroupEventHeader, LendingPoolBankOracleCircuitBreakerEvent},
+    prelude::MarginfiError,
+    state::{
+        marginfi_group::{Bank, MarginfiGroup},
+        price::OraclePriceFeedAdapter,
+    },
+    MarginfiResult,
+};
+use anchor_lang::prelude::*;
+
+/// Oracle circuit breaker, permissionless.
+///
+/// 1. Pauses an operational bank when none of its oracles give a usable price, so users get
+///    `BankPaused` instead of oracle errors, and resumes it once a price is back.
+/// 2. Blocks deposits and borrows while the primary and fallback oracle diverge by more than
+///    `oracle_max_deviation_bps`, repays and withdrawals stay open.
+/// 3. Records the oracle price for banks with a TWAP, see [`Bank::record_twap_price`].
+///
+/// Failed transactions can't persist state, so this runs as a crank instead of inside health checks.
+///
+/// `ctx.remaining_accounts` holds the bank's oracles, see [`OraclePriceFeedAdapter::get_oracle_prices`].
+pub fn lending_pool_check_bank_oracle(ctx: Context<LendingPoolCheckBankOracle>) -> MarginfiResult {
+    let clock = Clock::get()?;
+    let mut bank = ctx.accounts.bank.load_mut()?;
+
+    let prices =
+        OraclePriceFeedAdapter::get_oracle_prices(&bank.config, ctx.remaining_accounts, &clock)?;
+
+    let oracle_down = prices.iter().all(Option::is_none);
+    let deviation_exceeded = match prices[..] {
+        [Some(primary), Some(fallback)] => bank
+            .config
+            .exceeds_oracle_max_deviation(primary, fallback)?,
+        _ => false,
+    };
+
+    if let Some(price) = prices.iter().flatten().next() {
+        bank.record_twap_price(*price, clock.unix_timestamp);
+    }
+
+    let paused_changed = bank.update_oracle_paused(oracle_down, clock.unix_timestamp);
+    let deviation_changed = bank.update_oracle_deviation_exceeded(deviation_exceeded);
+
+    if paused_changed || deviation_changed {
+        emit!(LendingPoolBankOracleCircuitBreakerEvent {
+            header: GroupEventHeader {
+                marginfi_group: ctx.accounts.marginfi_group.key(),
+                signer: None,
+            },
+            bank: ctx.accounts.bank.key(),
+            mint: bank.mint,
+            paused: oracle_down,
+            deviation_exceeded,
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolCheckBankOracle<'info> {
+    #[account(
+        constraint = marginfi_group.load()?.check_program_version().is_ok()
+            @ MarginfiError::IncompatibleProgramVersion,
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        constraint = bank.load()?.group == marginfi_group.key(),
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
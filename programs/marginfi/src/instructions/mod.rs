@@ -1,5 +1,7 @@
+pub mod fee_state;
 pub mod marginfi_account;
 pub mod marginfi_group;
 
+pub use fee_state::*;
 pub use marginfi_account::*;
 pub use marginfi_group::*;
@@ -10,7 +10,10 @@ pub mod utils;
 use anchor_lang::prelude::*;
 use instructions::*;
 use prelude::*;
-use state::marginfi_group::{BankConfigCompact, BankConfigOpt};
+use state::marginfi_group::{
+    BankConfigCompact, BankConfigOpt, BankOperationalState, BankVaultType, WrappedI80F48,
+};
+use state::price::OracleSetup;
 
 declare_id!("MFv2hWf31Z9kbCa1snEPYctwafyhdvnV7FZnsebVacA");
 
@@ -18,8 +21,32 @@ declare_id!("MFv2hWf31Z9kbCa1snEPYctwafyhdvnV7FZnsebVacA");
 pub mod marginfi {
     use super::*;
 
-    pub fn marginfi_group_initialize(ctx: Context<MarginfiGroupInitialize>) -> MarginfiResult {
-        marginfi_group::initialize_group(ctx)
+    /// Create the program wide fee state, signed by the program's upgrade authority
+    pub fn init_global_fee_state(
+        ctx: Context<InitFeeState>,
+        global_fee_admin: Pubkey,
+        global_fee_wallet: Pubkey,
+        program_fee_bps: u16,
+    ) -> MarginfiResult {
+        fee_state::initialize_fee_state(ctx, global_fee_admin, global_fee_wallet, program_fee_bps)
+    }
+
+    pub fn configure_global_fee_state(
+        ctx: Context<ConfigureFeeState>,
+        global_fee_admin: Option<Pubkey>,
+        global_fee_wallet: Option<Pubkey>,
+        program_fee_bps: Option<u16>,
+    ) -> MarginfiResult {
+        fee_state::configure_fee_state(ctx, global_fee_admin, global_fee_wallet, program_fee_bps)
+    }
+
+    /// `pyth_program_id` overrides the cluster's default Pyth program for legacy oracles,
+    /// e.g. on localnet deployments.
+    pub fn marginfi_group_initialize(
+        ctx: Context<MarginfiGroupInitialize>,
+        pyth_program_id: Option<Pubkey>,
+    ) -> MarginfiResult {
+        marginfi_group::initialize_group(ctx, pyth_program_id)
     }
 
     pub fn marginfi_group_configure(
@@ -29,6 +56,39 @@ pub mod marginfi {
         marginfi_group::configure(ctx, config)
     }
 
+    pub fn marginfi_group_migrate(ctx: Context<MarginfiGroupMigrate>) -> MarginfiResult {
+        marginfi_group::migrate_group(ctx)
+    }
+
+    pub fn marginfi_group_init_bank_registry(
+        ctx: Context<MarginfiGroupInitBankRegistry>,
+    ) -> MarginfiResult {
+        marginfi_group::initialize_bank_registry(ctx)
+    }
+
+    /// Create an address lookup table for the group's accounts, owned by a program PDA
+    pub fn marginfi_group_create_lookup_table(
+        ctx: Context<MarginfiGroupCreateLookupTable>,
+        recent_slot: u64,
+    ) -> MarginfiResult {
+        marginfi_group::create_lookup_table_for_group(ctx, recent_slot)
+    }
+
+    /// Add the bank, vault and oracle keys of the banks passed as remaining accounts
+    /// to the group's lookup table
+    pub fn marginfi_group_extend_lookup_table<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MarginfiGroupExtendLookupTable<'info>>,
+    ) -> MarginfiResult {
+        marginfi_group::extend_lookup_table_for_group(ctx)
+    }
+
+    /// Add banks created before the group's bank registry to it
+    pub fn lending_pool_register_banks<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingPoolRegisterBanks<'info>>,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_register_banks(ctx)
+    }
+
     pub fn lending_pool_add_bank(
         ctx: Context<LendingPoolAddBank>,
         bank_config: BankConfigCompact,
@@ -47,6 +107,28 @@ pub mod marginfi {
         marginfi_group::lending_pool_add_bank_with_seed(ctx, bank_config.into(), bank_seed)
     }
 
+    /// Add an isolated bank with a conservative config for a mint whitelisted by the group,
+    /// without the admin. The signer pays the group's creation fee.
+    pub fn lending_pool_add_bank_permissionless(
+        ctx: Context<LendingPoolAddBankPermissionless>,
+        oracle_setup: OracleSetup,
+        oracle_key: Pubkey,
+        bank_seed: u64,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_add_bank_permissionless(
+            ctx,
+            oracle_setup,
+            oracle_key,
+            bank_seed,
+        )
+    }
+
+    pub fn lending_pool_close_bank<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingPoolCloseBank<'info>>,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_close_bank(ctx)
+    }
+
     pub fn lending_pool_configure_bank(
         ctx: Context<LendingPoolConfigureBank>,
         bank_config_opt: BankConfigOpt,
@@ -54,6 +136,37 @@ pub mod marginfi {
         marginfi_group::lending_pool_configure_bank(ctx, bank_config_opt)
     }
 
+    /// Apply an oracle change proposed through `lending_pool_configure_bank` after the rotation delay
+    pub fn lending_pool_accept_bank_oracle(
+        ctx: Context<LendingPoolAcceptBankOracle>,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_accept_bank_oracle(ctx)
+    }
+
+    /// Pause, resume or set a bank to reduce only, the risk council can call this as well
+    pub fn lending_pool_set_bank_operational_state(
+        ctx: Context<LendingPoolSetBankOperationalState>,
+        operational_state: BankOperationalState,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_set_bank_operational_state(ctx, operational_state)
+    }
+
+    /// Terminal state for a compromised bank, balances are frozen at `settlement_price`
+    /// and can only be settled afterwards.
+    pub fn lending_pool_kill_bank(
+        ctx: Context<LendingPoolKillBank>,
+        settlement_price: WrappedI80F48,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_kill_bank(ctx, settlement_price)
+    }
+
+    pub fn lending_pool_migrate_bank_vault<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingPoolMigrateBankVault<'info>>,
+        vault_type: BankVaultType,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_migrate_bank_vault(ctx, vault_type)
+    }
+
     pub fn lending_pool_setup_emissions(
         ctx: Context<LendingPoolSetupEmissions>,
         flags: u64,
@@ -87,15 +200,33 @@ pub mod marginfi {
     // User instructions
 
     /// Initialize a marginfi account for a given group
-    pub fn marginfi_account_initialize(ctx: Context<MarginfiAccountInitialize>) -> MarginfiResult {
-        marginfi_account::initialize_account(ctx)
+    pub fn marginfi_account_initialize(
+        ctx: Context<MarginfiAccountInitialize>,
+        integrator_tag: Option<[u8; 32]>,
+        referrer: Option<Pubkey>,
+    ) -> MarginfiResult {
+        marginfi_account::initialize_account(ctx, integrator_tag, referrer)
+    }
+
+    pub fn marginfi_account_init_health_snapshot(
+        ctx: Context<MarginfiAccountInitHealthSnapshot>,
+    ) -> MarginfiResult {
+        marginfi_account::initialize_health_snapshot(ctx)
+    }
+
+    /// Permissionless crank refreshing the health snapshot of a marginfi account
+    pub fn marginfi_account_refresh_health_snapshot<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MarginfiAccountRefreshHealthSnapshot<'info>>,
+    ) -> MarginfiResult {
+        marginfi_account::refresh_health_snapshot(ctx)
     }
 
     pub fn lending_account_deposit<'info>(
         ctx: Context<'_, '_, 'info, 'info, LendingAccountDeposit<'info>>,
         amount: u64,
+        min_shares_out: Option<WrappedI80F48>,
     ) -> MarginfiResult {
-        marginfi_account::lending_account_deposit(ctx, amount)
+        marginfi_account::lending_account_deposit(ctx, amount, min_shares_out)
     }
 
     pub fn lending_account_repay<'info>(
@@ -106,19 +237,37 @@ pub mod marginfi {
         marginfi_account::lending_account_repay(ctx, amount, repay_all)
     }
 
+    /// Settle a balance in a killed bank: repay a liability in full or take the pro-rata
+    /// share of the liquidity vault for a deposit.
+    pub fn lending_account_settle_killed_balance<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountSettleKilledBalance<'info>>,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_settle_killed_balance(ctx)
+    }
+
     pub fn lending_account_withdraw<'info>(
         ctx: Context<'_, '_, 'info, 'info, LendingAccountWithdraw<'info>>,
         amount: u64,
         withdraw_all: Option<bool>,
+        max_shares_in: Option<WrappedI80F48>,
     ) -> MarginfiResult {
-        marginfi_account::lending_account_withdraw(ctx, amount, withdraw_all)
+        marginfi_account::lending_account_withdraw(ctx, amount, withdraw_all, max_shares_in)
     }
 
     pub fn lending_account_borrow<'info>(
         ctx: Context<'_, '_, 'info, 'info, LendingAccountBorrow<'info>>,
         amount: u64,
+        max_liability_shares: Option<WrappedI80F48>,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_borrow(ctx, amount, max_liability_shares)
+    }
+
+    pub fn lending_account_refinance<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountRefinance<'info>>,
+        amount: u64,
+        repay_all: Option<bool>,
     ) -> MarginfiResult {
-        marginfi_account::lending_account_borrow(ctx, amount)
+        marginfi_account::lending_account_refinance(ctx, amount, repay_all)
     }
 
     pub fn lending_account_close_balance(
@@ -127,6 +276,20 @@ pub mod marginfi {
         marginfi_account::lending_account_close_balance(ctx)
     }
 
+    pub fn lending_account_set_insured(
+        ctx: Context<LendingAccountSetInsured>,
+        insured: bool,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_set_insured(ctx, insured)
+    }
+
+    pub fn lending_account_lock_deposit(
+        ctx: Context<LendingAccountLockDeposit>,
+        lock_days: u16,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_lock_deposit(ctx, lock_days)
+    }
+
     pub fn lending_account_withdraw_emissions<'info>(
         ctx: Context<'_, '_, 'info, 'info, LendingAccountWithdrawEmissions<'info>>,
     ) -> MarginfiResult {
@@ -139,6 +302,13 @@ pub mod marginfi {
         marginfi_account::lending_account_settle_emissions(ctx)
     }
 
+    /// Transfer the referral fees a marginfi account owes its referrer in a bank
+    pub fn lending_account_claim_referral_fees<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountClaimReferralFees<'info>>,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_claim_referral_fees(ctx)
+    }
+
     /// Liquidate a lending account balance of an unhealthy marginfi account
     pub fn lending_account_liquidate<'info>(
         ctx: Context<'_, '_, 'info, 'info, LendingAccountLiquidate<'info>>,
@@ -147,6 +317,15 @@ pub mod marginfi {
         marginfi_account::lending_account_liquidate(ctx, asset_amount)
     }
 
+    /// Liquidate a lending account balance of an unhealthy marginfi account,
+    /// settling with the liquidator's token accounts instead of a marginfi account
+    pub fn lending_account_liquidate_to_token_account<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountLiquidateToTokenAccount<'info>>,
+        asset_amount: u64,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_liquidate_to_token_account(ctx, asset_amount)
+    }
+
     pub fn lending_account_start_flashloan(
         ctx: Context<LendingAccountStartFlashloan>,
         end_index: u64,
@@ -161,12 +340,32 @@ pub mod marginfi {
     }
 
     // Operational instructions
-    pub fn lending_pool_accrue_bank_interest(
-        ctx: Context<LendingPoolAccrueBankInterest>,
+    pub fn lending_pool_accrue_bank_interest<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingPoolAccrueBankInterest<'info>>,
     ) -> MarginfiResult {
         marginfi_group::lending_pool_accrue_bank_interest(ctx)
     }
 
+    /// Accrue interest on all banks passed as writable remaining accounts.
+    pub fn lending_pool_accrue_interest_many<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingPoolAccrueInterestMany<'info>>,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_accrue_interest_many(ctx)
+    }
+
+    /// Accrue interest and pay the caller the bank's accrual bounty if interest is overdue.
+    pub fn lending_pool_crank_bank_interest<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingPoolCrankBankInterest<'info>>,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_crank_bank_interest(ctx)
+    }
+
+    pub fn lending_pool_check_bank_oracle(
+        ctx: Context<LendingPoolCheckBankOracle>,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_check_bank_oracle(ctx)
+    }
+
     pub fn lending_pool_collect_bank_fees<'info>(
         ctx: Context<'_, '_, 'info, 'info, LendingPoolCollectBankFees<'info>>,
     ) -> MarginfiResult {
@@ -180,6 +379,14 @@ pub mod marginfi {
         marginfi_group::lending_pool_withdraw_fees(ctx, amount)
     }
 
+    /// Move fee vault tokens into the lending subsidy reserve of a bank with a lending rate floor.
+    pub fn lending_pool_fund_lending_subsidy<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingPoolFundLendingSubsidy<'info>>,
+        amount: u64,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_fund_lending_subsidy(ctx, amount)
+    }
+
     pub fn lending_pool_withdraw_insurance<'info>(
         ctx: Context<'_, '_, 'info, 'info, LendingPoolWithdrawInsurance<'info>>,
         amount: u64,
@@ -195,6 +402,20 @@ pub mod marginfi {
         marginfi_group::unset_account_flag(ctx, flag)
     }
 
+    pub fn set_account_equity_maintenance(
+        ctx: Context<SetAccountEquityMaintenance>,
+        buffer: Option<WrappedI80F48>,
+    ) -> MarginfiResult {
+        marginfi_group::set_account_equity_maintenance(ctx, buffer)
+    }
+
+    pub fn set_account_fee_tier(
+        ctx: Context<SetAccountFeeTier>,
+        discount_bps: u16,
+    ) -> MarginfiResult {
+        marginfi_group::set_account_fee_tier(ctx, discount_bps)
+    }
+
     pub fn set_new_account_authority(
         ctx: Context<MarginfiAccountSetAccountAuthority>,
     ) -> MarginfiResult {
@@ -204,6 +425,25 @@ pub mod marginfi {
     pub fn marginfi_account_close(ctx: Context<MarginfiAccountClose>) -> MarginfiResult {
         marginfi_account::close_account(ctx)
     }
+
+    // Test oracle instructions
+    #[cfg(feature = "mock-oracle")]
+    pub fn mock_oracle_initialize(
+        ctx: Context<MockOracleInitialize>,
+        price: WrappedI80F48,
+        confidence: WrappedI80F48,
+    ) -> MarginfiResult {
+        marginfi_group::mock_oracle_initialize(ctx, price, confidence)
+    }
+
+    #[cfg(feature = "mock-oracle")]
+    pub fn mock_oracle_set_price(
+        ctx: Context<MockOracleSetPrice>,
+        price: WrappedI80F48,
+        confidence: WrappedI80F48,
+    ) -> MarginfiResult {
+        marginfi_group::mock_oracle_set_price(ctx, price, confidence)
+    }
 }
 
 #[cfg(not(feature = "no-entrypoint"))]
@@ -0,0 +1,149 @@
+use super::marginfi_group::MarginfiGroup;
+use crate::{
+    assert_struct_align, assert_struct_size, check,
+    constants::MAX_REGISTRY_BANKS,
+    prelude::{MarginfiError, MarginfiResult},
+};
+use anchor_lang::prelude::*;
+
+#[cfg(any(feature = "test", feature = "client"))]
+use type_layout::TypeLayout;
+
+assert_struct_size!(BankRegistry, 8264);
+assert_struct_align!(BankRegistry, 8);
+/// Banks of a marginfi group, kept in a PDA (see [`crate::constants::BANK_REGISTRY_SEED`])
+/// so clients and other programs can enumerate them on-chain.
+///
+/// Once a group has a registry every bank added or closed has to update it,
+/// see [`MarginfiGroup::has_bank_registry`].
+#[account(zero_copy)]
+#[cfg_attr(
+    any(feature = "test", feature = "client"),
+    derive(Debug, PartialEq, Eq, TypeLayout)
+)]
+pub struct BankRegistry {
+    pub group: Pubkey,
+    pub bank_count: u16,
+    pub bump: u8,
+    pub _pad0: [u8; 5],
+    /// The first `bank_count` entries are the group's banks, in no particular order.
+    pub banks: [Pubkey; MAX_REGISTRY_BANKS],
+    pub _padding: [u64; 4],
+}
+
+impl BankRegistry {
+    pub fn get_banks(&self) -> &[Pubkey] {
+        &self.banks[..self.bank_count as usize]
+    }
+
+    pub fn add(&mut self, bank: Pubkey) -> MarginfiResult {
+        check!(
+            !self.get_banks().contains(&bank),
+            MarginfiError::InvalidConfig,
+            "Bank already registered"
+        );
+        check!(
+            (self.bank_count as usize) < MAX_REGISTRY_BANKS,
+            MarginfiError::BankRegistryFull
+        );
+
+        self.banks[self.bank_count as usize] = bank;
+        self.bank_count += 1;
+
+        Ok(())
+    }
+
+    /// Swap-remove `bank`, doing nothing if it was never registered.
+    pub fn remove(&mut self, bank: &Pubkey) {
+        if let Some(index) = self.get_banks().iter().position(|b| b == bank) {
+            let last = self.bank_count as usize - 1;
+
+            self.banks[index] = self.banks[last];
+            self.banks[last] = Pubkey::default();
+            self.bank_count -= 1;
+        }
+    }
+
+    /// Record a bank added to `group`, the registry is required once the group has one.
+    pub fn register(
+        group: &MarginfiGroup,
+        registry: Option<&AccountLoader<BankRegistry>>,
+        bank: Pubkey,
+    ) -> MarginfiResult {
+        match registry {
+            Some(registry) => registry.load_mut()?.add(bank),
+            None => {
+                check!(
+                    !group.has_bank_registry(),
+                    MarginfiError::BankRegistryMissing
+                );
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Forget a bank closed in `group`, the registry is required once the group has one.
+    pub fn unregister(
+        group: &MarginfiGroup,
+        registry: Option<&AccountLoader<BankRegistry>>,
+        bank: &Pubkey,
+    ) -> MarginfiResult {
+        match registry {
+            Some(registry) => {
+                registry.load_mut()?.remove(bank);
+
+                Ok(())
+            }
+            None => {
+                check!(
+                    !group.has_bank_registry(),
+                    MarginfiError::BankRegistryMissing
+                );
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bank_registry_add_remove() {
+        let mut registry = BankRegistry {
+            group: Pubkey::new_unique(),
+            bank_count: 0,
+            bump: 0,
+            _pad0: [0; 5],
+            banks: [Pubkey::default(); MAX_REGISTRY_BANKS],
+            _padding: [0; 4],
+        };
+        let banks = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+
+        for bank in banks {
+            registry.add(bank).unwrap();
+        }
+        assert_eq!(registry.get_banks(), &banks);
+        assert!(registry.add(banks[1]).is_err());
+
+        registry.remove(&banks[0]);
+        assert_eq!(registry.get_banks(), &[banks[2], banks[1]]);
+        assert_eq!(registry.banks[2], Pubkey::default());
+
+        // Unknown banks are ignored
+        registry.remove(&Pubkey::new_unique());
+        assert_eq!(registry.bank_count, 2);
+
+        for _ in 2..MAX_REGISTRY_BANKS {
+            registry.add(Pubkey::new_unique()).unwrap();
+        }
+        assert!(registry.add(Pubkey::new_unique()).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    assert_struct_align, assert_struct_size, check,
+    constants::WEIGHT_BPS_DENOMINATOR,
+    math_error,
+    prelude::{MarginfiError, MarginfiResult},
+    set_if_some,
+    state::marginfi_group::check_program_version,
+};
+use anchor_lang::prelude::*;
+use fixed::types::I80F48;
+
+#[cfg(any(feature = "test", feature = "client"))]
+use type_layout::TypeLayout;
+
+assert_struct_size!(FeeState, 168);
+assert_struct_align!(FeeState, 8);
+/// Program wide fee settings, kept in a singleton PDA (see [`crate::constants::FEE_STATE_SEED`]).
+///
+/// Groups are run by third parties, the program fee is skimmed from the group fees of every
+/// bank when they are collected and can only be changed by `global_fee_admin`.
+#[account(zero_copy)]
+#[cfg_attr(
+    any(feature = "test", feature = "client"),
+    derive(Debug, PartialEq, Eq, TypeLayout)
+)]
+#[derive(Default)]
+pub struct FeeState {
+    /// Key that may change the fee state, distinct from any group admin.
+    pub global_fee_admin: Pubkey,
+    /// Owner of the token accounts program fees are paid to.
+    pub global_fee_wallet: Pubkey,
+    /// Share in bps of collected group fees that goes to `global_fee_wallet`.
+    pub program_fee_bps: u16,
+    pub bump: u8,
+    pub _pad0: [u8; 5],
+    /// Version of the data model the fee state was created with, see
+    /// [`crate::constants::PROGRAM_VERSION`].
+    pub program_version: u64,
+    pub _padding: [u64; 11],
+}
+
+impl FeeState {
+    pub fn check_program_version(&self) -> MarginfiResult {
+        check_program_version(self.program_version)
+    }
+
+    /// Set the fee state parameters, validating the program fee.
+    pub fn configure(
+        &mut self,
+        global_fee_admin: Option<Pubkey>,
+        global_fee_wallet: Option<Pubkey>,
+        program_fee_bps: Option<u16>,
+    ) -> MarginfiResult {
+        set_if_some!(self.global_fee_admin, global_fee_admin);
+
+        set_if_some!(self.global_fee_wallet, global_fee_wallet);
+
+        if let Some(program_fee_bps) = program_fee_bps {
+            check!(
+                program_fee_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+                MarginfiError::InvalidConfig
+            );
+
+            self.program_fee_bps = program_fee_bps;
+        }
+
+        Ok(())
+    }
+
+    /// Program fee part of `group_fees`, rounded down to whole tokens.
+    pub fn calc_program_fees(&self, group_fees: I80F48) -> MarginfiResult<I80F48> {
+        Ok(group_fees
+            .checked_mul(I80F48::from_num(self.program_fee_bps))
+            .ok_or_else(math_error!())?
+            .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+            .ok_or_else(math_error!())?
+            .checked_floor()
+            .ok_or_else(math_error!())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixed_macro::types::I80F48;
+
+    #[test]
+    fn fee_state_program_fees() {
+        let mut fee_state = FeeState::default();
+        assert_eq!(
+            fee_state.calc_program_fees(I80F48!(1_000)).unwrap(),
+            I80F48::ZERO
+        );
+
+        assert!(fee_state.configure(None, None, Some(10_001)).is_err());
+
+        fee_state.configure(None, None, Some(1_250)).unwrap();
+        assert_eq!(
+            fee_state.calc_program_fees(I80F48!(1_000)).unwrap(),
+            I80F48!(125)
+        );
+        assert_eq!(
+            fee_state.calc_program_fees(I80F48!(1_001)).unwrap(),
+            I80F48!(125)
+        );
+    }
+}
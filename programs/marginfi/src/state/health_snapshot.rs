@@ -0,0 +1,86 @@
+use super::marginfi_group::WrappedI80F48;
+use crate::{
+    assert_struct_align, assert_struct_size, check, math_error,
+    prelude::{MarginfiError, MarginfiResult},
+};
+use anchor_lang::prelude::*;
+use fixed::types::I80F48;
+
+#[cfg(any(feature = "test", feature = "client"))]
+use type_layout::TypeLayout;
+
+assert_struct_size!(HealthSnapshot, 208);
+assert_struct_align!(HealthSnapshot, 8);
+/// Health of a marginfi account as last computed by the risk engine, kept in a PDA
+/// (see [`crate::constants::HEALTH_SNAPSHOT_SEED`]) that anyone can refresh with current oracles.
+///
+/// External protocols can read a recent, program verified health figure from it
+/// instead of supplying every bank and oracle of the account themselves.
+#[account(zero_copy)]
+#[cfg_attr(
+    any(feature = "test", feature = "client"),
+    derive(Debug, PartialEq, Eq, TypeLayout)
+)]
+#[derive(Default)]
+pub struct HealthSnapshot {
+    pub marginfi_account: Pubkey,
+    /// Weighted asset and liability values with the initial requirement,
+    /// stale oracles count their assets as zero.
+    pub asset_value_init: WrappedI80F48,
+    pub liability_value_init: WrappedI80F48,
+    /// Weighted asset and liability values with the maintenance requirement.
+    pub asset_value_maint: WrappedI80F48,
+    pub liability_value_maint: WrappedI80F48,
+    pub last_update_slot: u64,
+    pub last_update: i64,
+    pub bump: u8,
+    pub _pad0: [u8; 7],
+    pub _padding: [u64; 11],
+}
+
+impl HealthSnapshot {
+    /// Maintenance health of the account, failing if the snapshot is older than `max_age_slots`.
+    /// The account is liquidatable once this is negative.
+    pub fn get_maintenance_health(
+        &self,
+        current_slot: u64,
+        max_age_slots: u64,
+    ) -> MarginfiResult<I80F48> {
+        check!(
+            self.last_update_slot != 0
+                && current_slot.saturating_sub(self.last_update_slot) <= max_age_slots,
+            MarginfiError::StaleHealthSnapshot
+        );
+
+        Ok(I80F48::from(self.asset_value_maint)
+            .checked_sub(self.liability_value_maint.into())
+            .ok_or_else(math_error!())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixed_macro::types::I80F48;
+
+    #[test]
+    fn health_snapshot_max_age() {
+        let snapshot = HealthSnapshot {
+            asset_value_maint: I80F48!(150).into(),
+            liability_value_maint: I80F48!(100).into(),
+            last_update_slot: 1_000,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            snapshot.get_maintenance_health(1_010, 10).unwrap(),
+            I80F48!(50)
+        );
+        assert!(snapshot.get_maintenance_health(1_011, 10).is_err());
+
+        // Never refreshed
+        assert!(HealthSnapshot::default()
+            .get_maintenance_health(0, 10)
+            .is_err());
+    }
+}
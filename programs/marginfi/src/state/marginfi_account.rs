@@ -1,13 +1,14 @@
 use super::{
-    marginfi_group::{Bank, RiskTier, WrappedI80F48},
-    price::{OraclePriceFeedAdapter, OraclePriceType, PriceAdapter, PriceBias},
+    marginfi_group::{Bank, MarginfiGroup, RiskTier, WrappedI80F48},
+    price::{scale_by_exponent, OraclePriceFeedAdapter, OraclePriceType, PriceAdapter, PriceBias},
 };
 use crate::{
     assert_struct_align, assert_struct_size, check,
     constants::{
         BANKRUPT_THRESHOLD, EMISSIONS_FLAG_BORROW_ACTIVE, EMISSIONS_FLAG_LENDING_ACTIVE,
-        EMPTY_BALANCE_THRESHOLD, EXP_10_I80F48, MIN_EMISSIONS_START_TIME, SECONDS_PER_YEAR,
-        ZERO_AMOUNT_THRESHOLD,
+        EMPTY_BALANCE_THRESHOLD, MAX_CORRELATION_BUCKETS, MAX_ORACLE_KEYS, MAX_REFERRAL_FEE_BANKS,
+        MIN_EMISSIONS_START_TIME, MIN_INSURED_DURATION, SECONDS_PER_DAY, SECONDS_PER_YEAR,
+        WEIGHT_BPS_DENOMINATOR, ZERO_AMOUNT_THRESHOLD,
     },
     debug, math_error,
     prelude::{MarginfiError, MarginfiResult},
@@ -19,6 +20,7 @@ use fixed::types::I80F48;
 use std::{
     cmp::{max, min},
     ops::Not,
+    rc::Rc,
 };
 #[cfg(any(feature = "test", feature = "client"))]
 use type_layout::TypeLayout;
@@ -41,29 +43,131 @@ pub struct MarginfiAccount {
     /// Flags:
     /// - DISABLED_FLAG = 1 << 0 = 1 - This flag indicates that the account is disabled,
     /// and no further actions can be taken on it.
+    /// - IN_FLASHLOAN_FLAG = 1 << 1 = 2 - The account is in the middle of a multi-instruction
+    /// operation (flashloan, liquidate-and-swap) whose health is only checked at the end.
+    /// Actions that could bypass or interleave with that final check are rejected.
+    /// - FLASHLOAN_ENABLED_FLAG = 1 << 2 = 4 - Deprecated, any account can flashloan.
+    /// - TRANSFER_AUTHORITY_ALLOWED_FLAG = 1 << 3 = 8 - The account authority can be transferred.
+    /// - EQUITY_MAINTENANCE_FLAG = 1 << 4 = 16 - Maintenance is evaluated on net equity against
+    /// `equity_maintenance_buffer` instead of weighted assets vs weighted liabilities.
     pub account_flags: u64, // 8
-    pub _padding: [u64; 63],             // 504
+    /// USD value the account equity must stay above when `EQUITY_MAINTENANCE_FLAG` is set.
+    pub equity_maintenance_buffer: WrappedI80F48, // 16
+    /// Label set at creation by the integrator that opened the account (frontend id, strategy),
+    /// all zero if untagged. Starts at byte 1824 of the account data for gPA memcmp filters.
+    pub integrator_tag: [u8; 32], // 32
+    /// Receives the group's `referral_fee_bps` of the group fees generated by this account's
+    /// borrows, set at creation and default if the account wasn't referred.
+    /// See [`BankAccountWrapper::settle_group_fees`].
+    pub referrer: Pubkey, // 32
+    /// Referral fees owed to `referrer`, claimed through `lending_account_claim_referral_fees`.
+    pub referral_fees: [ReferralFeeBalance; MAX_REFERRAL_FEE_BANKS], // 48 * 4 = 192
+    /// Discount in bps on the group fees charged on this account's borrows, set by the group
+    /// admin. See [`BankAccountWrapper::settle_group_fees`].
+    pub fee_tier_discount_bps: u16, // 2
+    pub _pad0: [u8; 6],                  // 6
+    pub _padding: [u64; 28],             // 224
+}
+
+/// Referral fees owed in the tokens of `bank`, a default `bank` marks an unused entry.
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[cfg_attr(
+    any(feature = "test", feature = "client"),
+    derive(Debug, PartialEq, Eq, TypeLayout)
+)]
+#[derive(Default)]
+pub struct ReferralFeeBalance {
+    pub bank: Pubkey,
+    pub outstanding: WrappedI80F48,
 }
 
 pub const DISABLED_FLAG: u64 = 1 << 0;
 pub const IN_FLASHLOAN_FLAG: u64 = 1 << 1;
 pub const FLASHLOAN_ENABLED_FLAG: u64 = 1 << 2;
 pub const TRANSFER_AUTHORITY_ALLOWED_FLAG: u64 = 1 << 3;
+pub const EQUITY_MAINTENANCE_FLAG: u64 = 1 << 4;
 
 impl MarginfiAccount {
     /// Set the initial data for the marginfi account.
-    pub fn initialize(&mut self, group: Pubkey, authority: Pubkey) {
+    pub fn initialize(
+        &mut self,
+        group: Pubkey,
+        authority: Pubkey,
+        integrator_tag: [u8; 32],
+        referrer: Pubkey,
+    ) {
         self.authority = authority;
         self.group = group;
+        self.integrator_tag = integrator_tag;
+        self.referrer = referrer;
     }
 
-    pub fn get_remaining_accounts_len(&self) -> usize {
-        self.lending_account
-            .balances
-            .iter()
-            .filter(|b| b.active)
-            .count()
-            * 2 // TODO: Make account count oracle setup specific
+    /// Borrow the lending account along with what the group fees of its liability in `bank_pk`
+    /// are shared with, see [`BankAccountWrapper::settle_group_fees`].
+    fn split_group_fee_sharing(
+        &mut self,
+        bank_pk: &Pubkey,
+        referral_fee_bps: u16,
+    ) -> (&mut LendingAccount, GroupFeeSharing<'_>) {
+        let referred = self.referrer != Pubkey::default();
+
+        (
+            &mut self.lending_account,
+            GroupFeeSharing {
+                bank_pk: *bank_pk,
+                discount_bps: self.fee_tier_discount_bps,
+                referral_fee_bps: if referred { referral_fee_bps } else { 0 },
+                referral_fees: referred.then_some(&mut self.referral_fees),
+            },
+        )
+    }
+
+    /// Set the fee tier discount, in bps of the group fees charged on the account's borrows.
+    pub fn set_fee_tier(&mut self, discount_bps: u16) -> MarginfiResult {
+        check!(
+            discount_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+            MarginfiError::InvalidConfig
+        );
+
+        self.fee_tier_discount_bps = discount_bps;
+
+        Ok(())
+    }
+
+    /// Take up to `max_amount` whole tokens of the referral fees owed in `bank_pk`. The entry is
+    /// freed once less than a token is left.
+    pub fn take_referral_fees(&mut self, bank_pk: &Pubkey, max_amount: u64) -> MarginfiResult<u64> {
+        let referral_fees = match self
+            .referral_fees
+            .iter_mut()
+            .find(|fees| fees.bank == *bank_pk)
+        {
+            Some(fees) => fees,
+            None => return Ok(0),
+        };
+
+        let outstanding = I80F48::from(referral_fees.outstanding);
+        let amount = outstanding
+            .checked_floor()
+            .ok_or_else(math_error!())?
+            .checked_to_num::<u64>()
+            .ok_or_else(math_error!())?
+            .min(max_amount);
+        let outstanding = outstanding
+            .checked_sub(I80F48::from_num(amount))
+            .ok_or_else(math_error!())?;
+
+        *referral_fees = if outstanding < I80F48::ONE {
+            ReferralFeeBalance::default()
+        } else {
+            ReferralFeeBalance {
+                bank: *bank_pk,
+                outstanding: outstanding.into(),
+            }
+        };
+
+        Ok(amount)
     }
 
     pub fn set_flag(&mut self, flag: u64) {
@@ -86,6 +190,12 @@ impl MarginfiAccount {
             return Err(MarginfiError::IllegalAccountAuthorityTransfer.into());
         }
 
+        // The flashloan end ix is signed by the authority, it must not change mid-flight
+        check!(
+            !self.get_flag(IN_FLASHLOAN_FLAG),
+            MarginfiError::AccountInFlashloan
+        );
+
         // update account authority
         let old_authority = self.authority;
         self.authority = new_authority;
@@ -102,15 +212,34 @@ impl MarginfiAccount {
         Ok(())
     }
 
+    /// Enable equity maintenance mode with the given buffer, or disable it with `None`.
+    pub fn set_equity_maintenance(&mut self, buffer: Option<I80F48>) -> MarginfiResult {
+        match buffer {
+            Some(buffer) => {
+                check!(!buffer.is_negative(), MarginfiError::InvalidConfig);
+
+                self.equity_maintenance_buffer = buffer.into();
+                self.set_flag(EQUITY_MAINTENANCE_FLAG);
+            }
+            None => {
+                self.equity_maintenance_buffer = I80F48::ZERO.into();
+                self.unset_flag(EQUITY_MAINTENANCE_FLAG);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn can_be_closed(&self) -> bool {
         let is_disabled = self.get_flag(DISABLED_FLAG);
+        let is_in_flashloan = self.get_flag(IN_FLASHLOAN_FLAG);
         let only_has_empty_balances = self
             .lending_account
             .balances
             .iter()
-            .all(|balance| balance.get_side().is_none());
+            .all(|balance| matches!(balance.get_side(), Ok(None)));
 
-        !is_disabled && only_has_empty_balances
+        !is_disabled && !is_in_flashloan && only_has_empty_balances
     }
 }
 
@@ -120,6 +249,15 @@ pub enum BalanceIncreaseType {
     RepayOnly,
     DepositOnly,
     BypassDepositLimit,
+    LiquidationRepay,
+}
+
+impl BalanceIncreaseType {
+    /// Changes the account authority made itself. Only these close an emptied balance or sweep
+    /// dust, liquidations expect the balances they touch to stay in place.
+    fn is_user_initiated(&self) -> bool {
+        matches!(self, Self::Any | Self::RepayOnly | Self::DepositOnly)
+    }
 }
 
 #[derive(Debug)]
@@ -128,6 +266,24 @@ pub enum BalanceDecreaseType {
     WithdrawOnly,
     BorrowOnly,
     BypassBorrowLimit,
+    LiquidationWithdraw,
+}
+
+impl BalanceDecreaseType {
+    /// See [`BalanceIncreaseType::is_user_initiated`].
+    fn is_user_initiated(&self) -> bool {
+        matches!(self, Self::Any | Self::WithdrawOnly | Self::BorrowOnly)
+    }
+}
+
+/// Token amount moved to settle a balance in a killed bank,
+/// see [`BankAccountWrapper::settle_killed`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum KilledBalanceSettlement {
+    /// Owed by the account authority to the liquidity vault.
+    Repay(u64),
+    /// Owed by the liquidity vault to the account authority.
+    Withdraw(u64),
 }
 
 #[derive(Copy, Clone)]
@@ -152,13 +308,20 @@ impl RequirementType {
 
 pub struct BankAccountWithPriceFeed<'a, 'info> {
     bank: AccountInfo<'info>,
-    price_feed: Box<MarginfiResult<OraclePriceFeedAdapter>>,
+    price_feed: Rc<MarginfiResult<OraclePriceFeedAdapter>>,
     balance: &'a Balance,
 }
 
+/// Primary oracle account, number of oracle accounts passed and the bank config fields that
+/// affect how they are parsed. Banks that agree on all of them share one parsed price feed.
+type PriceFeedCacheKey = (Pubkey, usize, u8, [Pubkey; MAX_ORACLE_KEYS], u64, u32, u16);
+
+/// Stored in [`Balance::side`], `0` means the balance is empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
 pub enum BalanceSide {
-    Assets,
-    Liabilities,
+    Assets = 1,
+    Liabilities = 2,
 }
 
 impl<'info> BankAccountWithPriceFeed<'_, 'info> {
@@ -166,11 +329,7 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
         lending_account: &'a LendingAccount,
         remaining_ais: &'info [AccountInfo<'info>],
     ) -> MarginfiResult<Vec<BankAccountWithPriceFeed<'a, 'info>>> {
-        let active_balances = lending_account
-            .balances
-            .iter()
-            .filter(|balance| balance.active)
-            .collect::<Vec<_>>();
+        let active_balances = lending_account.iter_active_balances().collect::<Vec<_>>();
 
         debug!("Expecting {} remaining accounts", active_balances.len() * 2);
         debug!("Got {} remaining accounts", remaining_ais.len());
@@ -181,31 +340,58 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
         );
 
         let clock = Clock::get()?;
+        // Balances in banks on the same oracle, e.g. SOL and its LSTs, parse it only once
+        let mut price_feed_cache: Vec<(
+            PriceFeedCacheKey,
+            Rc<MarginfiResult<OraclePriceFeedAdapter>>,
+        )> = Vec::new();
 
         active_balances
             .iter()
-            .enumerate()
-            .map(|(i, balance)| {
-                let bank_index = i * 2;
+            .map(|balance| {
+                // Each bank is followed by its primary oracle and, optionally, its fallback
+                // oracle. Banks may come in any order, and banks of balances closed earlier in
+                // the instruction, e.g. by a withdraw that emptied the balance, are ignored.
+                let bank_index = remaining_ais
+                    .iter()
+                    .position(|bank_ai| balance.bank_pk.eq(bank_ai.key))
+                    .ok_or_else(|| error!(MarginfiError::InvalidBankAccount))?;
+                let bank_ai = &remaining_ais[bank_index];
                 let oracle_ai_idx = bank_index + 1;
 
-                let bank_ai = remaining_ais.get(bank_index).unwrap();
-
-                check!(
-                    balance.bank_pk.eq(bank_ai.key),
-                    MarginfiError::InvalidBankAccount
-                );
-
                 let price_adapter = {
-                    let oracle_ais = &remaining_ais[oracle_ai_idx..oracle_ai_idx + 1];
                     let bank_al = AccountLoader::<Bank>::try_from(bank_ai)?;
                     let bank = bank_al.load()?;
-
-                    Box::new(OraclePriceFeedAdapter::try_from_bank_config(
-                        &bank.config,
-                        oracle_ais,
-                        &clock,
-                    ))
+                    let oracle_ais = remaining_ais
+                        .get(oracle_ai_idx..)
+                        .filter(|oracle_ais| !oracle_ais.is_empty())
+                        .ok_or_else(|| error!(MarginfiError::MissingPythOrBankAccount))?;
+                    let oracle_ais = &oracle_ais
+                        [..OraclePriceFeedAdapter::get_oracle_ais_len(&bank.config, oracle_ais)];
+
+                    let cache_key = (
+                        *oracle_ais[0].key,
+                        oracle_ais.len(),
+                        bank.config.oracle_setup as u8,
+                        bank.config.oracle_keys,
+                        bank.config.get_oracle_max_age(),
+                        bank.config.oracle_max_age_slots,
+                        bank.config.oracle_max_confidence_bps,
+                    );
+
+                    match price_feed_cache.iter().find(|(key, _)| key == &cache_key) {
+                        Some((_, price_feed)) => price_feed.clone(),
+                        None => {
+                            let price_feed = Rc::new(OraclePriceFeedAdapter::try_from_bank_config(
+                                &bank.config,
+                                oracle_ais,
+                                &clock,
+                            ));
+                            price_feed_cache.push((cache_key, price_feed.clone()));
+
+                            price_feed
+                        }
+                    }
                 };
 
                 Ok(BankAccountWithPriceFeed {
@@ -222,7 +408,8 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
     ///
     /// Nuances:
     /// 1. Maintenance requirement is calculated using the real time price feed.
-    /// 2. Initial requirement is calculated using the time weighted price feed, if available.
+    /// 2. Initial requirement is calculated using the time weighted price feed, if available,
+    ///    bounded by the bank's TWAP if enabled.
     /// 3. Initial requirement is discounted by the initial discount, if enabled and the usd limit is exceeded.
     /// 4. Assets are only calculated for collateral risk tier.
     /// 5. Oracle errors are ignored for deposits in isolated risk tier.
@@ -233,7 +420,7 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
     where
         'info: 'a,
     {
-        match self.balance.get_side() {
+        match self.balance.get_side()? {
             Some(side) => {
                 // SAFETY: We are shortening 'info -> 'a
                 let shorter_bank: &'a AccountInfo<'a> = unsafe { core::mem::transmute(&self.bank) };
@@ -260,6 +447,10 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
         requirement_type: RequirementType,
         bank: &'a Bank,
     ) -> MarginfiResult<I80F48> {
+        if bank.is_fixed_price_collateral_blocked() {
+            return Ok(I80F48::ZERO);
+        }
+
         match bank.config.risk_tier {
             RiskTier::Collateral => {
                 let price_feed = self.try_get_price_feed();
@@ -278,9 +469,20 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
                     .config
                     .get_weight(requirement_type, BalanceSide::Assets);
 
-                let lower_price = price_feed.get_price_of_type(
-                    requirement_type.get_oracle_price_type(),
-                    Some(PriceBias::Low),
+                if matches!(requirement_type, RequirementType::Initial)
+                    && self.balance.locked_until != 0
+                    && self.balance.is_locked(Clock::get()?.unix_timestamp as u64)
+                {
+                    asset_weight = bank.config.get_locked_asset_weight_init()?;
+                }
+
+                let lower_price = bank.bound_by_twap(
+                    price_feed.get_price_of_type(
+                        bank.get_oracle_price_type(requirement_type),
+                        Some(PriceBias::Low),
+                    )?,
+                    requirement_type,
+                    PriceBias::Low,
                 )?;
 
                 if matches!(requirement_type, RequirementType::Initial) {
@@ -294,7 +496,7 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
                 }
 
                 calc_value(
-                    bank.get_asset_amount(self.balance.asset_shares.into())?,
+                    bank.get_asset_amount(self.balance.asset_shares.into(), self.balance.insured)?,
                     lower_price,
                     bank.mint_decimals,
                     Some(asset_weight),
@@ -315,9 +517,13 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
             .config
             .get_weight(requirement_type, BalanceSide::Liabilities);
 
-        let higher_price = price_feed.get_price_of_type(
-            requirement_type.get_oracle_price_type(),
-            Some(PriceBias::High),
+        let higher_price = bank.bound_by_twap(
+            price_feed.get_price_of_type(
+                bank.get_oracle_price_type(requirement_type),
+                Some(PriceBias::High),
+            )?,
+            requirement_type,
+            PriceBias::High,
         )?;
 
         calc_value(
@@ -328,6 +534,79 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
         )
     }
 
+    /// Unweighted value of the balance and the value of its weight haircut, if the bank is in a
+    /// correlation bucket. Returned in the form of (bucket, side, value, haircut value).
+    ///
+    /// Balances without a usable price get no portfolio margin offset.
+    fn calc_correlation_bucket_value<'a>(
+        &'a self,
+        requirement_type: RequirementType,
+    ) -> MarginfiResult<Option<(u8, BalanceSide, I80F48, I80F48)>>
+    where
+        'info: 'a,
+    {
+        let Some(side) = self.balance.get_side()? else {
+            return Ok(None);
+        };
+
+        // SAFETY: We are shortening 'info -> 'a
+        let shorter_bank: &'a AccountInfo<'a> = unsafe { core::mem::transmute(&self.bank) };
+        let bank_al = AccountLoader::<Bank>::try_from(shorter_bank)?;
+        let bank = bank_al.load()?;
+
+        let bucket = bank.config.correlation_bucket;
+        if bucket == 0 {
+            return Ok(None);
+        }
+
+        let Ok(price_feed) = self.try_get_price_feed() else {
+            return Ok(None);
+        };
+
+        let (amount, price_bias) = match side {
+            BalanceSide::Assets => {
+                if bank.config.risk_tier == RiskTier::Isolated
+                    || bank.is_fixed_price_collateral_blocked()
+                {
+                    return Ok(None);
+                }
+
+                (
+                    bank.get_asset_amount(self.balance.asset_shares.into(), self.balance.insured)?,
+                    PriceBias::Low,
+                )
+            }
+            BalanceSide::Liabilities => (
+                bank.get_liability_amount(self.balance.liability_shares.into())?,
+                PriceBias::High,
+            ),
+        };
+
+        let price = bank.bound_by_twap(
+            price_feed.get_price_of_type(
+                bank.get_oracle_price_type(requirement_type),
+                Some(price_bias),
+            )?,
+            requirement_type,
+            price_bias,
+        )?;
+        let value = calc_value(amount, price, bank.mint_decimals, None)?;
+
+        let weight = bank.config.get_weight(requirement_type, side);
+        let haircut = match side {
+            BalanceSide::Assets => I80F48::ONE.checked_sub(weight),
+            BalanceSide::Liabilities => weight.checked_sub(I80F48::ONE),
+        }
+        .ok_or_else(math_error!())?;
+
+        Ok(Some((
+            bucket,
+            side,
+            value,
+            value.checked_mul(haircut).ok_or_else(math_error!())?,
+        )))
+    }
+
     fn try_get_price_feed(&self) -> std::result::Result<&OraclePriceFeedAdapter, PriceFeedError> {
         match self.price_feed.as_ref() {
             Ok(a) => Ok(a),
@@ -369,40 +648,21 @@ pub fn calc_value(
         return Ok(I80F48::ZERO);
     }
 
-    let scaling_factor = EXP_10_I80F48[mint_decimals as usize];
-
-    let weighted_asset_amount = if let Some(weight) = weight {
-        amount.checked_mul(weight).unwrap()
-    } else {
-        amount
-    };
-
     #[cfg(target_os = "solana")]
     debug!(
-        "weighted_asset_qt: {}, price: {}, expo: {}",
-        weighted_asset_amount, price, mint_decimals
+        "amount: {}, weight: {:?}, price: {}, expo: {}",
+        amount, weight, price, mint_decimals
     );
 
-    let value = weighted_asset_amount
-        .checked_mul(price)
-        .ok_or_else(math_error!())?
-        .checked_div(scaling_factor)
-        .ok_or_else(math_error!())?;
-
-    Ok(value)
+    Ok(
+        marginfi_math::value::calc_value(amount, price, mint_decimals, weight)
+            .ok_or_else(math_error!())?,
+    )
 }
 
 #[inline]
 pub fn calc_amount(value: I80F48, price: I80F48, mint_decimals: u8) -> MarginfiResult<I80F48> {
-    let scaling_factor = EXP_10_I80F48[mint_decimals as usize];
-
-    let qt = value
-        .checked_mul(scaling_factor)
-        .ok_or_else(math_error!())?
-        .checked_div(price)
-        .ok_or_else(math_error!())?;
-
-    Ok(qt)
+    Ok(marginfi_math::value::calc_amount(value, price, mint_decimals).ok_or_else(math_error!())?)
 }
 
 pub enum RiskRequirementType {
@@ -421,9 +681,45 @@ impl RiskRequirementType {
     }
 }
 
+/// Direction a balance changing operation is expected to move account health,
+/// asserted by handlers when built with the `sanity` feature.
+#[cfg(feature = "sanity")]
+#[derive(Debug, Clone, Copy)]
+pub enum HealthDelta {
+    /// Deposits and repays
+    NonDecreasing,
+    /// Withdraws and borrows
+    NonIncreasing,
+}
+
+#[cfg(feature = "sanity")]
+impl HealthDelta {
+    pub fn check(self, health_before: I80F48, health_after: I80F48) -> MarginfiResult {
+        let is_expected = match self {
+            HealthDelta::NonDecreasing => health_after >= health_before,
+            HealthDelta::NonIncreasing => health_after <= health_before,
+        };
+
+        if !is_expected {
+            msg!(
+                "Health moved from {} to {}, expected {:?}",
+                health_before,
+                health_after,
+                self
+            );
+        }
+
+        check!(is_expected, MarginfiError::HealthDeltaSanityCheckFailed);
+
+        Ok(())
+    }
+}
+
 pub struct RiskEngine<'a, 'info> {
     marginfi_account: &'a MarginfiAccount,
     bank_accounts_with_price: Vec<BankAccountWithPriceFeed<'a, 'info>>,
+    correlation_offsets_bps: [u16; MAX_CORRELATION_BUCKETS],
+    recovery_asset_weight_multiplier: Option<I80F48>,
 }
 
 impl<'info> RiskEngine<'_, 'info> {
@@ -451,28 +747,74 @@ impl<'info> RiskEngine<'_, 'info> {
         Ok(RiskEngine {
             marginfi_account,
             bank_accounts_with_price,
+            correlation_offsets_bps: [0; MAX_CORRELATION_BUCKETS],
+            recovery_asset_weight_multiplier: None,
         })
     }
 
+    /// Apply the portfolio margin offsets and recovery mode of the group.
+    /// Without a group, hedged positions get no offset.
+    pub fn with_group(mut self, marginfi_group: &MarginfiGroup) -> Self {
+        self.correlation_offsets_bps = marginfi_group.correlation_offsets_bps;
+        self.recovery_asset_weight_multiplier =
+            marginfi_group.get_recovery_asset_weight_multiplier();
+        self
+    }
+
     /// Checks account is healthy after performing actions that increase risk (removing liquidity).
     ///
     /// `IN_FLASHLOAN_FLAG` behavior.
-    /// - Health check is skipped.
+    /// - Health check is skipped, unless the group is in recovery mode: `end_flashloan` has no
+    ///   group to apply it with, so the check can't be deferred.
     /// - `remaining_ais` can be an empty vec.
     pub fn check_account_init_health<'a>(
         marginfi_account: &'a MarginfiAccount,
         remaining_ais: &'info [AccountInfo<'info>],
+        marginfi_group: &MarginfiGroup,
     ) -> MarginfiResult<()> {
-        if marginfi_account.get_flag(IN_FLASHLOAN_FLAG) {
+        if marginfi_account.get_flag(IN_FLASHLOAN_FLAG)
+            && marginfi_group
+                .get_recovery_asset_weight_multiplier()
+                .is_none()
+        {
             return Ok(());
         }
 
+        Self::new_no_flashloan_check(marginfi_account, remaining_ais)?
+            .with_group(marginfi_group)
+            .check_account_health(RiskRequirementType::Initial)?;
+
+        Ok(())
+    }
+
+    /// Checks account is healthy at the end of a flashloan, without the group.
+    ///
+    /// Hedged positions get no portfolio margin offset. Recovery mode was already applied by
+    /// every risk increasing action inside the flashloan, see [`Self::check_account_init_health`].
+    pub fn check_flashloan_end_health<'a>(
+        marginfi_account: &'a MarginfiAccount,
+        remaining_ais: &'info [AccountInfo<'info>],
+    ) -> MarginfiResult<()> {
         Self::new_no_flashloan_check(marginfi_account, remaining_ais)?
             .check_account_health(RiskRequirementType::Initial)?;
 
         Ok(())
     }
 
+    /// Maintenance health recorded around balance changing operations in sanity mode,
+    /// see [`HealthDelta`]. `remaining_ais` must hold the bank and oracle of every balance
+    /// the account has before and after the operation.
+    #[cfg(feature = "sanity")]
+    pub fn get_sanity_health<'a>(
+        marginfi_account: &'a MarginfiAccount,
+        remaining_ais: &'info [AccountInfo<'info>],
+    ) -> MarginfiResult<I80F48> {
+        let (assets, liabilities) = Self::new_no_flashloan_check(marginfi_account, remaining_ais)?
+            .get_account_health_components(RiskRequirementType::Maintenance)?;
+
+        Ok(assets.checked_sub(liabilities).ok_or_else(math_error!())?)
+    }
+
     /// Returns the total assets and liabilities of the account in the form of (assets, liabilities)
     pub fn get_account_health_components(
         &self,
@@ -496,9 +838,124 @@ impl<'info> RiskEngine<'_, 'info> {
                 .ok_or_else(math_error!())?;
         }
 
+        if !matches!(requirement_type, RiskRequirementType::Equity) {
+            let correlation_credit =
+                self.calc_correlation_credit(requirement_type.to_weight_type())?;
+
+            debug!("Correlation credit: {}", correlation_credit);
+
+            total_assets = total_assets
+                .checked_add(correlation_credit)
+                .ok_or_else(math_error!())?;
+        }
+
+        if let Some(multiplier) = self.recovery_asset_weight_multiplier {
+            if matches!(requirement_type, RiskRequirementType::Initial) {
+                total_assets = total_assets
+                    .checked_mul(multiplier)
+                    .ok_or_else(math_error!())?;
+            }
+        }
+
         Ok((total_assets, total_liabilities))
     }
 
+    /// Portfolio margin credit for hedged positions.
+    ///
+    /// Within each correlation bucket, the matched value `min(assets, liabilities)` has the
+    /// bucket offset applied to the average asset and liability weight haircuts of the bucket.
+    fn calc_correlation_credit(&self, requirement_type: RequirementType) -> MarginfiResult<I80F48> {
+        if self
+            .correlation_offsets_bps
+            .iter()
+            .all(|offset| *offset == 0)
+        {
+            return Ok(I80F48::ZERO);
+        }
+
+        // (asset value, asset haircut value, liability value, liability haircut value)
+        let mut buckets =
+            [(I80F48::ZERO, I80F48::ZERO, I80F48::ZERO, I80F48::ZERO); MAX_CORRELATION_BUCKETS];
+
+        for a in &self.bank_accounts_with_price {
+            if let Some((bucket, side, value, haircut_value)) =
+                a.calc_correlation_bucket_value(requirement_type)?
+            {
+                let (asset_value, asset_haircut, liability_value, liability_haircut) =
+                    &mut buckets[bucket as usize - 1];
+
+                let (total_value, total_haircut) = match side {
+                    BalanceSide::Assets => (asset_value, asset_haircut),
+                    BalanceSide::Liabilities => (liability_value, liability_haircut),
+                };
+
+                *total_value = total_value.checked_add(value).ok_or_else(math_error!())?;
+                *total_haircut = total_haircut
+                    .checked_add(haircut_value)
+                    .ok_or_else(math_error!())?;
+            }
+        }
+
+        let mut credit = I80F48::ZERO;
+
+        for ((asset_value, asset_haircut, liability_value, liability_haircut), offset_bps) in
+            buckets.iter().zip(self.correlation_offsets_bps)
+        {
+            let hedged_value = min(*asset_value, *liability_value);
+
+            if offset_bps == 0 || hedged_value <= I80F48::ZERO {
+                continue;
+            }
+
+            let hedged_haircut = hedged_value
+                .checked_mul(*asset_haircut)
+                .ok_or_else(math_error!())?
+                .checked_div(*asset_value)
+                .ok_or_else(math_error!())?
+                .checked_add(
+                    hedged_value
+                        .checked_mul(*liability_haircut)
+                        .ok_or_else(math_error!())?
+                        .checked_div(*liability_value)
+                        .ok_or_else(math_error!())?,
+                )
+                .ok_or_else(math_error!())?;
+
+            let offset = I80F48::from_num(offset_bps)
+                .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+                .ok_or_else(math_error!())?;
+
+            credit = credit
+                .checked_add(
+                    hedged_haircut
+                        .checked_mul(offset)
+                        .ok_or_else(math_error!())?,
+                )
+                .ok_or_else(math_error!())?;
+        }
+
+        Ok(credit)
+    }
+
+    /// Returns the maintenance health components in the form of (assets, liabilities).
+    ///
+    /// Accounts in equity maintenance mode use unweighted values, with the flat buffer
+    /// added to the liabilities.
+    pub fn get_maintenance_health_components(&self) -> MarginfiResult<(I80F48, I80F48)> {
+        if !self.marginfi_account.get_flag(EQUITY_MAINTENANCE_FLAG) {
+            return self.get_account_health_components(RiskRequirementType::Maintenance);
+        }
+
+        let (assets, liabilities) =
+            self.get_account_health_components(RiskRequirementType::Equity)?;
+        let buffer: I80F48 = self.marginfi_account.equity_maintenance_buffer.into();
+
+        Ok((
+            assets,
+            liabilities.checked_add(buffer).ok_or_else(math_error!())?,
+        ))
+    }
+
     pub fn get_account_health(
         &'info self,
         requirement_type: RiskRequirementType,
@@ -531,11 +988,12 @@ impl<'info> RiskEngine<'_, 'info> {
     }
 
     /// Checks
-    /// 1. Account is liquidatable
+    /// 1. Account is liquidatable, either unhealthy or the liability expired
     /// 2. Account has an outstanding liability for the provided liability bank
     pub fn check_pre_liquidation_condition_and_get_account_health(
         &self,
         bank_pk: &Pubkey,
+        liability_expired: bool,
     ) -> MarginfiResult<I80F48> {
         check!(
             !self.marginfi_account.get_flag(IN_FLASHLOAN_FLAG),
@@ -560,8 +1018,7 @@ impl<'info> RiskEngine<'_, 'info> {
             MarginfiError::IllegalLiquidation
         );
 
-        let (assets, liabs) =
-            self.get_account_health_components(RiskRequirementType::Maintenance)?;
+        let (assets, liabs) = self.get_maintenance_health_components()?;
 
         let account_health = assets.checked_sub(liabs).ok_or_else(math_error!())?;
 
@@ -571,7 +1028,7 @@ impl<'info> RiskEngine<'_, 'info> {
         );
 
         check!(
-            account_health <= I80F48::ZERO,
+            account_health <= I80F48::ZERO || liability_expired,
             MarginfiError::IllegalLiquidation,
             "Account not unhealthy"
         );
@@ -589,10 +1046,14 @@ impl<'info> RiskEngine<'_, 'info> {
     ///
     /// 1. We check that the paid off liability is not zero. Assuming the liquidation always pays off some liability, this ensures that the liquidation was not too large.
     /// 2. We check that the account is still at most at the maintenance requirement level. This ensures that the liquidation was not too large overall.
+    ///
+    /// Liquidations of an expired liability on a healthy account may pay off the whole liability,
+    /// but can't leave the account below the maintenance requirement.
     pub fn check_post_liquidation_condition_and_get_account_health(
         &self,
         bank_pk: &Pubkey,
         pre_liquidation_health: I80F48,
+        pre_liquidation_weighted_health: Option<I80F48>,
     ) -> MarginfiResult<I80F48> {
         check!(
             !self.marginfi_account.get_flag(IN_FLASHLOAN_FLAG),
@@ -603,12 +1064,16 @@ impl<'info> RiskEngine<'_, 'info> {
             .bank_accounts_with_price
             .iter()
             .find(|a| a.balance.bank_pk == *bank_pk)
-            .unwrap();
+            .ok_or(MarginfiError::IllegalLiquidation)?;
+
+        // Only reachable for an expired liability, see the pre liquidation check
+        let is_maturity_liquidation = pre_liquidation_health > I80F48::ZERO;
 
         check!(
-            liability_bank_balance
-                .is_empty(BalanceSide::Liabilities)
-                .not(),
+            is_maturity_liquidation
+                || liability_bank_balance
+                    .is_empty(BalanceSide::Liabilities)
+                    .not(),
             MarginfiError::IllegalLiquidation,
             "Liability payoff too severe, exhausted liability"
         );
@@ -619,11 +1084,20 @@ impl<'info> RiskEngine<'_, 'info> {
             "Liability payoff too severe, liability balance has assets"
         );
 
-        let (assets, liabs) =
-            self.get_account_health_components(RiskRequirementType::Maintenance)?;
+        let (assets, liabs) = self.get_maintenance_health_components()?;
 
         let account_health = assets.checked_sub(liabs).ok_or_else(math_error!())?;
 
+        if is_maturity_liquidation {
+            check!(
+                account_health >= I80F48::ZERO,
+                MarginfiError::IllegalLiquidation,
+                "Maturity liquidation left account below maintenance requirement"
+            );
+
+            return Ok(account_health);
+        }
+
         check!(
             account_health <= I80F48::ZERO,
             MarginfiError::IllegalLiquidation,
@@ -635,8 +1109,19 @@ impl<'info> RiskEngine<'_, 'info> {
             account_health, assets, liabs, pre_liquidation_health,
         );
 
+        // Liquidation fees always reduce net equity, so liquidations of accounts in equity
+        // maintenance mode have to improve their weighted maintenance health instead
+        let (post_health, pre_health) = match pre_liquidation_weighted_health {
+            Some(pre_weighted_health) => (
+                self.get_equity_mode_liquidation_health()?
+                    .ok_or(MarginfiError::IllegalLiquidation)?,
+                pre_weighted_health,
+            ),
+            None => (account_health, pre_liquidation_health),
+        };
+
         check!(
-            account_health > pre_liquidation_health,
+            post_health > pre_health,
             MarginfiError::IllegalLiquidation,
             "Post liquidation health worse"
         );
@@ -644,6 +1129,20 @@ impl<'info> RiskEngine<'_, 'info> {
         Ok(account_health)
     }
 
+    /// Weighted maintenance health of an account in equity maintenance mode, `None` otherwise.
+    /// Its liquidations have to improve it, see
+    /// [`RiskEngine::check_post_liquidation_condition_and_get_account_health`].
+    pub fn get_equity_mode_liquidation_health(&self) -> MarginfiResult<Option<I80F48>> {
+        if !self.marginfi_account.get_flag(EQUITY_MAINTENANCE_FLAG) {
+            return Ok(None);
+        }
+
+        let (assets, liabs) =
+            self.get_account_health_components(RiskRequirementType::Maintenance)?;
+
+        Ok(Some(assets.checked_sub(liabs).ok_or_else(math_error!())?))
+    }
+
     /// Check that the account is in a bankrupt state.
     /// Account needs to be insolvent and total value of assets need to be below the bankruptcy threshold.
     pub fn check_account_bankrupt(&self) -> MarginfiResult {
@@ -720,21 +1219,95 @@ pub struct LendingAccount {
     pub _padding: [u64; 8],                                // 8 * 8 = 64
 }
 
+/// Balances are stored sorted by bank pubkey, with all active balances packed at the front
+/// and at most one balance per bank. The order is restored whenever a balance is created, closing
+/// a balance in place leaves a gap until then. Accounts created before the invariant existed are
+/// upgraded the same way. Lookups binary search while the invariant holds and scan all slots
+/// otherwise.
+///
+/// Remaining accounts for the risk engine don't have to follow this order.
 impl LendingAccount {
     pub fn get_first_empty_balance(&self) -> Option<usize> {
         self.balances.iter().position(|b| !b.active)
     }
-}
 
-#[cfg(any(feature = "test", feature = "client"))]
-impl LendingAccount {
+    /// Restore the ordering invariant, e.g. after a balance was closed in place.
+    /// Errors if two active balances point to the same bank.
+    fn sort_balances(&mut self) -> MarginfiResult {
+        self.balances.sort_unstable_by(|a, b| {
+            b.active
+                .cmp(&a.active)
+                .then_with(|| a.bank_pk.cmp(&b.bank_pk))
+        });
+
+        check!(
+            self.balances
+                .windows(2)
+                .all(|w| !(w[1].active && w[0].bank_pk == w[1].bank_pk)),
+            MarginfiError::IllegalBalanceState,
+            "Duplicate balances for the same bank"
+        );
+
+        Ok(())
+    }
+
+    /// Whether the active balances are packed at the front and strictly sorted by bank.
+    fn is_sorted(&self) -> bool {
+        let active_len = self
+            .get_first_empty_balance()
+            .unwrap_or(MAX_LENDING_ACCOUNT_BALANCES);
+
+        self.balances[active_len..].iter().all(|b| !b.active)
+            && self.balances[..active_len]
+                .windows(2)
+                .all(|w| w[0].bank_pk < w[1].bank_pk)
+    }
+
+    /// Index of the active balance of `bank_pk`.
+    pub fn find_balance_index(&self, bank_pk: &Pubkey) -> Option<usize> {
+        if !self.is_sorted() {
+            return self
+                .balances
+                .iter()
+                .position(|b| b.active && b.bank_pk == *bank_pk);
+        }
+
+        let active_len = self
+            .get_first_empty_balance()
+            .unwrap_or(MAX_LENDING_ACCOUNT_BALANCES);
+
+        self.balances[..active_len]
+            .binary_search_by(|balance| balance.bank_pk.cmp(bank_pk))
+            .ok()
+    }
+
+    /// Insert a new active balance at its sorted position, shifting later balances back by one.
+    /// Restores the ordering invariant first. Assumes `bank_pk` has no balance yet.
+    fn insert_balance(&mut self, balance: Balance) -> MarginfiResult<usize> {
+        if !self.is_sorted() {
+            self.sort_balances()?;
+        }
+
+        let empty_index = self
+            .get_first_empty_balance()
+            .ok_or_else(|| error!(MarginfiError::LendingAccountBalanceSlotsFull))?;
+
+        let insert_index =
+            self.balances[..empty_index].partition_point(|b| b.bank_pk < balance.bank_pk);
+
+        self.balances[insert_index..=empty_index].rotate_right(1);
+        self.balances[insert_index] = balance;
+
+        Ok(insert_index)
+    }
+
+    /// Active balance of `bank_pk`.
     pub fn get_balance(&self, bank_pk: &Pubkey) -> Option<&Balance> {
-        self.balances
-            .iter()
-            .find(|balance| balance.active && balance.bank_pk.eq(bank_pk))
+        self.find_balance_index(bank_pk)
+            .map(|index| &self.balances[index])
     }
 
-    pub fn get_active_balances_iter(&self) -> impl Iterator<Item = &Balance> {
+    pub fn iter_active_balances(&self) -> impl Iterator<Item = &Balance> {
         self.balances.iter().filter(|b| b.active)
     }
 }
@@ -750,12 +1323,25 @@ assert_struct_align!(Balance, 8);
 pub struct Balance {
     pub active: bool,
     pub bank_pk: Pubkey,
-    pub _pad0: [u8; 7],
+    /// [`BalanceSide`] discriminant, `0` if the balance is empty.
+    /// A balance never holds both asset and liability shares.
+    pub side: u8,
+    /// Asset shares are in the bank's insured share class, see [`Bank::insured_asset_share_value`].
+    pub insured: bool,
+    pub _pad0: [u8; 1],
+    /// Deposit can't be withdrawn before this time, except in a liquidation. `0` if not locked.
+    /// See [`BankAccountWrapper::lock`].
+    pub locked_until: u32,
     pub asset_shares: WrappedI80F48,
     pub liability_shares: WrappedI80F48,
     pub emissions_outstanding: WrappedI80F48,
-    pub last_update: u64,
-    pub _padding: [u64; 1],
+    pub last_update: u32,
+    /// Time the current side of the balance was opened, `0` if the balance is empty.
+    /// See [`Balance::update_side_opened_at`].
+    pub side_opened_at: u32,
+    /// [`Bank::group_fee_growth`] when the liability was last settled,
+    /// see [`BankAccountWrapper::settle_group_fees`].
+    pub group_fee_growth_snapshot: u64,
 }
 
 impl Balance {
@@ -801,33 +1387,115 @@ impl Balance {
         Ok(())
     }
 
-    pub fn get_side(&self) -> Option<BalanceSide> {
-        let asset_shares = I80F48::from(self.asset_shares);
-        let liability_shares = I80F48::from(self.liability_shares);
+    pub fn is_locked(&self, current_timestamp: u64) -> bool {
+        current_timestamp < self.locked_until as u64
+    }
+
+    /// Errors if the stored side doesn't match the shares. Balances written before the side was
+    /// stored have none and only go by their shares.
+    pub fn get_side(&self) -> MarginfiResult<Option<BalanceSide>> {
+        let side = self.get_side_from_shares()?;
+
+        check!(
+            self.side == 0 || side.map(|side| side as u8) == Some(self.side),
+            MarginfiError::IllegalBalanceState,
+            "Stored balance side doesn't match the shares"
+        );
+
+        Ok(side)
+    }
+
+    fn get_side_from_shares(&self) -> MarginfiResult<Option<BalanceSide>> {
+        let has_assets = !self.is_empty(BalanceSide::Assets);
+        let has_liabilities = !self.is_empty(BalanceSide::Liabilities);
 
-        assert!(
-            asset_shares < EMPTY_BALANCE_THRESHOLD || liability_shares < EMPTY_BALANCE_THRESHOLD
+        check!(
+            !(has_assets && has_liabilities),
+            MarginfiError::IllegalBalanceState,
+            "Balance has both assets and liabilities"
         );
 
-        if I80F48::from(self.liability_shares) >= EMPTY_BALANCE_THRESHOLD {
+        Ok(if has_liabilities {
             Some(BalanceSide::Liabilities)
-        } else if I80F48::from(self.asset_shares) >= EMPTY_BALANCE_THRESHOLD {
+        } else if has_assets {
             Some(BalanceSide::Assets)
         } else {
             None
-        }
+        })
     }
 
-    pub fn empty_deactivated() -> Self {
-        Balance {
-            active: false,
-            bank_pk: Pubkey::default(),
-            _pad0: [0; 7],
+    /// Recompute the stored side after the shares changed.
+    /// Errors if both asset and liability shares are populated.
+    pub fn update_side(&mut self) -> MarginfiResult {
+        self.side = self.get_side_from_shares()?.map_or(0, |side| side as u8);
+
+        Ok(())
+    }
+
+    /// Start the clock when a side is opened and reset it once the balance is empty.
+    /// Balances opened before the time was tracked start the clock on their next update.
+    ///
+    /// Liabilities keep their original open time, see [`BankConfig::is_liability_expired`].
+    /// Deposits topping up an open asset position move the open time to the amount weighted average,
+    /// so a large top-up doesn't inherit the age of a small earlier deposit.
+    pub fn update_side_opened_at(
+        &mut self,
+        previous_side: Option<BalanceSide>,
+        previous_asset_amount: I80F48,
+        asset_amount_increase: I80F48,
+        current_timestamp: u64,
+    ) -> MarginfiResult {
+        let side = self.get_side()?;
+
+        if side.is_none() {
+            self.side_opened_at = 0;
+            // A lock ends with the deposit it covers
+            self.locked_until = 0;
+        } else if side != previous_side || self.side_opened_at == 0 {
+            self.side_opened_at = current_timestamp
+                .try_into()
+                .ok()
+                .ok_or_else(math_error!())?;
+        } else if side == Some(BalanceSide::Assets)
+            && asset_amount_increase.is_positive_with_tolerance(ZERO_AMOUNT_THRESHOLD)
+        {
+            let increase_ratio = asset_amount_increase
+                .checked_div(
+                    previous_asset_amount
+                        .checked_add(asset_amount_increase)
+                        .ok_or_else(math_error!())?,
+                )
+                .ok_or_else(math_error!())?;
+            let elapsed = current_timestamp.saturating_sub(self.side_opened_at as u64);
+            let shift: u32 = I80F48::from_num(elapsed)
+                .checked_mul(increase_ratio)
+                .ok_or_else(math_error!())?
+                .checked_to_num()
+                .ok_or_else(math_error!())?;
+
+            self.side_opened_at = self
+                .side_opened_at
+                .checked_add(shift)
+                .ok_or_else(math_error!())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn empty_deactivated() -> Self {
+        Balance {
+            active: false,
+            bank_pk: Pubkey::default(),
+            side: 0,
+            insured: false,
+            _pad0: [0; 1],
+            locked_until: 0,
             asset_shares: WrappedI80F48::from(I80F48::ZERO),
             liability_shares: WrappedI80F48::from(I80F48::ZERO),
             emissions_outstanding: WrappedI80F48::from(I80F48::ZERO),
             last_update: 0,
-            _padding: [0; 1],
+            side_opened_at: 0,
+            group_fee_growth_snapshot: 0,
         }
     }
 }
@@ -835,6 +1503,20 @@ impl Balance {
 pub struct BankAccountWrapper<'a> {
     pub balance: &'a mut Balance,
     pub bank: &'a mut Bank,
+    pub group_fee_sharing: GroupFeeSharing<'a>,
+}
+
+/// What the group fees charged on an account's liability are shared with,
+/// see [`BankAccountWrapper::settle_group_fees`]. The default shares nothing.
+#[derive(Default)]
+pub struct GroupFeeSharing<'a> {
+    pub bank_pk: Pubkey,
+    /// The account's fee tier discount, rebated against the liability.
+    pub discount_bps: u16,
+    /// The group's referral fee, `0` if the account wasn't referred.
+    pub referral_fee_bps: u16,
+    /// Referral fees the account owes its referrer, `None` if it wasn't referred.
+    pub referral_fees: Option<&'a mut [ReferralFeeBalance; MAX_REFERRAL_FEE_BANKS]>,
 }
 
 impl<'a> BankAccountWrapper<'a> {
@@ -842,15 +1524,21 @@ impl<'a> BankAccountWrapper<'a> {
     pub fn find(
         bank_pk: &Pubkey,
         bank: &'a mut Bank,
-        lending_account: &'a mut LendingAccount,
+        marginfi_account: &'a mut MarginfiAccount,
+        referral_fee_bps: u16,
     ) -> MarginfiResult<BankAccountWrapper<'a>> {
-        let balance = lending_account
-            .balances
-            .iter_mut()
-            .find(|balance| balance.active && balance.bank_pk.eq(bank_pk))
+        let (lending_account, group_fee_sharing) =
+            marginfi_account.split_group_fee_sharing(bank_pk, referral_fee_bps);
+
+        let balance_index = lending_account
+            .find_balance_index(bank_pk)
             .ok_or_else(|| error!(MarginfiError::BankAccountNotFound))?;
 
-        Ok(Self { balance, bank })
+        Ok(Self {
+            balance: &mut lending_account.balances[balance_index],
+            bank,
+            group_fee_sharing,
+        })
     }
 
     // Find existing user lending account balance by bank address.
@@ -858,12 +1546,13 @@ impl<'a> BankAccountWrapper<'a> {
     pub fn find_or_create(
         bank_pk: &Pubkey,
         bank: &'a mut Bank,
-        lending_account: &'a mut LendingAccount,
+        marginfi_account: &'a mut MarginfiAccount,
+        referral_fee_bps: u16,
     ) -> MarginfiResult<BankAccountWrapper<'a>> {
-        let balance_index = lending_account
-            .balances
-            .iter()
-            .position(|balance| balance.active && balance.bank_pk.eq(bank_pk));
+        let (lending_account, group_fee_sharing) =
+            marginfi_account.split_group_fee_sharing(bank_pk, referral_fee_bps);
+
+        let balance_index = lending_account.find_balance_index(bank_pk);
 
         match balance_index {
             Some(balance_index) => {
@@ -872,32 +1561,116 @@ impl<'a> BankAccountWrapper<'a> {
                     .get_mut(balance_index)
                     .ok_or_else(|| error!(MarginfiError::BankAccountNotFound))?;
 
-                Ok(Self { balance, bank })
+                Ok(Self {
+                    balance,
+                    bank,
+                    group_fee_sharing,
+                })
             }
             None => {
-                let empty_index = lending_account
-                    .get_first_empty_balance()
-                    .ok_or_else(|| error!(MarginfiError::LendingAccountBalanceSlotsFull))?;
-
-                lending_account.balances[empty_index] = Balance {
+                let balance_index = lending_account.insert_balance(Balance {
                     active: true,
                     bank_pk: *bank_pk,
-                    _pad0: [0; 7],
+                    side: 0,
+                    insured: false,
+                    _pad0: [0; 1],
+                    locked_until: 0,
                     asset_shares: I80F48::ZERO.into(),
                     liability_shares: I80F48::ZERO.into(),
                     emissions_outstanding: I80F48::ZERO.into(),
-                    last_update: Clock::get()?.unix_timestamp as u64,
-                    _padding: [0; 1],
-                };
+                    last_update: Clock::get()?
+                        .unix_timestamp
+                        .try_into()
+                        .ok()
+                        .ok_or_else(math_error!())?,
+                    side_opened_at: 0,
+                    group_fee_growth_snapshot: bank.get_group_fee_growth_snapshot(),
+                })?;
 
                 Ok(Self {
-                    balance: lending_account.balances.get_mut(empty_index).unwrap(),
+                    balance: &mut lending_account.balances[balance_index],
                     bank,
+                    group_fee_sharing,
                 })
             }
         }
     }
 
+    /// Share the group fees charged on the liability since it was last settled, see
+    /// [`Bank::group_fee_growth`]. The fee tier discount is rebated against the liability and the
+    /// referrer is credited `referral_fee_bps` of the same fees, capped so the two never add up to
+    /// more than the fees. Both are taken out of the bank's group fees, see
+    /// [`Bank::share_group_fees`].
+    ///
+    /// Runs before every balance change, while the shares that were charged are still in place.
+    /// Nothing is credited when the account owes its referrer fees in too many other banks, or
+    /// once the bank was killed and forgot its group fees.
+    pub fn settle_group_fees(&mut self) -> MarginfiResult {
+        let BankAccountWrapper {
+            balance,
+            bank,
+            group_fee_sharing,
+        } = self;
+
+        let group_fee_growth_snapshot = balance.group_fee_growth_snapshot;
+        balance.group_fee_growth_snapshot = bank.get_group_fee_growth_snapshot();
+
+        if balance.get_side()? != Some(BalanceSide::Liabilities) || bank.is_killed() {
+            return Ok(());
+        }
+
+        let group_fees =
+            bank.get_group_fees_since(balance.liability_shares.into(), group_fee_growth_snapshot)?;
+
+        let bank_pk = group_fee_sharing.bank_pk;
+        let referral_index = group_fee_sharing.referral_fees.as_ref().and_then(|fees| {
+            fees.iter()
+                .position(|fees| fees.bank == bank_pk)
+                .or_else(|| fees.iter().position(|fees| fees.bank == Pubkey::default()))
+        });
+
+        let discount_bps = group_fee_sharing.discount_bps as u32;
+        let referral_fee_bps = match referral_index {
+            Some(_) => (group_fee_sharing.referral_fee_bps as u32)
+                .min(WEIGHT_BPS_DENOMINATOR.saturating_sub(discount_bps)),
+            None => 0,
+        };
+        let share_of_group_fees = |bps: u32| -> MarginfiResult<I80F48> {
+            Ok(group_fees
+                .checked_mul(I80F48::from_num(bps))
+                .ok_or_else(math_error!())?
+                .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+                .ok_or_else(math_error!())?)
+        };
+
+        let rebate = share_of_group_fees(discount_bps)?
+            .min(bank.get_liability_amount(balance.liability_shares.into())?);
+
+        if rebate > I80F48::ZERO {
+            let rebate_shares = bank.get_liability_shares(rebate)?;
+            balance.change_liability_shares(-rebate_shares)?;
+            bank.change_liability_shares(-rebate_shares, true)?;
+            balance.update_side()?;
+        }
+
+        let referral_amount = share_of_group_fees(referral_fee_bps)?;
+
+        if let (Some(index), Some(referral_fees)) =
+            (referral_index, group_fee_sharing.referral_fees.as_mut())
+        {
+            if referral_amount > I80F48::ZERO {
+                let referral_fees = &mut referral_fees[index];
+                referral_fees.bank = bank_pk;
+                referral_fees.outstanding = I80F48::from(referral_fees.outstanding)
+                    .checked_add(referral_amount)
+                    .ok_or_else(math_error!())?
+                    .into();
+            }
+        }
+
+        bank.share_group_fees(rebate.max(I80F48::ZERO), referral_amount)
+    }
+
     // ------------ Borrow / Lend primitives
 
     /// Deposit an asset, will repay any outstanding liabilities.
@@ -915,6 +1688,11 @@ impl<'a> BankAccountWrapper<'a> {
         self.decrease_balance_internal(amount, BalanceDecreaseType::WithdrawOnly)
     }
 
+    /// Withdraw the liquidatee's collateral in a liquidation, locked deposits can be seized.
+    pub fn withdraw_in_liquidation(&mut self, amount: I80F48) -> MarginfiResult {
+        self.decrease_balance_internal(amount, BalanceDecreaseType::LiquidationWithdraw)
+    }
+
     /// Incur a borrow, will withdraw any existing assets.
     pub fn borrow(&mut self, amount: I80F48) -> MarginfiResult {
         self.decrease_balance_internal(amount, BalanceDecreaseType::Any)
@@ -932,6 +1710,12 @@ impl<'a> BankAccountWrapper<'a> {
         self.increase_balance_internal(amount, BalanceIncreaseType::BypassDepositLimit)
     }
 
+    /// Pay down the liquidatee's liability in a liquidation. The balance stays open even if the
+    /// liability is paid off in full, the post liquidation checks still look it up.
+    pub fn repay_in_liquidation(&mut self, amount: I80F48) -> MarginfiResult {
+        self.increase_balance_internal(amount, BalanceIncreaseType::LiquidationRepay)
+    }
+
     /// Withdraw asset and create/increase liability depending on
     /// the specified deposit amount and the existing balance.
     pub fn decrease_balance(&mut self, amount: I80F48) -> MarginfiResult {
@@ -950,14 +1734,21 @@ impl<'a> BankAccountWrapper<'a> {
     /// Withdraw existing asset in full - will error if there is no asset.
     pub fn withdraw_all(&mut self) -> MarginfiResult<u64> {
         self.claim_emissions(Clock::get()?.unix_timestamp as u64)?;
+        self.settle_group_fees()?;
 
         let balance = &mut self.balance;
         let bank = &mut self.bank;
 
         bank.assert_operational_mode(None)?;
 
+        check!(
+            balance.locked_until == 0 || !balance.is_locked(Clock::get()?.unix_timestamp as u64),
+            MarginfiError::DepositLocked
+        );
+
         let total_asset_shares: I80F48 = balance.asset_shares.into();
-        let current_asset_amount = bank.get_asset_amount(total_asset_shares)?;
+        let insured = balance.insured;
+        let current_asset_amount = bank.get_asset_amount(total_asset_shares, insured)?;
         let current_liability_amount =
             bank.get_liability_amount(balance.liability_shares.into())?;
 
@@ -974,7 +1765,7 @@ impl<'a> BankAccountWrapper<'a> {
         );
 
         balance.close()?;
-        bank.change_asset_shares(-total_asset_shares, false)?;
+        bank.change_asset_shares(-total_asset_shares, insured, false)?;
 
         bank.check_utilization_ratio()?;
 
@@ -999,6 +1790,7 @@ impl<'a> BankAccountWrapper<'a> {
     /// Repay existing liability in full - will error if there is no liability.
     pub fn repay_all(&mut self) -> MarginfiResult<u64> {
         self.claim_emissions(Clock::get()?.unix_timestamp as u64)?;
+        self.settle_group_fees()?;
 
         let balance = &mut self.balance;
         let bank = &mut self.bank;
@@ -1007,7 +1799,8 @@ impl<'a> BankAccountWrapper<'a> {
 
         let total_liability_shares: I80F48 = balance.liability_shares.into();
         let current_liability_amount = bank.get_liability_amount(total_liability_shares)?;
-        let current_asset_amount = bank.get_asset_amount(balance.asset_shares.into())?;
+        let current_asset_amount =
+            bank.get_asset_amount(balance.asset_shares.into(), balance.insured)?;
 
         debug!("Repaying all: {}", current_liability_amount,);
 
@@ -1042,15 +1835,84 @@ impl<'a> BankAccountWrapper<'a> {
             .ok_or_else(math_error!())?)
     }
 
+    /// Settle the balance in a killed bank. A liability is repaid in full, a deposit gets its
+    /// pro-rata share of what is left in the liquidity vault and the remainder is written off.
+    /// Later repayments go to the depositors that haven't settled yet.
+    pub fn settle_killed(
+        &mut self,
+        liquidity_vault_amount: u64,
+        current_timestamp: i64,
+    ) -> MarginfiResult<KilledBalanceSettlement> {
+        self.claim_emissions(current_timestamp as u64)?;
+
+        let balance = &mut self.balance;
+        let bank = &mut self.bank;
+
+        check!(bank.is_killed(), MarginfiError::BankNotKilled);
+
+        let total_liability_shares: I80F48 = balance.liability_shares.into();
+        let current_liability_amount = bank.get_liability_amount(total_liability_shares)?;
+
+        if current_liability_amount.is_positive_with_tolerance(ZERO_AMOUNT_THRESHOLD) {
+            balance.close()?;
+            bank.change_liability_shares(-total_liability_shares, false)?;
+
+            let spl_deposit_amount = current_liability_amount
+                .checked_ceil()
+                .ok_or_else(math_error!())?;
+
+            return Ok(KilledBalanceSettlement::Repay(
+                spl_deposit_amount
+                    .checked_to_num()
+                    .ok_or_else(math_error!())?,
+            ));
+        }
+
+        let total_asset_shares: I80F48 = balance.asset_shares.into();
+        let insured = balance.insured;
+        let current_asset_amount = bank.get_asset_amount(total_asset_shares, insured)?;
+        let total_asset_amount = bank.get_total_asset_amount()?;
+
+        check!(
+            current_asset_amount.is_positive_with_tolerance(ZERO_AMOUNT_THRESHOLD),
+            MarginfiError::NoAssetFound
+        );
+
+        let pro_rata_amount = current_asset_amount
+            .checked_mul(I80F48::from_num(liquidity_vault_amount))
+            .ok_or_else(math_error!())?
+            .checked_div(total_asset_amount)
+            .ok_or_else(math_error!())?
+            .min(current_asset_amount);
+
+        debug!(
+            "Settling {} of {} deposited in killed bank",
+            pro_rata_amount, current_asset_amount
+        );
+
+        balance.close()?;
+        bank.change_asset_shares(-total_asset_shares, insured, false)?;
+
+        Ok(KilledBalanceSettlement::Withdraw(
+            pro_rata_amount
+                .checked_floor()
+                .ok_or_else(math_error!())?
+                .checked_to_num()
+                .ok_or_else(math_error!())?,
+        ))
+    }
+
     pub fn close_balance(&mut self) -> MarginfiResult<()> {
         self.claim_emissions(Clock::get()?.unix_timestamp as u64)?;
+        self.settle_group_fees()?;
 
         let balance = &mut self.balance;
         let bank = &mut self.bank;
 
         let current_liability_amount =
             bank.get_liability_amount(balance.liability_shares.into())?;
-        let current_asset_amount = bank.get_asset_amount(balance.asset_shares.into())?;
+        let current_asset_amount =
+            bank.get_asset_amount(balance.asset_shares.into(), balance.insured)?;
 
         check!(
             current_liability_amount.is_zero_with_tolerance(ZERO_AMOUNT_THRESHOLD),
@@ -1069,6 +1931,78 @@ impl<'a> BankAccountWrapper<'a> {
         Ok(())
     }
 
+    /// Lock the deposit for `lock_days`, it can't be withdrawn before the lock matures
+    /// except in a liquidation. Deposits added while locked are locked as well.
+    ///
+    /// While locked, the deposit gets the bank's locked initial weight and emissions boosts.
+    /// An existing lock can be extended but not shortened.
+    pub fn lock(&mut self, lock_days: u16, current_timestamp: u64) -> MarginfiResult {
+        check!(
+            matches!(self.balance.get_side()?, Some(BalanceSide::Assets)),
+            MarginfiError::NoAssetFound
+        );
+
+        let locked_until: u32 = current_timestamp
+            .checked_add(lock_days as u64 * SECONDS_PER_DAY)
+            .and_then(|locked_until| locked_until.try_into().ok())
+            .ok_or_else(math_error!())?;
+
+        self.balance.locked_until = self.balance.locked_until.max(locked_until);
+
+        Ok(())
+    }
+
+    /// Move the deposit between the bank's uninsured and insured share classes.
+    /// The deposit amount stays the same, only the shares are converted.
+    ///
+    /// Insuring a deposit locks it for at least [`MIN_INSURED_DURATION`], see [`Self::lock`],
+    /// and a locked deposit can't leave the insured class.
+    pub fn set_insured(&mut self, insured: bool, current_timestamp: u64) -> MarginfiResult {
+        if self.balance.insured == insured {
+            return Ok(());
+        }
+
+        check!(
+            insured || !self.balance.is_locked(current_timestamp),
+            MarginfiError::DepositLocked
+        );
+
+        check!(
+            !insured || self.bank.config.insurance_premium_bps > 0,
+            MarginfiError::DepositInsuranceNotOffered
+        );
+
+        check!(
+            matches!(self.balance.get_side()?, Some(BalanceSide::Assets)),
+            MarginfiError::NoAssetFound
+        );
+
+        let balance = &mut self.balance;
+        let bank = &mut self.bank;
+
+        let asset_shares: I80F48 = balance.asset_shares.into();
+        let asset_amount = bank.get_asset_amount(asset_shares, balance.insured)?;
+        let new_asset_shares = bank.get_asset_shares(asset_amount, insured)?;
+
+        bank.change_asset_shares(-asset_shares, balance.insured, true)?;
+        bank.change_asset_shares(new_asset_shares, insured, true)?;
+
+        balance.asset_shares = new_asset_shares.into();
+        balance.insured = insured;
+        balance.update_side()?;
+
+        if insured {
+            let locked_until: u32 = current_timestamp
+                .checked_add(MIN_INSURED_DURATION)
+                .and_then(|locked_until| locked_until.try_into().ok())
+                .ok_or_else(math_error!())?;
+
+            balance.locked_until = balance.locked_until.max(locked_until);
+        }
+
+        Ok(())
+    }
+
     // ------------ Internal accounting logic
 
     fn increase_balance_internal(
@@ -1082,10 +2016,14 @@ impl<'a> BankAccountWrapper<'a> {
         );
 
         self.claim_emissions(Clock::get()?.unix_timestamp as u64)?;
+        self.settle_group_fees()?;
 
         let balance = &mut self.balance;
         let bank = &mut self.bank;
 
+        let previous_side = balance.get_side()?;
+        let current_asset_amount =
+            bank.get_asset_amount(balance.asset_shares.into(), balance.insured)?;
         let current_liability_shares: I80F48 = balance.liability_shares.into();
         let current_liability_amount = bank.get_liability_amount(current_liability_shares)?;
 
@@ -1112,7 +2050,9 @@ impl<'a> BankAccountWrapper<'a> {
                     MarginfiError::OperationDepositOnly
                 );
             }
-            BalanceIncreaseType::Any | BalanceIncreaseType::BypassDepositLimit => {}
+            BalanceIncreaseType::Any
+            | BalanceIncreaseType::BypassDepositLimit
+            | BalanceIncreaseType::LiquidationRepay => {}
         }
 
         {
@@ -1121,18 +2061,44 @@ impl<'a> BankAccountWrapper<'a> {
             bank.assert_operational_mode(Some(is_asset_amount_increasing))?;
         }
 
-        let asset_shares_increase = bank.get_asset_shares(asset_amount_increase)?;
+        let asset_shares_increase =
+            bank.get_asset_shares(asset_amount_increase, balance.insured)?;
         balance.change_asset_shares(asset_shares_increase)?;
         bank.change_asset_shares(
             asset_shares_increase,
+            balance.insured,
             matches!(operation_type, BalanceIncreaseType::BypassDepositLimit),
         )?;
 
+        if asset_shares_increase.is_positive()
+            && !matches!(operation_type, BalanceIncreaseType::BypassDepositLimit)
+        {
+            bank.check_account_deposit_limit(
+                bank.get_asset_amount(balance.asset_shares.into(), balance.insured)?,
+            )?;
+        }
+
         let liability_shares_decrease = bank.get_liability_shares(liability_amount_decrease)?;
         // TODO: Use `IncreaseType` to skip certain balance updates, and save on compute.
         balance.change_liability_shares(-liability_shares_decrease)?;
         bank.change_liability_shares(-liability_shares_decrease, true)?;
 
+        if liability_amount_decrease.is_positive() && operation_type.is_user_initiated() {
+            Self::sweep_dust(balance, bank, BalanceSide::Liabilities)?;
+        }
+
+        balance.update_side()?;
+        balance.update_side_opened_at(
+            previous_side,
+            current_asset_amount,
+            asset_amount_increase,
+            Clock::get()?.unix_timestamp as u64,
+        )?;
+
+        if operation_type.is_user_initiated() {
+            self.close_if_empty()?;
+        }
+
         Ok(())
     }
 
@@ -1147,12 +2113,14 @@ impl<'a> BankAccountWrapper<'a> {
         );
 
         self.claim_emissions(Clock::get()?.unix_timestamp as u64)?;
+        self.settle_group_fees()?;
 
         let balance = &mut self.balance;
         let bank = &mut self.bank;
 
+        let previous_side = balance.get_side()?;
         let current_asset_shares: I80F48 = balance.asset_shares.into();
-        let current_asset_amount = bank.get_asset_amount(current_asset_shares)?;
+        let current_asset_amount = bank.get_asset_amount(current_asset_shares, balance.insured)?;
 
         let (asset_amount_decrease, liability_amount_increase) = (
             min(current_asset_amount, balance_delta),
@@ -1165,7 +2133,7 @@ impl<'a> BankAccountWrapper<'a> {
         );
 
         match operation_type {
-            BalanceDecreaseType::WithdrawOnly => {
+            BalanceDecreaseType::WithdrawOnly | BalanceDecreaseType::LiquidationWithdraw => {
                 check!(
                     liability_amount_increase.is_zero_with_tolerance(ZERO_AMOUNT_THRESHOLD),
                     MarginfiError::OperationWithdrawOnly
@@ -1180,15 +2148,24 @@ impl<'a> BankAccountWrapper<'a> {
             _ => {}
         }
 
+        check!(
+            matches!(operation_type, BalanceDecreaseType::LiquidationWithdraw)
+                || asset_amount_decrease.is_zero_with_tolerance(ZERO_AMOUNT_THRESHOLD)
+                || balance.locked_until == 0
+                || !balance.is_locked(Clock::get()?.unix_timestamp as u64),
+            MarginfiError::DepositLocked
+        );
+
         {
             let is_liability_amount_increasing =
                 liability_amount_increase.is_positive_with_tolerance(ZERO_AMOUNT_THRESHOLD);
             bank.assert_operational_mode(Some(is_liability_amount_increasing))?;
         }
 
-        let asset_shares_decrease = bank.get_asset_shares(asset_amount_decrease)?;
+        let asset_shares_decrease =
+            bank.get_asset_shares(asset_amount_decrease, balance.insured)?;
         balance.change_asset_shares(-asset_shares_decrease)?;
-        bank.change_asset_shares(-asset_shares_decrease, false)?;
+        bank.change_asset_shares(-asset_shares_decrease, balance.insured, false)?;
 
         let liability_shares_increase = bank.get_liability_shares(liability_amount_increase)?;
         balance.change_liability_shares(liability_shares_increase)?;
@@ -1197,21 +2174,103 @@ impl<'a> BankAccountWrapper<'a> {
             matches!(operation_type, BalanceDecreaseType::BypassBorrowLimit),
         )?;
 
+        if liability_amount_increase.is_positive_with_tolerance(ZERO_AMOUNT_THRESHOLD)
+            && !matches!(operation_type, BalanceDecreaseType::BypassBorrowLimit)
+        {
+            bank.check_min_borrow_amount(
+                bank.get_liability_amount(balance.liability_shares.into())?,
+            )?;
+        }
+
+        if asset_amount_decrease.is_positive() && operation_type.is_user_initiated() {
+            Self::sweep_dust(balance, bank, BalanceSide::Assets)?;
+        }
+
         bank.check_utilization_ratio()?;
 
+        balance.update_side()?;
+        balance.update_side_opened_at(
+            previous_side,
+            I80F48::ZERO,
+            I80F48::ZERO,
+            Clock::get()?.unix_timestamp as u64,
+        )?;
+
+        if operation_type.is_user_initiated() {
+            self.close_if_empty()?;
+        }
+
+        Ok(())
+    }
+
+    /// Zero the `side` of a balance that was just reduced below the bank's dust threshold,
+    /// see [`Bank::sweep_dust`]. Like [`Self::close_if_empty`], only done for user initiated changes.
+    fn sweep_dust(balance: &mut Balance, bank: &mut Bank, side: BalanceSide) -> MarginfiResult {
+        let (shares, amount) = match side {
+            BalanceSide::Assets => {
+                let shares: I80F48 = balance.asset_shares.into();
+                (shares, bank.get_asset_amount(shares, balance.insured)?)
+            }
+            BalanceSide::Liabilities => {
+                let shares: I80F48 = balance.liability_shares.into();
+                (shares, bank.get_liability_amount(shares)?)
+            }
+        };
+
+        let dust_amount = bank.get_dust_amount(amount);
+
+        if dust_amount == I80F48::ZERO {
+            return Ok(());
+        }
+
+        match side {
+            BalanceSide::Assets => {
+                balance.change_asset_shares(-shares)?;
+                bank.change_asset_shares(-shares, balance.insured, true)?;
+            }
+            BalanceSide::Liabilities => {
+                balance.change_liability_shares(-shares)?;
+                bank.change_liability_shares(-shares, true)?;
+            }
+        }
+
+        bank.sweep_dust(dust_amount, side)
+    }
+
+    /// Close the balance once neither side holds shares, returning any dust shares to the bank.
+    ///
+    /// Balances with unclaimed emissions stay open so they can still be withdrawn.
+    /// Only done for user initiated changes, see [`BalanceIncreaseType::is_user_initiated`].
+    fn close_if_empty(&mut self) -> MarginfiResult {
+        if self.balance.get_side()?.is_some()
+            || I80F48::from(self.balance.emissions_outstanding) >= I80F48::ONE
+        {
+            return Ok(());
+        }
+
+        let dust_asset_shares: I80F48 = self.balance.asset_shares.into();
+        let dust_liability_shares: I80F48 = self.balance.liability_shares.into();
+        let insured = self.balance.insured;
+
+        self.balance.close()?;
+        self.bank
+            .change_asset_shares(-dust_asset_shares, insured, true)?;
+        self.bank
+            .change_liability_shares(-dust_liability_shares, true)?;
+
         Ok(())
     }
 
     /// Claim any unclaimed emissions and add them to the outstanding emissions amount.
     pub fn claim_emissions(&mut self, current_timestamp: u64) -> MarginfiResult {
         if let Some(balance_amount) = match (
-            self.balance.get_side(),
+            self.balance.get_side()?,
             self.bank.get_flag(EMISSIONS_FLAG_LENDING_ACTIVE),
             self.bank.get_flag(EMISSIONS_FLAG_BORROW_ACTIVE),
         ) {
             (Some(BalanceSide::Assets), true, _) => Some(
                 self.bank
-                    .get_asset_amount(self.balance.asset_shares.into())?,
+                    .get_asset_amount(self.balance.asset_shares.into(), self.balance.insured)?,
             ),
             (Some(BalanceSide::Liabilities), _, true) => Some(
                 self.bank
@@ -1219,10 +2278,10 @@ impl<'a> BankAccountWrapper<'a> {
             ),
             _ => None,
         } {
-            let last_update = if self.balance.last_update < MIN_EMISSIONS_START_TIME {
+            let last_update = if (self.balance.last_update as u64) < MIN_EMISSIONS_START_TIME {
                 current_timestamp
             } else {
-                self.balance.last_update
+                self.balance.last_update as u64
             };
             let period = I80F48::from_num(
                 current_timestamp
@@ -1237,6 +2296,34 @@ impl<'a> BankAccountWrapper<'a> {
                 emissions_rate,
             )?;
 
+            // Deposits are boosted by their age at the start of the period,
+            // and for the part of the period they were locked
+            let emissions = if self.balance.get_side()? == Some(BalanceSide::Assets) {
+                let deposit_age = if self.balance.side_opened_at == 0 {
+                    0
+                } else {
+                    last_update.saturating_sub(self.balance.side_opened_at as u64)
+                };
+                let locked_period = (self.balance.locked_until as u64)
+                    .min(current_timestamp)
+                    .saturating_sub(last_update);
+
+                emissions
+                    .checked_mul(
+                        self.bank
+                            .config
+                            .get_emissions_boost_multiplier(deposit_age)?,
+                    )
+                    .ok_or_else(math_error!())?
+                    .checked_mul(self.bank.config.get_locked_emissions_boost_multiplier(
+                        locked_period,
+                        current_timestamp.saturating_sub(last_update),
+                    )?)
+                    .ok_or_else(math_error!())?
+            } else {
+                emissions
+            };
+
             let emissions_real = min(emissions, I80F48::from(self.bank.emissions_remaining));
 
             if emissions != emissions_real {
@@ -1267,7 +2354,10 @@ impl<'a> BankAccountWrapper<'a> {
             .into();
         }
 
-        self.balance.last_update = current_timestamp;
+        self.balance.last_update = current_timestamp
+            .try_into()
+            .ok()
+            .ok_or_else(math_error!())?;
 
         Ok(())
     }
@@ -1359,10 +2449,7 @@ fn calc_emissions(
     mint_decimals: usize,
     emissions_rate: I80F48,
 ) -> MarginfiResult<I80F48> {
-    let exponent = EXP_10_I80F48[mint_decimals];
-    let balance_amount_ui = balance_amount
-        .checked_div(exponent)
-        .ok_or_else(math_error!())?;
+    let balance_amount_ui = scale_by_exponent(balance_amount, -(mint_decimals as i32))?;
 
     let emissions = period
         .checked_mul(balance_amount_ui)
@@ -1378,6 +2465,10 @@ fn calc_emissions(
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{
+        constants::EXP_10_I80F48,
+        state::marginfi_group::{BankConfig, BankOperationalState},
+    };
     use fixed_macro::types::I80F48;
 
     #[test]
@@ -1398,6 +2489,50 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_calc_value_extreme_decimals() {
+        // BONK-style: the whole ~93T supply of a 5 decimal mint at $0.000025
+        let bonk_price = I80F48!(2_500) / I80F48!(100_000_000);
+        let bonk_value =
+            calc_value(I80F48!(9_300_000_000_000_000_000), bonk_price, 5, None).unwrap();
+        assert!((bonk_value - I80F48!(2_325_000_000)).abs() < I80F48!(1));
+
+        let bonk_amount = calc_amount(bonk_value, bonk_price, 5).unwrap();
+        assert!((bonk_amount - I80F48!(9_300_000_000_000_000_000)).abs() < I80F48!(1));
+
+        // 0 decimals
+        assert_eq!(
+            calc_value(I80F48!(3), I80F48!(1_500), 0, None).unwrap(),
+            I80F48!(4_500)
+        );
+        assert_eq!(
+            calc_amount(I80F48!(4_500), I80F48!(1_500), 0).unwrap(),
+            I80F48!(3)
+        );
+
+        // 12 decimals, amount * price alone overflows I80F48
+        let amount = I80F48!(100_000_000_000_000_000_000_000);
+        assert!(amount.checked_mul(I80F48!(100_000)).is_none());
+        assert_eq!(
+            calc_value(amount, I80F48!(100_000), 12, None).unwrap(),
+            I80F48!(10_000_000_000_000_000)
+        );
+        assert_eq!(
+            calc_amount(I80F48!(10_000_000_000_000_000), I80F48!(100_000), 12).unwrap(),
+            amount
+        );
+
+        // Out of the lookup table range still works
+        let value = calc_value(
+            I80F48!(100_000_000_000_000_000_000_000),
+            I80F48!(2),
+            30,
+            None,
+        )
+        .unwrap();
+        assert!((value - I80F48!(0.0000002)).abs() < I80F48!(0.000000000001));
+    }
+
     #[test]
     fn test_account_authority_transfer() {
         let group: [u8; 32] = [0; 32];
@@ -1412,17 +2547,27 @@ mod test {
                 balances: [Balance {
                     active: true,
                     bank_pk: bank_pk.into(),
-                    _pad0: [0; 7],
+                    side: 0,
+                    insured: false,
+                    _pad0: [0; 1],
+                    locked_until: 0,
                     asset_shares: WrappedI80F48::default(),
                     liability_shares: WrappedI80F48::default(),
                     emissions_outstanding: WrappedI80F48::default(),
                     last_update: 0,
-                    _padding: [0_u64],
+                    side_opened_at: 0,
+                    group_fee_growth_snapshot: 0,
                 }; 16],
                 _padding: [0; 8],
             },
             account_flags: TRANSFER_AUTHORITY_ALLOWED_FLAG,
-            _padding: [0; 63],
+            equity_maintenance_buffer: WrappedI80F48::default(),
+            integrator_tag: [0; 32],
+            referrer: Pubkey::default(),
+            referral_fees: [ReferralFeeBalance::default(); MAX_REFERRAL_FEE_BANKS],
+            fee_tier_discount_bps: 0,
+            _pad0: [0; 6],
+            _padding: [0; 28],
         };
 
         assert!(acc.get_flag(TRANSFER_AUTHORITY_ALLOWED_FLAG));
@@ -1433,6 +2578,809 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_in_flashloan_blocks_authority_transfer_and_close() {
+        let mut acc = MarginfiAccount {
+            group: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            lending_account: LendingAccount {
+                balances: [Balance::empty_deactivated(); 16],
+                _padding: [0; 8],
+            },
+            account_flags: TRANSFER_AUTHORITY_ALLOWED_FLAG | IN_FLASHLOAN_FLAG,
+            equity_maintenance_buffer: WrappedI80F48::default(),
+            integrator_tag: [0; 32],
+            referrer: Pubkey::default(),
+            referral_fees: [ReferralFeeBalance::default(); MAX_REFERRAL_FEE_BANKS],
+            fee_tier_discount_bps: 0,
+            _pad0: [0; 6],
+            _padding: [0; 28],
+        };
+
+        assert!(!acc.can_be_closed());
+        assert!(acc
+            .set_new_account_authority_checked(Pubkey::new_unique())
+            .is_err());
+
+        acc.unset_flag(IN_FLASHLOAN_FLAG);
+
+        assert!(acc.can_be_closed());
+        assert!(acc
+            .set_new_account_authority_checked(Pubkey::new_unique())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_referral_fees() {
+        let bank_pk = Pubkey::new_unique();
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(2_000_000).into(),
+            total_liability_shares: I80F48!(1_000_000).into(),
+            collected_group_fees_outstanding: I80F48!(1_000_000).into(),
+            // Half a token per share charged since the balance was settled, the low 64 bits
+            // wrapping around
+            group_fee_growth: I80F48::from_bits((1 << 64) + I80F48!(0.5).to_bits() - 1).into(),
+            ..Default::default()
+        };
+
+        let mut balance = Balance::empty_deactivated();
+        balance.active = true;
+        balance.bank_pk = bank_pk;
+        balance.side = BalanceSide::Liabilities as u8;
+        balance.liability_shares = I80F48!(1_000_000).into();
+        balance.group_fee_growth_snapshot = u64::MAX;
+
+        let mut acc = MarginfiAccount {
+            group: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            lending_account: LendingAccount {
+                balances: [Balance::empty_deactivated(); 16],
+                _padding: [0; 8],
+            },
+            account_flags: 0,
+            equity_maintenance_buffer: WrappedI80F48::default(),
+            integrator_tag: [0; 32],
+            referrer: Pubkey::default(),
+            referral_fees: [ReferralFeeBalance::default(); MAX_REFERRAL_FEE_BANKS],
+            fee_tier_discount_bps: 0,
+            _pad0: [0; 6],
+            _padding: [0; 28],
+        };
+        acc.lending_account.balances[0] = balance;
+
+        let settle = |acc: &mut MarginfiAccount, bank: &mut Bank| {
+            BankAccountWrapper::find(&bank_pk, bank, acc, 5_000)
+                .unwrap()
+                .settle_group_fees()
+                .unwrap()
+        };
+
+        // Not referred
+        let snapshot = acc.lending_account.balances[0].group_fee_growth_snapshot;
+        settle(&mut acc, &mut bank);
+        assert_eq!(acc.referral_fees[0].bank, Pubkey::default());
+
+        // Half of the 500k group fees charged on 1M of liability shares
+        acc.lending_account.balances[0].group_fee_growth_snapshot = snapshot;
+        acc.referrer = Pubkey::new_unique();
+        settle(&mut acc, &mut bank);
+        assert_eq!(acc.referral_fees[0].bank, bank_pk);
+        assert_eq!(
+            I80F48::from(acc.referral_fees[0].outstanding),
+            I80F48!(250_000)
+        );
+        assert_eq!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48!(750_000)
+        );
+        assert_eq!(
+            I80F48::from(bank.referral_fees_outstanding),
+            I80F48!(250_000)
+        );
+
+        // The fees are only credited once
+        settle(&mut acc, &mut bank);
+        assert_eq!(
+            I80F48::from(acc.referral_fees[0].outstanding),
+            I80F48!(250_000)
+        );
+
+        assert_eq!(
+            acc.take_referral_fees(&Pubkey::new_unique(), 100).unwrap(),
+            0
+        );
+        assert_eq!(acc.take_referral_fees(&bank_pk, 100_000).unwrap(), 100_000);
+        assert_eq!(acc.take_referral_fees(&bank_pk, u64::MAX).unwrap(), 150_000);
+        assert_eq!(acc.referral_fees[0].bank, Pubkey::default());
+    }
+
+    /// Balance changes read the clock, which isn't available off chain.
+    struct ClockStub;
+
+    impl solana_program::program_stubs::SyscallStubs for ClockStub {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Clock) = Clock::default() };
+            solana_program::entrypoint::SUCCESS
+        }
+    }
+
+    #[test]
+    fn test_liquidation_keeps_emptied_balances() {
+        solana_program::program_stubs::set_syscall_stubs(Box::new(ClockStub));
+
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(2_000).into(),
+            total_liability_shares: I80F48!(1_000).into(),
+            config: BankConfig {
+                operational_state: BankOperationalState::Operational,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let balance = |side: BalanceSide, shares: I80F48| {
+            let mut balance = Balance::empty_deactivated();
+            balance.active = true;
+            balance.side = side as u8;
+            match side {
+                BalanceSide::Assets => balance.asset_shares = shares.into(),
+                BalanceSide::Liabilities => balance.liability_shares = shares.into(),
+            }
+            balance
+        };
+
+        // Liquidating a liability to zero keeps the balance for the post liquidation checks
+        let mut liability = balance(BalanceSide::Liabilities, I80F48!(500));
+        BankAccountWrapper {
+            balance: &mut liability,
+            bank: &mut bank,
+            group_fee_sharing: GroupFeeSharing::default(),
+        }
+        .repay_in_liquidation(I80F48!(500))
+        .unwrap();
+        assert!(liability.active);
+        assert!(liability.is_empty(BalanceSide::Liabilities));
+
+        let mut asset = balance(BalanceSide::Assets, I80F48!(400));
+        BankAccountWrapper {
+            balance: &mut asset,
+            bank: &mut bank,
+            group_fee_sharing: GroupFeeSharing::default(),
+        }
+        .withdraw_in_liquidation(I80F48!(400))
+        .unwrap();
+        assert!(asset.active);
+
+        // The same changes made by the account authority close the balances
+        let mut liability = balance(BalanceSide::Liabilities, I80F48!(500));
+        BankAccountWrapper {
+            balance: &mut liability,
+            bank: &mut bank,
+            group_fee_sharing: GroupFeeSharing::default(),
+        }
+        .repay(I80F48!(500))
+        .unwrap();
+        assert!(!liability.active);
+
+        let mut asset = balance(BalanceSide::Assets, I80F48!(400));
+        BankAccountWrapper {
+            balance: &mut asset,
+            bank: &mut bank,
+            group_fee_sharing: GroupFeeSharing::default(),
+        }
+        .withdraw(I80F48!(400))
+        .unwrap();
+        assert!(!asset.active);
+
+        assert_eq!(I80F48::from(bank.total_liability_shares), I80F48::ZERO);
+        assert_eq!(I80F48::from(bank.total_asset_shares), I80F48!(1_200));
+    }
+
+    #[test]
+    fn test_settle_killed_balance() {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000).into(),
+            total_liability_shares: I80F48!(500).into(),
+            config: BankConfig {
+                operational_state: BankOperationalState::Operational,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut deposit = Balance::empty_deactivated();
+        deposit.active = true;
+        deposit.side = BalanceSide::Assets as u8;
+        deposit.asset_shares = I80F48!(400).into();
+
+        let mut borrow = Balance::empty_deactivated();
+        borrow.active = true;
+        borrow.side = BalanceSide::Liabilities as u8;
+        borrow.liability_shares = I80F48!(500).into();
+
+        assert!(BankAccountWrapper {
+            balance: &mut deposit,
+            bank: &mut bank,
+            group_fee_sharing: GroupFeeSharing::default(),
+        }
+        .settle_killed(500, 0)
+        .is_err());
+
+        bank.kill(I80F48!(2)).unwrap();
+
+        // 500 of 1000 deposited is still in the vault
+        assert_eq!(
+            BankAccountWrapper {
+                balance: &mut deposit,
+                bank: &mut bank,
+                group_fee_sharing: GroupFeeSharing::default(),
+            }
+            .settle_killed(500, 0)
+            .unwrap(),
+            KilledBalanceSettlement::Withdraw(200)
+        );
+        assert!(!deposit.active);
+        assert_eq!(I80F48::from(bank.total_asset_shares), I80F48!(600));
+
+        assert_eq!(
+            BankAccountWrapper {
+                balance: &mut borrow,
+                bank: &mut bank,
+                group_fee_sharing: GroupFeeSharing::default(),
+            }
+            .settle_killed(300, 0)
+            .unwrap(),
+            KilledBalanceSettlement::Repay(500)
+        );
+        assert!(!borrow.active);
+        assert_eq!(I80F48::from(bank.total_liability_shares), I80F48::ZERO);
+    }
+
+    fn fee_sharing_test_setup(fee_tier_discount_bps: u16) -> (Pubkey, Bank, MarginfiAccount) {
+        let bank_pk = Pubkey::new_unique();
+        let bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(2_000_000).into(),
+            total_liability_shares: I80F48!(1_000_000).into(),
+            collected_group_fees_outstanding: I80F48!(1_000_000).into(),
+            // Half a token per share charged since the balance was opened
+            group_fee_growth: I80F48!(0.5).into(),
+            ..Default::default()
+        };
+
+        let mut balance = Balance::empty_deactivated();
+        balance.active = true;
+        balance.bank_pk = bank_pk;
+        balance.side = BalanceSide::Liabilities as u8;
+        balance.liability_shares = I80F48!(1_000_000).into();
+
+        let mut acc = MarginfiAccount {
+            group: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            lending_account: LendingAccount {
+                balances: [Balance::empty_deactivated(); 16],
+                _padding: [0; 8],
+            },
+            account_flags: 0,
+            equity_maintenance_buffer: WrappedI80F48::default(),
+            integrator_tag: [0; 32],
+            referrer: Pubkey::default(),
+            referral_fees: [ReferralFeeBalance::default(); MAX_REFERRAL_FEE_BANKS],
+            fee_tier_discount_bps: 0,
+            _pad0: [0; 6],
+            _padding: [0; 28],
+        };
+        acc.lending_account.balances[0] = balance;
+        acc.set_fee_tier(fee_tier_discount_bps).unwrap();
+
+        (bank_pk, bank, acc)
+    }
+
+    #[test]
+    fn test_fee_tier_discount() {
+        let (bank_pk, mut bank, mut acc) = fee_sharing_test_setup(5_000);
+
+        assert!(acc.set_fee_tier(10_001).is_err());
+
+        // Half of the 500k group fees charged on 1M of liability shares
+        BankAccountWrapper::find(&bank_pk, &mut bank, &mut acc, 0)
+            .unwrap()
+            .settle_group_fees()
+            .unwrap();
+        assert_eq!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48!(750_000)
+        );
+        assert_eq!(
+            I80F48::from(acc.lending_account.balances[0].liability_shares),
+            I80F48!(750_000)
+        );
+        assert_eq!(
+            I80F48::from(bank.total_liability_shares),
+            I80F48::from(acc.lending_account.balances[0].liability_shares)
+        );
+
+        // The fees are only rebated once
+        BankAccountWrapper::find(&bank_pk, &mut bank, &mut acc, 0)
+            .unwrap()
+            .settle_group_fees()
+            .unwrap();
+        assert_eq!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48!(750_000)
+        );
+    }
+
+    #[test]
+    fn test_fee_tier_rebate_repays_liability() {
+        let (bank_pk, mut bank, mut acc) = fee_sharing_test_setup(10_000);
+        // As much group fees charged as the liability is worth
+        bank.group_fee_growth = I80F48::ONE.into();
+
+        BankAccountWrapper::find(&bank_pk, &mut bank, &mut acc, 0)
+            .unwrap()
+            .settle_group_fees()
+            .unwrap();
+        assert_eq!(
+            I80F48::from(acc.lending_account.balances[0].liability_shares),
+            I80F48::ZERO
+        );
+        assert_eq!(acc.lending_account.balances[0].get_side().unwrap(), None);
+    }
+
+    #[test]
+    fn test_fee_tier_discount_with_referral() {
+        let (bank_pk, mut bank, mut acc) = fee_sharing_test_setup(8_000);
+        acc.referrer = Pubkey::new_unique();
+
+        // 80% of the 500k group fees are rebated, the referrer only gets the remaining 20%
+        // instead of 50%
+        BankAccountWrapper::find(&bank_pk, &mut bank, &mut acc, 5_000)
+            .unwrap()
+            .settle_group_fees()
+            .unwrap();
+        assert_eq!(
+            I80F48::from(acc.lending_account.balances[0].liability_shares),
+            I80F48!(600_000)
+        );
+        assert_eq!(
+            I80F48::from(acc.referral_fees[0].outstanding),
+            I80F48!(100_000)
+        );
+        assert_eq!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48!(500_000)
+        );
+    }
+
+    #[test]
+    fn test_group_fees_shared_after_collection() {
+        let (bank_pk, mut bank, mut acc) = fee_sharing_test_setup(5_000);
+        acc.referrer = Pubkey::new_unique();
+        // The group fees were collected before the liability was settled
+        bank.collected_group_fees_outstanding = I80F48::ZERO.into();
+
+        BankAccountWrapper::find(&bank_pk, &mut bank, &mut acc, 5_000)
+            .unwrap()
+            .settle_group_fees()
+            .unwrap();
+        assert_eq!(
+            I80F48::from(acc.lending_account.balances[0].liability_shares),
+            I80F48!(750_000)
+        );
+        assert_eq!(
+            I80F48::from(acc.referral_fees[0].outstanding),
+            I80F48!(250_000)
+        );
+        assert_eq!(
+            I80F48::from(bank.referral_fees_outstanding),
+            I80F48!(250_000)
+        );
+        // Repaid out of the next group fees
+        assert_eq!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48!(-500_000)
+        );
+    }
+
+    #[test]
+    fn test_group_fees_settled_on_balance_change() {
+        solana_program::program_stubs::set_syscall_stubs(Box::new(ClockStub));
+
+        let (bank_pk, mut bank, mut acc) = fee_sharing_test_setup(5_000);
+        bank.config.operational_state = BankOperationalState::Operational;
+
+        // Taking on more liability in a liquidation rebates the fees of the existing shares first
+        BankAccountWrapper::find(&bank_pk, &mut bank, &mut acc, 0)
+            .unwrap()
+            .decrease_balance_in_liquidation(I80F48!(250_000))
+            .unwrap();
+        assert_eq!(
+            I80F48::from(acc.lending_account.balances[0].liability_shares),
+            I80F48!(1_000_000)
+        );
+        assert_eq!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48!(750_000)
+        );
+        assert_eq!(
+            acc.lending_account.balances[0].group_fee_growth_snapshot,
+            bank.get_group_fee_growth_snapshot()
+        );
+    }
+
+    #[test]
+    fn test_equity_maintenance_mode() {
+        let mut acc = MarginfiAccount {
+            group: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            lending_account: LendingAccount {
+                balances: [Balance::empty_deactivated(); 16],
+                _padding: [0; 8],
+            },
+            account_flags: 0,
+            equity_maintenance_buffer: WrappedI80F48::default(),
+            integrator_tag: [0; 32],
+            referrer: Pubkey::default(),
+            referral_fees: [ReferralFeeBalance::default(); MAX_REFERRAL_FEE_BANKS],
+            fee_tier_discount_bps: 0,
+            _pad0: [0; 6],
+            _padding: [0; 28],
+        };
+
+        assert!(acc.set_equity_maintenance(Some(I80F48!(-1))).is_err());
+        assert!(!acc.get_flag(EQUITY_MAINTENANCE_FLAG));
+
+        acc.set_equity_maintenance(Some(I80F48!(10_000))).unwrap();
+        assert!(acc.get_flag(EQUITY_MAINTENANCE_FLAG));
+        assert_eq!(I80F48::from(acc.equity_maintenance_buffer), I80F48!(10_000));
+
+        acc.set_equity_maintenance(None).unwrap();
+        assert!(!acc.get_flag(EQUITY_MAINTENANCE_FLAG));
+        assert_eq!(I80F48::from(acc.equity_maintenance_buffer), I80F48::ZERO);
+    }
+
+    #[test]
+    fn test_balances_sorted_by_bank() {
+        let mut lending_account = LendingAccount {
+            balances: [Balance::empty_deactivated(); 16],
+            _padding: [0; 8],
+        };
+
+        let banks: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).rev().collect();
+
+        for bank_pk in banks.iter() {
+            let mut balance = Balance::empty_deactivated();
+            balance.active = true;
+            balance.bank_pk = *bank_pk;
+            lending_account.insert_balance(balance).unwrap();
+        }
+
+        let mut sorted_banks = banks.clone();
+        sorted_banks.sort();
+
+        for (i, bank_pk) in sorted_banks.iter().enumerate() {
+            assert_eq!(lending_account.balances[i].bank_pk, *bank_pk);
+            assert_eq!(lending_account.find_balance_index(bank_pk), Some(i));
+        }
+        assert!(!lending_account.balances[banks.len()].active);
+        assert_eq!(
+            lending_account.find_balance_index(&Pubkey::new_unique()),
+            None
+        );
+
+        // Closing a balance in place leaves a gap, lookups scan past it
+        lending_account.balances[1].close().unwrap();
+        assert!(!lending_account.is_sorted());
+        assert_eq!(lending_account.find_balance_index(&sorted_banks[1]), None);
+        assert_eq!(
+            lending_account.find_balance_index(&sorted_banks[2]),
+            Some(2)
+        );
+        assert_eq!(
+            lending_account.find_balance_index(&sorted_banks[4]),
+            Some(4)
+        );
+
+        // Creating a balance packs the gap back
+        let new_bank = Pubkey::new_unique();
+        let mut balance = Balance::empty_deactivated();
+        balance.active = true;
+        balance.bank_pk = new_bank;
+        lending_account.insert_balance(balance).unwrap();
+        assert!(lending_account.is_sorted());
+        assert_eq!(lending_account.get_first_empty_balance(), Some(banks.len()));
+        assert!(lending_account.get_balance(&sorted_banks[1]).is_none());
+        assert_eq!(
+            lending_account.get_balance(&new_bank).unwrap().bank_pk,
+            new_bank
+        );
+        for bank_pk in sorted_banks.iter().skip(2) {
+            assert_eq!(
+                lending_account.get_balance(bank_pk).unwrap().bank_pk,
+                *bank_pk
+            );
+        }
+        assert_eq!(lending_account.iter_active_balances().count(), banks.len());
+
+        // Duplicate balances for the same bank are rejected
+        lending_account.balances[1].bank_pk = lending_account.balances[0].bank_pk;
+        assert!(lending_account.sort_balances().is_err());
+    }
+
+    #[test]
+    fn test_get_balance_legacy_order() {
+        let mut lending_account = LendingAccount {
+            balances: [Balance::empty_deactivated(); 16],
+            _padding: [0; 8],
+        };
+
+        // Legacy accounts fill the first empty slot, in any bank order and with gaps
+        let mut banks: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        banks.sort();
+        for (index, bank_pk) in [(0, banks[2]), (2, banks[0]), (5, banks[1])] {
+            lending_account.balances[index].active = true;
+            lending_account.balances[index].bank_pk = bank_pk;
+        }
+
+        assert_eq!(
+            lending_account.get_balance(&banks[0]).unwrap().bank_pk,
+            banks[0]
+        );
+        assert_eq!(
+            lending_account.get_balance(&banks[1]).unwrap().bank_pk,
+            banks[1]
+        );
+        assert_eq!(
+            lending_account.get_balance(&banks[2]).unwrap().bank_pk,
+            banks[2]
+        );
+        assert_eq!(lending_account.find_balance_index(&banks[1]), Some(5));
+        assert!(lending_account.get_balance(&Pubkey::new_unique()).is_none());
+        assert!(lending_account
+            .iter_active_balances()
+            .map(|b| b.bank_pk)
+            .eq([banks[2], banks[0], banks[1]]));
+    }
+
+    #[test]
+    fn test_balance_side() {
+        let mut balance = Balance::empty_deactivated();
+        balance.active = true;
+        assert!(balance.get_side().unwrap().is_none());
+
+        // Legacy balances without a stored side fall back to the shares
+        balance.asset_shares = I80F48!(100).into();
+        assert_eq!(balance.get_side().unwrap(), Some(BalanceSide::Assets));
+
+        balance.update_side().unwrap();
+        assert_eq!(balance.side, BalanceSide::Assets as u8);
+
+        balance.liability_shares = I80F48!(100).into();
+        assert!(balance.update_side().is_err());
+
+        balance.asset_shares = I80F48!(0.5).into();
+        balance.update_side().unwrap();
+        assert_eq!(balance.get_side().unwrap(), Some(BalanceSide::Liabilities));
+
+        balance.liability_shares = I80F48::ZERO.into();
+        balance.update_side().unwrap();
+        assert_eq!(balance.side, 0);
+        assert!(balance.get_side().unwrap().is_none());
+
+        // A stored side has to match the shares
+        balance.side = BalanceSide::Assets as u8;
+        assert!(balance.get_side().is_err());
+        balance.liability_shares = I80F48!(100).into();
+        assert!(balance.get_side().is_err());
+        balance.asset_shares = I80F48!(100).into();
+        balance.side = 0;
+        assert!(balance.get_side().is_err());
+    }
+
+    #[test]
+    fn test_side_opened_at() {
+        let config = BankConfig {
+            max_borrow_duration: 100,
+            ..Default::default()
+        };
+        let mut balance = Balance::empty_deactivated();
+
+        balance.liability_shares = I80F48!(100).into();
+        balance.update_side().unwrap();
+        balance
+            .update_side_opened_at(None, I80F48::ZERO, I80F48::ZERO, 1_000)
+            .unwrap();
+        assert_eq!(balance.side_opened_at, 1_000);
+
+        // Adding to the liability keeps the original open time
+        balance
+            .update_side_opened_at(
+                Some(BalanceSide::Liabilities),
+                I80F48::ZERO,
+                I80F48::ZERO,
+                1_050,
+            )
+            .unwrap();
+        assert_eq!(balance.side_opened_at, 1_000);
+
+        assert!(!config.is_liability_expired(balance.side_opened_at, 1_099));
+        assert!(config.is_liability_expired(balance.side_opened_at, 1_100));
+        assert!(!BankConfig::default().is_liability_expired(balance.side_opened_at, 1_100));
+
+        // Flipping to assets restarts the clock
+        balance.liability_shares = I80F48::ZERO.into();
+        balance.asset_shares = I80F48!(100).into();
+        balance.update_side().unwrap();
+        balance
+            .update_side_opened_at(
+                Some(BalanceSide::Liabilities),
+                I80F48::ZERO,
+                I80F48!(100),
+                1_200,
+            )
+            .unwrap();
+        assert_eq!(balance.side_opened_at, 1_200);
+
+        // Doubling the deposit halves its age
+        balance
+            .update_side_opened_at(Some(BalanceSide::Assets), I80F48!(100), I80F48!(100), 1_400)
+            .unwrap();
+        assert_eq!(balance.side_opened_at, 1_300);
+
+        balance.asset_shares = I80F48::ZERO.into();
+        balance.update_side().unwrap();
+        balance
+            .update_side_opened_at(Some(BalanceSide::Assets), I80F48::ZERO, I80F48::ZERO, 1_500)
+            .unwrap();
+        assert_eq!(balance.side_opened_at, 0);
+    }
+
+    #[test]
+    fn test_emissions_boost_multiplier() {
+        let mut config = BankConfig::default();
+        assert_eq!(
+            config.get_emissions_boost_multiplier(1_000).unwrap(),
+            I80F48::ONE
+        );
+
+        config.emissions_boost_bps = 5_000;
+        config.emissions_boost_duration = 1_000;
+        assert_eq!(
+            config.get_emissions_boost_multiplier(0).unwrap(),
+            I80F48::ONE
+        );
+        assert_eq!(
+            config.get_emissions_boost_multiplier(500).unwrap(),
+            I80F48!(1.25)
+        );
+        assert_eq!(
+            config.get_emissions_boost_multiplier(5_000).unwrap(),
+            I80F48!(1.5)
+        );
+    }
+
+    #[test]
+    fn test_locked_deposit() {
+        let mut balance = Balance::empty_deactivated();
+        balance.locked_until = 1_000;
+        assert!(balance.is_locked(999));
+        assert!(!balance.is_locked(1_000));
+
+        // Closing the deposit drops the lock
+        balance
+            .update_side_opened_at(Some(BalanceSide::Assets), I80F48::ZERO, I80F48::ZERO, 500)
+            .unwrap();
+        assert_eq!(balance.locked_until, 0);
+
+        let mut config = BankConfig {
+            asset_weight_init: I80F48!(0.5).into(),
+            asset_weight_maint: I80F48!(0.875).into(),
+            ..Default::default()
+        };
+        assert_eq!(config.get_locked_asset_weight_init().unwrap(), I80F48!(0.5));
+
+        config.locked_asset_weight_boost_bps = 2_500;
+        assert_eq!(
+            config.get_locked_asset_weight_init().unwrap(),
+            I80F48!(0.75)
+        );
+
+        // Capped at the maintenance weight
+        config.locked_asset_weight_boost_bps = 5_000;
+        assert_eq!(
+            config.get_locked_asset_weight_init().unwrap(),
+            I80F48!(0.875)
+        );
+
+        config.locked_emissions_boost_bps = 5_000;
+        assert_eq!(
+            config
+                .get_locked_emissions_boost_multiplier(0, 100)
+                .unwrap(),
+            I80F48::ONE
+        );
+        assert_eq!(
+            config
+                .get_locked_emissions_boost_multiplier(50, 100)
+                .unwrap(),
+            I80F48!(1.25)
+        );
+        assert_eq!(
+            config
+                .get_locked_emissions_boost_multiplier(100, 100)
+                .unwrap(),
+            I80F48!(1.5)
+        );
+    }
+
+    #[test]
+    fn test_insured_deposit_lock() {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000).into(),
+            config: BankConfig {
+                insurance_premium_bps: 1_000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut balance = Balance::empty_deactivated();
+        balance.active = true;
+        balance.side = BalanceSide::Assets as u8;
+        balance.asset_shares = I80F48!(1_000).into();
+
+        let mut bank_account = BankAccountWrapper {
+            balance: &mut balance,
+            bank: &mut bank,
+            group_fee_sharing: GroupFeeSharing::default(),
+        };
+
+        bank_account.set_insured(true, 1_000).unwrap();
+        assert_eq!(
+            bank_account.balance.locked_until as u64,
+            1_000 + MIN_INSURED_DURATION
+        );
+
+        // Can't switch back out right after, e.g. once a bankruptcy was handled
+        assert!(bank_account
+            .set_insured(false, 1_000 + MIN_INSURED_DURATION - 1)
+            .is_err());
+
+        bank_account
+            .set_insured(false, 1_000 + MIN_INSURED_DURATION)
+            .unwrap();
+        assert!(!bank_account.balance.insured);
+        assert_eq!(I80F48::from(bank.total_asset_shares), I80F48!(1_000));
+        assert_eq!(I80F48::from(bank.total_insured_asset_shares), I80F48::ZERO);
+    }
+
+    #[cfg(feature = "sanity")]
+    #[test]
+    fn test_health_delta() {
+        assert!(HealthDelta::NonDecreasing
+            .check(I80F48!(10), I80F48!(10))
+            .is_ok());
+        assert!(HealthDelta::NonDecreasing
+            .check(I80F48!(10), I80F48!(9))
+            .is_err());
+        assert!(HealthDelta::NonIncreasing
+            .check(I80F48!(10), I80F48!(9))
+            .is_ok());
+        assert!(HealthDelta::NonIncreasing
+            .check(I80F48!(10), I80F48!(11))
+            .is_err());
+    }
+
     #[test]
     fn test_calc_emissions() {
         let balance_amount: u64 = 106153222432271169;
@@ -1,6 +1,6 @@
 use super::{
     marginfi_account::{BalanceSide, RequirementType},
-    price::{OraclePriceFeedAdapter, OracleSetup},
+    price::{OraclePriceFeedAdapter, OraclePriceType, OracleSetup, PriceBias},
 };
 use crate::borsh::{BorshDeserialize, BorshSerialize};
 #[cfg(not(feature = "client"))]
@@ -8,11 +8,18 @@ use crate::events::{GroupEventHeader, LendingPoolBankAccrueInterestEvent};
 use crate::{
     assert_struct_align, assert_struct_size, check,
     constants::{
-        EMISSION_FLAGS, FEE_VAULT_AUTHORITY_SEED, FEE_VAULT_SEED, GROUP_FLAGS,
+        BANK_CLOSED_FLAG, BANK_CONFIG_FROZEN_FLAG, BANK_REGISTRY_FLAG, EMISSION_FLAGS,
+        FEE_VAULT_AUTHORITY_SEED, FEE_VAULT_SEED, FIXED_PRICE_COLLATERAL_FLAG, GROUP_FLAGS,
         INSURANCE_VAULT_AUTHORITY_SEED, INSURANCE_VAULT_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED,
-        LIQUIDITY_VAULT_SEED, MAX_ORACLE_KEYS, MAX_PYTH_ORACLE_AGE, MAX_SWB_ORACLE_AGE,
-        PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG, PYTH_ID, SECONDS_PER_YEAR,
-        TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE,
+        LIQUIDITY_VAULT_SEED, MAINTENANCE_EMA_PRICE_FLAG, MAX_CONF_INTERVAL,
+        MAX_CORRELATION_BUCKETS, MAX_CORRELATION_OFFSET_BPS, MAX_CPI_ALLOWED_PROGRAMS,
+        MAX_INTEREST_RATE_KINKS, MAX_ORACLE_KEYS, MAX_PERMISSIONLESS_MINTS, MAX_PYTH_ORACLE_AGE,
+        MAX_SWB_ORACLE_AGE, MIN_ADAPTIVE_PLATEAU_INTEREST_RATE, MIN_COMPATIBLE_PROGRAM_VERSION,
+        ORACLE_DEVIATION_FLAG, ORACLE_PAUSED_FLAG, ORACLE_ROTATION_DELAY_SLOTS,
+        PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG, PERMISSIONLESS_BANK_CREATION_FLAG,
+        PERMISSIONLESS_LIABILITY_WEIGHT_INIT, PERMISSIONLESS_LIABILITY_WEIGHT_MAINT,
+        PROGRAM_VERSION, PYTH_ID, SECONDS_PER_DAY, TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE,
+        TWAP_OBSERVATIONS, WEIGHT_BPS_DENOMINATOR,
     },
     debug, math_error,
     prelude::MarginfiError,
@@ -24,8 +31,20 @@ use anchor_lang::prelude::borsh;
 use anchor_lang::prelude::*;
 use anchor_spl::token_interface::*;
 use fixed::types::I80F48;
+use marginfi_math::{
+    interest::{
+        self, calc_accrued_interest_payment_per_period, calc_interest_payment_for_period,
+        InterestRateFees,
+    },
+    shares::{amount_to_shares, calc_socialized_share_value, shares_to_amount},
+    weights::{bps_to_weight, weight_to_bps},
+};
 use pyth_sdk_solana::{state::SolanaPriceAccount, PriceFeed};
 use pyth_solana_receiver_sdk::price_update::FeedId;
+use solana_program::{
+    instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT},
+    sysvar::instructions,
+};
 #[cfg(feature = "client")]
 use std::fmt::Display;
 use std::{
@@ -44,7 +63,47 @@ use type_layout::TypeLayout;
 #[derive(Default)]
 pub struct MarginfiGroup {
     pub admin: Pubkey,
-    pub _padding_0: [[u64; 2]; 32],
+    /// Version of the data model the group was created with or last migrated to.
+    /// See [`PROGRAM_VERSION`].
+    pub program_version: u64,
+    /// Share in bps of the group fees generated by referred accounts' borrows that is owed to
+    /// their referrer, see `BankAccountWrapper::settle_group_fees`.
+    pub referral_fee_bps: u16,
+    pub _pad0: [u8; 6],
+    /// Portfolio margin offsets in bps for correlation buckets `1..=MAX_CORRELATION_BUCKETS`,
+    /// see [`BankConfig::correlation_bucket`]. All zero disables portfolio margin for the group.
+    pub correlation_offsets_bps: [u16; MAX_CORRELATION_BUCKETS],
+    /// Program that owns the Pyth legacy price accounts banks of this group may use.
+    /// Defaults to [`PYTH_ID`] when unset, see [`MarginfiGroup::get_pyth_program_id`].
+    pub pyth_program_id: Pubkey,
+    /// Multiplier applied to all initial asset weights while the group is in recovery mode,
+    /// zero when recovery mode is off. See [`MarginfiGroup::get_recovery_asset_weight_multiplier`].
+    pub recovery_asset_weight_multiplier: WrappedI80F48,
+    /// Key that may change the operational state of banks and nothing else, default if unset.
+    /// See [`MarginfiGroup::is_admin_or_risk_council`].
+    pub risk_council: Pubkey,
+    /// Programs allowed to call user instructions through CPI, unrestricted when all default.
+    /// See [`MarginfiGroup::check_cpi_caller`].
+    pub cpi_allowed_programs: [Pubkey; MAX_CPI_ALLOWED_PROGRAMS],
+    /// Key that may withdraw from bank insurance vaults besides the admin, default if unset.
+    /// See [`MarginfiGroup::is_admin_or_insurance_admin`].
+    pub insurance_admin: Pubkey,
+    /// See [`PERMISSIONLESS_BANK_CREATION_FLAG`] and [`BANK_REGISTRY_FLAG`].
+    pub flags: u64,
+    /// Receives the permissionless bank creation fee.
+    pub fee_wallet: Pubkey,
+    /// Lamports paid to `fee_wallet` by whoever adds a permissionless bank.
+    pub permissionless_bank_creation_fee: u64,
+    /// Deposit and borrow limits of permissionless banks in whole tokens,
+    /// see [`BankConfig::permissionless`].
+    pub permissionless_deposit_limit: u64,
+    pub permissionless_borrow_limit: u64,
+    /// Mints permissionless banks can be added for, default entries are unused.
+    pub permissionless_mints: [Pubkey; MAX_PERMISSIONLESS_MINTS],
+    /// Address lookup table with the group's bank, vault and oracle keys, default if unset.
+    /// Owned by the group's lookup table authority PDA, see `marginfi_group_create_lookup_table`.
+    pub lookup_table: Pubkey,
+    pub _padding_0: [[u64; 2]; 1],
     pub _padding_1: [[u64; 2]; 32],
 }
 
@@ -55,6 +114,79 @@ impl MarginfiGroup {
     pub fn configure(&mut self, config: &GroupConfig) -> MarginfiResult {
         set_if_some!(self.admin, config.admin);
 
+        set_if_some!(self.risk_council, config.risk_council);
+
+        set_if_some!(self.insurance_admin, config.insurance_admin);
+
+        if let Some(referral_fee_bps) = config.referral_fee_bps {
+            check!(
+                referral_fee_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+                MarginfiError::InvalidConfig
+            );
+
+            self.referral_fee_bps = referral_fee_bps;
+        }
+
+        set_if_some!(self.cpi_allowed_programs, config.cpi_allowed_programs);
+
+        if let Some(enabled) = config.permissionless_bank_creation {
+            msg!("Setting permissionless bank creation to {}", enabled);
+            if enabled {
+                self.flags |= PERMISSIONLESS_BANK_CREATION_FLAG;
+            } else {
+                self.flags &= !PERMISSIONLESS_BANK_CREATION_FLAG;
+            }
+        }
+
+        set_if_some!(self.fee_wallet, config.fee_wallet);
+
+        set_if_some!(
+            self.permissionless_bank_creation_fee,
+            config.permissionless_bank_creation_fee
+        );
+
+        set_if_some!(
+            self.permissionless_deposit_limit,
+            config.permissionless_deposit_limit
+        );
+
+        set_if_some!(
+            self.permissionless_borrow_limit,
+            config.permissionless_borrow_limit
+        );
+
+        set_if_some!(self.permissionless_mints, config.permissionless_mints);
+
+        // The creation fee can't be collected without a wallet to pay it to.
+        check!(
+            !self.is_permissionless_bank_creation_enabled() || self.fee_wallet != Pubkey::default(),
+            MarginfiError::InvalidConfig
+        );
+
+        if let Some(offsets) = config.correlation_offsets_bps {
+            check!(
+                offsets
+                    .iter()
+                    .all(|offset| *offset <= MAX_CORRELATION_OFFSET_BPS),
+                MarginfiError::InvalidConfig
+            );
+
+            msg!("Setting correlation offsets to {:?} bps", offsets);
+            self.correlation_offsets_bps = offsets;
+        }
+
+        if let Some(multiplier) = config.recovery_asset_weight_multiplier {
+            let multiplier = I80F48::from(multiplier);
+
+            check!(
+                multiplier >= I80F48::ZERO && multiplier <= I80F48::ONE,
+                MarginfiError::InvalidConfig
+            );
+
+            msg!("Setting recovery asset weight multiplier to {}", multiplier);
+            self.recovery_asset_weight_multiplier = multiplier.into();
+        }
+
         Ok(())
     }
 
@@ -62,15 +194,180 @@ impl MarginfiGroup {
     /// This should be called only when the group is first initialized.
     /// Both margin requirements are initially set to 100% and should be configured before use.
     #[allow(clippy::too_many_arguments)]
-    pub fn set_initial_configuration(&mut self, admin_pk: Pubkey) {
+    pub fn set_initial_configuration(&mut self, admin_pk: Pubkey, pyth_program_id: Option<Pubkey>) {
         self.admin = admin_pk;
+        self.program_version = PROGRAM_VERSION;
+        self.pyth_program_id = pyth_program_id.unwrap_or_default();
+    }
+
+    /// Pyth program accepted as owner of legacy price accounts,
+    /// falls back to the cluster's [`PYTH_ID`] for groups that don't set one.
+    pub fn get_pyth_program_id(&self) -> Pubkey {
+        if self.pyth_program_id == Pubkey::default() {
+            PYTH_ID
+        } else {
+            self.pyth_program_id
+        }
+    }
+
+    /// Recovery mode tightens the initial weight of every deposit by this multiplier,
+    /// stopping new risk while repays and withdraws keep working. `None` when inactive.
+    pub fn get_recovery_asset_weight_multiplier(&self) -> Option<I80F48> {
+        let multiplier = I80F48::from(self.recovery_asset_weight_multiplier);
+
+        if multiplier == I80F48::ZERO {
+            None
+        } else {
+            Some(multiplier)
+        }
+    }
+
+    /// Bank operational states can be flipped by the admin or the risk council.
+    pub fn is_admin_or_risk_council(&self, signer: &Pubkey) -> bool {
+        *signer == self.admin
+            || (self.risk_council != Pubkey::default() && *signer == self.risk_council)
+    }
+
+    /// Insurance vaults can be drawn down by the admin or the insurance admin.
+    pub fn is_admin_or_insurance_admin(&self, signer: &Pubkey) -> bool {
+        *signer == self.admin
+            || (self.insurance_admin != Pubkey::default() && *signer == self.insurance_admin)
+    }
+
+    /// See [`crate::state::bank_registry::BankRegistry`].
+    pub fn has_bank_registry(&self) -> bool {
+        self.flags & BANK_REGISTRY_FLAG != 0
+    }
+
+    pub fn is_permissionless_bank_creation_enabled(&self) -> bool {
+        self.flags & PERMISSIONLESS_BANK_CREATION_FLAG != 0
+    }
+
+    /// Anyone can add a bank for `mint` when permissionless bank creation is enabled
+    /// and the mint is whitelisted.
+    pub fn check_permissionless_bank_mint(&self, mint: &Pubkey) -> MarginfiResult {
+        check!(
+            self.is_permissionless_bank_creation_enabled()
+                && *mint != Pubkey::default()
+                && self.permissionless_mints.contains(mint),
+            MarginfiError::PermissionlessBankCreationNotAllowed
+        );
+
+        Ok(())
+    }
+
+    /// Whether `program_id` may call user instructions of this group through CPI.
+    pub fn is_cpi_caller_allowed(&self, program_id: &Pubkey) -> bool {
+        self.cpi_allowed_programs
+            .iter()
+            .all(|allowed| *allowed == Pubkey::default())
+            || self
+                .cpi_allowed_programs
+                .iter()
+                .any(|allowed| *allowed != Pubkey::default() && allowed == program_id)
+    }
+
+    /// User instructions invoked through CPI have to come from an allowed program,
+    /// identified as the program of the top level instruction via the instructions sysvar.
+    /// Such callers append the instructions sysvar to the instruction's remaining accounts.
+    ///
+    /// Top level invocations and groups without an allow-list are always accepted,
+    /// and don't need the sysvar.
+    pub fn check_cpi_caller(&self, remaining_ais: &[AccountInfo]) -> MarginfiResult {
+        if self
+            .cpi_allowed_programs
+            .iter()
+            .all(|allowed| *allowed == Pubkey::default())
+            || get_stack_height() == TRANSACTION_LEVEL_STACK_HEIGHT
+        {
+            return Ok(());
+        }
+
+        let ixs_sysvar = remaining_ais
+            .iter()
+            .find(|ai| instructions::check_id(ai.key))
+            .ok_or(MarginfiError::CpiCallerNotAllowed)?;
+        let current_ix_idx = instructions::load_current_index_checked(ixs_sysvar)?;
+        let current_ix =
+            instructions::load_instruction_at_checked(current_ix_idx.into(), ixs_sysvar)?;
+
+        check!(
+            self.is_cpi_caller_allowed(&current_ix.program_id),
+            MarginfiError::CpiCallerNotAllowed
+        );
+
+        Ok(())
     }
+
+    /// Instructions only operate on groups whose data model this program understands.
+    /// Groups below [`MIN_COMPATIBLE_PROGRAM_VERSION`] have to be migrated first.
+    pub fn check_program_version(&self) -> MarginfiResult {
+        check_program_version(self.program_version)
+    }
+
+    /// Migrate the group one version at a time up to [`PROGRAM_VERSION`].
+    /// Returns the version the group was on before the migration.
+    pub fn migrate(&mut self) -> MarginfiResult<u64> {
+        let old_version = self.program_version;
+
+        check!(
+            old_version <= PROGRAM_VERSION,
+            MarginfiError::IncompatibleProgramVersion
+        );
+
+        while self.program_version < PROGRAM_VERSION {
+            match self.program_version {
+                // v0 -> v1: introduces `program_version`, no data changes.
+                0 => {}
+                // v1 -> v2: new group, bank and account fields taken from padding, their zero
+                // value is the default.
+                1 => {}
+                // v2 -> v3: `Bank::group_fee_growth` widened into the padding behind it, existing
+                // values read the same. `Bank::referral_fees_outstanding` starts at zero.
+                2 => {}
+                _ => return Err(MarginfiError::IncompatibleProgramVersion.into()),
+            }
+
+            self.program_version += 1;
+        }
+
+        Ok(old_version)
+    }
+}
+
+/// Check an account's `program_version` is one this program understands, see
+/// [`MarginfiGroup::check_program_version`].
+pub fn check_program_version(program_version: u64) -> MarginfiResult {
+    check!(
+        (MIN_COMPATIBLE_PROGRAM_VERSION..=PROGRAM_VERSION).contains(&program_version),
+        MarginfiError::IncompatibleProgramVersion
+    );
+
+    Ok(())
 }
 
 #[cfg_attr(any(feature = "test", feature = "client"), derive(TypeLayout))]
 #[derive(AnchorSerialize, AnchorDeserialize, Default, Debug, Clone)]
 pub struct GroupConfig {
     pub admin: Option<Pubkey>,
+    pub correlation_offsets_bps: Option<[u16; MAX_CORRELATION_BUCKETS]>,
+    /// Set to a value in (0, 1] to enter recovery mode, zero leaves it.
+    pub recovery_asset_weight_multiplier: Option<WrappedI80F48>,
+    /// Set to the default pubkey to remove the risk council.
+    pub risk_council: Option<Pubkey>,
+    /// Set to all default pubkeys to allow any CPI caller.
+    pub cpi_allowed_programs: Option<[Pubkey; MAX_CPI_ALLOWED_PROGRAMS]>,
+    /// Set to the default pubkey to remove the insurance admin.
+    pub insurance_admin: Option<Pubkey>,
+    pub referral_fee_bps: Option<u16>,
+    /// Requires a `fee_wallet` to be set.
+    pub permissionless_bank_creation: Option<bool>,
+    pub fee_wallet: Option<Pubkey>,
+    pub permissionless_bank_creation_fee: Option<u64>,
+    pub permissionless_deposit_limit: Option<u64>,
+    pub permissionless_borrow_limit: Option<u64>,
+    /// Set to all default pubkeys to remove every mint from the whitelist.
+    pub permissionless_mints: Option<[Pubkey; MAX_PERMISSIONLESS_MINTS]>,
 }
 
 /// Load and validate a pyth price feed account.
@@ -110,7 +407,16 @@ impl From<InterestRateConfigCompact> for InterestRateConfig {
             insurance_ir_fee: ir_config.insurance_ir_fee,
             protocol_fixed_fee_apr: ir_config.protocol_fixed_fee_apr,
             protocol_ir_fee: ir_config.protocol_ir_fee,
-            _padding: [[0; 2]; 8],
+            prev_optimal_utilization_rate: I80F48::ZERO.into(),
+            prev_plateau_interest_rate: I80F48::ZERO.into(),
+            prev_max_interest_rate: I80F48::ZERO.into(),
+            curve_ramp_start: 0,
+            curve_ramp_duration: 0,
+            kinks: Default::default(),
+            adaptive_rate_speed_bps: 0,
+            _pad0: [0; 4],
+            max_borrow_apr_cap: I80F48::ZERO.into(),
+            min_lending_apr: I80F48::ZERO.into(),
         }
     }
 }
@@ -148,7 +454,56 @@ pub struct InterestRateConfig {
     pub protocol_fixed_fee_apr: WrappedI80F48,
     pub protocol_ir_fee: WrappedI80F48,
 
-    pub _padding: [[u64; 2]; 8], // 16 * 8 = 128 bytes
+    // Curve ramp, see `InterestRateConfig::ramped`
+    pub prev_optimal_utilization_rate: WrappedI80F48,
+    pub prev_plateau_interest_rate: WrappedI80F48,
+    pub prev_max_interest_rate: WrappedI80F48,
+    pub curve_ramp_start: i64,
+    /// Seconds over which curve param changes are phased in, 0 applies them immediately.
+    pub curve_ramp_duration: u64,
+
+    /// Additional curve points between `optimal_utilization_rate` and 100% utilization, unused
+    /// points are zeroed and come last. Kinks are not ramped.
+    pub kinks: [InterestRateKink; MAX_INTEREST_RATE_KINKS],
+
+    /// Daily drift in bps of the plateau rate while utilization is at 0% or 100%, scaled down
+    /// closer to the optimal utilization rate. Zero disables the adaptive rate controller,
+    /// see [`Bank::get_adaptive_plateau_interest_rate`].
+    pub adaptive_rate_speed_bps: u32,
+    pub _pad0: [u8; 4],
+
+    /// Upper bound of the borrowing rate after fees, zero if unbounded.
+    /// See [`InterestRateConfig::calc_interest_rate`].
+    pub max_borrow_apr_cap: WrappedI80F48,
+
+    /// Lending rate guaranteed to depositors, zero if none. The shortfall below it is paid out of
+    /// the bank's lending subsidy reserve as interest accrues,
+    /// see [`Bank::lending_subsidy_reserve`].
+    pub min_lending_apr: WrappedI80F48, // 8 * 3 + 8 + 16 + 16 = 64 bytes
+}
+
+/// Interest rate curve point in basis points, 10_000 bps = 100% utilization or 100% APR.
+#[zero_copy]
+#[repr(C)]
+#[cfg_attr(any(feature = "test", feature = "client"), derive(TypeLayout))]
+#[derive(Default, Debug, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct InterestRateKink {
+    pub utilization_bps: u32,
+    pub rate_bps: u32,
+}
+
+impl InterestRateKink {
+    pub fn is_active(&self) -> bool {
+        self.utilization_bps != 0
+    }
+
+    /// (utilization ratio, interest rate)
+    pub fn to_point(&self) -> MarginfiResult<(I80F48, I80F48)> {
+        Ok((
+            WeightsBps::to_weight(self.utilization_bps)?,
+            WeightsBps::to_weight(self.rate_bps)?,
+        ))
+    }
 }
 
 impl InterestRateConfig {
@@ -156,76 +511,55 @@ impl InterestRateConfig {
     /// Rate is denominated in APR (0-).
     ///
     /// Return (`lending_rate`, `borrowing_rate`, `group_fees_apr`, `insurance_fees_apr`)
+    ///
+    /// With a `max_borrow_apr_cap` the base rate is lowered until the borrowing rate fits under
+    /// the cap, so lenders and fee rates give up their share of the excess in proportion.
     pub fn calc_interest_rate(
         &self,
         utilization_ratio: I80F48,
     ) -> Option<(I80F48, I80F48, I80F48, I80F48)> {
-        let protocol_ir_fee = I80F48::from(self.protocol_ir_fee);
-        let insurance_ir_fee = I80F48::from(self.insurance_ir_fee);
-
-        let protocol_fixed_fee_apr = I80F48::from(self.protocol_fixed_fee_apr);
-        let insurance_fee_fixed_apr = I80F48::from(self.insurance_fee_fixed_apr);
-
-        let rate_fee = protocol_ir_fee + insurance_ir_fee;
-        let total_fixed_fee_apr = protocol_fixed_fee_apr + insurance_fee_fixed_apr;
-
-        let base_rate = self.interest_rate_curve(utilization_ratio)?;
-
-        // Lending rate is adjusted for utilization ratio to symmetrize payments between borrowers and depositors.
-        let lending_rate = base_rate.checked_mul(utilization_ratio)?;
-
-        // Borrowing rate is adjusted for fees.
-        // borrowing_rate = base_rate + base_rate * rate_fee + total_fixed_fee_apr
-        let borrowing_rate = base_rate
-            .checked_mul(I80F48::ONE.checked_add(rate_fee)?)?
-            .checked_add(total_fixed_fee_apr)?;
-
-        let group_fees_apr = calc_fee_rate(
-            base_rate,
-            self.protocol_ir_fee.into(),
-            self.protocol_fixed_fee_apr.into(),
-        )?;
-
-        let insurance_fees_apr = calc_fee_rate(
-            base_rate,
-            self.insurance_ir_fee.into(),
-            self.insurance_fee_fixed_apr.into(),
-        )?;
+        self.calc_interest_rate_smoothed(utilization_ratio, utilization_ratio)
+    }
 
-        assert!(lending_rate >= I80F48::ZERO);
-        assert!(borrowing_rate >= I80F48::ZERO);
-        assert!(group_fees_apr >= I80F48::ZERO);
-        assert!(insurance_fees_apr >= I80F48::ZERO);
+    /// [`InterestRateConfig::calc_interest_rate`] with the curve evaluated at the smoothed
+    /// `curve_utilization_ratio`. The lending rate is still scaled by the actual
+    /// `utilization_ratio` so depositors receive exactly the base interest borrowers pay.
+    pub fn calc_interest_rate_smoothed(
+        &self,
+        utilization_ratio: I80F48,
+        curve_utilization_ratio: I80F48,
+    ) -> Option<(I80F48, I80F48, I80F48, I80F48)> {
+        let base_rate = self.interest_rate_curve(curve_utilization_ratio)?;
 
         // TODO: Add liquidation discount check
 
-        Some((
-            lending_rate,
-            borrowing_rate,
-            group_fees_apr,
-            insurance_fees_apr,
-        ))
+        interest::calc_interest_rate(
+            base_rate,
+            utilization_ratio,
+            &InterestRateFees {
+                protocol_ir_fee: self.protocol_ir_fee.into(),
+                insurance_ir_fee: self.insurance_ir_fee.into(),
+                protocol_fixed_fee_apr: self.protocol_fixed_fee_apr.into(),
+                insurance_fee_fixed_apr: self.insurance_fee_fixed_apr.into(),
+            },
+            self.max_borrow_apr_cap.into(),
+        )
     }
 
-    /// Piecewise linear interest rate function.
-    /// The curves approaches the `plateau_interest_rate` as the utilization ratio approaches the `optimal_utilization_rate`,
-    /// once the utilization ratio exceeds the `optimal_utilization_rate`, the curve approaches the `max_interest_rate`.
-    ///
-    /// To be clear we don't particularly appreciate the piecewise linear nature of this "curve", but it is what it is.
+    /// Base rate of the curve through the plateau at the `optimal_utilization_rate`, the active
+    /// `kinks` and the `max_interest_rate`, see [`interest::interest_rate_curve`].
     #[inline]
     fn interest_rate_curve(&self, ur: I80F48) -> Option<I80F48> {
-        let optimal_ur = self.optimal_utilization_rate.into();
-        let plateau_ir = self.plateau_interest_rate.into();
-        let max_ir: I80F48 = self.max_interest_rate.into();
-
-        if ur <= optimal_ur {
-            ur.checked_div(optimal_ur)?.checked_mul(plateau_ir)
-        } else {
-            (ur - optimal_ur)
-                .checked_div(I80F48::ONE - optimal_ur)?
-                .checked_mul(max_ir - plateau_ir)?
-                .checked_add(plateau_ir)
-        }
+        interest::interest_rate_curve(
+            ur,
+            self.optimal_utilization_rate.into(),
+            self.plateau_interest_rate.into(),
+            self.max_interest_rate.into(),
+            self.kinks
+                .iter()
+                .filter(|kink| kink.is_active())
+                .filter_map(|kink| kink.to_point().ok()),
+        )
     }
 
     pub fn validate(&self) -> MarginfiResult {
@@ -241,16 +575,181 @@ impl InterestRateConfig {
         check!(max_ir > I80F48::ZERO, MarginfiError::InvalidConfig);
         check!(plateau_ir < max_ir, MarginfiError::InvalidConfig);
 
+        // Kinks must increase in utilization and must not decrease in rate
+        let mut prev = (optimal_ur, plateau_ir);
+        let mut inactive_seen = false;
+
+        for kink in self.kinks.iter() {
+            if !kink.is_active() {
+                check!(kink.rate_bps == 0, MarginfiError::InvalidConfig);
+                inactive_seen = true;
+                continue;
+            }
+
+            check!(!inactive_seen, MarginfiError::InvalidConfig);
+
+            let (kink_ur, kink_ir) = kink.to_point()?;
+
+            check!(
+                kink_ur > prev.0 && kink_ur < I80F48::ONE,
+                MarginfiError::InvalidConfig
+            );
+            check!(kink_ir >= prev.1, MarginfiError::InvalidConfig);
+
+            prev = (kink_ur, kink_ir);
+        }
+
+        check!(prev.1 <= max_ir, MarginfiError::InvalidConfig);
+
+        // Fees are never negative, and the rate based fees together can at most double the base rate
+        let insurance_ir_fee = I80F48::from(self.insurance_ir_fee);
+        let protocol_ir_fee = I80F48::from(self.protocol_ir_fee);
+
+        check!(
+            insurance_ir_fee >= I80F48::ZERO
+                && protocol_ir_fee >= I80F48::ZERO
+                && I80F48::from(self.insurance_fee_fixed_apr) >= I80F48::ZERO
+                && I80F48::from(self.protocol_fixed_fee_apr) >= I80F48::ZERO,
+            MarginfiError::InvalidConfig
+        );
+        check!(
+            insurance_ir_fee
+                .checked_add(protocol_ir_fee)
+                .ok_or_else(math_error!())?
+                <= I80F48::ONE,
+            MarginfiError::InvalidConfig
+        );
+
+        // The cap has to leave room for the fixed fees
+        let max_borrow_apr_cap = I80F48::from(self.max_borrow_apr_cap);
+        let total_fixed_fee_apr = I80F48::from(self.protocol_fixed_fee_apr)
+            .checked_add(self.insurance_fee_fixed_apr.into())
+            .ok_or_else(math_error!())?;
+
+        check!(
+            max_borrow_apr_cap == I80F48::ZERO || max_borrow_apr_cap > total_fixed_fee_apr,
+            MarginfiError::InvalidConfig
+        );
+
+        let min_lending_apr = I80F48::from(self.min_lending_apr);
+
+        check!(
+            min_lending_apr >= I80F48::ZERO && min_lending_apr <= max_ir,
+            MarginfiError::InvalidConfig
+        );
+
         Ok(())
     }
 
-    pub fn update(&mut self, ir_config: &InterestRateConfigOpt) {
+    /// Amount owed to depositors over `time_delta` to lift the lending rate at `utilization_ratio`
+    /// (with the curve at `curve_utilization_ratio`) up to `min_lending_apr`.
+    pub fn calc_lending_subsidy(
+        &self,
+        utilization_ratio: I80F48,
+        curve_utilization_ratio: I80F48,
+        total_assets_amount: I80F48,
+        time_delta: u64,
+    ) -> MarginfiResult<I80F48> {
+        let min_lending_apr = I80F48::from(self.min_lending_apr);
+
+        if min_lending_apr == I80F48::ZERO || total_assets_amount == I80F48::ZERO {
+            return Ok(I80F48::ZERO);
+        }
+
+        let (lending_apr, _, _, _) = self
+            .calc_interest_rate_smoothed(utilization_ratio, curve_utilization_ratio)
+            .ok_or_else(math_error!())?;
+
+        if lending_apr >= min_lending_apr {
+            return Ok(I80F48::ZERO);
+        }
+
+        Ok(calc_interest_payment_for_period(
+            min_lending_apr - lending_apr,
+            time_delta,
+            total_assets_amount,
+        )
+        .ok_or_else(math_error!())?)
+    }
+
+    /// Curve params in effect at `timestamp`.
+    ///
+    /// After a curve update the params are interpolated linearly from the `prev_*` curve to the
+    /// current one over `curve_ramp_duration` seconds, so existing borrowers don't see rate shocks.
+    /// Fees are not ramped.
+    pub fn ramped(&self, timestamp: i64) -> Option<InterestRateConfig> {
+        let ramp_end = self
+            .curve_ramp_start
+            .checked_add(self.curve_ramp_duration.try_into().ok()?)?;
+
+        if timestamp >= ramp_end {
+            return Some(*self);
+        }
+
+        let elapsed = timestamp.checked_sub(self.curve_ramp_start)?.max(0);
+        let progress =
+            I80F48::from_num(elapsed).checked_div(I80F48::from_num(self.curve_ramp_duration))?;
+
+        let interpolate = |prev: WrappedI80F48, current: WrappedI80F48| -> Option<WrappedI80F48> {
+            let prev = I80F48::from(prev);
+            let current = I80F48::from(current);
+
+            Some(
+                current
+                    .checked_sub(prev)?
+                    .checked_mul(progress)?
+                    .checked_add(prev)?
+                    .into(),
+            )
+        };
+
+        Some(InterestRateConfig {
+            optimal_utilization_rate: interpolate(
+                self.prev_optimal_utilization_rate,
+                self.optimal_utilization_rate,
+            )?,
+            plateau_interest_rate: interpolate(
+                self.prev_plateau_interest_rate,
+                self.plateau_interest_rate,
+            )?,
+            max_interest_rate: interpolate(self.prev_max_interest_rate, self.max_interest_rate)?,
+            ..*self
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        ir_config: &InterestRateConfigOpt,
+        current_timestamp: i64,
+    ) -> MarginfiResult {
+        if ir_config.optimal_utilization_rate.is_some()
+            || ir_config.plateau_interest_rate.is_some()
+            || ir_config.max_interest_rate.is_some()
+            || ir_config.curve_ramp_duration.is_some()
+        {
+            // Ramp from the curve currently in effect, which may be mid-ramp itself
+            let current = self.ramped(current_timestamp).ok_or_else(math_error!())?;
+
+            self.prev_optimal_utilization_rate = current.optimal_utilization_rate;
+            self.prev_plateau_interest_rate = current.plateau_interest_rate;
+            self.prev_max_interest_rate = current.max_interest_rate;
+            self.curve_ramp_start = current_timestamp;
+        }
+
+        set_if_some!(self.curve_ramp_duration, ir_config.curve_ramp_duration);
         set_if_some!(
             self.optimal_utilization_rate,
             ir_config.optimal_utilization_rate
         );
         set_if_some!(self.plateau_interest_rate, ir_config.plateau_interest_rate);
         set_if_some!(self.max_interest_rate, ir_config.max_interest_rate);
+        set_if_some!(self.kinks, ir_config.kinks);
+        set_if_some!(
+            self.adaptive_rate_speed_bps,
+            ir_config.adaptive_rate_speed_bps
+        );
+        set_if_some!(self.max_borrow_apr_cap, ir_config.max_borrow_apr_cap);
+        set_if_some!(self.min_lending_apr, ir_config.min_lending_apr);
         set_if_some!(
             self.insurance_fee_fixed_apr,
             ir_config.insurance_fee_fixed_apr
@@ -261,6 +760,8 @@ impl InterestRateConfig {
             ir_config.protocol_fixed_fee_apr
         );
         set_if_some!(self.protocol_ir_fee, ir_config.protocol_ir_fee);
+
+        Ok(())
     }
 }
 
@@ -278,6 +779,16 @@ pub struct InterestRateConfigOpt {
     pub insurance_ir_fee: Option<WrappedI80F48>,
     pub protocol_fixed_fee_apr: Option<WrappedI80F48>,
     pub protocol_ir_fee: Option<WrappedI80F48>,
+
+    pub curve_ramp_duration: Option<u64>,
+
+    pub kinks: Option<[InterestRateKink; MAX_INTEREST_RATE_KINKS]>,
+
+    pub adaptive_rate_speed_bps: Option<u32>,
+
+    pub max_borrow_apr_cap: Option<WrappedI80F48>,
+
+    pub min_lending_apr: Option<WrappedI80F48>,
 }
 
 assert_struct_size!(Bank, 1856);
@@ -342,8 +853,113 @@ pub struct Bank {
     pub emissions_remaining: WrappedI80F48,
     pub emissions_mint: Pubkey,
 
-    pub _padding_0: [[u64; 2]; 28],
-    pub _padding_1: [[u64; 2]; 32], // 16 * 2 * 32 = 1024B
+    /// Share value of insured deposits, see [`Balance::insured`].
+    /// Insured deposits pay a premium on their yield and absorb socialized losses last.
+    pub insured_asset_share_value: WrappedI80F48,
+    pub total_insured_asset_shares: WrappedI80F48,
+
+    /// Last time the bank left the `Paused` state, see [`Bank::get_liquidation_grace_fee`].
+    pub unpaused_at: i64,
+    /// Seconds after an unpause during which liquidations involving the bank are capped at
+    /// `liquidation_grace_fee_bps`, so users can top up after an oracle outage. Zero disables it.
+    pub liquidation_grace_period: u32,
+    /// Liquidator fee cap during the grace period, zero disables liquidations entirely.
+    pub liquidation_grace_fee_bps: u16,
+    pub _pad3: [u8; 2],
+
+    /// Max native amount that can be borrowed from the bank within one window,
+    /// see [`Bank::record_borrow_volume`].
+    pub borrow_window_cap: u64,
+    /// Amount borrowed in the window starting at `borrow_window_start_slot`.
+    pub borrow_window_volume: u64,
+    pub borrow_window_start_slot: u64,
+    /// Window length in slots, zero disables the borrow circuit breaker.
+    pub borrow_window_slots: u32,
+    pub _pad4: [u8; 4],
+
+    /// Oracle proposed through `lending_pool_configure_bank`, applied by
+    /// [`Bank::accept_pending_oracle`] once the rotation delay has passed.
+    pub pending_oracle_keys: [Pubkey; MAX_ORACLE_KEYS],
+    /// `OracleSetup::None` when no rotation is pending.
+    pub pending_oracle_setup: OracleSetup,
+    pub _pad5: [u8; 7],
+    /// Slot the pending oracle was proposed at.
+    pub pending_oracle_slot: u64,
+
+    /// Seconds of recorded prices averaged into the TWAP that bounds initial requirements,
+    /// zero disables it, see [`Bank::get_twap_price`].
+    pub twap_window: u32,
+    /// Index of the latest observation in `twap_prices`.
+    pub twap_head: u8,
+    pub _pad6: [u8; 11],
+    /// Ring buffer of oracle prices recorded by `lending_pool_check_bank_oracle`.
+    pub twap_prices: [WrappedI80F48; TWAP_OBSERVATIONS],
+    /// Timestamps of `twap_prices`, zero for an empty slot.
+    pub twap_timestamps: [i64; TWAP_OBSERVATIONS],
+
+    /// Fee vault tokens paid to keepers accruing interest through
+    /// `lending_pool_crank_bank_interest`, zero disables the bounty.
+    pub accrual_bounty: u64,
+    /// Seconds since the last accrual after which the bounty is paid,
+    /// see [`Bank::get_accrual_bounty`].
+    pub accrual_bounty_min_age: u32,
+    pub _pad7: [u8; 4],
+
+    /// Plateau interest rate steered by the adaptive rate controller, zero until the controller
+    /// first runs. See [`Bank::get_adaptive_plateau_interest_rate`].
+    pub adaptive_plateau_interest_rate: WrappedI80F48,
+
+    /// Lifetime totals accrued by the bank, only ever increased by [`Bank::accrue_interest`].
+    pub lifetime_insurance_fees_collected: WrappedI80F48,
+    pub lifetime_group_fees_collected: WrappedI80F48,
+    /// Interest credited to depositors, net of deposit insurance premiums.
+    pub lifetime_lender_interest_paid: WrappedI80F48,
+
+    /// Fee vault tokens moved into the liquidity vault to pay the lending rate floor,
+    /// see [`InterestRateConfig::min_lending_apr`]. The group admin funds it through
+    /// `lending_pool_fund_lending_subsidy`, `lending_pool_accrue_bank_interest` moves it back to the
+    /// fee vault once the floor is removed.
+    /// [`Bank::accrue_interest`] pays the subsidy to the depositors of each period out of it,
+    /// whatever it can't cover is not owed.
+    pub lending_subsidy_reserve: WrappedI80F48,
+
+    /// Seconds over which utilization is averaged before the interest rate curve is applied,
+    /// zero uses the current utilization. See [`Bank::update_smoothed_utilization_rate`].
+    pub utilization_smoothing_window: u32,
+    pub _pad8: [u8; 12],
+    /// Time weighted utilization, only maintained while `utilization_smoothing_window` is set.
+    pub smoothed_utilization_rate: WrappedI80F48,
+
+    /// APR charged on all deposits and paid to the insurance vault regardless of utilization,
+    /// zero disables it. See [`Bank::charge_insurance_deposit_fee`].
+    pub insurance_deposit_fee_apr: WrappedI80F48,
+
+    /// Most a single account can hold deposited in the bank, in native tokens, zero if
+    /// unlimited. Interest can push a balance past it, only new deposits are rejected.
+    pub max_deposit_per_account: u64,
+    /// Smallest liability an account can hold after borrowing, in native tokens, zero if unset.
+    pub min_borrow_amount: u64,
+
+    /// Balances reduced below this many native tokens are zeroed, zero disables it.
+    /// See [`Bank::sweep_dust`].
+    pub dust_threshold: u64,
+    /// Highest utilization in bps a borrow can leave the bank at, keeping a withdrawal buffer
+    /// for lenders. Zero if unlimited, see [`Bank::check_borrow_utilization`].
+    pub max_borrow_utilization_bps: u16,
+    /// Share of total deposits in bps that must stay in the liquidity vault after a borrow,
+    /// so it can never be lent out. Zero if disabled, see [`Bank::check_liquidity_reserve`].
+    pub liquidity_reserve_bps: u16,
+    pub _pad10: [u8; 4],
+
+    /// Group fees charged per liability share since the bank was created, wraps around.
+    /// Balances keep the low 64 bits of it, only the growth between two readings is meaningful,
+    /// see [`Bank::get_group_fees_since`].
+    pub group_fee_growth: WrappedI80F48,
+    /// Group fees shared with referrers and not claimed yet, kept in the liquidity vault.
+    /// Fee collection leaves them alone, see [`Bank::share_group_fees`].
+    pub referral_fees_outstanding: WrappedI80F48,
+
+    pub _padding_1: [[u64; 2]; 21], // 16 * 2 * 21 = 672B
 }
 
 impl Bank {
@@ -383,6 +999,8 @@ impl Bank {
             collected_group_fees_outstanding: I80F48::ZERO.into(),
             total_liability_shares: I80F48::ZERO.into(),
             total_asset_shares: I80F48::ZERO.into(),
+            insured_asset_share_value: I80F48::ONE.into(),
+            total_insured_asset_shares: I80F48::ZERO.into(),
             last_update: current_timestamp,
             config,
             flags: 0,
@@ -394,42 +1012,74 @@ impl Bank {
     }
 
     pub fn get_liability_amount(&self, shares: I80F48) -> MarginfiResult<I80F48> {
-        Ok(shares
-            .checked_mul(self.liability_share_value.into())
-            .ok_or_else(math_error!())?)
+        Ok(
+            shares_to_amount(shares, self.liability_share_value.into())
+                .ok_or_else(math_error!())?,
+        )
+    }
+
+    /// Share value of the insured or uninsured deposit share class.
+    /// An empty insured class tracks the uninsured share value.
+    pub fn get_asset_share_value(&self, insured: bool) -> I80F48 {
+        if insured && I80F48::from(self.total_insured_asset_shares) != I80F48::ZERO {
+            self.insured_asset_share_value.into()
+        } else {
+            self.asset_share_value.into()
+        }
+    }
+
+    pub fn get_asset_amount(&self, shares: I80F48, insured: bool) -> MarginfiResult<I80F48> {
+        Ok(
+            shares_to_amount(shares, self.get_asset_share_value(insured))
+                .ok_or_else(math_error!())?,
+        )
     }
 
-    pub fn get_asset_amount(&self, shares: I80F48) -> MarginfiResult<I80F48> {
-        Ok(shares
-            .checked_mul(self.asset_share_value.into())
+    /// Total deposits of both share classes.
+    pub fn get_total_asset_amount(&self) -> MarginfiResult<I80F48> {
+        Ok(self
+            .get_asset_amount(self.total_asset_shares.into(), false)?
+            .checked_add(self.get_asset_amount(self.total_insured_asset_shares.into(), true)?)
             .ok_or_else(math_error!())?)
     }
 
     pub fn get_liability_shares(&self, value: I80F48) -> MarginfiResult<I80F48> {
-        Ok(value
-            .checked_div(self.liability_share_value.into())
-            .ok_or_else(math_error!())?)
+        Ok(amount_to_shares(value, self.liability_share_value.into()).ok_or_else(math_error!())?)
     }
 
-    pub fn get_asset_shares(&self, value: I80F48) -> MarginfiResult<I80F48> {
-        Ok(value
-            .checked_div(self.asset_share_value.into())
+    pub fn get_asset_shares(&self, value: I80F48, insured: bool) -> MarginfiResult<I80F48> {
+        Ok(amount_to_shares(value, self.get_asset_share_value(insured))
             .ok_or_else(math_error!())?)
     }
 
     pub fn change_asset_shares(
         &mut self,
         shares: I80F48,
+        insured: bool,
         bypass_deposit_limit: bool,
     ) -> MarginfiResult {
-        let total_asset_shares: I80F48 = self.total_asset_shares.into();
-        self.total_asset_shares = total_asset_shares
-            .checked_add(shares)
-            .ok_or_else(math_error!())?
-            .into();
+        if insured {
+            let total_insured_asset_shares: I80F48 = self.total_insured_asset_shares.into();
+
+            // The class is (re)started at the uninsured share value
+            if total_insured_asset_shares == I80F48::ZERO {
+                self.insured_asset_share_value = self.asset_share_value;
+            }
+
+            self.total_insured_asset_shares = total_insured_asset_shares
+                .checked_add(shares)
+                .ok_or_else(math_error!())?
+                .into();
+        } else {
+            let total_asset_shares: I80F48 = self.total_asset_shares.into();
+            self.total_asset_shares = total_asset_shares
+                .checked_add(shares)
+                .ok_or_else(math_error!())?
+                .into();
+        }
 
         if shares.is_positive() && self.config.is_deposit_limit_active() && !bypass_deposit_limit {
-            let total_deposits_amount = self.get_asset_amount(self.total_asset_shares.into())?;
+            let total_deposits_amount = self.get_total_asset_amount()?;
             let deposit_limit = I80F48::from_num(self.config.deposit_limit);
 
             check!(
@@ -441,13 +1091,67 @@ impl Bank {
         Ok(())
     }
 
+    /// Fails if an account holding `asset_amount` in the bank exceeds `max_deposit_per_account`.
+    pub fn check_account_deposit_limit(&self, asset_amount: I80F48) -> MarginfiResult {
+        if self.max_deposit_per_account == 0 {
+            return Ok(());
+        }
+
+        check!(
+            asset_amount <= I80F48::from_num(self.max_deposit_per_account),
+            MarginfiError::AccountDepositCapacityExceeded
+        );
+
+        Ok(())
+    }
+
+    /// Fails if an account borrowing up to `liability_amount` stays below `min_borrow_amount`.
+    pub fn check_min_borrow_amount(&self, liability_amount: I80F48) -> MarginfiResult {
+        check!(
+            liability_amount >= I80F48::from_num(self.min_borrow_amount),
+            MarginfiError::BorrowBelowMinimum
+        );
+
+        Ok(())
+    }
+
+    /// Dust left on a balance side below `dust_threshold` once it was reduced, zero otherwise.
+    pub fn get_dust_amount(&self, amount: I80F48) -> I80F48 {
+        if amount.is_positive() && amount < I80F48::from_num(self.dust_threshold) {
+            amount
+        } else {
+            I80F48::ZERO
+        }
+    }
+
+    /// Write off `dust_amount` of a balance that just dropped below `dust_threshold`:
+    /// deposit dust is donated to the insurance fund, liability dust is socialized
+    /// between depositors. The caller removes the balance's shares.
+    pub fn sweep_dust(&mut self, dust_amount: I80F48, side: BalanceSide) -> MarginfiResult {
+        debug!("Sweeping {} of {:?} dust", dust_amount, side);
+
+        match side {
+            BalanceSide::Assets => {
+                self.collected_insurance_fees_outstanding = {
+                    dust_amount
+                        .checked_add(self.collected_insurance_fees_outstanding.into())
+                        .ok_or_else(math_error!())?
+                        .into()
+                };
+            }
+            BalanceSide::Liabilities => self.socialize_loss(dust_amount)?,
+        }
+
+        Ok(())
+    }
+
     pub fn maybe_get_asset_weight_init_discount(
         &self,
         price: I80F48,
     ) -> MarginfiResult<Option<I80F48>> {
         if self.config.usd_init_limit_active() {
             let bank_total_assets_value = calc_value(
-                self.get_asset_amount(self.total_asset_shares.into())?,
+                self.get_total_asset_amount()?,
                 price,
                 self.mint_decimals,
                 None,
@@ -509,7 +1213,7 @@ impl Bank {
     }
 
     pub fn check_utilization_ratio(&self) -> MarginfiResult {
-        let total_assets = self.get_asset_amount(self.total_asset_shares.into())?;
+        let total_assets = self.get_total_asset_amount()?;
         let total_liabilities = self.get_liability_amount(self.total_liability_shares.into())?;
 
         check!(
@@ -521,6 +1225,18 @@ impl Bank {
     }
 
     pub fn configure(&mut self, config: &BankConfigOpt) -> MarginfiResult {
+        check!(!self.get_flag(BANK_CLOSED_FLAG), MarginfiError::BankClosed);
+        check!(!self.is_killed(), MarginfiError::BankKilled);
+        check!(
+            !self.get_flag(BANK_CONFIG_FROZEN_FLAG) || config.is_operational_state_only(),
+            MarginfiError::BankConfigFrozen
+        );
+        // Only `lending_pool_kill_bank` settles the bank
+        check!(
+            !matches!(config.operational_state, Some(BankOperationalState::Killed)),
+            MarginfiError::InvalidConfig
+        );
+
         set_if_some!(self.config.asset_weight_init, config.asset_weight_init);
         set_if_some!(self.config.asset_weight_maint, config.asset_weight_maint);
         set_if_some!(
@@ -531,18 +1247,58 @@ impl Bank {
             self.config.liability_weight_maint,
             config.liability_weight_maint
         );
+
+        if let Some(weights_bps) = config.weights_bps {
+            msg!("Setting weights to {:?} bps", weights_bps);
+            self.config.set_weights_bps(weights_bps)?;
+        } else if config.asset_weight_init.is_some()
+            || config.asset_weight_maint.is_some()
+            || config.liability_weight_init.is_some()
+            || config.liability_weight_maint.is_some()
+        {
+            // Weights set directly no longer match the bps record
+            self.config.weights_bps = WeightsBps::default();
+        }
+
         set_if_some!(self.config.deposit_limit, config.deposit_limit);
 
         set_if_some!(self.config.borrow_limit, config.borrow_limit);
 
         set_if_some!(self.config.operational_state, config.operational_state);
 
-        set_if_some!(self.config.oracle_setup, config.oracle.map(|o| o.setup));
-
-        set_if_some!(self.config.oracle_keys, config.oracle.map(|o| o.keys));
+        // The admin takes over from the oracle circuit breaker
+        if config.operational_state.is_some() {
+            self.flags &= !ORACLE_PAUSED_FLAG;
+        }
 
         if let Some(ir_config) = &config.interest_rate_config {
-            self.config.interest_rate_config.update(ir_config);
+            self.config
+                .interest_rate_config
+                .update(ir_config, Clock::get()?.unix_timestamp)?;
+
+            // Restart the adaptive rate controller from the configured plateau rate
+            if ir_config.plateau_interest_rate.is_some()
+                || ir_config.adaptive_rate_speed_bps.is_some()
+            {
+                self.adaptive_plateau_interest_rate = I80F48::ZERO.into();
+            }
+        }
+
+        if let Some(fee_apr) = config.insurance_deposit_fee_apr {
+            let fee_apr = I80F48::from(fee_apr);
+
+            check!(
+                fee_apr >= I80F48::ZERO && fee_apr < I80F48::ONE,
+                MarginfiError::InvalidConfig
+            );
+
+            self.insurance_deposit_fee_apr = fee_apr.into();
+        }
+
+        if let Some(window) = config.utilization_smoothing_window {
+            // Start averaging from the current utilization
+            self.utilization_smoothing_window = window;
+            self.smoothed_utilization_rate = self.get_utilization_rate()?.into();
         }
 
         set_if_some!(self.config.risk_tier, config.risk_tier);
@@ -554,15 +1310,391 @@ impl Bank {
 
         set_if_some!(self.config.oracle_max_age, config.oracle_max_age);
 
+        set_if_some!(
+            self.config.oracle_max_age_slots,
+            config.oracle_max_age_slots
+        );
+
+        set_if_some!(
+            self.config.oracle_max_confidence_bps,
+            config.oracle_max_confidence_bps
+        );
+
+        set_if_some!(
+            self.config.oracle_max_deviation_bps,
+            config.oracle_max_deviation_bps
+        );
+
+        set_if_some!(self.config.correlation_bucket, config.correlation_bucket);
+
+        set_if_some!(
+            self.config.insurance_premium_bps,
+            config.insurance_premium_bps
+        );
+
+        set_if_some!(self.config.max_borrow_duration, config.max_borrow_duration);
+
+        set_if_some!(self.config.emissions_boost_bps, config.emissions_boost_bps);
+
+        set_if_some!(
+            self.config.emissions_boost_duration,
+            config.emissions_boost_duration
+        );
+
+        set_if_some!(
+            self.config.locked_asset_weight_boost_bps,
+            config.locked_asset_weight_boost_bps
+        );
+
+        set_if_some!(
+            self.config.locked_emissions_boost_bps,
+            config.locked_emissions_boost_bps
+        );
+
+        set_if_some!(
+            self.liquidation_grace_period,
+            config.liquidation_grace_period
+        );
+
+        if let Some(grace_fee_bps) = config.liquidation_grace_fee_bps {
+            check!(
+                grace_fee_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+                MarginfiError::InvalidConfig
+            );
+
+            self.liquidation_grace_fee_bps = grace_fee_bps;
+        }
+
+        set_if_some!(self.borrow_window_cap, config.borrow_window_cap);
+
+        set_if_some!(self.borrow_window_slots, config.borrow_window_slots);
+
+        set_if_some!(self.twap_window, config.twap_window);
+
+        set_if_some!(self.max_deposit_per_account, config.max_deposit_per_account);
+
+        set_if_some!(self.min_borrow_amount, config.min_borrow_amount);
+
+        set_if_some!(self.dust_threshold, config.dust_threshold);
+
+        if let Some(max_borrow_utilization_bps) = config.max_borrow_utilization_bps {
+            check!(
+                max_borrow_utilization_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+                MarginfiError::InvalidConfig
+            );
+
+            self.max_borrow_utilization_bps = max_borrow_utilization_bps;
+        }
+
+        if let Some(liquidity_reserve_bps) = config.liquidity_reserve_bps {
+            check!(
+                liquidity_reserve_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+                MarginfiError::InvalidConfig
+            );
+
+            self.liquidity_reserve_bps = liquidity_reserve_bps;
+        }
+
+        set_if_some!(self.accrual_bounty, config.accrual_bounty);
+
+        set_if_some!(self.accrual_bounty_min_age, config.accrual_bounty_min_age);
+
+        // A bounty without a min age could be drained by cranking every second
+        check!(
+            self.accrual_bounty == 0 || self.accrual_bounty_min_age != 0,
+            MarginfiError::InvalidConfig
+        );
+
         if let Some(flag) = config.permissionless_bad_debt_settlement {
             self.update_flag(flag, PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG);
         }
 
+        if let Some(price) = config.fixed_price {
+            self.config.set_fixed_price(price.into());
+        }
+
+        if let Some(flag) = config.fixed_price_collateral {
+            self.update_flag(flag, FIXED_PRICE_COLLATERAL_FLAG);
+        }
+
+        if let Some(flag) = config.maintenance_ema_price {
+            self.update_flag(flag, MAINTENANCE_EMA_PRICE_FLAG);
+        }
+
+        if let Some(freeze) = config.freeze_config {
+            // Freezing is one-way
+            check!(freeze, MarginfiError::BankConfigFrozen);
+            msg!("Freezing bank config");
+            self.flags |= BANK_CONFIG_FROZEN_FLAG;
+        }
+
         self.config.validate()?;
 
         Ok(())
     }
 
+    /// Plateau interest rate set by the adaptive rate controller, `None` if the controller is off.
+    /// Overrides the configured (and ramped) `plateau_interest_rate` when accruing interest.
+    pub fn get_adaptive_plateau_interest_rate(&self) -> Option<I80F48> {
+        let ir_config = &self.config.interest_rate_config;
+
+        if ir_config.adaptive_rate_speed_bps == 0 {
+            return None;
+        }
+
+        let plateau_ir = I80F48::from(self.adaptive_plateau_interest_rate);
+
+        Some(if plateau_ir == I80F48::ZERO {
+            ir_config.plateau_interest_rate.into()
+        } else {
+            plateau_ir
+        })
+    }
+
+    /// Interest rate curve in effect at `timestamp`, ramped and with the plateau rate of the
+    /// adaptive rate controller.
+    pub fn get_interest_rate_config(&self, timestamp: i64) -> MarginfiResult<InterestRateConfig> {
+        let mut interest_rate_config = self
+            .config
+            .interest_rate_config
+            .ramped(timestamp)
+            .ok_or_else(math_error!())?;
+
+        if let Some(plateau_ir) = self.get_adaptive_plateau_interest_rate() {
+            interest_rate_config.plateau_interest_rate = plateau_ir.into();
+        }
+
+        Ok(interest_rate_config)
+    }
+
+    /// Reading of [`Bank::group_fee_growth`] kept by balances, its low 64 bits.
+    pub fn get_group_fee_growth_snapshot(&self) -> u64 {
+        I80F48::from(self.group_fee_growth).to_bits() as u64
+    }
+
+    /// Group fees charged on `liability_shares` since [`Bank::group_fee_growth`] read
+    /// `group_fee_growth_snapshot`. The growth in between wraps around past 2^16 tokens per share.
+    pub fn get_group_fees_since(
+        &self,
+        liability_shares: I80F48,
+        group_fee_growth_snapshot: u64,
+    ) -> MarginfiResult<I80F48> {
+        let growth = self
+            .get_group_fee_growth_snapshot()
+            .wrapping_sub(group_fee_growth_snapshot);
+
+        Ok(I80F48::from_bits(growth as i128)
+            .checked_mul(liability_shares)
+            .ok_or_else(math_error!())?)
+    }
+
+    /// Spread `group_fees` charged on the bank's liabilities over the liability shares in
+    /// [`Bank::group_fee_growth`].
+    fn accrue_group_fee_growth(&mut self, group_fees: I80F48) -> MarginfiResult {
+        let total_liability_shares: I80F48 = self.total_liability_shares.into();
+
+        if total_liability_shares == I80F48::ZERO {
+            return Ok(());
+        }
+
+        let growth = group_fees
+            .checked_div(total_liability_shares)
+            .ok_or_else(math_error!())?;
+
+        self.group_fee_growth = I80F48::from(self.group_fee_growth)
+            .wrapping_add(growth)
+            .into();
+
+        Ok(())
+    }
+
+    /// Take the group fees shared when a liability is settled out of the bank's group fees:
+    /// `rebate` was forgiven on the liability and `referral_fees` are owed to the referrer
+    /// until claimed. Fees already collected leave the outstanding group fees negative, the
+    /// group fees charged next repay them before any are collected again.
+    pub fn share_group_fees(&mut self, rebate: I80F48, referral_fees: I80F48) -> MarginfiResult {
+        self.collected_group_fees_outstanding = I80F48::from(self.collected_group_fees_outstanding)
+            .checked_sub(rebate)
+            .and_then(|outstanding| outstanding.checked_sub(referral_fees))
+            .ok_or_else(math_error!())?
+            .into();
+        self.referral_fees_outstanding = I80F48::from(self.referral_fees_outstanding)
+            .checked_add(referral_fees)
+            .ok_or_else(math_error!())?
+            .into();
+
+        Ok(())
+    }
+
+    /// Pay out `amount` of the referral fees owed by the bank, see
+    /// [`Bank::referral_fees_outstanding`]. Fees credited before it was tracked aren't counted,
+    /// so it bottoms out at zero.
+    pub fn pay_referral_fees(&mut self, amount: I80F48) -> MarginfiResult {
+        self.referral_fees_outstanding = I80F48::from(self.referral_fees_outstanding)
+            .checked_sub(amount)
+            .ok_or_else(math_error!())?
+            .max(I80F48::ZERO)
+            .into();
+
+        Ok(())
+    }
+
+    /// Ratio of liabilities to assets, zero for an empty bank.
+    pub fn get_utilization_rate(&self) -> MarginfiResult<I80F48> {
+        let total_assets = self.get_total_asset_amount()?;
+
+        if total_assets == I80F48::ZERO {
+            return Ok(I80F48::ZERO);
+        }
+
+        Ok(self
+            .get_liability_amount(self.total_liability_shares.into())?
+            .checked_div(total_assets)
+            .ok_or_else(math_error!())?)
+    }
+
+    /// Fails if a borrow left the bank above `max_borrow_utilization_bps`.
+    /// Repays, withdrawals and interest can still push utilization past it.
+    pub fn check_borrow_utilization(&self) -> MarginfiResult {
+        if self.max_borrow_utilization_bps == 0 {
+            return Ok(());
+        }
+
+        let max_utilization_rate = I80F48::from_num(self.max_borrow_utilization_bps)
+            .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+            .ok_or_else(math_error!())?;
+
+        check!(
+            self.get_utilization_rate()? <= max_utilization_rate,
+            MarginfiError::BorrowUtilizationExceeded
+        );
+
+        Ok(())
+    }
+
+    /// Fails if `liquidity_vault_amount`, the vault balance left after a borrow, is below
+    /// `liquidity_reserve_bps` of total deposits.
+    pub fn check_liquidity_reserve(&self, liquidity_vault_amount: u64) -> MarginfiResult {
+        if self.liquidity_reserve_bps == 0 {
+            return Ok(());
+        }
+
+        let reserve_amount = self
+            .get_total_asset_amount()?
+            .checked_mul(I80F48::from_num(self.liquidity_reserve_bps))
+            .ok_or_else(math_error!())?
+            .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+            .ok_or_else(math_error!())?;
+
+        check!(
+            I80F48::from_num(liquidity_vault_amount) >= reserve_amount,
+            MarginfiError::LiquidityReserveExceeded
+        );
+
+        Ok(())
+    }
+
+    /// Fold the utilization of the last `time_delta` seconds into the time weighted utilization
+    /// and return the utilization the interest rate curve is evaluated at.
+    ///
+    /// Interest is accrued before every balance change, so `utilization_rate` held over the whole
+    /// period. It moves the average in proportion to the share of the smoothing window it spans,
+    /// which keeps a large deposit or withdrawal right before an accrual from swinging the rate.
+    fn update_smoothed_utilization_rate(
+        &mut self,
+        utilization_rate: I80F48,
+        time_delta: u64,
+    ) -> MarginfiResult<I80F48> {
+        let window = self.utilization_smoothing_window as u64;
+
+        if window == 0 {
+            return Ok(utilization_rate);
+        }
+
+        let smoothed_ur = I80F48::from(self.smoothed_utilization_rate);
+        let weight = if time_delta >= window {
+            I80F48::ONE
+        } else {
+            I80F48::from_num(time_delta)
+                .checked_div(I80F48::from_num(window))
+                .ok_or_else(math_error!())?
+        };
+
+        let smoothed_ur = smoothed_ur
+            .checked_add(
+                utilization_rate
+                    .checked_sub(smoothed_ur)
+                    .ok_or_else(math_error!())?
+                    .checked_mul(weight)
+                    .ok_or_else(math_error!())?,
+            )
+            .ok_or_else(math_error!())?;
+
+        self.smoothed_utilization_rate = smoothed_ur.into();
+
+        Ok(smoothed_ur)
+    }
+
+    /// Integral controller keeping utilization near the optimal utilization rate: the plateau
+    /// rate rises while utilization is above optimal and falls while it is below, in proportion
+    /// to the distance normalized to [-1, 1]. The rate is kept between
+    /// [`MIN_ADAPTIVE_PLATEAU_INTEREST_RATE`] and the rate of the next curve point.
+    fn update_adaptive_plateau_interest_rate(
+        &mut self,
+        ir_config: &InterestRateConfig,
+        utilization_rate: I80F48,
+        time_delta: u64,
+    ) -> MarginfiResult {
+        let plateau_ir = match self.get_adaptive_plateau_interest_rate() {
+            Some(plateau_ir) => plateau_ir,
+            None => return Ok(()),
+        };
+
+        let optimal_ur: I80F48 = ir_config.optimal_utilization_rate.into();
+        let error = if utilization_rate > optimal_ur {
+            (utilization_rate - optimal_ur).checked_div(I80F48::ONE - optimal_ur)
+        } else {
+            (utilization_rate - optimal_ur).checked_div(optimal_ur)
+        }
+        .ok_or_else(math_error!())?
+        .min(I80F48::ONE);
+
+        let adjustment = WeightsBps::to_weight(ir_config.adaptive_rate_speed_bps)?
+            .checked_mul(error)
+            .ok_or_else(math_error!())?
+            .checked_mul(I80F48::from_num(time_delta))
+            .ok_or_else(math_error!())?
+            .checked_div(I80F48::from_num(SECONDS_PER_DAY))
+            .ok_or_else(math_error!())?;
+
+        let next_point_ir = match ir_config.kinks.iter().find(|kink| kink.is_active()) {
+            Some(kink) => kink.to_point()?.1,
+            None => ir_config.max_interest_rate.into(),
+        };
+
+        let plateau_ir = plateau_ir
+            .checked_mul(I80F48::ONE + adjustment)
+            .ok_or_else(math_error!())?
+            .max(MIN_ADAPTIVE_PLATEAU_INTEREST_RATE)
+            .min(next_point_ir);
+
+        self.adaptive_plateau_interest_rate = plateau_ir.into();
+
+        Ok(())
+    }
+
+    /// Bounty owed to a keeper accruing interest at `current_timestamp`. Zero if the bounty is
+    /// disabled or interest was accrued within the last `accrual_bounty_min_age` seconds.
+    pub fn get_accrual_bounty(&self, current_timestamp: i64) -> u64 {
+        let age = current_timestamp.saturating_sub(self.last_update);
+
+        if self.accrual_bounty == 0 || age <= self.accrual_bounty_min_age as i64 {
+            0
+        } else {
+            self.accrual_bounty
+        }
+    }
+
     /// Calculate the interest rate accrual state changes for a given time period
     ///
     /// Collected protocol and insurance fees are stored in state.
@@ -581,12 +1713,51 @@ impl Bank {
             return Ok(());
         }
 
-        let total_assets = self.get_asset_amount(self.total_asset_shares.into())?;
+        // Balances of a killed bank stay as they were when it was killed
+        if self.is_killed() {
+            self.last_update = current_timestamp;
+            return Ok(());
+        }
+
+        let total_assets = self.get_total_asset_amount()?;
         let total_liabilities = self.get_liability_amount(self.total_liability_shares.into())?;
 
+        // Evaluate a ramping curve in the middle of the accrual period
+        let interest_rate_config =
+            self.get_interest_rate_config(self.last_update + (time_delta / 2) as i64)?;
+
+        let utilization_rate = if total_assets == I80F48::ZERO {
+            I80F48::ZERO
+        } else {
+            total_liabilities
+                .checked_div(total_assets)
+                .ok_or_else(math_error!())?
+        };
+        self.update_adaptive_plateau_interest_rate(
+            &interest_rate_config,
+            utilization_rate,
+            time_delta,
+        )?;
+
+        let curve_utilization_rate =
+            self.update_smoothed_utilization_rate(utilization_rate, time_delta)?;
+
         self.last_update = current_timestamp;
 
+        let lending_subsidy = interest_rate_config
+            .calc_lending_subsidy(
+                utilization_rate,
+                curve_utilization_rate,
+                total_assets,
+                time_delta,
+            )?
+            .min(self.lending_subsidy_reserve.into());
+        self.distribute_lending_subsidy(lending_subsidy)?;
+        let subsidized_total_assets = self.get_total_asset_amount()?;
+
         if (total_assets == I80F48::ZERO) || (total_liabilities == I80F48::ZERO) {
+            let insurance_collected = self.charge_insurance_deposit_fee(time_delta)?;
+
             #[cfg(not(feature = "client"))]
             emit!(LendingPoolBankAccrueInterestEvent {
                 header: GroupEventHeader {
@@ -597,7 +1768,7 @@ impl Bank {
                 mint: self.mint,
                 delta: time_delta,
                 fees_collected: 0.,
-                insurance_collected: 0.,
+                insurance_collected: insurance_collected.to_num::<f64>(),
             });
 
             return Ok(());
@@ -608,12 +1779,30 @@ impl Bank {
                 time_delta,
                 total_assets,
                 total_liabilities,
-                &self.config.interest_rate_config,
+                curve_utilization_rate,
+                &interest_rate_config,
                 self.asset_share_value.into(),
                 self.liability_share_value.into(),
             )
             .ok_or_else(math_error!())?;
 
+        let old_asset_share_value: I80F48 = self.asset_share_value.into();
+        let insurance_premium_collected = if old_asset_share_value == I80F48::ZERO {
+            I80F48::ZERO
+        } else {
+            self.accrue_insured_asset_share_value(
+                asset_share_value
+                    .checked_div(old_asset_share_value)
+                    .ok_or_else(math_error!())?
+                    .checked_sub(I80F48::ONE)
+                    .ok_or_else(math_error!())?,
+            )?
+        };
+
+        let insurance_collected = insurance_collected
+            .checked_add(insurance_premium_collected)
+            .ok_or_else(math_error!())?;
+
         debug!("deposit share value: {}\nliability share value: {}\nfees collected: {}\ninsurance collected: {}",
             asset_share_value, liability_share_value, fees_collected, insurance_collected);
 
@@ -626,6 +1815,7 @@ impl Bank {
                 .ok_or_else(math_error!())?
                 .into()
         };
+        self.accrue_group_fee_growth(fees_collected)?;
 
         self.collected_insurance_fees_outstanding = {
             insurance_collected
@@ -634,6 +1824,34 @@ impl Bank {
                 .into()
         };
 
+        let lender_interest_paid = self
+            .get_total_asset_amount()?
+            .checked_sub(subsidized_total_assets)
+            .ok_or_else(math_error!())?;
+
+        self.lifetime_group_fees_collected = {
+            fees_collected
+                .checked_add(self.lifetime_group_fees_collected.into())
+                .ok_or_else(math_error!())?
+                .into()
+        };
+        self.lifetime_insurance_fees_collected = {
+            insurance_collected
+                .checked_add(self.lifetime_insurance_fees_collected.into())
+                .ok_or_else(math_error!())?
+                .into()
+        };
+        self.lifetime_lender_interest_paid = {
+            lender_interest_paid
+                .checked_add(self.lifetime_lender_interest_paid.into())
+                .ok_or_else(math_error!())?
+                .into()
+        };
+
+        let insurance_collected = insurance_collected
+            .checked_add(self.charge_insurance_deposit_fee(time_delta)?)
+            .ok_or_else(math_error!())?;
+
         #[cfg(not(feature = "client"))]
         {
             #[cfg(feature = "debug")]
@@ -757,30 +1975,498 @@ impl Bank {
         Ok(())
     }
 
-    /// Socialize a loss `loss_amount` among depositors,
-    /// the `total_deposit_shares` stays the same, but total value of deposits is
-    /// reduced by `loss_amount`;
-    pub fn socialize_loss(&mut self, loss_amount: I80F48) -> MarginfiResult {
-        let total_asset_shares: I80F48 = self.total_asset_shares.into();
-        let old_asset_share_value: I80F48 = self.asset_share_value.into();
+    /// Vault address stored for `vault_type` and the bump of its authority PDA.
+    pub fn get_vault_and_authority_bump(&self, vault_type: BankVaultType) -> (Pubkey, u8) {
+        match vault_type {
+            BankVaultType::Liquidity => (self.liquidity_vault, self.liquidity_vault_authority_bump),
+            BankVaultType::Insurance => (self.insurance_vault, self.insurance_vault_authority_bump),
+            BankVaultType::Fee => (self.fee_vault, self.fee_vault_authority_bump),
+        }
+    }
 
-        let new_share_value = total_asset_shares
-            .checked_mul(old_asset_share_value)
-            .ok_or_else(math_error!())?
-            .checked_sub(loss_amount)
-            .ok_or_else(math_error!())?
-            .checked_div(total_asset_shares)
+    /// Point `vault_type` at a new token account owned by the same vault authority.
+    /// The vault bump is cleared, a migrated vault isn't necessarily derived from the vault seed.
+    pub fn set_vault(&mut self, vault_type: BankVaultType, vault: Pubkey) {
+        match vault_type {
+            BankVaultType::Liquidity => {
+                self.liquidity_vault = vault;
+                self.liquidity_vault_bump = 0;
+            }
+            BankVaultType::Insurance => {
+                self.insurance_vault = vault;
+                self.insurance_vault_bump = 0;
+            }
+            BankVaultType::Fee => {
+                self.fee_vault = vault;
+                self.fee_vault_bump = 0;
+            }
+        }
+    }
+
+    /// Grow the insured share value by `growth` (the relative growth of the uninsured share
+    /// value) minus the insurance premium. Returns the premium collected for the insurance vault.
+    fn accrue_insured_asset_share_value(&mut self, growth: I80F48) -> MarginfiResult<I80F48> {
+        let total_insured_asset_shares: I80F48 = self.total_insured_asset_shares.into();
+
+        if total_insured_asset_shares == I80F48::ZERO || growth <= I80F48::ZERO {
+            return Ok(I80F48::ZERO);
+        }
+
+        let insured_asset_share_value: I80F48 = self.insured_asset_share_value.into();
+        let premium_rate = I80F48::from_num(self.config.insurance_premium_bps)
+            .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
             .ok_or_else(math_error!())?;
 
-        self.asset_share_value = new_share_value.into();
+        let premium_growth = growth.checked_mul(premium_rate).ok_or_else(math_error!())?;
 
-        Ok(())
+        self.insured_asset_share_value = insured_asset_share_value
+            .checked_mul(
+                I80F48::ONE
+                    .checked_add(growth)
+                    .ok_or_else(math_error!())?
+                    .checked_sub(premium_growth)
+                    .ok_or_else(math_error!())?,
+            )
+            .ok_or_else(math_error!())?
+            .into();
+
+        Ok(total_insured_asset_shares
+            .checked_mul(insured_asset_share_value)
+            .ok_or_else(math_error!())?
+            .checked_mul(premium_growth)
+            .ok_or_else(math_error!())?)
     }
 
-    pub fn assert_operational_mode(
-        &self,
-        is_asset_or_liability_amount_increasing: Option<bool>,
+    /// Charge depositors `insurance_deposit_fee_apr` over `time_delta` by lowering the value of
+    /// insured and uninsured deposit shares alike, and owe it to the insurance vault.
+    /// This builds insurance cover even when there is little borrowing.
+    fn charge_insurance_deposit_fee(&mut self, time_delta: u64) -> MarginfiResult<I80F48> {
+        let fee_apr = I80F48::from(self.insurance_deposit_fee_apr);
+        let total_assets = self.get_total_asset_amount()?;
+
+        if fee_apr == I80F48::ZERO || total_assets == I80F48::ZERO {
+            return Ok(I80F48::ZERO);
+        }
+
+        let fee = calc_interest_payment_for_period(fee_apr, time_delta, total_assets)
+            .ok_or_else(math_error!())?
+            .min(total_assets);
+
+        let remaining = I80F48::ONE
+            .checked_sub(fee.checked_div(total_assets).ok_or_else(math_error!())?)
+            .ok_or_else(math_error!())?;
+
+        self.asset_share_value = I80F48::from(self.asset_share_value)
+            .checked_mul(remaining)
+            .ok_or_else(math_error!())?
+            .into();
+        self.insured_asset_share_value = I80F48::from(self.insured_asset_share_value)
+            .checked_mul(remaining)
+            .ok_or_else(math_error!())?
+            .into();
+
+        self.collected_insurance_fees_outstanding = {
+            fee.checked_add(self.collected_insurance_fees_outstanding.into())
+                .ok_or_else(math_error!())?
+                .into()
+        };
+        self.lifetime_insurance_fees_collected = {
+            fee.checked_add(self.lifetime_insurance_fees_collected.into())
+                .ok_or_else(math_error!())?
+                .into()
+        };
+
+        Ok(fee)
+    }
+
+    /// Tokens of the liquidity vault that belong to referrers, kept back when the bank is closed
+    /// so referral fees can still be claimed afterwards.
+    pub fn get_referral_fee_escrow(&self) -> MarginfiResult<u64> {
+        I80F48::from(self.referral_fees_outstanding)
+            .checked_ceil()
+            .and_then(|escrow| escrow.checked_to_num::<u64>())
+            .ok_or_else(math_error!())
+    }
+
+    /// Retire an empty bank: pause it for good and forget the fees it still owes and its
+    /// lending subsidy reserve, the caller sweeps and closes the vaults. Referral fees stay owed,
+    /// see [`Bank::get_referral_fee_escrow`].
+    pub fn close(&mut self) -> MarginfiResult {
+        check!(!self.get_flag(BANK_CLOSED_FLAG), MarginfiError::BankClosed);
+        check!(
+            I80F48::from(self.total_asset_shares) == I80F48::ZERO
+                && I80F48::from(self.total_insured_asset_shares) == I80F48::ZERO
+                && I80F48::from(self.total_liability_shares) == I80F48::ZERO
+                && I80F48::from(self.emissions_remaining) < I80F48::ONE,
+            MarginfiError::BankNotEmpty
+        );
+
+        self.config.operational_state = BankOperationalState::Paused;
+        self.flags = (self.flags & !ORACLE_PAUSED_FLAG) | BANK_CLOSED_FLAG;
+
+        self.collected_group_fees_outstanding = I80F48::ZERO.into();
+        self.collected_insurance_fees_outstanding = I80F48::ZERO.into();
+        self.lending_subsidy_reserve = I80F48::ZERO.into();
+
+        Ok(())
+    }
+
+    /// Kill a compromised bank for good. Interest stops and the bank is priced at
+    /// `settlement_price` through a fixed price oracle, so its deposits no longer count as
+    /// collateral while liabilities keep their last good value. Balances can only be settled
+    /// through `lending_account_settle_killed_balance` afterwards.
+    ///
+    /// Fees still owed, referral fees included, and the lending subsidy reserve are forgotten, the
+    /// liquidity vault belongs to depositors.
+    /// Interest must be accrued before calling this.
+    pub fn kill(&mut self, settlement_price: I80F48) -> MarginfiResult {
+        check!(!self.get_flag(BANK_CLOSED_FLAG), MarginfiError::BankClosed);
+        check!(!self.is_killed(), MarginfiError::BankKilled);
+        check!(
+            settlement_price > I80F48::ZERO,
+            MarginfiError::InvalidConfig
+        );
+
+        self.config.operational_state = BankOperationalState::Killed;
+        self.config.set_fixed_price(settlement_price);
+        self.flags &= !(ORACLE_PAUSED_FLAG | ORACLE_DEVIATION_FLAG | FIXED_PRICE_COLLATERAL_FLAG);
+
+        self.pending_oracle_setup = OracleSetup::None;
+        self.pending_oracle_keys = [Pubkey::default(); MAX_ORACLE_KEYS];
+        self.pending_oracle_slot = 0;
+
+        self.collected_group_fees_outstanding = I80F48::ZERO.into();
+        self.collected_insurance_fees_outstanding = I80F48::ZERO.into();
+        self.referral_fees_outstanding = I80F48::ZERO.into();
+        self.lending_subsidy_reserve = I80F48::ZERO.into();
+
+        Ok(())
+    }
+
+    pub fn is_killed(&self) -> bool {
+        matches!(self.config.operational_state, BankOperationalState::Killed)
+    }
+
+    /// Pay `amount` of the lending subsidy reserve to depositors by raising the value of
+    /// insured and uninsured deposit shares alike. The tokens are already in the liquidity vault.
+    fn distribute_lending_subsidy(&mut self, amount: I80F48) -> MarginfiResult {
+        let reserve = I80F48::from(self.lending_subsidy_reserve);
+        let total_assets = self.get_total_asset_amount()?;
+
+        check!(amount <= reserve, MarginfiError::MathError);
+
+        if amount == I80F48::ZERO || total_assets == I80F48::ZERO {
+            return Ok(());
+        }
+
+        let growth = I80F48::ONE
+            .checked_add(amount.checked_div(total_assets).ok_or_else(math_error!())?)
+            .ok_or_else(math_error!())?;
+
+        self.asset_share_value = I80F48::from(self.asset_share_value)
+            .checked_mul(growth)
+            .ok_or_else(math_error!())?
+            .into();
+        self.insured_asset_share_value = I80F48::from(self.insured_asset_share_value)
+            .checked_mul(growth)
+            .ok_or_else(math_error!())?
+            .into();
+
+        self.lending_subsidy_reserve = (reserve - amount).into();
+        self.lifetime_lender_interest_paid = I80F48::from(self.lifetime_lender_interest_paid)
+            .checked_add(amount)
+            .ok_or_else(math_error!())?
+            .into();
+
+        Ok(())
+    }
+
+    /// Socialize a loss `loss_amount` among depositors,
+    /// the `total_deposit_shares` stays the same, but total value of deposits is
+    /// reduced by `loss_amount`;
+    ///
+    /// Uninsured deposits absorb the loss first, insured deposits only take what is left.
+    pub fn socialize_loss(&mut self, loss_amount: I80F48) -> MarginfiResult {
+        let uninsured_amount = self.get_asset_amount(self.total_asset_shares.into(), false)?;
+        let uninsured_loss = loss_amount.min(uninsured_amount);
+        let insured_loss = loss_amount
+            .checked_sub(uninsured_loss)
+            .ok_or_else(math_error!())?;
+
+        if uninsured_loss > I80F48::ZERO {
+            self.asset_share_value = calc_socialized_share_value(
+                self.total_asset_shares.into(),
+                self.asset_share_value.into(),
+                uninsured_loss,
+            )
+            .ok_or_else(math_error!())?
+            .into();
+        }
+
+        if insured_loss > I80F48::ZERO {
+            self.insured_asset_share_value = calc_socialized_share_value(
+                self.total_insured_asset_shares.into(),
+                self.insured_asset_share_value.into(),
+                insured_loss,
+            )
+            .ok_or_else(math_error!())?
+            .into();
+        }
+
+        Ok(())
+    }
+
+    /// Start the liquidation grace period if the bank just left the `Paused` state.
+    pub fn update_unpaused_at(
+        &mut self,
+        previous_state: BankOperationalState,
+        current_timestamp: i64,
+    ) {
+        if matches!(previous_state, BankOperationalState::Paused)
+            && !matches!(self.config.operational_state, BankOperationalState::Paused)
+        {
+            self.unpaused_at = current_timestamp;
+        }
+    }
+
+    /// Oracle circuit breaker: pause an operational bank once its oracles are down,
+    /// and resume it once they are back if the breaker paused it.
+    /// Returns whether the operational state changed.
+    pub fn update_oracle_paused(&mut self, oracle_down: bool, current_timestamp: i64) -> bool {
+        let previous_state = self.config.operational_state;
+
+        match (oracle_down, previous_state) {
+            (true, BankOperationalState::Operational) => {
+                self.config.operational_state = BankOperationalState::Paused;
+                self.flags |= ORACLE_PAUSED_FLAG;
+            }
+            (false, BankOperationalState::Paused) if self.get_flag(ORACLE_PAUSED_FLAG) => {
+                self.config.operational_state = BankOperationalState::Operational;
+                self.flags &= !ORACLE_PAUSED_FLAG;
+                self.update_unpaused_at(previous_state, current_timestamp);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Set or clear [`ORACLE_DEVIATION_FLAG`], returns whether it changed.
+    pub fn update_oracle_deviation_exceeded(&mut self, exceeded: bool) -> bool {
+        if self.get_flag(ORACLE_DEVIATION_FLAG) == exceeded {
+            return false;
+        }
+
+        if exceeded {
+            self.flags |= ORACLE_DEVIATION_FLAG;
+        } else {
+            self.flags &= !ORACLE_DEVIATION_FLAG;
+        }
+
+        true
+    }
+
+    /// Stage an oracle change, it can be accepted [`ORACLE_ROTATION_DELAY_SLOTS`] after
+    /// `current_slot`. Replaces any rotation already pending.
+    pub fn propose_oracle(&mut self, oracle: OracleConfig, current_slot: u64) {
+        self.pending_oracle_setup = oracle.setup;
+        self.pending_oracle_keys = oracle.keys;
+        self.pending_oracle_slot = current_slot;
+    }
+
+    pub fn get_pending_oracle(&self) -> Option<OracleConfig> {
+        if matches!(self.pending_oracle_setup, OracleSetup::None) {
+            None
+        } else {
+            Some(OracleConfig {
+                setup: self.pending_oracle_setup,
+                keys: self.pending_oracle_keys,
+            })
+        }
+    }
+
+    /// Switch the bank to the pending oracle once the rotation delay has passed.
+    /// Interest must be accrued before calling this.
+    pub fn accept_pending_oracle(&mut self, current_slot: u64) -> MarginfiResult<OracleConfig> {
+        let oracle = self
+            .get_pending_oracle()
+            .ok_or(MarginfiError::OracleRotationNotPending)?;
+
+        // A rotation proposed before the freeze doesn't go through either
+        check!(
+            !self.get_flag(BANK_CONFIG_FROZEN_FLAG),
+            MarginfiError::BankConfigFrozen
+        );
+
+        check!(
+            current_slot
+                >= self
+                    .pending_oracle_slot
+                    .saturating_add(ORACLE_ROTATION_DELAY_SLOTS),
+            MarginfiError::OracleRotationDelayNotElapsed
+        );
+
+        self.config.oracle_setup = oracle.setup;
+        self.config.oracle_keys = oracle.keys;
+
+        self.pending_oracle_setup = OracleSetup::None;
+        self.pending_oracle_keys = [Pubkey::default(); MAX_ORACLE_KEYS];
+        self.pending_oracle_slot = 0;
+
+        // Prices of the old oracle don't carry over
+        self.twap_head = 0;
+        self.twap_timestamps = [0; TWAP_OBSERVATIONS];
+
+        Ok(oracle)
+    }
+
+    /// Record an oracle price for the TWAP, returns whether it was stored. Observations are
+    /// spaced at least `twap_window / TWAP_OBSERVATIONS` apart, so repeated cranks can't flush
+    /// the buffer.
+    pub fn record_twap_price(&mut self, price: I80F48, current_timestamp: i64) -> bool {
+        if self.twap_window == 0 {
+            return false;
+        }
+
+        let latest_timestamp = self.twap_timestamps[self.twap_head as usize];
+        let spacing = self.twap_window as i64 / TWAP_OBSERVATIONS as i64;
+
+        if latest_timestamp != 0
+            && (current_timestamp <= latest_timestamp
+                || current_timestamp - latest_timestamp < spacing)
+        {
+            return false;
+        }
+
+        let head = (self.twap_head as usize + 1) % TWAP_OBSERVATIONS;
+        self.twap_prices[head] = price.into();
+        self.twap_timestamps[head] = current_timestamp;
+        self.twap_head = head as u8;
+
+        true
+    }
+
+    /// Time weighted average of the recorded prices over the `twap_window` seconds up to the
+    /// latest observation, each price holding until the next one. `None` without observations.
+    pub fn get_twap_price(&self) -> MarginfiResult<Option<I80F48>> {
+        let head = self.twap_head as usize;
+        let latest_timestamp = self.twap_timestamps[head];
+
+        if self.twap_window == 0 || latest_timestamp == 0 {
+            return Ok(None);
+        }
+
+        let window_start = latest_timestamp.saturating_sub(self.twap_window as i64);
+        let mut end = latest_timestamp;
+        let mut weighted_sum = I80F48::ZERO;
+        let mut total_duration = 0i64;
+
+        for i in 1..TWAP_OBSERVATIONS {
+            let index = (head + TWAP_OBSERVATIONS - i) % TWAP_OBSERVATIONS;
+            let timestamp = self.twap_timestamps[index];
+
+            if timestamp == 0 || timestamp >= end || end <= window_start {
+                break;
+            }
+
+            let duration = end - timestamp.max(window_start);
+
+            weighted_sum = I80F48::from(self.twap_prices[index])
+                .checked_mul(I80F48::from_num(duration))
+                .and_then(|value| weighted_sum.checked_add(value))
+                .ok_or_else(math_error!())?;
+            total_duration += duration;
+            end = timestamp;
+        }
+
+        if total_duration == 0 {
+            return Ok(Some(self.twap_prices[head].into()));
+        }
+
+        Ok(Some(
+            weighted_sum
+                .checked_div(I80F48::from_num(total_duration))
+                .ok_or_else(math_error!())?,
+        ))
+    }
+
+    /// Initial requirements take the more conservative of the oracle price and the TWAP,
+    /// so a short price spike can't be borrowed against. Other requirements use `price` as is.
+    pub fn bound_by_twap(
+        &self,
+        price: I80F48,
+        requirement_type: RequirementType,
+        bias: PriceBias,
+    ) -> MarginfiResult<I80F48> {
+        if !matches!(requirement_type, RequirementType::Initial) {
+            return Ok(price);
+        }
+
+        Ok(match (self.get_twap_price()?, bias) {
+            (Some(twap), PriceBias::Low) => price.min(twap),
+            (Some(twap), PriceBias::High) => price.max(twap),
+            (None, _) => price,
+        })
+    }
+
+    /// Circuit breaker against fast drains: add `amount` to the borrow volume of the current
+    /// window and fail if that exceeds `borrow_window_cap`. Windows are fixed blocks of
+    /// `borrow_window_slots` slots, starting with the first borrow after the previous one ended.
+    pub fn record_borrow_volume(&mut self, amount: u64, current_slot: u64) -> MarginfiResult {
+        if self.borrow_window_slots == 0 {
+            return Ok(());
+        }
+
+        if current_slot
+            >= self
+                .borrow_window_start_slot
+                .saturating_add(self.borrow_window_slots as u64)
+        {
+            self.borrow_window_start_slot = current_slot;
+            self.borrow_window_volume = 0;
+        }
+
+        let borrow_window_volume = self
+            .borrow_window_volume
+            .checked_add(amount)
+            .ok_or_else(math_error!())?;
+
+        check!(
+            borrow_window_volume <= self.borrow_window_cap,
+            MarginfiError::BorrowWindowCapExceeded
+        );
+
+        self.borrow_window_volume = borrow_window_volume;
+
+        Ok(())
+    }
+
+    /// Liquidator fee cap while the bank is in its post-unpause grace period, `None` outside of it.
+    pub fn get_liquidation_grace_fee(&self, current_timestamp: i64) -> Option<I80F48> {
+        let in_grace_period = self.liquidation_grace_period != 0
+            && self.unpaused_at != 0
+            && current_timestamp
+                < self
+                    .unpaused_at
+                    .saturating_add(self.liquidation_grace_period as i64);
+
+        in_grace_period.then(|| {
+            I80F48::from_num(self.liquidation_grace_fee_bps)
+                / I80F48::from_num(WEIGHT_BPS_DENOMINATOR)
+        })
+    }
+
+    pub fn assert_operational_mode(
+        &self,
+        is_asset_or_liability_amount_increasing: Option<bool>,
     ) -> Result<()> {
+        if self.get_flag(ORACLE_DEVIATION_FLAG) {
+            check!(
+                is_asset_or_liability_amount_increasing != Some(true),
+                MarginfiError::OracleDeviationExceeded
+            );
+        }
+
         match self.config.operational_state {
             BankOperationalState::Paused => Err(MarginfiError::BankPaused.into()),
             BankOperationalState::Operational => Ok(()),
@@ -796,6 +2482,7 @@ impl Bank {
 
                 Ok(())
             }
+            BankOperationalState::Killed => Err(MarginfiError::BankKilled.into()),
         }
     }
 
@@ -805,7 +2492,23 @@ impl Bank {
 
     pub(crate) fn override_emissions_flag(&mut self, flag: u64) {
         assert!(Self::verify_emissions_flags(flag));
-        self.flags = flag;
+        self.flags = flag | (self.flags & BANK_CONFIG_FROZEN_FLAG);
+    }
+
+    /// Deposits into a bank with a fixed price oracle don't count towards account health
+    /// unless [`FIXED_PRICE_COLLATERAL_FLAG`] is set.
+    pub fn is_fixed_price_collateral_blocked(&self) -> bool {
+        matches!(self.config.oracle_setup, OracleSetup::Fixed)
+            && !self.get_flag(FIXED_PRICE_COLLATERAL_FLAG)
+    }
+
+    /// Oracle price used for a health requirement of this bank.
+    pub fn get_oracle_price_type(&self, requirement_type: RequirementType) -> OraclePriceType {
+        if self.get_flag(MAINTENANCE_EMA_PRICE_FLAG) {
+            OraclePriceType::TimeWeighted
+        } else {
+            requirement_type.get_oracle_price_type()
+        }
     }
 
     pub(crate) fn update_flag(&mut self, value: bool, flag: u64) {
@@ -858,18 +2561,20 @@ fn calc_interest_rate_accrual_state_changes(
     time_delta: u64,
     total_assets_amount: I80F48,
     total_liabilities_amount: I80F48,
+    curve_utilization_rate: I80F48,
     interest_rate_config: &InterestRateConfig,
     asset_share_value: I80F48,
     liability_share_value: I80F48,
 ) -> Option<(I80F48, I80F48, I80F48, I80F48)> {
     let utilization_rate = total_liabilities_amount.checked_div(total_assets_amount)?;
-    let (lending_apr, borrowing_apr, group_fee_apr, insurance_fee_apr) =
-        interest_rate_config.calc_interest_rate(utilization_rate)?;
+    let (lending_apr, borrowing_apr, group_fee_apr, insurance_fee_apr) = interest_rate_config
+        .calc_interest_rate_smoothed(utilization_rate, curve_utilization_rate)?;
 
     debug!(
-        "Accruing interest for {} seconds. Utilization rate: {}. Lending APR: {}. Borrowing APR: {}. Group fee APR: {}. Insurance fee APR: {}.",
+        "Accruing interest for {} seconds. Utilization rate: {}. Curve utilization rate: {}. Lending APR: {}. Borrowing APR: {}. Group fee APR: {}. Insurance fee APR: {}.",
         time_delta,
         utilization_rate,
+        curve_utilization_rate,
         lending_apr,
         borrowing_apr,
         group_fee_apr,
@@ -884,41 +2589,6 @@ fn calc_interest_rate_accrual_state_changes(
     ))
 }
 
-/// Calculates the fee rate for a given base rate and fees specified.
-/// The returned rate is only the fee rate without the base rate.
-///
-/// Used for calculating the fees charged to the borrowers.
-fn calc_fee_rate(base_rate: I80F48, rate_fees: I80F48, fixed_fees: I80F48) -> Option<I80F48> {
-    base_rate.checked_mul(rate_fees)?.checked_add(fixed_fees)
-}
-
-/// Calculates the accrued interest payment per period `time_delta` in a principal value `value` for interest rate (in APR) `arp`.
-/// Result is the new principal value.
-fn calc_accrued_interest_payment_per_period(
-    apr: I80F48,
-    time_delta: u64,
-    value: I80F48,
-) -> Option<I80F48> {
-    let ir_per_period = apr
-        .checked_mul(time_delta.into())?
-        .checked_div(SECONDS_PER_YEAR)?;
-
-    let new_value = value.checked_mul(I80F48::ONE.checked_add(ir_per_period)?)?;
-
-    Some(new_value)
-}
-
-/// Calculates the interest payment for a given period `time_delta` in a principal value `value` for interest rate (in APR) `arp`.
-/// Result is the interest payment.
-fn calc_interest_payment_for_period(apr: I80F48, time_delta: u64, value: I80F48) -> Option<I80F48> {
-    let interest_payment = value
-        .checked_mul(apr)?
-        .checked_mul(time_delta.into())?
-        .checked_div(SECONDS_PER_YEAR)?;
-
-    Some(interest_payment)
-}
-
 #[repr(u8)]
 #[cfg_attr(any(feature = "test", feature = "client"), derive(PartialEq, Eq))]
 #[derive(Copy, Clone, Debug, AnchorSerialize, AnchorDeserialize)]
@@ -926,6 +2596,8 @@ pub enum BankOperationalState {
     Paused,
     Operational,
     ReduceOnly,
+    /// Terminal, set through `lending_pool_kill_bank`, see [`Bank::kill`].
+    Killed,
 }
 
 #[cfg(feature = "client")]
@@ -935,6 +2607,7 @@ impl Display for BankOperationalState {
             BankOperationalState::Paused => write!(f, "Paused"),
             BankOperationalState::Operational => write!(f, "Operational"),
             BankOperationalState::ReduceOnly => write!(f, "ReduceOnly"),
+            BankOperationalState::Killed => write!(f, "Killed"),
         }
     }
 }
@@ -1016,10 +2689,21 @@ impl From<BankConfigCompact> for BankConfig {
             _pad0: [0; 6],
             borrow_limit: config.borrow_limit,
             risk_tier: config.risk_tier,
-            _pad1: [0; 7],
+            _pad1: [0; 3],
+            oracle_max_age_slots: 0,
             total_asset_value_init_limit: config.total_asset_value_init_limit,
             oracle_max_age: config.oracle_max_age,
-            _padding: [0; 38],
+            correlation_bucket: 0,
+            _pad2: [0; 1],
+            weights_bps: WeightsBps::default(),
+            insurance_premium_bps: 0,
+            oracle_max_confidence_bps: 0,
+            max_borrow_duration: 0,
+            emissions_boost_bps: 0,
+            oracle_max_deviation_bps: 0,
+            emissions_boost_duration: 0,
+            locked_asset_weight_boost_bps: 0,
+            locked_emissions_boost_bps: 0,
         }
     }
 }
@@ -1077,7 +2761,12 @@ pub struct BankConfig {
 
     pub risk_tier: RiskTier,
 
-    pub _pad1: [u8; 7],
+    pub _pad1: [u8; 3],
+
+    /// Max distance in slots between the oracle's publish slot and the current slot.
+    /// Replaces the `oracle_max_age` timestamp check when set, so drift of the cluster clock
+    /// can't make prices look stale or fresh. `0` uses `oracle_max_age`.
+    pub oracle_max_age_slots: u32,
 
     /// USD denominated limit for calculating asset value for initialization margin requirements.
     /// Example, if total SOL deposits are equal to $1M and the limit it set to $500K,
@@ -1090,9 +2779,48 @@ pub struct BankConfig {
     pub total_asset_value_init_limit: u64,
 
     /// Time window in seconds for the oracle price feed to be considered live.
+    /// `0` uses the oracle provider's default, see [`BankConfig::get_oracle_max_age`].
     pub oracle_max_age: u16,
 
-    pub _padding: [u8; 38],
+    /// Correlation bucket of the bank for portfolio margin, `0` if the bank is in none.
+    /// Offsets per bucket are configured on the group.
+    pub correlation_bucket: u8,
+
+    pub _pad2: [u8; 1],
+
+    /// Weights as configured in basis points, the I80F48 weights above are derived from these.
+    /// All zero if the weights were configured as I80F48 directly.
+    pub weights_bps: WeightsBps,
+
+    /// Premium in bps of the yield that insured deposits pay to the insurance vault,
+    /// `0` disables insurance for new depositors.
+    pub insurance_premium_bps: u16,
+
+    /// Cap in bps of the price on the confidence interval that biased prices are widened by,
+    /// `0` uses [`crate::constants::MAX_CONF_INTERVAL`]. Raise it for volatile or thin feeds.
+    pub oracle_max_confidence_bps: u16,
+
+    /// Max time in seconds a liability can stay open, `0` if borrows don't expire.
+    /// Expired liabilities can be liquidated regardless of account health,
+    /// see [`crate::constants::LIQUIDATION_MATURITY_LIQUIDATOR_FEE`].
+    pub max_borrow_duration: u32,
+
+    /// Extra emissions in bps for deposits held for `emissions_boost_duration`,
+    /// ramping up linearly with the deposit age. `0` disables the boost.
+    pub emissions_boost_bps: u16,
+
+    /// Max divergence in bps between the primary and fallback oracle before the oracle
+    /// circuit breaker blocks deposits and borrows, `0` disables the check.
+    pub oracle_max_deviation_bps: u16,
+
+    /// Deposit age in seconds at which the full emissions boost applies.
+    pub emissions_boost_duration: u32,
+
+    /// Added to the initial asset weight of locked deposits, capped at the maintenance weight.
+    pub locked_asset_weight_boost_bps: u16,
+
+    /// Extra emissions in bps for locked deposits.
+    pub locked_emissions_boost_bps: u16,
 }
 
 impl Default for BankConfig {
@@ -1110,15 +2838,61 @@ impl Default for BankConfig {
             oracle_keys: [Pubkey::default(); MAX_ORACLE_KEYS],
             _pad0: [0; 6],
             risk_tier: RiskTier::Isolated,
-            _pad1: [0; 7],
+            _pad1: [0; 3],
+            oracle_max_age_slots: 0,
             total_asset_value_init_limit: TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE,
             oracle_max_age: 0,
-            _padding: [0; 38],
+            correlation_bucket: 0,
+            _pad2: [0; 1],
+            weights_bps: WeightsBps::default(),
+            insurance_premium_bps: 0,
+            oracle_max_confidence_bps: 0,
+            max_borrow_duration: 0,
+            emissions_boost_bps: 0,
+            oracle_max_deviation_bps: 0,
+            emissions_boost_duration: 0,
+            locked_asset_weight_boost_bps: 0,
+            locked_emissions_boost_bps: 0,
         }
     }
 }
 
 impl BankConfig {
+    /// Conservative template forced on banks added without the admin: isolated tier, no
+    /// collateral value, raised liability weights and the group's caps, given in whole tokens.
+    /// Only the oracle is chosen by the creator, the admin can loosen the rest later.
+    pub fn permissionless(
+        oracle_setup: OracleSetup,
+        oracle_key: Pubkey,
+        mint_decimals: u8,
+        group: &MarginfiGroup,
+    ) -> Self {
+        let scale = 10u64.saturating_pow(mint_decimals as u32);
+        let mut oracle_keys = [Pubkey::default(); MAX_ORACLE_KEYS];
+        oracle_keys[0] = oracle_key;
+
+        Self {
+            asset_weight_init: I80F48::ZERO.into(),
+            asset_weight_maint: I80F48::ZERO.into(),
+            liability_weight_init: PERMISSIONLESS_LIABILITY_WEIGHT_INIT.into(),
+            liability_weight_maint: PERMISSIONLESS_LIABILITY_WEIGHT_MAINT.into(),
+            deposit_limit: group.permissionless_deposit_limit.saturating_mul(scale),
+            borrow_limit: group.permissionless_borrow_limit.saturating_mul(scale),
+            interest_rate_config: InterestRateConfig {
+                optimal_utilization_rate: I80F48::from_num(0.8).into(),
+                plateau_interest_rate: I80F48::from_num(0.1).into(),
+                max_interest_rate: I80F48::from_num(3).into(),
+                insurance_ir_fee: I80F48::from_num(0.1).into(),
+                ..Default::default()
+            },
+            operational_state: BankOperationalState::Operational,
+            oracle_setup,
+            oracle_keys,
+            risk_tier: RiskTier::Isolated,
+            ..Default::default()
+        }
+    }
+
     #[inline]
     pub fn get_weights(&self, req_type: RequirementType) -> (I80F48, I80F48) {
         match req_type {
@@ -1153,6 +2927,25 @@ impl BankConfig {
         }
     }
 
+    /// Set the weights from basis points, keeping the exact values alongside the derived I80F48 weights.
+    pub fn set_weights_bps(&mut self, weights_bps: WeightsBps) -> MarginfiResult {
+        self.asset_weight_init = WeightsBps::to_weight(weights_bps.asset_weight_init)?.into();
+        self.asset_weight_maint = WeightsBps::to_weight(weights_bps.asset_weight_maint)?.into();
+        self.liability_weight_init =
+            WeightsBps::to_weight(weights_bps.liability_weight_init)?.into();
+        self.liability_weight_maint =
+            WeightsBps::to_weight(weights_bps.liability_weight_maint)?.into();
+        self.weights_bps = weights_bps;
+
+        Ok(())
+    }
+
+    /// Weights in basis points, if they were configured that way.
+    pub fn get_weights_bps(&self) -> Option<WeightsBps> {
+        // Liability weights are at least 1.0, so zero means unset
+        (self.weights_bps.liability_weight_init != 0).then_some(self.weights_bps)
+    }
+
     pub fn validate(&self) -> MarginfiResult {
         let asset_init_w = I80F48::from(self.asset_weight_init);
         let asset_maint_w = I80F48::from(self.asset_weight_maint);
@@ -1174,6 +2967,41 @@ impl BankConfig {
 
         self.interest_rate_config.validate()?;
 
+        check!(
+            self.correlation_bucket as usize <= MAX_CORRELATION_BUCKETS,
+            MarginfiError::InvalidConfig
+        );
+
+        check!(
+            self.insurance_premium_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+            MarginfiError::InvalidConfig
+        );
+
+        check!(
+            self.oracle_max_confidence_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+            MarginfiError::InvalidConfig
+        );
+
+        check!(
+            self.oracle_max_deviation_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+            MarginfiError::InvalidConfig
+        );
+
+        check!(
+            self.emissions_boost_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+            MarginfiError::InvalidConfig
+        );
+
+        check!(
+            self.locked_asset_weight_boost_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+            MarginfiError::InvalidConfig
+        );
+
+        check!(
+            self.locked_emissions_boost_bps as u32 <= WEIGHT_BPS_DENOMINATOR,
+            MarginfiError::InvalidConfig
+        );
+
         if self.risk_tier == RiskTier::Isolated {
             check!(asset_init_w == I80F48::ZERO, MarginfiError::InvalidConfig);
             check!(asset_maint_w == I80F48::ZERO, MarginfiError::InvalidConfig);
@@ -1192,8 +3020,12 @@ impl BankConfig {
         self.borrow_limit != u64::MAX
     }
 
-    pub fn validate_oracle_setup(&self, ais: &[AccountInfo]) -> MarginfiResult {
-        OraclePriceFeedAdapter::validate_bank_config(self, ais)?;
+    pub fn validate_oracle_setup(
+        &self,
+        ais: &[AccountInfo],
+        pyth_program_id: &Pubkey,
+    ) -> MarginfiResult {
+        OraclePriceFeedAdapter::validate_bank_config(self, ais, pyth_program_id)?;
         Ok(())
     }
 
@@ -1201,15 +3033,154 @@ impl BankConfig {
         self.total_asset_value_init_limit != TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE
     }
 
-    #[inline]
-    pub fn get_oracle_max_age(&self) -> u64 {
+    /// Emissions multiplier for a deposit of the given age in seconds, `1` without a boost.
+    pub fn get_emissions_boost_multiplier(&self, deposit_age: u64) -> MarginfiResult<I80F48> {
+        if self.emissions_boost_bps == 0 {
+            return Ok(I80F48::ONE);
+        }
+
+        let ramp = if self.emissions_boost_duration == 0 {
+            I80F48::ONE
+        } else {
+            I80F48::from_num(deposit_age)
+                .checked_div(I80F48::from_num(self.emissions_boost_duration))
+                .ok_or_else(math_error!())?
+                .min(I80F48::ONE)
+        };
+
+        I80F48::from_num(self.emissions_boost_bps)
+            .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+            .ok_or_else(math_error!())?
+            .checked_mul(ramp)
+            .ok_or_else(math_error!())?
+            .checked_add(I80F48::ONE)
+            .ok_or_else(math_error!())
+    }
+
+    /// Emissions multiplier for a deposit that was locked for `locked_period` of the `period` seconds.
+    pub fn get_locked_emissions_boost_multiplier(
+        &self,
+        locked_period: u64,
+        period: u64,
+    ) -> MarginfiResult<I80F48> {
+        if self.locked_emissions_boost_bps == 0 || locked_period == 0 {
+            return Ok(I80F48::ONE);
+        }
+
+        I80F48::from_num(self.locked_emissions_boost_bps)
+            .checked_mul(I80F48::from_num(locked_period.min(period)))
+            .ok_or_else(math_error!())?
+            .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+            .ok_or_else(math_error!())?
+            .checked_div(I80F48::from_num(period))
+            .ok_or_else(math_error!())?
+            .checked_add(I80F48::ONE)
+            .ok_or_else(math_error!())
+    }
+
+    /// Initial asset weight of a locked deposit.
+    /// Capped at the maintenance weight, so the boost can't open a position that is immediately liquidatable.
+    pub fn get_locked_asset_weight_init(&self) -> MarginfiResult<I80F48> {
+        let boost = I80F48::from_num(self.locked_asset_weight_boost_bps)
+            .checked_div(I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+            .ok_or_else(math_error!())?;
+
+        Ok(I80F48::from(self.asset_weight_init)
+            .checked_add(boost)
+            .ok_or_else(math_error!())?
+            .min(self.asset_weight_maint.into()))
+    }
+
+    /// Whether a liability opened at `liability_opened_at` ran past the max borrow duration.
+    /// Liabilities opened before the open time was tracked (`0`) never expire.
+    pub fn is_liability_expired(&self, liability_opened_at: u32, current_timestamp: i64) -> bool {
+        self.max_borrow_duration != 0
+            && liability_opened_at != 0
+            && current_timestamp >= liability_opened_at as i64 + self.max_borrow_duration as i64
+    }
+
+    #[inline]
+    /// Max age in seconds of a price the risk engine accepts, older prices fail with `StaleOracle`.
+    /// Falls back to a per-provider default when `oracle_max_age` is unset.
+    pub fn get_oracle_max_age(&self) -> u64 {
         match (self.oracle_max_age, self.oracle_setup) {
-            (0, OracleSetup::SwitchboardV2) => MAX_SWB_ORACLE_AGE,
-            (0, OracleSetup::PythLegacy | OracleSetup::PythPushOracle) => MAX_PYTH_ORACLE_AGE,
+            (0, OracleSetup::SwitchboardV2 | OracleSetup::SwitchboardPull) => MAX_SWB_ORACLE_AGE,
+            (0, OracleSetup::PythLegacy | OracleSetup::PythPushOracle | OracleSetup::Chainlink) => {
+                MAX_PYTH_ORACLE_AGE
+            }
+            #[cfg(feature = "mock-oracle")]
+            (0, OracleSetup::Mock) => MAX_PYTH_ORACLE_AGE,
             (n, _) => n as u64,
         }
     }
 
+    /// Max confidence interval as a fraction of the price, see `oracle_max_confidence_bps`.
+    pub fn get_oracle_max_confidence(&self) -> I80F48 {
+        if self.oracle_max_confidence_bps == 0 {
+            MAX_CONF_INTERVAL
+        } else {
+            I80F48::from_num(self.oracle_max_confidence_bps)
+                / I80F48::from_num(WEIGHT_BPS_DENOMINATOR)
+        }
+    }
+
+    /// Whether the fallback oracle price diverges from the primary by more than `oracle_max_deviation_bps`.
+    pub fn exceeds_oracle_max_deviation(
+        &self,
+        primary_price: I80F48,
+        fallback_price: I80F48,
+    ) -> MarginfiResult<bool> {
+        if self.oracle_max_deviation_bps == 0 {
+            return Ok(false);
+        }
+
+        let deviation = primary_price
+            .checked_sub(fallback_price)
+            .ok_or_else(math_error!())?
+            .abs()
+            .checked_div(primary_price)
+            .ok_or_else(math_error!())?;
+
+        Ok(deviation
+            > I80F48::from_num(self.oracle_max_deviation_bps)
+                / I80F48::from_num(WEIGHT_BPS_DENOMINATOR))
+    }
+
+    /// Price of a bank with [`OracleSetup::Fixed`], stored in the first 16 bytes of `oracle_keys[0]`.
+    /// `None` for other setups or a price that is not positive.
+    pub fn get_fixed_price(&self) -> Option<I80F48> {
+        if !matches!(self.oracle_setup, OracleSetup::Fixed) {
+            return None;
+        }
+
+        let bytes: [u8; 16] = self.oracle_keys[0].as_ref()[..16].try_into().unwrap();
+        let price = I80F48::from_le_bytes(bytes);
+
+        (price > I80F48::ZERO).then_some(price)
+    }
+
+    /// Switch the bank to [`OracleSetup::Fixed`] at `price`.
+    pub fn set_fixed_price(&mut self, price: I80F48) {
+        let mut key = [0; 32];
+        key[..16].copy_from_slice(&price.to_le_bytes());
+
+        self.oracle_setup = OracleSetup::Fixed;
+        self.oracle_keys = [Pubkey::default(); MAX_ORACLE_KEYS];
+        self.oracle_keys[0] = Pubkey::new_from_array(key);
+    }
+
+    /// Secondary oracle of the same provider as the primary, used when the primary
+    /// is stale or invalid. Stored in `oracle_keys[1]`, a feed id for Pyth push oracles.
+    pub fn get_fallback_oracle_key(&self) -> Option<&Pubkey> {
+        let key = &self.oracle_keys[1];
+
+        if key == &Pubkey::default() || key == &self.oracle_keys[0] {
+            None
+        } else {
+            Some(key)
+        }
+    }
+
     pub fn get_pyth_push_oracle_feed_id(&self) -> Option<&FeedId> {
         if matches!(self.oracle_setup, OracleSetup::PythPushOracle) {
             let bytes: &[u8; 32] = self.oracle_keys[0].as_ref().try_into().unwrap();
@@ -1251,6 +3222,47 @@ impl From<WrappedI80F48> for I80F48 {
     }
 }
 
+/// Serialized as an exact decimal string (e.g. `"0.75"`) instead of the raw little endian bits.
+#[cfg(feature = "client")]
+impl serde::Serialize for WrappedI80F48 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&I80F48::from(*self))
+    }
+}
+
+#[cfg(feature = "client")]
+impl<'de> serde::Deserialize<'de> for WrappedI80F48 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse::<I80F48>()
+            .map(Into::into)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Bank weights in basis points, 10_000 bps = 1.0.
+#[zero_copy]
+#[repr(C)]
+#[cfg_attr(any(feature = "test", feature = "client"), derive(TypeLayout))]
+#[derive(Default, Debug, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct WeightsBps {
+    pub asset_weight_init: u32,
+    pub asset_weight_maint: u32,
+    pub liability_weight_init: u32,
+    pub liability_weight_maint: u32,
+}
+
+impl WeightsBps {
+    pub fn to_weight(bps: u32) -> MarginfiResult<I80F48> {
+        Ok(bps_to_weight(bps).ok_or_else(math_error!())?)
+    }
+
+    /// Inverse of [`WeightsBps::to_weight`], rounds to the nearest basis point.
+    pub fn from_weight(weight: I80F48) -> MarginfiResult<u32> {
+        Ok(weight_to_bps(weight).ok_or_else(math_error!())?)
+    }
+}
+
 #[cfg_attr(
     any(feature = "test", feature = "client"),
     derive(Clone, PartialEq, Eq, TypeLayout)
@@ -1268,6 +3280,7 @@ pub struct BankConfigOpt {
 
     pub operational_state: Option<BankOperationalState>,
 
+    /// Proposed only, see [`Bank::propose_oracle`].
     pub oracle: Option<OracleConfig>,
 
     pub interest_rate_config: Option<InterestRateConfigOpt>,
@@ -1278,7 +3291,87 @@ pub struct BankConfigOpt {
 
     pub oracle_max_age: Option<u16>,
 
+    pub oracle_max_age_slots: Option<u32>,
+
+    pub oracle_max_confidence_bps: Option<u16>,
+
+    pub oracle_max_deviation_bps: Option<u16>,
+
     pub permissionless_bad_debt_settlement: Option<bool>,
+
+    /// Switches the bank to a fixed price oracle at this price.
+    pub fixed_price: Option<WrappedI80F48>,
+
+    /// Allow deposits priced by a fixed price oracle to count as collateral.
+    pub fixed_price_collateral: Option<bool>,
+
+    /// Use the EMA price for maintenance health and liquidations.
+    pub maintenance_ema_price: Option<bool>,
+
+    /// Overrides the I80F48 weights above when set.
+    pub weights_bps: Option<WeightsBps>,
+
+    pub correlation_bucket: Option<u8>,
+
+    pub insurance_premium_bps: Option<u16>,
+
+    pub max_borrow_duration: Option<u32>,
+
+    pub emissions_boost_bps: Option<u16>,
+
+    pub emissions_boost_duration: Option<u32>,
+
+    pub locked_asset_weight_boost_bps: Option<u16>,
+
+    pub locked_emissions_boost_bps: Option<u16>,
+
+    pub liquidation_grace_period: Option<u32>,
+
+    pub liquidation_grace_fee_bps: Option<u16>,
+
+    pub borrow_window_cap: Option<u64>,
+
+    pub borrow_window_slots: Option<u32>,
+
+    pub twap_window: Option<u32>,
+
+    pub accrual_bounty: Option<u64>,
+
+    pub accrual_bounty_min_age: Option<u32>,
+
+    pub utilization_smoothing_window: Option<u32>,
+
+    pub insurance_deposit_fee_apr: Option<WrappedI80F48>,
+
+    pub max_deposit_per_account: Option<u64>,
+
+    pub min_borrow_amount: Option<u64>,
+
+    pub dust_threshold: Option<u64>,
+
+    pub max_borrow_utilization_bps: Option<u16>,
+
+    pub liquidity_reserve_bps: Option<u16>,
+
+    /// Only `true` is accepted, see [`BANK_CONFIG_FROZEN_FLAG`].
+    pub freeze_config: Option<bool>,
+}
+
+impl BankConfigOpt {
+    /// Whether this only changes the operational state, the one thing a frozen bank accepts.
+    /// Compared serialized so fields added later are covered as well.
+    pub fn is_operational_state_only(&self) -> bool {
+        let operational_state_only = BankConfigOpt {
+            operational_state: self.operational_state,
+            freeze_config: self.freeze_config,
+            ..Default::default()
+        };
+
+        matches!(
+            (self.try_to_vec(), operational_state_only.try_to_vec()),
+            (Ok(config), Ok(allowed)) if config == allowed
+        )
+    }
 }
 
 #[cfg_attr(
@@ -1291,7 +3384,7 @@ pub struct OracleConfig {
     pub keys: [Pubkey; MAX_ORACLE_KEYS],
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, AnchorSerialize, AnchorDeserialize)]
 pub enum BankVaultType {
     Liquidity,
     Insurance,
@@ -1329,139 +3422,680 @@ macro_rules! assert_eq_with_tolerance {
     };
 }
 
+/// Bank configurations for common asset types, for tests and clients setting up banks.
+#[cfg(any(test, feature = "test", feature = "client"))]
+pub mod presets {
+    use super::*;
+    use fixed_macro::types::I80F48;
+
+    /// Kinked curve with 80% optimal utilization and small insurance and protocol fees.
+    pub fn preset_interest_rate_config() -> InterestRateConfig {
+        InterestRateConfig {
+            optimal_utilization_rate: I80F48!(0.8).into(),
+            plateau_interest_rate: I80F48!(0.1).into(),
+            max_interest_rate: I80F48!(1).into(),
+            insurance_fee_fixed_apr: I80F48!(0.01).into(),
+            insurance_ir_fee: I80F48!(0.05).into(),
+            protocol_fixed_fee_apr: I80F48!(0.01).into(),
+            protocol_ir_fee: I80F48!(0.05).into(),
+            ..Default::default()
+        }
+    }
+
+    /// Stablecoin with 6 decimals (USDT-like), 1B deposit / 500M borrow limit.
+    pub fn preset_stable_6_decimals_bank_config() -> BankConfig {
+        BankConfig {
+            asset_weight_init: I80F48!(0.9).into(),
+            asset_weight_maint: I80F48!(0.95).into(),
+            liability_weight_init: I80F48!(1.1).into(),
+            liability_weight_maint: I80F48!(1.05).into(),
+            deposit_limit: 1_000_000_000 * 10u64.pow(6),
+            borrow_limit: 500_000_000 * 10u64.pow(6),
+            interest_rate_config: preset_interest_rate_config(),
+            operational_state: BankOperationalState::Operational,
+            risk_tier: RiskTier::Collateral,
+            ..Default::default()
+        }
+    }
+
+    /// Liquid staking token with 9 decimals, priced close to SOL.
+    pub fn preset_lst_9_decimals_bank_config() -> BankConfig {
+        BankConfig {
+            asset_weight_init: I80F48!(0.65).into(),
+            asset_weight_maint: I80F48!(0.8).into(),
+            liability_weight_init: I80F48!(1.3).into(),
+            liability_weight_maint: I80F48!(1.2).into(),
+            deposit_limit: 1_000_000 * 10u64.pow(9),
+            borrow_limit: 100_000 * 10u64.pow(9),
+            interest_rate_config: preset_interest_rate_config(),
+            operational_state: BankOperationalState::Operational,
+            risk_tier: RiskTier::Collateral,
+            ..Default::default()
+        }
+    }
+
+    /// Isolated, non-collateral bank with punitive liability weights.
+    pub fn preset_isolated_meme_bank_config() -> BankConfig {
+        BankConfig {
+            asset_weight_init: I80F48::ZERO.into(),
+            asset_weight_maint: I80F48::ZERO.into(),
+            liability_weight_init: I80F48!(2.5).into(),
+            liability_weight_maint: I80F48!(1.5).into(),
+            deposit_limit: 10_000_000_000 * 10u64.pow(6),
+            borrow_limit: 1_000_000_000 * 10u64.pow(6),
+            interest_rate_config: InterestRateConfig {
+                max_interest_rate: I80F48!(5).into(),
+                ..preset_interest_rate_config()
+            },
+            operational_state: BankOperationalState::Operational,
+            risk_tier: RiskTier::Isolated,
+            ..Default::default()
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::{SystemTime, UNIX_EPOCH};
 
+    use super::presets::*;
     use super::*;
+    use crate::constants::SECONDS_PER_YEAR;
     use fixed_macro::types::I80F48;
 
+    #[cfg(feature = "client")]
     #[test]
-    /// Tests that the interest payment for a 1 year period with 100% APR is 1.
-    fn interest_payment_100apr_1year() {
-        let apr = I80F48::ONE;
-        let time_delta = 31_536_000; // 1 year
-        let value = I80F48::ONE;
+    fn wrapped_i80f48_decimal_serde_roundtrip() {
+        for value in [
+            I80F48::ZERO,
+            I80F48!(0.75),
+            I80F48!(-1.5),
+            I80F48::from_num(1) / I80F48::from_num(3),
+            I80F48::DELTA,
+            I80F48::MAX,
+            I80F48::MIN,
+        ] {
+            let wrapped = WrappedI80F48::from(value);
+            let json = serde_json::to_string(&wrapped).unwrap();
+            assert_eq!(json, format!("\"{}\"", value));
+
+            let decoded: WrappedI80F48 = serde_json::from_str(&json).unwrap();
+            assert_eq!(I80F48::from(decoded), value);
+        }
 
-        assert_eq_with_tolerance!(
-            calc_interest_payment_for_period(apr, time_delta, value).unwrap(),
-            I80F48::ONE,
-            I80F48!(0.001)
-        );
+        assert!(serde_json::from_str::<WrappedI80F48>("\"not a number\"").is_err());
     }
 
-    /// Tests that the interest payment for a 1 year period with 50% APR is 0.5.
     #[test]
-    fn interest_payment_50apr_1year() {
-        let apr = I80F48::from_num(0.5);
-        let time_delta = 31_536_000; // 1 year
-        let value = I80F48::ONE;
-
-        assert_eq_with_tolerance!(
-            calc_interest_payment_for_period(apr, time_delta, value).unwrap(),
-            I80F48::from_num(0.5),
-            I80F48!(0.001)
-        );
+    fn group_program_version_migration() {
+        let mut group = MarginfiGroup::default();
+        assert_eq!(group.program_version, 0);
+        group.check_program_version().unwrap();
+
+        assert_eq!(group.migrate().unwrap(), 0);
+        assert_eq!(group.program_version, PROGRAM_VERSION);
+        group.check_program_version().unwrap();
+
+        // Already current, migration is a no-op
+        assert_eq!(group.migrate().unwrap(), PROGRAM_VERSION);
+
+        // Groups written by a newer program are rejected
+        group.program_version = PROGRAM_VERSION + 1;
+        assert!(group.check_program_version().is_err());
+        assert!(group.migrate().is_err());
     }
-    /// P: 1_000_000
-    /// Apr: 12%
-    /// Time: 1 second
+
     #[test]
-    fn interest_payment_12apr_1second() {
-        let apr = I80F48!(0.12);
-        let time_delta = 1;
-        let value = I80F48!(1_000_000);
+    fn group_correlation_offsets_config() {
+        let mut group = MarginfiGroup::default();
+
+        let mut offsets = [0; MAX_CORRELATION_BUCKETS];
+        offsets[0] = MAX_CORRELATION_OFFSET_BPS + 1;
+        assert!(group
+            .configure(&GroupConfig {
+                correlation_offsets_bps: Some(offsets),
+                ..Default::default()
+            })
+            .is_err());
 
-        assert_eq_with_tolerance!(
-            calc_interest_payment_for_period(apr, time_delta, value).unwrap(),
-            I80F48!(0.0038),
-            I80F48!(0.001)
-        );
-    }
+        offsets[0] = 2_500;
+        group
+            .configure(&GroupConfig {
+                correlation_offsets_bps: Some(offsets),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(group.correlation_offsets_bps, offsets);
 
-    #[test]
-    /// apr: 100%
-    /// time: 1 year
-    /// principal: 2
-    /// expected: 4
-    fn accrued_interest_apr100_year1() {
-        assert_eq_with_tolerance!(
-            calc_accrued_interest_payment_per_period(I80F48!(1), 31_536_000, I80F48!(2)).unwrap(),
-            I80F48!(4),
-            I80F48!(0.001)
-        );
+        let mut config = preset_lst_9_decimals_bank_config();
+        config.correlation_bucket = MAX_CORRELATION_BUCKETS as u8;
+        config.validate().unwrap();
+        config.correlation_bucket = MAX_CORRELATION_BUCKETS as u8 + 1;
+        assert!(config.validate().is_err());
     }
 
     #[test]
-    /// apr: 50%
-    /// time: 1 year
-    /// principal: 2
-    /// expected: 3
-    fn accrued_interest_apr50_year1() {
-        assert_eq_with_tolerance!(
-            calc_accrued_interest_payment_per_period(I80F48!(0.5), 31_536_000, I80F48!(2)).unwrap(),
-            I80F48!(3),
-            I80F48!(0.001)
+    fn group_recovery_mode_config() {
+        let mut group = MarginfiGroup::default();
+        assert!(group.get_recovery_asset_weight_multiplier().is_none());
+
+        assert!(group
+            .configure(&GroupConfig {
+                recovery_asset_weight_multiplier: Some(I80F48!(1.1).into()),
+                ..Default::default()
+            })
+            .is_err());
+
+        group
+            .configure(&GroupConfig {
+                recovery_asset_weight_multiplier: Some(I80F48!(0.5).into()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            group.get_recovery_asset_weight_multiplier(),
+            Some(I80F48!(0.5))
         );
+
+        group
+            .configure(&GroupConfig {
+                recovery_asset_weight_multiplier: Some(I80F48::ZERO.into()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(group.get_recovery_asset_weight_multiplier().is_none());
     }
 
     #[test]
-    /// apr: 12%
-    /// time: 1 second
-    /// principal: 1_000_000
-    /// expected: 1_038
-    fn accrued_interest_apr12_year1() {
-        assert_eq_with_tolerance!(
-            calc_accrued_interest_payment_per_period(I80F48!(0.12), 1, I80F48!(1_000_000)).unwrap(),
-            I80F48!(1_000_000.0038),
-            I80F48!(0.001)
+    fn group_permissionless_bank_creation() {
+        let mint = Pubkey::new_unique();
+        let mut group = MarginfiGroup::default();
+
+        assert!(group.check_permissionless_bank_mint(&mint).is_err());
+
+        // No fee wallet to collect the creation fee
+        assert!(group
+            .configure(&GroupConfig {
+                permissionless_bank_creation: Some(true),
+                ..Default::default()
+            })
+            .is_err());
+
+        let mut permissionless_mints = [Pubkey::default(); MAX_PERMISSIONLESS_MINTS];
+        permissionless_mints[1] = mint;
+        group
+            .configure(&GroupConfig {
+                permissionless_bank_creation: Some(true),
+                fee_wallet: Some(Pubkey::new_unique()),
+                permissionless_deposit_limit: Some(1_000),
+                permissionless_borrow_limit: Some(100),
+                permissionless_mints: Some(permissionless_mints),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert!(group.check_permissionless_bank_mint(&mint).is_ok());
+        assert!(group
+            .check_permissionless_bank_mint(&Pubkey::new_unique())
+            .is_err());
+        assert!(group
+            .check_permissionless_bank_mint(&Pubkey::default())
+            .is_err());
+
+        let config = BankConfig::permissionless(
+            OracleSetup::PythPushOracle,
+            Pubkey::new_unique(),
+            6,
+            &group,
         );
+        config.validate().unwrap();
+        assert_eq!(config.risk_tier, RiskTier::Isolated);
+        assert_eq!(I80F48::from(config.asset_weight_init), I80F48::ZERO);
+        assert_eq!(I80F48::from(config.asset_weight_maint), I80F48::ZERO);
+        assert_eq!(config.deposit_limit, 1_000_000_000);
+        assert_eq!(config.borrow_limit, 100_000_000);
+
+        group
+            .configure(&GroupConfig {
+                permissionless_bank_creation: Some(false),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(group.check_permissionless_bank_mint(&mint).is_err());
     }
 
     #[test]
-    /// ur: 0
-    /// protocol_fixed_fee: 0.01
-    fn ir_config_calc_interest_rate_pff_01() {
-        let config = InterestRateConfig {
-            optimal_utilization_rate: I80F48!(0.6).into(),
-            plateau_interest_rate: I80F48!(0.40).into(),
-            protocol_fixed_fee_apr: I80F48!(0.01).into(),
+    fn group_risk_council() {
+        let admin = Pubkey::new_unique();
+        let council = Pubkey::new_unique();
+        let mut group = MarginfiGroup {
+            admin,
             ..Default::default()
         };
 
-        let (lending_apr, borrow_apr, group_fees_apr, insurance_apr) =
-            config.calc_interest_rate(I80F48!(0)).unwrap();
+        assert!(group.is_admin_or_risk_council(&admin));
+        assert!(!group.is_admin_or_risk_council(&council));
+        assert!(!group.is_admin_or_risk_council(&Pubkey::default()));
 
-        assert_eq_with_tolerance!(lending_apr, I80F48!(0), I80F48!(0.001));
-        assert_eq_with_tolerance!(borrow_apr, I80F48!(0.01), I80F48!(0.001));
-        assert_eq_with_tolerance!(group_fees_apr, I80F48!(0.01), I80F48!(0.001));
-        assert_eq_with_tolerance!(insurance_apr, I80F48!(0), I80F48!(0.001));
+        group
+            .configure(&GroupConfig {
+                risk_council: Some(council),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(group.is_admin_or_risk_council(&council));
+        assert_eq!(group.admin, admin);
     }
 
     #[test]
-    /// ur: 0.5
-    /// protocol_fixed_fee: 0.01
-    /// optimal_utilization_rate: 0.5
-    /// plateau_interest_rate: 0.4
-    fn ir_config_calc_interest_rate_pff_01_ur_05() {
-        let config = InterestRateConfig {
-            optimal_utilization_rate: I80F48!(0.5).into(),
-            plateau_interest_rate: I80F48!(0.4).into(),
-            protocol_fixed_fee_apr: I80F48!(0.01).into(),
-            insurance_ir_fee: I80F48!(0.1).into(),
+    fn group_insurance_admin() {
+        let admin = Pubkey::new_unique();
+        let insurance_admin = Pubkey::new_unique();
+        let mut group = MarginfiGroup {
+            admin,
             ..Default::default()
         };
 
-        let (lending_apr, borrow_apr, group_fees_apr, insurance_apr) =
-            config.calc_interest_rate(I80F48!(0.5)).unwrap();
+        assert!(group.is_admin_or_insurance_admin(&admin));
+        assert!(!group.is_admin_or_insurance_admin(&insurance_admin));
+        assert!(!group.is_admin_or_insurance_admin(&Pubkey::default()));
 
-        assert_eq_with_tolerance!(lending_apr, I80F48!(0.2), I80F48!(0.001));
-        assert_eq_with_tolerance!(borrow_apr, I80F48!(0.45), I80F48!(0.001));
-        assert_eq_with_tolerance!(group_fees_apr, I80F48!(0.01), I80F48!(0.001));
-        assert_eq_with_tolerance!(insurance_apr, I80F48!(0.04), I80F48!(0.001));
+        group
+            .configure(&GroupConfig {
+                insurance_admin: Some(insurance_admin),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(group.is_admin_or_insurance_admin(&insurance_admin));
+        assert!(!group.is_admin_or_risk_council(&insurance_admin));
+
+        group
+            .configure(&GroupConfig {
+                insurance_admin: Some(Pubkey::default()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(!group.is_admin_or_insurance_admin(&insurance_admin));
     }
 
-    /// ur: 0.8
+    #[test]
+    fn group_cpi_allow_list() {
+        let vault_program = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let mut group = MarginfiGroup::default();
+
+        assert!(group.is_cpi_caller_allowed(&other_program));
+
+        let mut allowed = [Pubkey::default(); MAX_CPI_ALLOWED_PROGRAMS];
+        allowed[0] = vault_program;
+        group
+            .configure(&GroupConfig {
+                cpi_allowed_programs: Some(allowed),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert!(group.is_cpi_caller_allowed(&vault_program));
+        assert!(!group.is_cpi_caller_allowed(&other_program));
+        assert!(!group.is_cpi_caller_allowed(&Pubkey::default()));
+    }
+
+    #[test]
+    fn bank_config_presets_are_valid() {
+        for config in [
+            preset_stable_6_decimals_bank_config(),
+            preset_lst_9_decimals_bank_config(),
+            preset_isolated_meme_bank_config(),
+        ] {
+            config.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn weights_bps_round_trip() {
+        for bps in 0..=50_000 {
+            let weight = WeightsBps::to_weight(bps).unwrap();
+            assert_eq!(WeightsBps::from_weight(weight).unwrap(), bps);
+        }
+
+        let mut config = preset_lst_9_decimals_bank_config();
+        assert!(config.get_weights_bps().is_none());
+
+        let weights_bps = WeightsBps {
+            asset_weight_init: 6_500,
+            asset_weight_maint: 8_000,
+            liability_weight_init: 13_000,
+            liability_weight_maint: 12_000,
+        };
+        config.set_weights_bps(weights_bps).unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.get_weights_bps(), Some(weights_bps));
+        assert_eq!(
+            config.get_weights(RequirementType::Initial),
+            (
+                WeightsBps::to_weight(6_500).unwrap(),
+                WeightsBps::to_weight(13_000).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn interest_rate_curve_ramp() {
+        let mut config = preset_interest_rate_config();
+
+        // Without a ramp duration changes apply immediately
+        config
+            .update(
+                &InterestRateConfigOpt {
+                    plateau_interest_rate: Some(I80F48!(0.2).into()),
+                    ..Default::default()
+                },
+                1_000,
+            )
+            .unwrap();
+        assert_eq!(
+            I80F48::from(config.ramped(1_000).unwrap().plateau_interest_rate),
+            I80F48!(0.2)
+        );
+
+        config
+            .update(
+                &InterestRateConfigOpt {
+                    plateau_interest_rate: Some(I80F48!(0.4).into()),
+                    curve_ramp_duration: Some(1_000),
+                    ..Default::default()
+                },
+                2_000,
+            )
+            .unwrap();
+
+        let halfway = config.ramped(2_500).unwrap();
+        assert_eq_with_tolerance!(
+            I80F48::from(halfway.plateau_interest_rate),
+            I80F48!(0.3),
+            I80F48!(0.000001)
+        );
+        assert_eq!(
+            I80F48::from(halfway.optimal_utilization_rate),
+            I80F48::from(config.optimal_utilization_rate)
+        );
+        halfway.validate().unwrap();
+
+        assert_eq!(
+            I80F48::from(config.ramped(1_500).unwrap().plateau_interest_rate),
+            I80F48!(0.2)
+        );
+        assert_eq!(
+            I80F48::from(config.ramped(3_000).unwrap().plateau_interest_rate),
+            I80F48!(0.4)
+        );
+
+        // Updating mid-ramp starts the new ramp from the curve in effect
+        config
+            .update(
+                &InterestRateConfigOpt {
+                    plateau_interest_rate: Some(I80F48!(0.1).into()),
+                    ..Default::default()
+                },
+                2_500,
+            )
+            .unwrap();
+        assert_eq_with_tolerance!(
+            I80F48::from(config.ramped(2_500).unwrap().plateau_interest_rate),
+            I80F48!(0.3),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            I80F48::from(config.ramped(3_000).unwrap().plateau_interest_rate),
+            I80F48!(0.2),
+            I80F48!(0.000001)
+        );
+    }
+
+    #[test]
+    fn interest_rate_config_fee_bounds() {
+        let config = preset_interest_rate_config();
+        config.validate().unwrap();
+
+        let with = |ir_config: InterestRateConfigOpt| {
+            let mut config = config;
+            config.update(&ir_config, 0).unwrap();
+            config.validate()
+        };
+
+        assert!(with(InterestRateConfigOpt {
+            protocol_fixed_fee_apr: Some(I80F48!(-0.01).into()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(with(InterestRateConfigOpt {
+            insurance_ir_fee: Some(I80F48!(-0.01).into()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(with(InterestRateConfigOpt {
+            insurance_ir_fee: Some(I80F48!(0.6).into()),
+            protocol_ir_fee: Some(I80F48!(0.5).into()),
+            ..Default::default()
+        })
+        .is_err());
+        with(InterestRateConfigOpt {
+            insurance_ir_fee: Some(I80F48!(0.5).into()),
+            protocol_ir_fee: Some(I80F48!(0.5).into()),
+            ..Default::default()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn interest_rate_curve_kinks() {
+        let kink = |utilization_bps, rate_bps| InterestRateKink {
+            utilization_bps,
+            rate_bps,
+        };
+
+        let mut config = preset_interest_rate_config();
+        config
+            .update(
+                &InterestRateConfigOpt {
+                    kinks: Some([kink(9_000, 3_000), kink(9_500, 6_000), kink(0, 0)]),
+                    ..Default::default()
+                },
+                0,
+            )
+            .unwrap();
+        config.validate().unwrap();
+
+        for (ur, expected_ir) in [
+            (I80F48!(0.4), I80F48!(0.05)),
+            (I80F48!(0.8), I80F48!(0.1)),
+            (I80F48!(0.85), I80F48!(0.2)),
+            (I80F48!(0.925), I80F48!(0.45)),
+            (I80F48!(0.975), I80F48!(0.8)),
+            (I80F48!(1), I80F48!(1)),
+        ] {
+            assert_eq_with_tolerance!(
+                config.interest_rate_curve(ur).unwrap(),
+                expected_ir,
+                I80F48!(0.000001)
+            );
+        }
+
+        for kinks in [
+            // Below the optimal utilization rate
+            [kink(7_000, 3_000), kink(0, 0), kink(0, 0)],
+            // Not increasing in utilization
+            [kink(9_500, 3_000), kink(9_000, 6_000), kink(0, 0)],
+            // Decreasing rate
+            [kink(9_000, 3_000), kink(9_500, 2_000), kink(0, 0)],
+            // Above the max interest rate
+            [kink(9_000, 15_000), kink(0, 0), kink(0, 0)],
+            // Active kink after an unused one
+            [kink(9_000, 3_000), kink(0, 0), kink(9_500, 6_000)],
+        ] {
+            config.kinks = kinks;
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn interest_rate_max_borrow_apr_cap() {
+        let mut config = preset_interest_rate_config();
+        let (lending_rate, borrowing_rate, group_fees_apr, _) =
+            config.calc_interest_rate(I80F48::ONE).unwrap();
+        assert_eq_with_tolerance!(borrowing_rate, I80F48!(1.12), I80F48!(0.000001));
+
+        config.max_borrow_apr_cap = I80F48!(0.57).into();
+        config.validate().unwrap();
+
+        // Base rate halves to 0.5, lenders and fees take the cut in proportion
+        let (capped_lending_rate, capped_borrowing_rate, capped_group_fees_apr, _) =
+            config.calc_interest_rate(I80F48::ONE).unwrap();
+        assert_eq_with_tolerance!(capped_borrowing_rate, I80F48!(0.57), I80F48!(0.000001));
+        assert_eq_with_tolerance!(capped_lending_rate, lending_rate / 2, I80F48!(0.000001));
+        assert_eq_with_tolerance!(capped_group_fees_apr, I80F48!(0.035), I80F48!(0.000001));
+        assert!(capped_group_fees_apr < group_fees_apr);
+
+        // Below the cap rates are unchanged
+        assert_eq!(
+            config.calc_interest_rate(I80F48!(0.4)).unwrap(),
+            preset_interest_rate_config()
+                .calc_interest_rate(I80F48!(0.4))
+                .unwrap()
+        );
+
+        // The cap can't undercut the fixed fees
+        config.max_borrow_apr_cap = I80F48!(0.02).into();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    /// Tests that the interest payment for a 1 year period with 100% APR is 1.
+    fn interest_payment_100apr_1year() {
+        let apr = I80F48::ONE;
+        let time_delta = 31_536_000; // 1 year
+        let value = I80F48::ONE;
+
+        assert_eq_with_tolerance!(
+            calc_interest_payment_for_period(apr, time_delta, value).unwrap(),
+            I80F48::ONE,
+            I80F48!(0.001)
+        );
+    }
+
+    /// Tests that the interest payment for a 1 year period with 50% APR is 0.5.
+    #[test]
+    fn interest_payment_50apr_1year() {
+        let apr = I80F48::from_num(0.5);
+        let time_delta = 31_536_000; // 1 year
+        let value = I80F48::ONE;
+
+        assert_eq_with_tolerance!(
+            calc_interest_payment_for_period(apr, time_delta, value).unwrap(),
+            I80F48::from_num(0.5),
+            I80F48!(0.001)
+        );
+    }
+    /// P: 1_000_000
+    /// Apr: 12%
+    /// Time: 1 second
+    #[test]
+    fn interest_payment_12apr_1second() {
+        let apr = I80F48!(0.12);
+        let time_delta = 1;
+        let value = I80F48!(1_000_000);
+
+        assert_eq_with_tolerance!(
+            calc_interest_payment_for_period(apr, time_delta, value).unwrap(),
+            I80F48!(0.0038),
+            I80F48!(0.001)
+        );
+    }
+
+    #[test]
+    /// apr: 100%
+    /// time: 1 year
+    /// principal: 2
+    /// expected: 4
+    fn accrued_interest_apr100_year1() {
+        assert_eq_with_tolerance!(
+            calc_accrued_interest_payment_per_period(I80F48!(1), 31_536_000, I80F48!(2)).unwrap(),
+            I80F48!(4),
+            I80F48!(0.001)
+        );
+    }
+
+    #[test]
+    /// apr: 50%
+    /// time: 1 year
+    /// principal: 2
+    /// expected: 3
+    fn accrued_interest_apr50_year1() {
+        assert_eq_with_tolerance!(
+            calc_accrued_interest_payment_per_period(I80F48!(0.5), 31_536_000, I80F48!(2)).unwrap(),
+            I80F48!(3),
+            I80F48!(0.001)
+        );
+    }
+
+    #[test]
+    /// apr: 12%
+    /// time: 1 second
+    /// principal: 1_000_000
+    /// expected: 1_038
+    fn accrued_interest_apr12_year1() {
+        assert_eq_with_tolerance!(
+            calc_accrued_interest_payment_per_period(I80F48!(0.12), 1, I80F48!(1_000_000)).unwrap(),
+            I80F48!(1_000_000.0038),
+            I80F48!(0.001)
+        );
+    }
+
+    #[test]
+    /// ur: 0
+    /// protocol_fixed_fee: 0.01
+    fn ir_config_calc_interest_rate_pff_01() {
+        let config = InterestRateConfig {
+            optimal_utilization_rate: I80F48!(0.6).into(),
+            plateau_interest_rate: I80F48!(0.40).into(),
+            protocol_fixed_fee_apr: I80F48!(0.01).into(),
+            ..Default::default()
+        };
+
+        let (lending_apr, borrow_apr, group_fees_apr, insurance_apr) =
+            config.calc_interest_rate(I80F48!(0)).unwrap();
+
+        assert_eq_with_tolerance!(lending_apr, I80F48!(0), I80F48!(0.001));
+        assert_eq_with_tolerance!(borrow_apr, I80F48!(0.01), I80F48!(0.001));
+        assert_eq_with_tolerance!(group_fees_apr, I80F48!(0.01), I80F48!(0.001));
+        assert_eq_with_tolerance!(insurance_apr, I80F48!(0), I80F48!(0.001));
+    }
+
+    #[test]
+    /// ur: 0.5
+    /// protocol_fixed_fee: 0.01
+    /// optimal_utilization_rate: 0.5
+    /// plateau_interest_rate: 0.4
+    fn ir_config_calc_interest_rate_pff_01_ur_05() {
+        let config = InterestRateConfig {
+            optimal_utilization_rate: I80F48!(0.5).into(),
+            plateau_interest_rate: I80F48!(0.4).into(),
+            protocol_fixed_fee_apr: I80F48!(0.01).into(),
+            insurance_ir_fee: I80F48!(0.1).into(),
+            ..Default::default()
+        };
+
+        let (lending_apr, borrow_apr, group_fees_apr, insurance_apr) =
+            config.calc_interest_rate(I80F48!(0.5)).unwrap();
+
+        assert_eq_with_tolerance!(lending_apr, I80F48!(0.2), I80F48!(0.001));
+        assert_eq_with_tolerance!(borrow_apr, I80F48!(0.45), I80F48!(0.001));
+        assert_eq_with_tolerance!(group_fees_apr, I80F48!(0.01), I80F48!(0.001));
+        assert_eq_with_tolerance!(insurance_apr, I80F48!(0.04), I80F48!(0.001));
+    }
+
+    /// ur: 0.8
     /// protocol_fixed_fee: 0.01
     /// optimal_utilization_rate: 0.5
     /// plateau_interest_rate: 0.4
@@ -1522,7 +4156,7 @@ mod tests {
             ..Default::default()
         };
 
-        let pre_net_assets = bank.get_asset_amount(bank.total_asset_shares.into())?
+        let pre_net_assets = bank.get_total_asset_amount()?
             - bank.get_liability_amount(bank.total_liability_shares.into())?;
 
         let mut clock = Clock::default();
@@ -1539,8 +4173,7 @@ mod tests {
         let post_collected_fees = I80F48::from(bank.collected_group_fees_outstanding)
             + I80F48::from(bank.collected_insurance_fees_outstanding);
 
-        let post_net_assets = bank.get_asset_amount(bank.total_asset_shares.into())?
-            + post_collected_fees
+        let post_net_assets = bank.get_total_asset_amount()? + post_collected_fees
             - bank.get_liability_amount(bank.total_liability_shares.into())?;
 
         assert_eq_with_tolerance!(pre_net_assets, post_net_assets, I80F48!(1));
@@ -1605,6 +4238,7 @@ mod tests {
                 3600,
                 total_asset_shares,
                 total_liability_shares,
+                total_liability_shares / total_asset_shares,
                 &ir_config,
                 asset_share_value,
                 liab_share_value,
@@ -1648,4 +4282,982 @@ mod tests {
 
         Ok(())
     }
+
+    /// Small deterministic xorshift generator, so the differential test is reproducible.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next_f64(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        /// Log-uniform sample in `[min, max)` to cover extreme magnitudes.
+        fn next_log_range(&mut self, min: f64, max: f64) -> f64 {
+            (min.ln() + (max.ln() - min.ln()) * self.next_f64()).exp()
+        }
+    }
+
+    /// `scale` is the principal the value was derived from; rounding of the fixed point
+    /// utilization ratio and rates is bounded relative to it rather than to the result.
+    fn assert_close(label: &str, actual: I80F48, expected: f64, scale: f64) {
+        let actual = actual.to_num::<f64>();
+        let tolerance = expected.abs() * 1e-6 + scale * 1e-9 + 1e-12;
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{}: fixed point {} diverged from reference {}",
+            label,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    /// Differential test of the fixed point interest and share math against an f64 reference model.
+    fn interest_accrual_matches_float_reference() {
+        let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+        let seconds_per_year = SECONDS_PER_YEAR.to_num::<f64>();
+
+        for _ in 0..10_000 {
+            let optimal_ur = 0.01 + 0.98 * rng.next_f64();
+            let plateau_ir = rng.next_log_range(1e-4, 1.);
+            let max_ir = plateau_ir + rng.next_log_range(1e-4, 100.);
+            let protocol_ir_fee = 0.5 * rng.next_f64();
+            let insurance_ir_fee = 0.5 * rng.next_f64();
+            let protocol_fixed_fee_apr = 0.1 * rng.next_f64();
+            let insurance_fixed_fee_apr = 0.1 * rng.next_f64();
+
+            let config = InterestRateConfig {
+                optimal_utilization_rate: I80F48::from_num(optimal_ur).into(),
+                plateau_interest_rate: I80F48::from_num(plateau_ir).into(),
+                max_interest_rate: I80F48::from_num(max_ir).into(),
+                insurance_fee_fixed_apr: I80F48::from_num(insurance_fixed_fee_apr).into(),
+                insurance_ir_fee: I80F48::from_num(insurance_ir_fee).into(),
+                protocol_fixed_fee_apr: I80F48::from_num(protocol_fixed_fee_apr).into(),
+                protocol_ir_fee: I80F48::from_num(protocol_ir_fee).into(),
+                ..Default::default()
+            };
+
+            let total_assets = rng.next_log_range(1., 1e12).floor();
+            let total_liabilities = (total_assets * rng.next_f64()).floor();
+            let asset_share_value = rng.next_log_range(1e-3, 1e3);
+            let liability_share_value = rng.next_log_range(1e-3, 1e3);
+            let time_delta = rng.next_log_range(1., 31_536_000.) as u64;
+
+            // Reference model, evaluated on the same (already quantized) inputs.
+            let q = |v: f64| I80F48::from_num(v).to_num::<f64>();
+            let (optimal_ur, plateau_ir, max_ir) = (q(optimal_ur), q(plateau_ir), q(max_ir));
+            let (protocol_ir_fee, insurance_ir_fee) = (q(protocol_ir_fee), q(insurance_ir_fee));
+            let (protocol_fixed_fee_apr, insurance_fixed_fee_apr) =
+                (q(protocol_fixed_fee_apr), q(insurance_fixed_fee_apr));
+            let (asset_share_value, liability_share_value) =
+                (q(asset_share_value), q(liability_share_value));
+
+            let ur = total_liabilities / total_assets;
+            let base_rate = if ur <= optimal_ur {
+                ur / optimal_ur * plateau_ir
+            } else {
+                (ur - optimal_ur) / (1. - optimal_ur) * (max_ir - plateau_ir) + plateau_ir
+            };
+            let lending_apr = base_rate * ur;
+            let borrowing_apr = base_rate * (1. + protocol_ir_fee + insurance_ir_fee)
+                + protocol_fixed_fee_apr
+                + insurance_fixed_fee_apr;
+            let group_fee_apr = base_rate * protocol_ir_fee + protocol_fixed_fee_apr;
+            let insurance_fee_apr = base_rate * insurance_ir_fee + insurance_fixed_fee_apr;
+            let period = time_delta as f64 / seconds_per_year;
+
+            let (new_asset_share_value, new_liability_share_value, group_fees, insurance_fees) =
+                calc_interest_rate_accrual_state_changes(
+                    time_delta,
+                    I80F48::from_num(total_assets),
+                    I80F48::from_num(total_liabilities),
+                    I80F48::from_num(total_liabilities) / I80F48::from_num(total_assets),
+                    &config,
+                    I80F48::from_num(asset_share_value),
+                    I80F48::from_num(liability_share_value),
+                )
+                .unwrap();
+
+            assert_close(
+                "asset share value",
+                new_asset_share_value,
+                asset_share_value * (1. + lending_apr * period),
+                asset_share_value,
+            );
+            assert_close(
+                "liability share value",
+                new_liability_share_value,
+                liability_share_value * (1. + borrowing_apr * period),
+                liability_share_value,
+            );
+            assert_close(
+                "group fees",
+                group_fees,
+                total_liabilities * group_fee_apr * period,
+                total_liabilities,
+            );
+            assert_close(
+                "insurance fees",
+                insurance_fees,
+                total_liabilities * insurance_fee_apr * period,
+                total_liabilities,
+            );
+        }
+    }
+
+    #[test]
+    fn insured_deposits_premium_and_loss() {
+        let mut bank = Bank {
+            asset_share_value: I80F48!(1).into(),
+            total_asset_shares: I80F48!(100).into(),
+            insured_asset_share_value: I80F48!(1).into(),
+            total_insured_asset_shares: I80F48!(100).into(),
+            config: BankConfig {
+                insurance_premium_bps: 1_000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // 10% of the 10% yield goes to the insurance vault
+        let premium = bank.accrue_insured_asset_share_value(I80F48!(0.1)).unwrap();
+        assert_eq_with_tolerance!(premium, I80F48!(1), I80F48!(0.0001));
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.insured_asset_share_value),
+            I80F48!(1.09),
+            I80F48!(0.0001)
+        );
+
+        // Uninsured deposits absorb the loss first
+        bank.socialize_loss(I80F48!(60)).unwrap();
+        assert_eq!(I80F48::from(bank.asset_share_value), I80F48!(0.4));
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.insured_asset_share_value),
+            I80F48!(1.09),
+            I80F48!(0.0001)
+        );
+
+        bank.socialize_loss(I80F48!(49)).unwrap();
+        assert_eq!(I80F48::from(bank.asset_share_value), I80F48!(0));
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.insured_asset_share_value),
+            I80F48!(1.0),
+            I80F48!(0.0001)
+        );
+    }
+
+    #[test]
+    fn bank_liquidation_grace_period() {
+        let mut bank = Bank {
+            liquidation_grace_period: 100,
+            liquidation_grace_fee_bps: 5_000,
+            ..Default::default()
+        };
+
+        // Never unpaused, no grace period
+        assert_eq!(bank.get_liquidation_grace_fee(1_000), None);
+
+        bank.config.operational_state = BankOperationalState::ReduceOnly;
+        bank.update_unpaused_at(BankOperationalState::Operational, 1_000);
+        assert_eq!(bank.unpaused_at, 0);
+
+        bank.config.operational_state = BankOperationalState::Operational;
+        bank.update_unpaused_at(BankOperationalState::Paused, 1_000);
+        assert_eq!(bank.unpaused_at, 1_000);
+
+        assert_eq!(bank.get_liquidation_grace_fee(1_099), Some(I80F48!(0.5)));
+        assert_eq!(bank.get_liquidation_grace_fee(1_100), None);
+    }
+
+    #[test]
+    fn bank_config_oracle_max_age() {
+        let mut config = BankConfig {
+            oracle_setup: OracleSetup::SwitchboardPull,
+            ..Default::default()
+        };
+        assert_eq!(config.get_oracle_max_age(), MAX_SWB_ORACLE_AGE);
+
+        config.oracle_setup = OracleSetup::PythPushOracle;
+        assert_eq!(config.get_oracle_max_age(), MAX_PYTH_ORACLE_AGE);
+
+        config.oracle_max_age = 15;
+        assert_eq!(config.get_oracle_max_age(), 15);
+    }
+
+    #[test]
+    fn bank_fixed_price_collateral_flag() {
+        let mut bank = Bank::default();
+        assert!(!bank.is_fixed_price_collateral_blocked());
+
+        bank.config.set_fixed_price(I80F48::ONE);
+        assert!(bank.is_fixed_price_collateral_blocked());
+
+        bank.update_flag(true, FIXED_PRICE_COLLATERAL_FLAG);
+        assert!(!bank.is_fixed_price_collateral_blocked());
+    }
+
+    #[test]
+    fn bank_maintenance_ema_price() {
+        let mut bank = Bank::default();
+        assert!(matches!(
+            bank.get_oracle_price_type(RequirementType::Maintenance),
+            OraclePriceType::RealTime
+        ));
+
+        bank.update_flag(true, MAINTENANCE_EMA_PRICE_FLAG);
+        assert!(matches!(
+            bank.get_oracle_price_type(RequirementType::Maintenance),
+            OraclePriceType::TimeWeighted
+        ));
+        assert!(matches!(
+            bank.get_oracle_price_type(RequirementType::Initial),
+            OraclePriceType::TimeWeighted
+        ));
+    }
+
+    #[test]
+    fn bank_oracle_circuit_breaker() {
+        let mut bank = Bank::default();
+        bank.config.operational_state = BankOperationalState::Operational;
+
+        assert!(!bank.update_oracle_paused(false, 100));
+        assert!(bank.update_oracle_paused(true, 100));
+        assert!(matches!(
+            bank.config.operational_state,
+            BankOperationalState::Paused
+        ));
+        assert!(!bank.update_oracle_paused(true, 200));
+
+        assert!(bank.update_oracle_paused(false, 300));
+        assert!(matches!(
+            bank.config.operational_state,
+            BankOperationalState::Operational
+        ));
+        assert_eq!(bank.unpaused_at, 300);
+
+        // Banks paused by the admin stay paused
+        bank.config.operational_state = BankOperationalState::Paused;
+        assert!(!bank.update_oracle_paused(false, 400));
+
+        // Reduce only banks are left alone
+        bank.config.operational_state = BankOperationalState::ReduceOnly;
+        assert!(!bank.update_oracle_paused(true, 400));
+    }
+
+    #[test]
+    fn bank_oracle_deviation() {
+        let mut bank = Bank::default();
+        bank.config.operational_state = BankOperationalState::Operational;
+        bank.config.oracle_max_deviation_bps = 200;
+
+        assert!(!bank
+            .config
+            .exceeds_oracle_max_deviation(I80F48!(100), I80F48!(98))
+            .unwrap());
+        assert!(bank
+            .config
+            .exceeds_oracle_max_deviation(I80F48!(100), I80F48!(97.5))
+            .unwrap());
+        assert!(bank
+            .config
+            .exceeds_oracle_max_deviation(I80F48!(100), I80F48!(102.5))
+            .unwrap());
+
+        assert!(bank.update_oracle_deviation_exceeded(true));
+        assert!(!bank.update_oracle_deviation_exceeded(true));
+
+        // Only repays and withdrawals go through
+        assert!(bank.assert_operational_mode(Some(true)).is_err());
+        assert!(bank.assert_operational_mode(Some(false)).is_ok());
+
+        assert!(bank.update_oracle_deviation_exceeded(false));
+        assert!(bank.assert_operational_mode(Some(true)).is_ok());
+    }
+
+    #[test]
+    fn bank_oracle_rotation() {
+        let old_key = Pubkey::new_unique();
+        let new_key = Pubkey::new_unique();
+
+        let mut bank = Bank::default();
+        bank.config.oracle_setup = OracleSetup::PythLegacy;
+        bank.config.oracle_keys[0] = old_key;
+
+        assert!(bank.accept_pending_oracle(100).is_err());
+
+        let mut keys = [Pubkey::default(); MAX_ORACLE_KEYS];
+        keys[0] = new_key;
+        bank.propose_oracle(
+            OracleConfig {
+                setup: OracleSetup::SwitchboardPull,
+                keys,
+            },
+            100,
+        );
+
+        // Pricing keeps using the old oracle during the delay
+        assert_eq!(bank.config.oracle_keys[0], old_key);
+        assert!(bank
+            .accept_pending_oracle(100 + ORACLE_ROTATION_DELAY_SLOTS - 1)
+            .is_err());
+
+        let oracle = bank
+            .accept_pending_oracle(100 + ORACLE_ROTATION_DELAY_SLOTS)
+            .unwrap();
+        assert_eq!(oracle.keys[0], new_key);
+        assert_eq!(bank.config.oracle_setup, OracleSetup::SwitchboardPull);
+        assert_eq!(bank.config.oracle_keys[0], new_key);
+        assert!(bank.get_pending_oracle().is_none());
+    }
+
+    #[test]
+    fn bank_adaptive_plateau_interest_rate() {
+        let mut bank = Bank {
+            config: BankConfig {
+                interest_rate_config: preset_interest_rate_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let ir_config = bank.config.interest_rate_config;
+        let half_day = SECONDS_PER_DAY / 2;
+
+        bank.update_adaptive_plateau_interest_rate(&ir_config, I80F48::ONE, half_day)
+            .unwrap();
+        assert_eq!(bank.get_adaptive_plateau_interest_rate(), None);
+
+        bank.config.interest_rate_config.adaptive_rate_speed_bps = 10_000;
+        assert_eq!(
+            bank.get_adaptive_plateau_interest_rate(),
+            Some(I80F48!(0.1))
+        );
+
+        // Full utilization raises the rate by half the speed over half a day
+        bank.update_adaptive_plateau_interest_rate(&ir_config, I80F48::ONE, half_day)
+            .unwrap();
+        assert_eq_with_tolerance!(
+            bank.get_adaptive_plateau_interest_rate().unwrap(),
+            I80F48!(0.15),
+            I80F48!(0.000001)
+        );
+
+        // Optimal utilization holds it
+        bank.update_adaptive_plateau_interest_rate(&ir_config, I80F48!(0.8), half_day)
+            .unwrap();
+        assert_eq_with_tolerance!(
+            bank.get_adaptive_plateau_interest_rate().unwrap(),
+            I80F48!(0.15),
+            I80F48!(0.000001)
+        );
+
+        // Half way below optimal lowers it by a quarter over half a day
+        bank.update_adaptive_plateau_interest_rate(&ir_config, I80F48!(0.4), half_day)
+            .unwrap();
+        assert_eq_with_tolerance!(
+            bank.get_adaptive_plateau_interest_rate().unwrap(),
+            I80F48!(0.1125),
+            I80F48!(0.000001)
+        );
+
+        // Bounded by the floor and the max rate
+        bank.update_adaptive_plateau_interest_rate(&ir_config, I80F48::ZERO, SECONDS_PER_DAY)
+            .unwrap();
+        assert_eq!(
+            bank.get_adaptive_plateau_interest_rate(),
+            Some(MIN_ADAPTIVE_PLATEAU_INTEREST_RATE)
+        );
+        bank.update_adaptive_plateau_interest_rate(&ir_config, I80F48::ONE, 365 * SECONDS_PER_DAY)
+            .unwrap();
+        assert_eq!(bank.get_adaptive_plateau_interest_rate(), Some(I80F48::ONE));
+    }
+
+    #[test]
+    fn bank_lifetime_accrual_counters() {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000_000).into(),
+            total_liability_shares: I80F48!(500_000).into(),
+            config: BankConfig {
+                interest_rate_config: preset_interest_rate_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for ts in [SECONDS_PER_DAY, 2 * SECONDS_PER_DAY] {
+            bank.accrue_interest(
+                ts as i64,
+                #[cfg(not(feature = "client"))]
+                Pubkey::default(),
+            )
+            .unwrap();
+        }
+
+        // Nothing has been withdrawn, so the lifetime totals match what is outstanding
+        assert_eq!(
+            I80F48::from(bank.lifetime_group_fees_collected),
+            I80F48::from(bank.collected_group_fees_outstanding)
+        );
+        assert_eq!(
+            I80F48::from(bank.lifetime_insurance_fees_collected),
+            I80F48::from(bank.collected_insurance_fees_outstanding)
+        );
+        assert!(I80F48::from(bank.lifetime_group_fees_collected) > I80F48::ZERO);
+        // The fees were all charged on the same liability shares
+        assert_eq_with_tolerance!(
+            bank.get_group_fees_since(I80F48!(500_000), 0).unwrap(),
+            I80F48::from(bank.lifetime_group_fees_collected),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.lifetime_lender_interest_paid),
+            bank.get_total_asset_amount().unwrap() - I80F48!(1_000_000),
+            I80F48!(0.000001)
+        );
+        assert!(I80F48::from(bank.lifetime_lender_interest_paid) > I80F48::ZERO);
+    }
+
+    #[test]
+    fn bank_lending_subsidy() {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000_000).into(),
+            config: BankConfig {
+                interest_rate_config: InterestRateConfig {
+                    min_lending_apr: I80F48!(0.05).into(),
+                    ..preset_interest_rate_config()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Nothing is paid, or owed later, without a reserve
+        bank.accrue_interest(
+            SECONDS_PER_YEAR.to_num::<i64>(),
+            #[cfg(not(feature = "client"))]
+            Pubkey::default(),
+        )
+        .unwrap();
+        assert_eq!(bank.get_total_asset_amount().unwrap(), I80F48!(1_000_000));
+
+        // Without borrows the whole floor is subsidized, right away
+        bank.lending_subsidy_reserve = I80F48!(80_000).into();
+        bank.accrue_interest(
+            2 * SECONDS_PER_YEAR.to_num::<i64>(),
+            #[cfg(not(feature = "client"))]
+            Pubkey::default(),
+        )
+        .unwrap();
+        assert_eq_with_tolerance!(
+            bank.get_total_asset_amount().unwrap(),
+            I80F48!(1_050_000),
+            I80F48!(0.001)
+        );
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.lending_subsidy_reserve),
+            I80F48!(30_000),
+            I80F48!(0.001)
+        );
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.lifetime_lender_interest_paid),
+            I80F48!(50_000),
+            I80F48!(0.001)
+        );
+
+        // Capped at the reserve
+        bank.accrue_interest(
+            3 * SECONDS_PER_YEAR.to_num::<i64>(),
+            #[cfg(not(feature = "client"))]
+            Pubkey::default(),
+        )
+        .unwrap();
+        assert_eq_with_tolerance!(
+            bank.get_total_asset_amount().unwrap(),
+            I80F48!(1_080_000),
+            I80F48!(0.001)
+        );
+        assert_eq!(I80F48::from(bank.lending_subsidy_reserve), I80F48::ZERO);
+        assert!(bank.distribute_lending_subsidy(I80F48!(1)).is_err());
+
+        // A lending rate above the floor needs no subsidy
+        let ir_config = bank.config.interest_rate_config;
+        assert_eq!(
+            ir_config
+                .calc_lending_subsidy(I80F48!(0.9), I80F48!(0.9), I80F48!(1_000_000), 1_000)
+                .unwrap(),
+            I80F48::ZERO
+        );
+    }
+
+    #[test]
+    fn bank_smoothed_utilization_rate() {
+        let mut bank = Bank::default();
+
+        // Disabled
+        assert_eq!(
+            bank.update_smoothed_utilization_rate(I80F48!(0.9), 100)
+                .unwrap(),
+            I80F48!(0.9)
+        );
+        assert_eq!(I80F48::from(bank.smoothed_utilization_rate), I80F48::ZERO);
+
+        bank.utilization_smoothing_window = 1_000;
+        bank.smoothed_utilization_rate = I80F48!(0.5).into();
+
+        // A quarter of the window moves the average a quarter of the way
+        assert_eq_with_tolerance!(
+            bank.update_smoothed_utilization_rate(I80F48!(0.9), 250)
+                .unwrap(),
+            I80F48!(0.6),
+            I80F48!(0.000001)
+        );
+        assert_eq!(
+            bank.update_smoothed_utilization_rate(I80F48!(0.9), 0)
+                .unwrap(),
+            I80F48::from(bank.smoothed_utilization_rate)
+        );
+
+        // A full window replaces it
+        assert_eq!(
+            bank.update_smoothed_utilization_rate(I80F48!(0.2), 5_000)
+                .unwrap(),
+            I80F48!(0.2)
+        );
+
+        // The lending rate follows the actual utilization
+        let ir_config = preset_interest_rate_config();
+        let (lending_apr, borrowing_apr, _, _) = ir_config
+            .calc_interest_rate_smoothed(I80F48!(0.4), I80F48!(0.8))
+            .unwrap();
+        let (_, plateau_borrowing_apr, _, _) = ir_config.calc_interest_rate(I80F48!(0.8)).unwrap();
+        assert_eq!(borrowing_apr, plateau_borrowing_apr);
+        assert_eq_with_tolerance!(lending_apr, I80F48!(0.04), I80F48!(0.000001));
+    }
+
+    #[test]
+    fn bank_insurance_deposit_fee() {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000_000).into(),
+            config: BankConfig {
+                interest_rate_config: preset_interest_rate_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Disabled
+        assert_eq!(
+            bank.charge_insurance_deposit_fee(1_000).unwrap(),
+            I80F48::ZERO
+        );
+
+        // Charged even without borrows
+        bank.insurance_deposit_fee_apr = I80F48!(0.01).into();
+        bank.accrue_interest(
+            SECONDS_PER_YEAR.to_num::<i64>(),
+            #[cfg(not(feature = "client"))]
+            Pubkey::default(),
+        )
+        .unwrap();
+
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.collected_insurance_fees_outstanding),
+            I80F48!(10_000),
+            I80F48!(0.001)
+        );
+        assert_eq_with_tolerance!(
+            bank.get_total_asset_amount().unwrap(),
+            I80F48!(990_000),
+            I80F48!(0.001)
+        );
+        assert_eq!(
+            I80F48::from(bank.lifetime_insurance_fees_collected),
+            I80F48::from(bank.collected_insurance_fees_outstanding)
+        );
+    }
+
+    #[test]
+    fn bank_account_deposit_limit() {
+        let mut bank = Bank::default();
+        assert!(bank.check_account_deposit_limit(I80F48!(1_000_000)).is_ok());
+
+        bank.max_deposit_per_account = 1_000;
+        assert!(bank.check_account_deposit_limit(I80F48!(1_000)).is_ok());
+        assert!(bank.check_account_deposit_limit(I80F48!(1_000.5)).is_err());
+    }
+
+    #[test]
+    fn bank_close() {
+        let mut bank = Bank {
+            total_liability_shares: I80F48!(1).into(),
+            collected_group_fees_outstanding: I80F48!(100).into(),
+            referral_fees_outstanding: I80F48!(10.5).into(),
+            config: BankConfig {
+                operational_state: BankOperationalState::Operational,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(bank.close().is_err());
+
+        bank.total_liability_shares = I80F48::ZERO.into();
+        bank.close().unwrap();
+
+        assert!(bank.get_flag(BANK_CLOSED_FLAG));
+        assert!(matches!(
+            bank.config.operational_state,
+            BankOperationalState::Paused
+        ));
+        assert_eq!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48::ZERO
+        );
+        assert_eq!(bank.get_referral_fee_escrow().unwrap(), 11);
+        assert!(bank.close().is_err());
+        assert!(bank.configure(&BankConfigOpt::default()).is_err());
+    }
+
+    #[test]
+    fn bank_min_borrow_and_dust() {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000).into(),
+            config: BankConfig {
+                interest_rate_config: preset_interest_rate_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Unset
+        bank.check_min_borrow_amount(I80F48!(1)).unwrap();
+        assert_eq!(bank.get_dust_amount(I80F48!(1)), I80F48::ZERO);
+
+        bank.configure(&BankConfigOpt {
+            min_borrow_amount: Some(100),
+            dust_threshold: Some(10),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(bank.check_min_borrow_amount(I80F48!(99)).is_err());
+        bank.check_min_borrow_amount(I80F48!(100)).unwrap();
+
+        assert_eq!(bank.get_dust_amount(I80F48!(9.5)), I80F48!(9.5));
+        assert_eq!(bank.get_dust_amount(I80F48!(10)), I80F48::ZERO);
+        assert_eq!(bank.get_dust_amount(I80F48::ZERO), I80F48::ZERO);
+
+        bank.sweep_dust(I80F48!(5), BalanceSide::Assets).unwrap();
+        assert_eq!(
+            I80F48::from(bank.collected_insurance_fees_outstanding),
+            I80F48!(5)
+        );
+
+        // 10 lost over 1000 deposited
+        bank.sweep_dust(I80F48!(10), BalanceSide::Liabilities)
+            .unwrap();
+        assert_eq!(
+            I80F48::from(bank.asset_share_value),
+            I80F48!(990) / I80F48!(1_000)
+        );
+    }
+
+    #[test]
+    fn bank_borrow_utilization_ceiling() {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000).into(),
+            total_liability_shares: I80F48!(960).into(),
+            config: BankConfig {
+                interest_rate_config: preset_interest_rate_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        bank.check_borrow_utilization().unwrap();
+
+        assert!(bank
+            .configure(&BankConfigOpt {
+                max_borrow_utilization_bps: Some(10_001),
+                ..Default::default()
+            })
+            .is_err());
+        bank.configure(&BankConfigOpt {
+            max_borrow_utilization_bps: Some(9_500),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(bank.check_borrow_utilization().is_err());
+
+        bank.total_liability_shares = I80F48!(950).into();
+        bank.check_borrow_utilization().unwrap();
+    }
+
+    #[test]
+    fn bank_liquidity_reserve() {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000).into(),
+            config: BankConfig {
+                interest_rate_config: preset_interest_rate_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        bank.check_liquidity_reserve(0).unwrap();
+
+        assert!(bank
+            .configure(&BankConfigOpt {
+                liquidity_reserve_bps: Some(10_001),
+                ..Default::default()
+            })
+            .is_err());
+        bank.configure(&BankConfigOpt {
+            liquidity_reserve_bps: Some(1_000),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(bank.check_liquidity_reserve(99).is_err());
+        bank.check_liquidity_reserve(100).unwrap();
+    }
+
+    #[test]
+    fn bank_kill() {
+        let mut bank = Bank {
+            last_update: 1_000,
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000).into(),
+            total_liability_shares: I80F48!(500).into(),
+            collected_group_fees_outstanding: I80F48!(10).into(),
+            flags: ORACLE_PAUSED_FLAG | FIXED_PRICE_COLLATERAL_FLAG,
+            config: BankConfig {
+                operational_state: BankOperationalState::Paused,
+                interest_rate_config: preset_interest_rate_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(bank.kill(I80F48::ZERO).is_err());
+        bank.kill(I80F48!(2)).unwrap();
+
+        assert!(bank.is_killed());
+        assert!(matches!(bank.config.oracle_setup, OracleSetup::Fixed));
+        assert!(bank.is_fixed_price_collateral_blocked());
+        assert!(!bank.get_flag(ORACLE_PAUSED_FLAG));
+        assert_eq!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48::ZERO
+        );
+
+        assert!(bank.assert_operational_mode(None).is_err());
+        assert!(bank.assert_operational_mode(Some(false)).is_err());
+        assert!(bank.kill(I80F48!(2)).is_err());
+        assert!(bank
+            .configure(&BankConfigOpt {
+                operational_state: Some(BankOperationalState::Operational),
+                ..Default::default()
+            })
+            .is_err());
+
+        // No more interest
+        bank.accrue_interest(
+            1_000 + 31_536_000,
+            #[cfg(not(feature = "client"))]
+            Pubkey::default(),
+        )
+        .unwrap();
+        assert_eq!(I80F48::from(bank.liability_share_value), I80F48::ONE);
+        assert_eq!(bank.last_update, 1_000 + 31_536_000);
+    }
+
+    #[test]
+    fn bank_config_frozen() {
+        let mut bank = Bank {
+            config: BankConfig {
+                operational_state: BankOperationalState::Operational,
+                interest_rate_config: preset_interest_rate_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        bank.configure(&BankConfigOpt {
+            freeze_config: Some(true),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(bank.get_flag(BANK_CONFIG_FROZEN_FLAG));
+
+        assert!(bank
+            .configure(&BankConfigOpt {
+                deposit_limit: Some(1_000),
+                ..Default::default()
+            })
+            .is_err());
+        assert!(bank
+            .configure(&BankConfigOpt {
+                freeze_config: Some(false),
+                ..Default::default()
+            })
+            .is_err());
+
+        bank.configure(&BankConfigOpt {
+            operational_state: Some(BankOperationalState::Paused),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(
+            bank.config.operational_state,
+            BankOperationalState::Paused
+        ));
+
+        // Emissions setup keeps the freeze
+        bank.override_emissions_flag(EMISSION_FLAGS);
+        assert!(bank.get_flag(BANK_CONFIG_FROZEN_FLAG));
+
+        bank.propose_oracle(
+            OracleConfig {
+                setup: OracleSetup::PythPushOracle,
+                keys: [Pubkey::new_unique(); MAX_ORACLE_KEYS],
+            },
+            0,
+        );
+        assert!(bank
+            .accept_pending_oracle(ORACLE_ROTATION_DELAY_SLOTS)
+            .is_err());
+    }
+
+    #[test]
+    fn bank_accrual_bounty() {
+        let mut bank = Bank {
+            last_update: 1_000,
+            ..Default::default()
+        };
+        assert_eq!(bank.get_accrual_bounty(10_000), 0);
+
+        bank.accrual_bounty = 500;
+        bank.accrual_bounty_min_age = 3_600;
+        assert_eq!(bank.get_accrual_bounty(1_000), 0);
+        assert_eq!(bank.get_accrual_bounty(4_600), 0);
+        assert_eq!(bank.get_accrual_bounty(4_601), 500);
+
+        bank.last_update = 4_601;
+        assert_eq!(bank.get_accrual_bounty(4_601), 0);
+    }
+
+    #[test]
+    fn bank_twap_price() {
+        let mut bank = Bank::default();
+
+        // Disabled
+        assert!(!bank.record_twap_price(I80F48!(10), 1_000));
+        assert_eq!(bank.get_twap_price().unwrap(), None);
+
+        bank.twap_window = 600;
+
+        assert!(bank.record_twap_price(I80F48!(10), 1_000));
+        assert_eq!(bank.get_twap_price().unwrap(), Some(I80F48!(10)));
+
+        // Too close to the previous observation
+        assert!(!bank.record_twap_price(I80F48!(100), 1_050));
+
+        assert!(bank.record_twap_price(I80F48!(20), 1_100));
+        assert!(bank.record_twap_price(I80F48!(100), 1_400));
+        // 100s at 10 and 300s at 20, the spike hasn't held yet
+        assert_eq!(bank.get_twap_price().unwrap(), Some(I80F48!(17.5)));
+
+        // The observation before the window is cut off: 300s at 20 and 300s at 100
+        assert!(bank.record_twap_price(I80F48!(100), 1_700));
+        assert_eq!(bank.get_twap_price().unwrap(), Some(I80F48!(60)));
+
+        // Only the initial requirement is bounded
+        assert_eq!(
+            bank.bound_by_twap(I80F48!(50), RequirementType::Initial, PriceBias::Low)
+                .unwrap(),
+            I80F48!(50)
+        );
+        assert_eq!(
+            bank.bound_by_twap(I80F48!(100), RequirementType::Initial, PriceBias::Low)
+                .unwrap(),
+            I80F48!(60)
+        );
+        assert_eq!(
+            bank.bound_by_twap(I80F48!(50), RequirementType::Initial, PriceBias::High)
+                .unwrap(),
+            I80F48!(60)
+        );
+        assert_eq!(
+            bank.bound_by_twap(I80F48!(100), RequirementType::Maintenance, PriceBias::Low)
+                .unwrap(),
+            I80F48!(100)
+        );
+    }
+
+    #[test]
+    fn bank_borrow_window_cap() {
+        let mut bank = Bank {
+            borrow_window_cap: 100,
+            ..Default::default()
+        };
+
+        // Disabled without a window
+        bank.record_borrow_volume(1_000, 10).unwrap();
+        assert_eq!(bank.borrow_window_volume, 0);
+
+        bank.borrow_window_slots = 5;
+        bank.record_borrow_volume(60, 10).unwrap();
+        bank.record_borrow_volume(40, 14).unwrap();
+        assert!(bank.record_borrow_volume(1, 14).is_err());
+        assert_eq!(bank.borrow_window_volume, 100);
+
+        // Next window starts fresh
+        bank.record_borrow_volume(100, 15).unwrap();
+        assert_eq!(bank.borrow_window_start_slot, 15);
+        assert_eq!(bank.borrow_window_volume, 100);
+    }
+
+    #[test]
+    fn bank_set_vault() {
+        let mut bank = Bank {
+            liquidity_vault: Pubkey::new_unique(),
+            liquidity_vault_bump: 254,
+            liquidity_vault_authority_bump: 253,
+            insurance_vault: Pubkey::new_unique(),
+            insurance_vault_bump: 252,
+            insurance_vault_authority_bump: 251,
+            ..Default::default()
+        };
+        let insurance_vault = bank.insurance_vault;
+        let new_vault = Pubkey::new_unique();
+
+        bank.set_vault(BankVaultType::Liquidity, new_vault);
+
+        assert_eq!(
+            bank.get_vault_and_authority_bump(BankVaultType::Liquidity),
+            (new_vault, 253)
+        );
+        assert_eq!(bank.liquidity_vault_bump, 0);
+        assert_eq!(
+            bank.get_vault_and_authority_bump(BankVaultType::Insurance),
+            (insurance_vault, 251)
+        );
+        assert_eq!(bank.insurance_vault_bump, 252);
+    }
 }
@@ -1,3 +1,6 @@
+pub mod bank_registry;
+pub mod fee_state;
+pub mod health_snapshot;
 pub mod marginfi_account;
 pub mod marginfi_group;
 pub mod price;
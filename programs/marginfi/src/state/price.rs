@@ -4,7 +4,10 @@ use anchor_lang::prelude::*;
 use enum_dispatch::enum_dispatch;
 use fixed::types::I80F48;
 pub use pyth_sdk_solana;
-use pyth_sdk_solana::{state::SolanaPriceAccount, Price, PriceFeed};
+use pyth_sdk_solana::{
+    state::{load_price_account, SolanaPriceAccount},
+    Price, PriceFeed,
+};
 use pyth_solana_receiver_sdk::price_update::{self, FeedId, PriceUpdateV2};
 use switchboard_solana::{
     AggregatorAccountData, AggregatorResolutionMode, SwitchboardDecimal, SWITCHBOARD_PROGRAM_ID,
@@ -13,14 +16,17 @@ use switchboard_solana::{
 use crate::{
     check,
     constants::{
-        CONF_INTERVAL_MULTIPLE, EXP_10, EXP_10_I80F48, MAX_CONF_INTERVAL,
-        MIN_PYTH_PUSH_VERIFICATION_LEVEL, PYTH_ID, STD_DEV_MULTIPLE, SWITCHBOARD_PULL_ID,
+        CHAINLINK_STORE_ID, CONF_INTERVAL_MULTIPLE, EXP_10, EXP_10_I80F48,
+        MIN_PYTH_PUSH_VERIFICATION_LEVEL, PYTH_PUSH_MARGINFI_SPONSORED_SHARD_ID,
+        PYTH_PUSH_PYTH_SPONSORED_SHARD_ID, STD_DEV_MULTIPLE, SWITCHBOARD_PULL_ID,
     },
     debug, math_error,
     prelude::*,
 };
 
 use super::marginfi_group::BankConfig;
+#[cfg(feature = "mock-oracle")]
+use super::marginfi_group::WrappedI80F48;
 use anchor_lang::prelude::borsh;
 use pyth_solana_receiver_sdk::PYTH_PUSH_ORACLE_ID;
 
@@ -33,6 +39,13 @@ pub enum OracleSetup {
     SwitchboardV2,
     PythPushOracle,
     SwitchboardPull,
+    /// Constant price stored in `oracle_keys[0]`, see [`BankConfig::get_fixed_price`].
+    Fixed,
+    /// Chainlink data feed account owned by the OCR2 store program.
+    Chainlink,
+    /// Price written directly to a [`MockOracle`] account, only for test deployments.
+    #[cfg(feature = "mock-oracle")]
+    Mock,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -66,6 +79,10 @@ pub enum OraclePriceFeedAdapter {
     SwitchboardV2(SwitchboardV2PriceFeed),
     PythPushOracle(PythPushOraclePriceFeed),
     SwitchboardPull(SwitchboardPullPriceFeed),
+    Fixed(FixedPriceFeed),
+    Chainlink(ChainlinkPriceFeed),
+    #[cfg(feature = "mock-oracle")]
+    Mock(MockPriceFeed),
 }
 
 impl OraclePriceFeedAdapter {
@@ -82,36 +99,144 @@ impl OraclePriceFeedAdapter {
         )
     }
 
+    /// Load the price feed of a bank. `ais` holds the bank's primary oracle, optionally followed
+    /// by its fallback oracle (`oracle_keys[1]`, same provider as the primary).
+    ///
+    /// The primary must always be passed, the fallback is only read once the primary has been
+    /// loaded and found stale or invalid, so callers can't pick which of the two prices is used.
+    /// If neither loads, the error of the primary is returned.
     pub fn try_from_bank_config_with_max_age(
         bank_config: &BankConfig,
         ais: &[AccountInfo],
         clock: &Clock,
         max_age: u64,
     ) -> MarginfiResult<Self> {
+        check!(
+            !ais.is_empty() && ais.len() == Self::get_oracle_ais_len(bank_config, ais),
+            MarginfiError::InvalidOracleAccount
+        );
+        check!(
+            Self::is_oracle_account(bank_config, &bank_config.oracle_keys[0], &ais[0]),
+            MarginfiError::InvalidOracleAccount
+        );
+
+        let (primary_ais, fallback_ais) = ais.split_at(1);
+        let primary = Self::try_from_oracle_key(
+            bank_config,
+            &bank_config.oracle_keys[0],
+            primary_ais,
+            clock,
+            max_age,
+        );
+
+        match (primary, bank_config.get_fallback_oracle_key()) {
+            (Err(err), Some(fallback_key)) if !fallback_ais.is_empty() => {
+                debug!("Primary oracle failed to load, trying fallback");
+
+                Self::try_from_oracle_key(bank_config, fallback_key, fallback_ais, clock, max_age)
+                    .map_err(|_| err)
+            }
+            (primary, _) => primary,
+        }
+    }
+
+    /// Number of accounts at the start of `ais` that are oracles of a bank with `bank_config`:
+    /// the primary, plus the fallback if the caller passed it right after.
+    pub fn get_oracle_ais_len(bank_config: &BankConfig, ais: &[AccountInfo]) -> usize {
+        match (bank_config.get_fallback_oracle_key(), ais.get(1)) {
+            (Some(fallback_key), Some(ai))
+                if Self::is_oracle_account(bank_config, fallback_key, ai) =>
+            {
+                2
+            }
+            _ => 1,
+        }
+    }
+
+    /// Whether `ai` is the account of the bank oracle `oracle_key`. Pyth push feeds can be posted
+    /// to any account, so for those `oracle_key` is matched against the feed id in the account.
+    fn is_oracle_account(bank_config: &BankConfig, oracle_key: &Pubkey, ai: &AccountInfo) -> bool {
+        match bank_config.oracle_setup {
+            OracleSetup::None => false,
+            // The oracle slot is kept so the remaining accounts layout doesn't change
+            OracleSetup::Fixed => true,
+            OracleSetup::PythPushOracle => load_price_update_v2_checked(ai)
+                .map(|price_update| price_update.price_message.feed_id == oracle_key.to_bytes())
+                .unwrap_or(false),
+            _ => ai.key == oracle_key,
+        }
+    }
+
+    /// Load the feed of one of the bank's oracles. With `oracle_max_age_slots` set, staleness
+    /// is measured by the distance to the oracle's publish slot instead of `max_age` seconds.
+    fn try_from_oracle_key(
+        bank_config: &BankConfig,
+        oracle_key: &Pubkey,
+        ais: &[AccountInfo],
+        clock: &Clock,
+        max_age: u64,
+    ) -> MarginfiResult<Self> {
+        if bank_config.oracle_max_age_slots == 0 {
+            return Self::load_from_oracle_key(bank_config, oracle_key, ais, clock, max_age);
+        }
+
+        // Any publish time passes, the slot check below replaces it
+        let price_feed =
+            Self::load_from_oracle_key(bank_config, oracle_key, ais, clock, u32::MAX as u64)?;
+
+        if let Some(publish_slot) = price_feed.get_publish_slot() {
+            check!(
+                clock.slot.saturating_sub(publish_slot) <= bank_config.oracle_max_age_slots as u64,
+                MarginfiError::StaleOracle
+            );
+        }
+
+        Ok(price_feed)
+    }
+
+    fn load_from_oracle_key(
+        bank_config: &BankConfig,
+        oracle_key: &Pubkey,
+        ais: &[AccountInfo],
+        clock: &Clock,
+        max_age: u64,
+    ) -> MarginfiResult<Self> {
+        let max_conf_interval = bank_config.get_oracle_max_confidence();
+
         match bank_config.oracle_setup {
             OracleSetup::None => Err(MarginfiError::OracleNotSetup.into()),
             OracleSetup::PythLegacy => {
                 check!(ais.len() == 1, MarginfiError::InvalidOracleAccount);
                 check!(
-                    ais[0].key == &bank_config.oracle_keys[0],
+                    ais[0].key == oracle_key,
                     MarginfiError::InvalidOracleAccount
                 );
 
                 let account_info = &ais[0];
 
                 Ok(OraclePriceFeedAdapter::PythLegacy(
-                    PythLegacyPriceFeed::load_checked(account_info, clock.unix_timestamp, max_age)?,
+                    PythLegacyPriceFeed::load_checked(
+                        account_info,
+                        clock.unix_timestamp,
+                        max_age,
+                        max_conf_interval,
+                    )?,
                 ))
             }
             OracleSetup::SwitchboardV2 => {
                 check!(ais.len() == 1, MarginfiError::InvalidOracleAccount);
                 check!(
-                    ais[0].key == &bank_config.oracle_keys[0],
+                    ais[0].key == oracle_key,
                     MarginfiError::InvalidOracleAccount
                 );
 
                 Ok(OraclePriceFeedAdapter::SwitchboardV2(
-                    SwitchboardV2PriceFeed::load_checked(&ais[0], clock.unix_timestamp, max_age)?,
+                    SwitchboardV2PriceFeed::load_checked(
+                        &ais[0],
+                        clock.unix_timestamp,
+                        max_age,
+                        max_conf_interval,
+                    )?,
                 ))
             }
             OracleSetup::PythPushOracle => {
@@ -124,7 +249,7 @@ impl OraclePriceFeedAdapter {
                     MarginfiError::InvalidOracleAccount
                 );
 
-                let price_feed_id = bank_config.get_pyth_push_oracle_feed_id().unwrap();
+                let price_feed_id: &FeedId = oracle_key.as_ref().try_into().unwrap();
 
                 Ok(OraclePriceFeedAdapter::PythPushOracle(
                     PythPushOraclePriceFeed::load_checked(
@@ -132,44 +257,208 @@ impl OraclePriceFeedAdapter {
                         price_feed_id,
                         clock,
                         max_age,
+                        max_conf_interval,
                     )?,
                 ))
             }
             OracleSetup::SwitchboardPull => {
                 check!(ais.len() == 1, MarginfiError::InvalidOracleAccount);
                 check!(
-                    ais[0].key == &bank_config.oracle_keys[0],
+                    ais[0].key == oracle_key,
                     MarginfiError::InvalidOracleAccount
                 );
 
                 Ok(OraclePriceFeedAdapter::SwitchboardPull(
-                    SwitchboardPullPriceFeed::load_checked(&ais[0], clock.unix_timestamp, max_age)?,
+                    SwitchboardPullPriceFeed::load_checked(
+                        &ais[0],
+                        clock.unix_timestamp,
+                        max_age,
+                        max_conf_interval,
+                    )?,
+                ))
+            }
+            OracleSetup::Fixed => {
+                // The oracle slot is kept so the remaining accounts layout doesn't change,
+                // its account is not read.
+                check!(ais.len() == 1, MarginfiError::InvalidOracleAccount);
+
+                Ok(OraclePriceFeedAdapter::Fixed(FixedPriceFeed {
+                    price: bank_config
+                        .get_fixed_price()
+                        .ok_or(MarginfiError::InvalidOracleSetup)?,
+                }))
+            }
+            OracleSetup::Chainlink => {
+                check!(ais.len() == 1, MarginfiError::InvalidOracleAccount);
+                check!(
+                    ais[0].key == oracle_key,
+                    MarginfiError::InvalidOracleAccount
+                );
+
+                Ok(OraclePriceFeedAdapter::Chainlink(
+                    ChainlinkPriceFeed::load_checked(&ais[0], clock.unix_timestamp, max_age)?,
                 ))
             }
+            #[cfg(feature = "mock-oracle")]
+            OracleSetup::Mock => {
+                check!(ais.len() == 1, MarginfiError::InvalidOracleAccount);
+                check!(
+                    ais[0].key == oracle_key,
+                    MarginfiError::InvalidOracleAccount
+                );
+
+                Ok(OraclePriceFeedAdapter::Mock(MockPriceFeed::load_checked(
+                    &ais[0],
+                    clock.unix_timestamp,
+                    max_age,
+                )?))
+            }
         }
     }
 
+    /// Slot the price was published at, `None` for prices that don't go stale.
+    pub fn get_publish_slot(&self) -> Option<u64> {
+        match self {
+            OraclePriceFeedAdapter::PythLegacy(feed) => Some(feed.publish_slot),
+            OraclePriceFeedAdapter::SwitchboardV2(feed) => {
+                Some(feed.aggregator_account.latest_confirmed_round_open_slot)
+            }
+            OraclePriceFeedAdapter::PythPushOracle(feed) => Some(feed.publish_slot),
+            OraclePriceFeedAdapter::SwitchboardPull(feed) => Some(feed.feed.result_slot),
+            OraclePriceFeedAdapter::Fixed(_) => None,
+            OraclePriceFeedAdapter::Chainlink(feed) => Some(feed.publish_slot),
+            #[cfg(feature = "mock-oracle")]
+            OraclePriceFeedAdapter::Mock(feed) => Some(feed.publish_slot),
+        }
+    }
+
+    /// Real time price of each of the bank's oracles, primary first, `None` for an oracle that is stale
+    /// or reports a price that is not positive. Fails if an account is not one of the oracles.
+    ///
+    /// `ais` holds the primary oracle followed by the fallback oracle if one is configured.
+    /// Pyth push feeds can be posted to any number of accounts, so each feed is represented by both
+    /// of its sponsored accounts (Pyth then marginfi shard) and is only down if both are.
+    pub fn get_oracle_prices(
+        bank_config: &BankConfig,
+        ais: &[AccountInfo],
+        clock: &Clock,
+    ) -> MarginfiResult<Vec<Option<I80F48>>> {
+        let oracle_keys = std::iter::once(&bank_config.oracle_keys[0])
+            .chain(bank_config.get_fallback_oracle_key())
+            .collect::<Vec<_>>();
+        let ais_per_oracle = match bank_config.oracle_setup {
+            OracleSetup::PythPushOracle => 2,
+            _ => 1,
+        };
+
+        check!(
+            ais.len() == oracle_keys.len() * ais_per_oracle,
+            MarginfiError::InvalidOracleAccount
+        );
+
+        let mut prices = Vec::with_capacity(oracle_keys.len());
+
+        for (oracle_key, oracle_ais) in oracle_keys.into_iter().zip(ais.chunks(ais_per_oracle)) {
+            if matches!(bank_config.oracle_setup, OracleSetup::PythPushOracle) {
+                let feed_id: &FeedId = oracle_key.as_ref().try_into().unwrap();
+
+                for (ai, shard_id) in oracle_ais.iter().zip([
+                    PYTH_PUSH_PYTH_SPONSORED_SHARD_ID,
+                    PYTH_PUSH_MARGINFI_SPONSORED_SHARD_ID,
+                ]) {
+                    check!(
+                        ai.key
+                            == &PythPushOraclePriceFeed::find_oracle_address(shard_id, feed_id).0,
+                        MarginfiError::InvalidOracleAccount
+                    );
+                }
+            }
+
+            let mut oracle_price = None;
+
+            for ai in oracle_ais {
+                match Self::try_from_oracle_key(
+                    bank_config,
+                    oracle_key,
+                    std::slice::from_ref(ai),
+                    clock,
+                    bank_config.get_oracle_max_age(),
+                ) {
+                    Ok(price_feed) => {
+                        let price =
+                            price_feed.get_price_of_type(OraclePriceType::RealTime, None)?;
+
+                        if price > I80F48::ZERO {
+                            oracle_price.get_or_insert(price);
+                        }
+                    }
+                    Err(err) if err == MarginfiError::StaleOracle.into() => {}
+                    Err(err) => return Err(err),
+                }
+            }
+
+            prices.push(oracle_price);
+        }
+
+        Ok(prices)
+    }
+
+    /// Check the oracle accounts of a bank config, `oracle_ais` holds the primary oracle
+    /// followed by the fallback oracle if one is configured.
     pub fn validate_bank_config(
         bank_config: &BankConfig,
         oracle_ais: &[AccountInfo],
+        pyth_program_id: &Pubkey,
+    ) -> MarginfiResult {
+        match bank_config.get_fallback_oracle_key() {
+            Some(fallback_key) => {
+                check!(oracle_ais.len() == 2, MarginfiError::InvalidOracleAccount);
+
+                Self::validate_oracle_key(
+                    bank_config,
+                    &bank_config.oracle_keys[0],
+                    &oracle_ais[0..1],
+                    pyth_program_id,
+                )?;
+                Self::validate_oracle_key(
+                    bank_config,
+                    fallback_key,
+                    &oracle_ais[1..2],
+                    pyth_program_id,
+                )
+            }
+            None => Self::validate_oracle_key(
+                bank_config,
+                &bank_config.oracle_keys[0],
+                oracle_ais,
+                pyth_program_id,
+            ),
+        }
+    }
+
+    fn validate_oracle_key(
+        bank_config: &BankConfig,
+        oracle_key: &Pubkey,
+        oracle_ais: &[AccountInfo],
+        pyth_program_id: &Pubkey,
     ) -> MarginfiResult {
         match bank_config.oracle_setup {
             OracleSetup::None => Err(MarginfiError::OracleNotSetup.into()),
             OracleSetup::PythLegacy => {
                 check!(oracle_ais.len() == 1, MarginfiError::InvalidOracleAccount);
                 check!(
-                    oracle_ais[0].key == &bank_config.oracle_keys[0],
+                    oracle_ais[0].key == oracle_key,
                     MarginfiError::InvalidOracleAccount
                 );
 
-                PythLegacyPriceFeed::check_ais(&oracle_ais[0])?;
+                PythLegacyPriceFeed::check_ais(&oracle_ais[0], pyth_program_id)?;
 
                 Ok(())
             }
             OracleSetup::SwitchboardV2 => {
                 check!(oracle_ais.len() == 1, MarginfiError::InvalidOracleAccount);
                 check!(
-                    oracle_ais[0].key == &bank_config.oracle_keys[0],
+                    oracle_ais[0].key == oracle_key,
                     MarginfiError::InvalidOracleAccount
                 );
 
@@ -182,7 +471,7 @@ impl OraclePriceFeedAdapter {
 
                 PythPushOraclePriceFeed::check_ai_and_feed_id(
                     &oracle_ais[0],
-                    bank_config.get_pyth_push_oracle_feed_id().unwrap(),
+                    oracle_key.as_ref().try_into().unwrap(),
                 )?;
 
                 Ok(())
@@ -190,12 +479,43 @@ impl OraclePriceFeedAdapter {
             OracleSetup::SwitchboardPull => {
                 check!(oracle_ais.len() == 1, MarginfiError::InvalidOracleAccount);
                 check!(
-                    oracle_ais[0].key == &bank_config.oracle_keys[0],
+                    oracle_ais[0].key == oracle_key,
                     MarginfiError::InvalidOracleAccount
                 );
 
                 SwitchboardPullPriceFeed::check_ais(&oracle_ais[0])?;
 
+                Ok(())
+            }
+            OracleSetup::Fixed => {
+                check!(
+                    bank_config.get_fixed_price().is_some(),
+                    MarginfiError::InvalidOracleSetup
+                );
+
+                Ok(())
+            }
+            OracleSetup::Chainlink => {
+                check!(oracle_ais.len() == 1, MarginfiError::InvalidOracleAccount);
+                check!(
+                    oracle_ais[0].key == oracle_key,
+                    MarginfiError::InvalidOracleAccount
+                );
+
+                ChainlinkPriceFeed::check_ais(&oracle_ais[0])?;
+
+                Ok(())
+            }
+            #[cfg(feature = "mock-oracle")]
+            OracleSetup::Mock => {
+                check!(oracle_ais.len() == 1, MarginfiError::InvalidOracleAccount);
+                check!(
+                    oracle_ais[0].key == oracle_key,
+                    MarginfiError::InvalidOracleAccount
+                );
+
+                MockPriceFeed::check_ais(&oracle_ais[0])?;
+
                 Ok(())
             }
         }
@@ -206,10 +526,19 @@ impl OraclePriceFeedAdapter {
 pub struct PythLegacyPriceFeed {
     ema_price: Box<Price>,
     price: Box<Price>,
+    /// Cap on the confidence interval as a fraction of the price.
+    max_conf_interval: I80F48,
+    /// Slot of the aggregate price.
+    publish_slot: u64,
 }
 
 impl PythLegacyPriceFeed {
-    pub fn load_checked(ai: &AccountInfo, current_time: i64, max_age: u64) -> MarginfiResult<Self> {
+    pub fn load_checked(
+        ai: &AccountInfo,
+        current_time: i64,
+        max_age: u64,
+        max_conf_interval: I80F48,
+    ) -> MarginfiResult<Self> {
         let price_feed = load_pyth_price_feed(ai)?;
 
         let ema_price = price_feed
@@ -220,13 +549,24 @@ impl PythLegacyPriceFeed {
             .get_price_no_older_than(current_time, max_age)
             .ok_or(MarginfiError::StaleOracle)?;
 
+        let publish_slot = load_price_account::<32, ()>(&ai.data.borrow())
+            .map_err(|_| MarginfiError::InvalidOracleAccount)?
+            .agg
+            .pub_slot;
+
         Ok(Self {
             ema_price: Box::new(ema_price),
             price: Box::new(price),
+            max_conf_interval,
+            publish_slot,
         })
     }
 
-    fn check_ais(ai: &AccountInfo) -> MarginfiResult {
+    fn check_ais(ai: &AccountInfo, pyth_program_id: &Pubkey) -> MarginfiResult {
+        check!(
+            ai.owner.eq(pyth_program_id),
+            MarginfiError::InvalidOracleAccount
+        );
         load_pyth_price_feed(ai)?;
         Ok(())
     }
@@ -243,11 +583,11 @@ impl PythLegacyPriceFeed {
                 .checked_mul(CONF_INTERVAL_MULTIPLE)
                 .ok_or_else(math_error!())?;
 
-        // Cap confidence interval to 5% of price
+        // Cap confidence interval to the configured fraction of price
         let price = pyth_price_components_to_i80f48(I80F48::from_num(price.price), price.expo)?;
 
         let max_conf_interval = price
-            .checked_mul(MAX_CONF_INTERVAL)
+            .checked_mul(self.max_conf_interval)
             .ok_or_else(math_error!())?;
 
         assert!(
@@ -307,6 +647,7 @@ impl PriceAdapter for PythLegacyPriceFeed {
 #[cfg_attr(feature = "client", derive(Clone, Debug))]
 pub struct SwitchboardPullPriceFeed {
     feed: Box<LitePullFeedAccountData>,
+    max_conf_interval: I80F48,
 }
 
 impl SwitchboardPullPriceFeed {
@@ -314,51 +655,46 @@ impl SwitchboardPullPriceFeed {
         ai: &AccountInfo,
         current_timestamp: i64,
         max_age: u64,
+        max_conf_interval: I80F48,
     ) -> MarginfiResult<Self> {
-        let ai_data = ai.data.borrow();
-
         check!(
             ai.owner.eq(&SWITCHBOARD_PULL_ID),
             MarginfiError::InvalidOracleAccount
         );
 
-        // let feed =
-        //     PullFeedAccountData::parse(ai_data).map_err(|_| MarginfiError::InvalidOracleAccount)?;
+        let feed = LitePullFeedAccountData::parse(&ai.data.borrow())?;
 
-        // Check staleness
+        check!(
+            current_timestamp.saturating_sub(feed.last_update_timestamp) <= max_age as i64,
+            MarginfiError::StaleOracle
+        );
 
         Ok(Self {
-            feed: Box::new(LitePullFeedAccountData { result: 0 }),
+            feed: Box::new(feed),
+            max_conf_interval,
         })
     }
 
     fn check_ais(ai: &AccountInfo) -> MarginfiResult {
-        let ai_data = ai.data.borrow();
-
         check!(
             ai.owner.eq(&SWITCHBOARD_PULL_ID),
             MarginfiError::InvalidOracleAccount
         );
 
-        // PullFeedAccountData::parse(ai_data).map_err(|_| MarginfiError::InvalidOracleAccount)?;
+        LitePullFeedAccountData::parse(&ai.data.borrow())?;
 
         Ok(())
     }
 
     fn get_price(&self) -> MarginfiResult<I80F48> {
         // Note: Pull oracles support mean (result.mean) or median (result.value)
-        let price: I80F48 = I80F48::from_num(0);
-
-        // WARNING: Adding a line like the following will cause the entire project to silently fail
+        // WARNING: Adding a `msg!` of the price here will cause the entire project to silently fail
         // to build, resulting in `Program not deployed` errors downstream when testing
-
-        // msg!("recorded price: {:?}", price);
-
-        Ok(price)
+        swb_on_demand_value_to_i80f48(self.feed.value)
     }
 
     fn get_confidence_interval(&self) -> MarginfiResult<I80F48> {
-        let std_div: I80F48 = I80F48::from_num(0);
+        let std_div = swb_on_demand_value_to_i80f48(self.feed.std_dev)?;
 
         let conf_interval = std_div
             .checked_mul(STD_DEV_MULTIPLE)
@@ -367,7 +703,7 @@ impl SwitchboardPullPriceFeed {
         let price = self.get_price()?;
 
         let max_conf_interval = price
-            .checked_mul(MAX_CONF_INTERVAL)
+            .checked_mul(self.max_conf_interval)
             .ok_or_else(math_error!())?;
 
         assert!(
@@ -413,6 +749,7 @@ impl PriceAdapter for SwitchboardPullPriceFeed {
 #[cfg_attr(feature = "client", derive(Clone, Debug))]
 pub struct SwitchboardV2PriceFeed {
     aggregator_account: Box<LiteAggregatorAccountData>,
+    max_conf_interval: I80F48,
 }
 
 impl SwitchboardV2PriceFeed {
@@ -420,6 +757,7 @@ impl SwitchboardV2PriceFeed {
         ai: &AccountInfo,
         current_timestamp: i64,
         max_age: u64,
+        max_conf_interval: I80F48,
     ) -> MarginfiResult<Self> {
         let ai_data = ai.data.borrow();
 
@@ -437,6 +775,7 @@ impl SwitchboardV2PriceFeed {
 
         Ok(Self {
             aggregator_account: Box::new(aggregator_account.into()),
+            max_conf_interval,
         })
     }
 
@@ -476,7 +815,7 @@ impl SwitchboardV2PriceFeed {
         let price = self.get_price()?;
 
         let max_conf_interval = price
-            .checked_mul(MAX_CONF_INTERVAL)
+            .checked_mul(self.max_conf_interval)
             .ok_or_else(math_error!())?;
 
         assert!(
@@ -526,34 +865,25 @@ pub fn load_price_update_v2_checked(ai: &AccountInfo) -> MarginfiResult<PriceUpd
     );
 
     let price_feed_data = ai.try_borrow_data()?;
-    let discriminator = &price_feed_data[0..8];
-
-    // check!(
-    //     discriminator == <PriceUpdateV2 as anchor_lang_29::Discriminator>::DISCRIMINATOR,
-    //     MarginfiError::InvalidOracleAccount
-    // );
-
-    Ok(PriceUpdateV2 {
-        write_authority: Pubkey::default(),
-        posted_slot: 0,
-        verification_level: pyth_solana_receiver_sdk::price_update::VerificationLevel::Full,
-        price_message: pyth_solana_receiver_sdk::price_update::PriceFeedMessage {
-            feed_id: [0; 32],
-            price: 0,
-            conf: 0,
-            exponent: 0,
-            publish_time: 0,
-            prev_publish_time: 0,
-            ema_price: 0,
-            ema_conf: 0,
-        },
-    })
+
+    check!(
+        price_feed_data.len() >= 8 && price_feed_data[0..8] == PriceUpdateV2::DISCRIMINATOR,
+        MarginfiError::InvalidOracleAccount
+    );
+
+    let price_update = PriceUpdateV2::deserialize(&mut &price_feed_data[8..])
+        .map_err(|_| MarginfiError::InvalidOracleAccount)?;
+
+    Ok(price_update)
 }
 
 #[cfg_attr(feature = "client", derive(Clone, Debug))]
 pub struct PythPushOraclePriceFeed {
     ema_price: Box<pyth_solana_receiver_sdk::price_update::Price>,
     price: Box<pyth_solana_receiver_sdk::price_update::Price>,
+    max_conf_interval: I80F48,
+    /// Slot the price update was posted at.
+    publish_slot: u64,
 }
 
 impl PythPushOraclePriceFeed {
@@ -578,6 +908,7 @@ impl PythPushOraclePriceFeed {
         feed_id: &FeedId,
         clock: &Clock,
         max_age: u64,
+        max_conf_interval: I80F48,
     ) -> MarginfiResult<Self> {
         let price_feed_account = load_price_update_v2_checked(ai)?;
 
@@ -619,6 +950,8 @@ impl PythPushOraclePriceFeed {
         Ok(Self {
             price: Box::new(price),
             ema_price: Box::new(ema_price),
+            max_conf_interval,
+            publish_slot: price_feed_account.posted_slot,
         })
     }
 
@@ -659,6 +992,8 @@ impl PythPushOraclePriceFeed {
         Ok(Self {
             price: Box::new(price),
             ema_price: Box::new(ema_price),
+            max_conf_interval: crate::constants::MAX_CONF_INTERVAL,
+            publish_slot: price_feed_account.posted_slot,
         })
     }
 
@@ -692,11 +1027,11 @@ impl PythPushOraclePriceFeed {
                 .checked_mul(CONF_INTERVAL_MULTIPLE)
                 .ok_or_else(math_error!())?;
 
-        // Cap confidence interval to 5% of price
+        // Cap confidence interval to the configured fraction of price
         let price = pyth_price_components_to_i80f48(I80F48::from_num(price.price), price.exponent)?;
 
         let max_conf_interval = price
-            .checked_mul(MAX_CONF_INTERVAL)
+            .checked_mul(self.max_conf_interval)
             .ok_or_else(math_error!())?;
 
         assert!(
@@ -767,11 +1102,299 @@ impl PriceAdapter for PythPushOraclePriceFeed {
     }
 }
 
+/// Constant price configured on the bank, for assets without a reliable feed.
+/// There is no confidence interval, so biased prices equal the fixed price.
+#[cfg_attr(feature = "client", derive(Clone, Debug))]
+pub struct FixedPriceFeed {
+    price: I80F48,
+}
+
+impl PriceAdapter for FixedPriceFeed {
+    fn get_price_of_type(
+        &self,
+        _price_type: OraclePriceType,
+        _bias: Option<PriceBias>,
+    ) -> MarginfiResult<I80F48> {
+        Ok(self.price)
+    }
+}
+
+/// Latest round of a Chainlink data feed. Chainlink publishes no confidence interval and no EMA,
+/// so all price types and biases return the round's answer.
+#[cfg_attr(feature = "client", derive(Clone, Debug))]
+pub struct ChainlinkPriceFeed {
+    price: I80F48,
+    publish_slot: u64,
+}
+
+impl ChainlinkPriceFeed {
+    /// Anchor discriminator of the store program's `Transmissions` account.
+    const DISCRIMINATOR: [u8; 8] = [96, 179, 69, 66, 128, 129, 73, 117];
+    /// The packed `Transmissions` header is followed by a ring buffer of rounds,
+    /// the header is padded to this size.
+    const HEADER_SIZE: usize = 192;
+    const DECIMALS_OFFSET: usize = 130;
+    const LATEST_ROUND_ID_OFFSET: usize = 135;
+    const LIVE_LENGTH_OFFSET: usize = 140;
+    const LIVE_CURSOR_OFFSET: usize = 144;
+    /// `slot: u64, timestamp: u32, _: u32, answer: i128, _: [u64; 2]`
+    const TRANSMISSION_SIZE: usize = 48;
+
+    pub fn load_checked(
+        ai: &AccountInfo,
+        current_timestamp: i64,
+        max_age: u64,
+    ) -> MarginfiResult<Self> {
+        let (price, timestamp, publish_slot) = Self::load_latest_round(ai)?;
+
+        check!(
+            current_timestamp.saturating_sub(timestamp) as u64 <= max_age,
+            MarginfiError::StaleOracle
+        );
+
+        Ok(Self {
+            price,
+            publish_slot,
+        })
+    }
+
+    fn check_ais(ai: &AccountInfo) -> MarginfiResult {
+        Self::load_latest_round(ai)?;
+        Ok(())
+    }
+
+    /// Price, timestamp and slot of the latest round, decimals normalized.
+    fn load_latest_round(ai: &AccountInfo) -> MarginfiResult<(I80F48, i64, u64)> {
+        check!(
+            ai.owner.eq(&CHAINLINK_STORE_ID),
+            MarginfiError::InvalidOracleAccount
+        );
+
+        let data = ai.try_borrow_data()?;
+
+        check!(
+            data.len() >= 8 + Self::HEADER_SIZE && data[..8] == Self::DISCRIMINATOR,
+            MarginfiError::InvalidOracleAccount
+        );
+
+        let header = &data[8..8 + Self::HEADER_SIZE];
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+
+        let decimals = header[Self::DECIMALS_OFFSET] as usize;
+        let latest_round_id = read_u32(Self::LATEST_ROUND_ID_OFFSET);
+        let live_length = read_u32(Self::LIVE_LENGTH_OFFSET) as usize;
+        let live_cursor = read_u32(Self::LIVE_CURSOR_OFFSET) as usize;
+
+        check!(
+            latest_round_id != 0 && live_length != 0,
+            MarginfiError::InvalidOracleAccount
+        );
+
+        // The cursor points at the slot the next round is written to
+        let latest_index = (live_cursor + live_length - 1) % live_length;
+        let offset = 8 + Self::HEADER_SIZE + latest_index * Self::TRANSMISSION_SIZE;
+
+        let transmission = data
+            .get(offset..offset + Self::TRANSMISSION_SIZE)
+            .ok_or(MarginfiError::InvalidOracleAccount)?;
+
+        let slot = u64::from_le_bytes(transmission[..8].try_into().unwrap());
+        let timestamp = u32::from_le_bytes(transmission[8..12].try_into().unwrap());
+        let answer = i128::from_le_bytes(transmission[16..32].try_into().unwrap());
+
+        check!(answer > 0, MarginfiError::InvalidPrice);
+
+        let price = scale_by_exponent(
+            I80F48::checked_from_num(answer).ok_or_else(math_error!())?,
+            -(decimals as i32),
+        )?;
+
+        Ok((price, timestamp as i64, slot))
+    }
+}
+
+impl PriceAdapter for ChainlinkPriceFeed {
+    fn get_price_of_type(
+        &self,
+        _price_type: OraclePriceType,
+        _bias: Option<PriceBias>,
+    ) -> MarginfiResult<I80F48> {
+        Ok(self.price)
+    }
+}
+
+/// Oracle account owned by this program whose price is set by its authority.
+/// Lets localnet and integration environments price banks without forging Pyth accounts.
+#[cfg(feature = "mock-oracle")]
+#[account(zero_copy)]
+#[repr(C)]
+pub struct MockOracle {
+    pub authority: Pubkey,
+    pub price: WrappedI80F48,
+    pub confidence: WrappedI80F48,
+    pub last_update: i64,
+    pub last_update_slot: u64,
+    /// See [`crate::constants::PROGRAM_VERSION`].
+    pub program_version: u64,
+    pub _padding: [u64; 6],
+}
+
+#[cfg(feature = "mock-oracle")]
+impl MockOracle {
+    pub fn check_program_version(&self) -> MarginfiResult {
+        crate::state::marginfi_group::check_program_version(self.program_version)
+    }
+
+    pub fn set_price(
+        &mut self,
+        price: I80F48,
+        confidence: I80F48,
+        clock: &Clock,
+    ) -> MarginfiResult {
+        check!(price > I80F48::ZERO, MarginfiError::InvalidOracleAccount);
+        check!(
+            confidence >= I80F48::ZERO,
+            MarginfiError::InvalidOracleAccount
+        );
+
+        self.price = price.into();
+        self.confidence = confidence.into();
+        self.last_update = clock.unix_timestamp;
+        self.last_update_slot = clock.slot;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mock-oracle")]
+#[cfg_attr(feature = "client", derive(Clone, Debug))]
+pub struct MockPriceFeed {
+    price: I80F48,
+    confidence: I80F48,
+    publish_slot: u64,
+}
+
+#[cfg(feature = "mock-oracle")]
+impl MockPriceFeed {
+    pub fn load_checked(
+        ai: &AccountInfo,
+        current_timestamp: i64,
+        max_age: u64,
+    ) -> MarginfiResult<Self> {
+        let oracle = load_mock_oracle(ai)?;
+
+        check!(
+            current_timestamp.saturating_sub(oracle.last_update) as u64 <= max_age,
+            MarginfiError::StaleOracle
+        );
+
+        Ok(Self {
+            price: oracle.price.into(),
+            confidence: oracle.confidence.into(),
+            publish_slot: oracle.last_update_slot,
+        })
+    }
+
+    fn check_ais(ai: &AccountInfo) -> MarginfiResult {
+        load_mock_oracle(ai)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mock-oracle")]
+impl PriceAdapter for MockPriceFeed {
+    fn get_price_of_type(
+        &self,
+        _price_type: OraclePriceType,
+        bias: Option<PriceBias>,
+    ) -> MarginfiResult<I80F48> {
+        match bias {
+            None => Ok(self.price),
+            Some(PriceBias::Low) => Ok(self
+                .price
+                .checked_sub(self.confidence)
+                .ok_or_else(math_error!())?),
+            Some(PriceBias::High) => Ok(self
+                .price
+                .checked_add(self.confidence)
+                .ok_or_else(math_error!())?),
+        }
+    }
+}
+
+#[cfg(feature = "mock-oracle")]
+fn load_mock_oracle(ai: &AccountInfo) -> MarginfiResult<MockOracle> {
+    check!(ai.owner.eq(&crate::ID), MarginfiError::InvalidOracleAccount);
+
+    let data = ai.try_borrow_data()?;
+    let size = std::mem::size_of::<MockOracle>();
+
+    check!(
+        data.len() >= 8 + size && data[..8] == MockOracle::DISCRIMINATOR,
+        MarginfiError::InvalidOracleAccount
+    );
+
+    Ok(bytemuck::pod_read_unaligned(&data[8..8 + size]))
+}
+
 /// A slimmed down version of the PullFeedAccountData struct copied from the
 /// switchboard-on-demand/src/pull_feed.rs
 #[cfg_attr(feature = "client", derive(Clone, Debug))]
 struct LitePullFeedAccountData {
-    pub result: u64,
+    /// Median of the latest oracle submissions, see [`swb_on_demand_value_to_i80f48`].
+    pub value: i128,
+    pub std_dev: i128,
+    pub last_update_timestamp: i64,
+    pub result_slot: u64,
+}
+
+impl LitePullFeedAccountData {
+    const DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+    // Offsets into the account data, after 32 oracle submissions and the feed settings
+    const LAST_UPDATE_TIMESTAMP_OFFSET: usize = 2216;
+    const RESULT_VALUE_OFFSET: usize = 2264;
+    const RESULT_STD_DEV_OFFSET: usize = 2280;
+    const RESULT_SLOT_OFFSET: usize = 2368;
+
+    fn parse(data: &[u8]) -> MarginfiResult<Self> {
+        check!(
+            data.len() >= Self::RESULT_SLOT_OFFSET + 8 && data[..8] == Self::DISCRIMINATOR,
+            MarginfiError::InvalidOracleAccount
+        );
+
+        let read_i128 =
+            |offset: usize| i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap());
+
+        Ok(Self {
+            value: read_i128(Self::RESULT_VALUE_OFFSET),
+            std_dev: read_i128(Self::RESULT_STD_DEV_OFFSET),
+            last_update_timestamp: i64::from_le_bytes(
+                data[Self::LAST_UPDATE_TIMESTAMP_OFFSET..Self::LAST_UPDATE_TIMESTAMP_OFFSET + 8]
+                    .try_into()
+                    .unwrap(),
+            ),
+            result_slot: u64::from_le_bytes(
+                data[Self::RESULT_SLOT_OFFSET..Self::RESULT_SLOT_OFFSET + 8]
+                    .try_into()
+                    .unwrap(),
+            ),
+        })
+    }
+}
+
+/// Switchboard on-demand results are fixed point numbers with 18 decimals.
+/// The integer and fractional part are converted separately, so prices above I80F48's
+/// range divided by 10^18 don't overflow.
+fn swb_on_demand_value_to_i80f48(value: i128) -> MarginfiResult<I80F48> {
+    const PRECISION: i128 = 1_000_000_000_000_000_000;
+
+    let integer = I80F48::checked_from_num(value / PRECISION).ok_or_else(math_error!())?;
+    let fraction = I80F48::from_num(value % PRECISION)
+        .checked_div(EXP_10_I80F48[18])
+        .ok_or_else(math_error!())?;
+
+    integer.checked_add(fraction).ok_or_else(math_error!())
 }
 
 /// A slimmed down version of the AggregatorAccountData struct copied from the switchboard-v2/src/aggregator.rs
@@ -784,6 +1407,7 @@ struct LiteAggregatorAccountData {
     pub latest_confirmed_round_result: SwitchboardDecimal,
     pub latest_confirmed_round_num_success: u32,
     pub latest_confirmed_round_std_deviation: SwitchboardDecimal,
+    pub latest_confirmed_round_open_slot: u64,
     /// Minimum number of oracle responses required before a round is validated.
     pub min_oracle_results: u32,
 }
@@ -798,6 +1422,7 @@ impl From<&AggregatorAccountData> for LiteAggregatorAccountData {
             },
             latest_confirmed_round_num_success: agg.latest_confirmed_round.num_success,
             latest_confirmed_round_std_deviation: agg.latest_confirmed_round.std_deviation,
+            latest_confirmed_round_open_slot: agg.latest_confirmed_round.round_open_slot,
             min_oracle_results: agg.min_oracle_results,
         }
     }
@@ -831,26 +1456,20 @@ impl LiteAggregatorAccountData {
 
 #[inline(always)]
 fn pyth_price_components_to_i80f48(price: I80F48, exponent: i32) -> MarginfiResult<I80F48> {
-    let scaling_factor = EXP_10_I80F48[exponent.unsigned_abs() as usize];
-
-    let price = if exponent == 0 {
-        price
-    } else if exponent < 0 {
-        price
-            .checked_div(scaling_factor)
-            .ok_or_else(math_error!())?
-    } else {
-        price
-            .checked_mul(scaling_factor)
-            .ok_or_else(math_error!())?
-    };
+    scale_by_exponent(price, exponent)
+}
 
-    Ok(price)
+/// Multiply `value` by `10^exponent` for any exponent, see
+/// [`marginfi_math::value::scale_by_exponent`].
+pub fn scale_by_exponent(value: I80F48, exponent: i32) -> MarginfiResult<I80F48> {
+    Ok(marginfi_math::value::scale_by_exponent(value, exponent).ok_or_else(math_error!())?)
 }
 
 /// Load and validate a pyth price feed account.
+///
+/// The owner is checked against the group's Pyth program when the oracle is configured
+/// (see [`PythLegacyPriceFeed::check_ais`]), after that the bank pins the oracle key.
 fn load_pyth_price_feed(ai: &AccountInfo) -> MarginfiResult<PriceFeed> {
-    check!(ai.owner.eq(&PYTH_ID), MarginfiError::InvalidOracleAccount);
     let price_feed = SolanaPriceAccount::account_info_to_feed(ai)
         .map_err(|_| MarginfiError::InvalidOracleAccount)?;
     Ok(price_feed)
@@ -890,9 +1509,46 @@ mod tests {
     use pretty_assertions::assert_eq;
     use rust_decimal::Decimal;
 
-    use crate::utils::hex_to_bytes;
+    use crate::{
+        constants::{MAX_CONF_INTERVAL, PYTH_ID},
+        utils::hex_to_bytes,
+    };
 
     use super::*;
+    #[test]
+    fn scale_by_exponent_extremes() {
+        assert_eq!(scale_by_exponent(I80F48!(123), 0).unwrap(), I80F48!(123));
+        assert_eq!(scale_by_exponent(I80F48!(2), 5).unwrap(), I80F48!(200_000));
+        assert_eq!(
+            scale_by_exponent(I80F48!(1), 23).unwrap(),
+            I80F48!(100_000_000_000_000_000_000_000)
+        );
+
+        // BONK-style Pyth price: 2500 * 10^-8
+        assert!(
+            (scale_by_exponent(I80F48!(2_500), -8).unwrap() - I80F48!(0.000025)).abs()
+                < I80F48!(0.000000000001)
+        );
+
+        // Beyond the lookup table, scaled in steps
+        assert_eq!(
+            scale_by_exponent(I80F48!(100_000_000_000_000_000_000_000), -30).unwrap(),
+            scale_by_exponent(I80F48!(1), -7).unwrap()
+        );
+
+        // Rounds to zero past the I80F48 resolution, overflows fail instead of panicking
+        assert_eq!(
+            scale_by_exponent(I80F48!(1), i32::MIN).unwrap(),
+            I80F48::ZERO
+        );
+        assert!(scale_by_exponent(I80F48!(1), 24).is_err());
+        assert!(scale_by_exponent(I80F48!(1), i32::MAX).is_err());
+        assert_eq!(
+            scale_by_exponent(I80F48::ZERO, i32::MAX).unwrap(),
+            I80F48::ZERO
+        );
+    }
+
     #[test]
     fn swb_decimal_test_18() {
         let decimal = SwitchboardDecimal {
@@ -943,6 +1599,8 @@ mod tests {
         let pyth_adapter = PythLegacyPriceFeed {
             ema_price: high_confidence_price,
             price: low_confidence_price,
+            max_conf_interval: MAX_CONF_INTERVAL,
+            publish_slot: 0,
         };
 
         // Test confidence interval when using EMA price (high confidence)
@@ -956,6 +1614,57 @@ mod tests {
         assert_eq!(low_conf_interval, I80F48!(2.12));
     }
 
+    #[test]
+    fn pyth_conf_interval_configured_cap() {
+        let price = Price {
+            price: 100i64 * EXP_10[6] as i64,
+            conf: 10u64 * EXP_10[6] as u64,
+            expo: -6,
+            publish_time: 0,
+        };
+
+        let mut bank_config = BankConfig {
+            oracle_max_confidence_bps: 1_250,
+            ..Default::default()
+        };
+
+        let pyth_adapter = PythLegacyPriceFeed {
+            ema_price: Box::new(price),
+            price: Box::new(price),
+            max_conf_interval: bank_config.get_oracle_max_confidence(),
+            publish_slot: 0,
+        };
+
+        // Capped at 12.5% instead of the default 5%
+        assert_eq!(
+            pyth_adapter.get_confidence_interval(false).unwrap(),
+            I80F48!(12.5)
+        );
+        assert_eq!(
+            pyth_adapter
+                .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low))
+                .unwrap(),
+            I80F48!(87.5)
+        );
+        assert_eq!(
+            pyth_adapter
+                .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::High))
+                .unwrap(),
+            I80F48!(112.5)
+        );
+
+        // A cap above the interval leaves it untouched
+        bank_config.oracle_max_confidence_bps = 2_500;
+        let pyth_adapter = PythLegacyPriceFeed {
+            max_conf_interval: bank_config.get_oracle_max_confidence(),
+            ..pyth_adapter
+        };
+        assert_eq!(
+            pyth_adapter.get_confidence_interval(false).unwrap(),
+            I80F48!(10) * CONF_INTERVAL_MULTIPLE
+        );
+    }
+
     #[test]
     fn switchboard_conf_interval_cap() {
         // Define a price with a 10% confidence interval
@@ -966,8 +1675,10 @@ mod tests {
                 latest_confirmed_round_result: SwitchboardDecimal::from_f64(100.0),
                 latest_confirmed_round_num_success: 1,
                 latest_confirmed_round_std_deviation: SwitchboardDecimal::from_f64(10.0),
+                latest_confirmed_round_open_slot: 0,
                 min_oracle_results: 1,
             }),
+            max_conf_interval: MAX_CONF_INTERVAL,
         };
 
         let swb_adapter_low_confidence = SwitchboardV2PriceFeed {
@@ -976,8 +1687,10 @@ mod tests {
                 latest_confirmed_round_result: SwitchboardDecimal::from_f64(100.0),
                 latest_confirmed_round_num_success: 1,
                 latest_confirmed_round_std_deviation: SwitchboardDecimal::from_f64(1.0),
+                latest_confirmed_round_open_slot: 0,
                 min_oracle_results: 1,
             }),
+            max_conf_interval: MAX_CONF_INTERVAL,
         };
 
         // Test confidence interval
@@ -996,6 +1709,82 @@ mod tests {
         assert_eq!(low_conf_interval, I80F48!(1.96));
     }
 
+    #[test]
+    fn pyth_push_price_update_decoding() {
+        let feed_id = [7; 32];
+        let update = PriceUpdateV2 {
+            write_authority: Pubkey::new_unique(),
+            verification_level: price_update::VerificationLevel::Full,
+            price_message: price_update::PriceFeedMessage {
+                feed_id,
+                price: 100 * EXP_10[6] as i64,
+                conf: EXP_10[6] as u64,
+                exponent: -6,
+                publish_time: 1_000,
+                prev_publish_time: 999,
+                ema_price: 99 * EXP_10[6] as i64,
+                ema_conf: EXP_10[6] as u64,
+            },
+            posted_slot: 10,
+        };
+
+        let mut data = vec![];
+        update.try_serialize(&mut data).unwrap();
+
+        let key = Pubkey::new_unique();
+        let owner = pyth_solana_receiver_sdk::id();
+        let mut lamports = 0;
+        let ai = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+
+        let loaded = load_price_update_v2_checked(&ai).unwrap();
+        assert_eq!(loaded.price_message.feed_id, feed_id);
+        assert_eq!(loaded.price_message.price, 100 * EXP_10[6] as i64);
+        assert_eq!(loaded.posted_slot, 10);
+
+        assert!(PythPushOraclePriceFeed::check_ai_and_feed_id(&ai, &feed_id).is_ok());
+        assert!(PythPushOraclePriceFeed::check_ai_and_feed_id(&ai, &[8; 32]).is_err());
+
+        // Wrong discriminator
+        let mut bad_data = data.clone();
+        bad_data[0] ^= 1;
+        let mut lamports = 0;
+        let ai = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut bad_data,
+            &owner,
+            false,
+            0,
+        );
+        assert!(load_price_update_v2_checked(&ai).is_err());
+
+        // Wrong owner
+        let other_owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let ai = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &other_owner,
+            false,
+            0,
+        );
+        assert!(load_price_update_v2_checked(&ai).is_err());
+    }
+
     #[test]
     fn pyth_and_pyth_push_cmp() {
         fn get_prices(
@@ -1033,11 +1822,15 @@ mod tests {
         let pyth_legacy = PythLegacyPriceFeed {
             ema_price: Box::new(legacy_ema),
             price: Box::new(legacy_price),
+            max_conf_interval: MAX_CONF_INTERVAL,
+            publish_slot: 0,
         };
 
         let pyth_push = PythPushOraclePriceFeed {
             ema_price: Box::new(push_price_ema),
             price: Box::new(push_price),
+            max_conf_interval: MAX_CONF_INTERVAL,
+            publish_slot: 0,
         };
 
         assert_eq!(
@@ -1108,11 +1901,15 @@ mod tests {
         let pyth_legacy = PythLegacyPriceFeed {
             ema_price: Box::new(legacy_ema),
             price: Box::new(legacy_price),
+            max_conf_interval: MAX_CONF_INTERVAL,
+            publish_slot: 0,
         };
 
         let pyth_push = PythPushOraclePriceFeed {
             ema_price: Box::new(push_price_ema),
             price: Box::new(push_price),
+            max_conf_interval: MAX_CONF_INTERVAL,
+            publish_slot: 0,
         };
 
         // Test high bias ema
@@ -1206,11 +2003,383 @@ mod tests {
         }
     }
 
+    /// Build a legacy Pyth price account with full control over the aggregate and EMA components.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_pyth_legacy_oracle_account(
+        price: i64,
+        conf: u64,
+        ema_price: i64,
+        ema_conf: u64,
+        expo: i32,
+        publish_time: i64,
+        status: pyth_sdk_solana::state::PriceStatus,
+    ) -> Account {
+        use pyth_sdk_solana::state::{AccountType, PriceInfo, Rational, MAGIC, VERSION_2};
+
+        let price_account = SolanaPriceAccount {
+            magic: MAGIC,
+            ver: VERSION_2,
+            atype: AccountType::Price as u32,
+            expo,
+            timestamp: publish_time,
+            prev_timestamp: publish_time,
+            prev_price: price,
+            prev_conf: conf,
+            ema_price: Rational {
+                val: ema_price,
+                numer: ema_price,
+                denom: 1,
+            },
+            ema_conf: Rational {
+                val: ema_conf as i64,
+                numer: ema_conf as i64,
+                denom: 1,
+            },
+            agg: PriceInfo {
+                price,
+                conf,
+                status,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        Account {
+            lamports: 1_000_000,
+            data: bytemuck::bytes_of(&price_account).to_vec(),
+            owner: PYTH_ID,
+            executable: false,
+            rent_epoch: 361,
+        }
+    }
+
+    #[test]
+    fn pyth_legacy_load_checked() {
+        use pyth_sdk_solana::state::PriceStatus;
+
+        let key = Pubkey::new_unique();
+        let mut acc = create_pyth_legacy_oracle_account(
+            100_000_000,
+            1_000_000,
+            99_000_000,
+            2_000_000,
+            -6,
+            1_000,
+            PriceStatus::Trading,
+        );
+        let ai = account_to_account_info(&mut acc, &key);
+
+        let feed = PythLegacyPriceFeed::load_checked(&ai, 1_030, 60, MAX_CONF_INTERVAL).unwrap();
+        assert_eq!(feed.get_unweighted_price().unwrap(), I80F48!(100));
+        assert_eq!(feed.get_ema_price().unwrap(), I80F48!(99));
+        assert_eq!(feed.get_confidence_interval(false).unwrap(), I80F48!(2.12));
+        assert_eq!(feed.get_confidence_interval(true).unwrap(), I80F48!(4.24));
+
+        // Price is older than max age
+        assert!(PythLegacyPriceFeed::load_checked(&ai, 1_061, 60, MAX_CONF_INTERVAL).is_err());
+    }
+
+    #[test]
+    fn fallback_oracle() {
+        use pyth_sdk_solana::state::PriceStatus;
+
+        let primary_key = Pubkey::new_unique();
+        let fallback_key = Pubkey::new_unique();
+
+        let mut primary_acc = create_pyth_legacy_oracle_account(
+            100_000_000,
+            1_000_000,
+            100_000_000,
+            1_000_000,
+            -6,
+            900,
+            PriceStatus::Trading,
+        );
+        let mut fallback_acc = create_pyth_legacy_oracle_account(
+            101_000_000,
+            1_000_000,
+            101_000_000,
+            1_000_000,
+            -6,
+            1_000,
+            PriceStatus::Trading,
+        );
+        let primary_ai = account_to_account_info(&mut primary_acc, &primary_key);
+        let fallback_ai = account_to_account_info(&mut fallback_acc, &fallback_key);
+
+        let mut bank_config = BankConfig {
+            oracle_setup: OracleSetup::PythLegacy,
+            oracle_max_age: 60,
+            ..Default::default()
+        };
+        bank_config.oracle_keys[0] = primary_key;
+
+        let clock = Clock {
+            unix_timestamp: 1_030,
+            ..Default::default()
+        };
+
+        // Primary is stale and there is no fallback
+        assert!(OraclePriceFeedAdapter::try_from_bank_config(
+            &bank_config,
+            &[primary_ai.clone()],
+            &clock
+        )
+        .is_err());
+        assert!(OraclePriceFeedAdapter::try_from_bank_config(
+            &bank_config,
+            &[fallback_ai.clone()],
+            &clock
+        )
+        .is_err());
+
+        bank_config.oracle_keys[1] = fallback_key;
+
+        let feed = OraclePriceFeedAdapter::try_from_bank_config(
+            &bank_config,
+            &[primary_ai.clone(), fallback_ai.clone()],
+            &clock,
+        )
+        .unwrap();
+        assert_eq!(
+            feed.get_price_of_type(OraclePriceType::RealTime, None)
+                .unwrap(),
+            I80F48!(101)
+        );
+
+        // Primary still fails on its own, and has to be passed for the fallback to be read
+        for ais in [
+            vec![primary_ai.clone()],
+            vec![fallback_ai.clone()],
+            vec![fallback_ai.clone(), fallback_ai.clone()],
+            vec![fallback_ai.clone(), primary_ai.clone()],
+        ] {
+            assert!(
+                OraclePriceFeedAdapter::try_from_bank_config(&bank_config, &ais, &clock).is_err()
+            );
+        }
+
+        // Both oracles are validated when configuring the bank
+        assert!(OraclePriceFeedAdapter::validate_bank_config(
+            &bank_config,
+            &[primary_ai.clone()],
+            &PYTH_ID
+        )
+        .is_err());
+        assert!(OraclePriceFeedAdapter::validate_bank_config(
+            &bank_config,
+            &[primary_ai, fallback_ai],
+            &PYTH_ID
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn fallback_oracle_unused_while_primary_is_fresh() {
+        use pyth_sdk_solana::state::PriceStatus;
+
+        let primary_key = Pubkey::new_unique();
+        let fallback_key = Pubkey::new_unique();
+
+        let mut primary_acc = create_pyth_legacy_oracle_account(
+            100_000_000,
+            1_000_000,
+            100_000_000,
+            1_000_000,
+            -6,
+            1_000,
+            PriceStatus::Trading,
+        );
+        let mut fallback_acc = create_pyth_legacy_oracle_account(
+            90_000_000,
+            1_000_000,
+            90_000_000,
+            1_000_000,
+            -6,
+            1_000,
+            PriceStatus::Trading,
+        );
+        let primary_ai = account_to_account_info(&mut primary_acc, &primary_key);
+        let fallback_ai = account_to_account_info(&mut fallback_acc, &fallback_key);
+
+        let mut bank_config = BankConfig {
+            oracle_setup: OracleSetup::PythLegacy,
+            oracle_max_age: 60,
+            ..Default::default()
+        };
+        bank_config.oracle_keys[0] = primary_key;
+        bank_config.oracle_keys[1] = fallback_key;
+
+        let clock = Clock {
+            unix_timestamp: 1_030,
+            ..Default::default()
+        };
+
+        // The fallback can't be passed in place of a fresh primary
+        assert!(OraclePriceFeedAdapter::try_from_bank_config(
+            &bank_config,
+            &[fallback_ai.clone()],
+            &clock
+        )
+        .is_err());
+
+        for ais in [
+            vec![primary_ai.clone()],
+            vec![primary_ai.clone(), fallback_ai.clone()],
+        ] {
+            let feed =
+                OraclePriceFeedAdapter::try_from_bank_config(&bank_config, &ais, &clock).unwrap();
+            assert_eq!(
+                feed.get_price_of_type(OraclePriceType::RealTime, None)
+                    .unwrap(),
+                I80F48!(100)
+            );
+        }
+
+        assert_eq!(
+            OraclePriceFeedAdapter::get_oracle_ais_len(
+                &bank_config,
+                &[primary_ai.clone(), fallback_ai]
+            ),
+            2
+        );
+        assert_eq!(
+            OraclePriceFeedAdapter::get_oracle_ais_len(
+                &bank_config,
+                &[primary_ai.clone(), primary_ai]
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn fixed_price_oracle() {
+        let mut bank_config = BankConfig::default();
+        assert!(bank_config.get_fixed_price().is_none());
+
+        bank_config.set_fixed_price(I80F48!(1.5));
+        assert_eq!(bank_config.get_fixed_price(), Some(I80F48!(1.5)));
+
+        let key = Pubkey::new_unique();
+        let mut acc = Account::default();
+        let ai = account_to_account_info(&mut acc, &key);
+
+        let feed =
+            OraclePriceFeedAdapter::try_from_bank_config(&bank_config, &[ai], &Clock::default())
+                .unwrap();
+
+        for bias in [None, Some(PriceBias::Low), Some(PriceBias::High)] {
+            assert_eq!(
+                feed.get_price_of_type(OraclePriceType::TimeWeighted, bias)
+                    .unwrap(),
+                I80F48!(1.5)
+            );
+        }
+
+        assert!(OraclePriceFeedAdapter::validate_bank_config(&bank_config, &[], &PYTH_ID).is_ok());
+
+        bank_config.set_fixed_price(I80F48::ZERO);
+        assert!(OraclePriceFeedAdapter::validate_bank_config(&bank_config, &[], &PYTH_ID).is_err());
+    }
+
+    #[test]
+    fn chainlink_load_checked() {
+        let mut data = vec![0u8; 8 + 192 + 3 * 48];
+        data[..8].copy_from_slice(&ChainlinkPriceFeed::DISCRIMINATOR);
+
+        let header = &mut data[8..200];
+        header[130] = 8;
+        header[135..139].copy_from_slice(&5u32.to_le_bytes());
+        header[140..144].copy_from_slice(&3u32.to_le_bytes());
+        header[144..148].copy_from_slice(&1u32.to_le_bytes());
+
+        // Cursor 1 means the latest round is in slot 0, slot 2 holds an older round
+        for (index, slot, timestamp, answer) in [
+            (0, 5_000u64, 1_000u32, 15_050_000_000i128),
+            (2, 4_750, 900, 1),
+        ] {
+            let transmission = &mut data[200 + index * 48..200 + (index + 1) * 48];
+            transmission[..8].copy_from_slice(&slot.to_le_bytes());
+            transmission[8..12].copy_from_slice(&timestamp.to_le_bytes());
+            transmission[16..32].copy_from_slice(&answer.to_le_bytes());
+        }
+
+        let key = Pubkey::new_unique();
+        let mut acc = Account {
+            lamports: 1_000_000,
+            data,
+            owner: CHAINLINK_STORE_ID,
+            executable: false,
+            rent_epoch: 361,
+        };
+        let ai = account_to_account_info(&mut acc, &key);
+
+        let feed = ChainlinkPriceFeed::load_checked(&ai, 1_030, 60).unwrap();
+        assert_eq!(
+            feed.get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low))
+                .unwrap(),
+            I80F48!(150.5)
+        );
+
+        assert!(ChainlinkPriceFeed::load_checked(&ai, 1_061, 60).is_err());
+
+        // Measured in slots, the cluster clock running ahead doesn't matter
+        let mut bank_config = BankConfig {
+            oracle_setup: OracleSetup::Chainlink,
+            oracle_max_age_slots: 150,
+            ..Default::default()
+        };
+        bank_config.oracle_keys[0] = key;
+
+        let clock = |slot| Clock {
+            slot,
+            unix_timestamp: 10_000,
+            ..Default::default()
+        };
+        let feed = OraclePriceFeedAdapter::try_from_bank_config(
+            &bank_config,
+            &[ai.clone()],
+            &clock(5_150),
+        )
+        .unwrap();
+        assert_eq!(feed.get_publish_slot(), Some(5_000));
+        assert!(matches!(
+            OraclePriceFeedAdapter::try_from_bank_config(&bank_config, &[ai], &clock(5_151)),
+            Err(err) if err == MarginfiError::StaleOracle.into()
+        ));
+    }
+
+    #[test]
+    fn pyth_legacy_check_ais_wrong_owner() {
+        use pyth_sdk_solana::state::PriceStatus;
+
+        let key = Pubkey::new_unique();
+        let mut acc = create_pyth_legacy_oracle_account(100, 1, 100, 1, 0, 0, PriceStatus::Trading);
+        acc.owner = Pubkey::new_unique();
+        let ai = account_to_account_info(&mut acc, &key);
+
+        assert!(PythLegacyPriceFeed::check_ais(&ai, &PYTH_ID).is_err());
+    }
+
+    #[test]
+    fn pyth_legacy_check_ais_custom_program_id() {
+        use pyth_sdk_solana::state::PriceStatus;
+
+        let key = Pubkey::new_unique();
+        let pyth_program_id = Pubkey::new_unique();
+        let mut acc = create_pyth_legacy_oracle_account(100, 1, 100, 1, 0, 0, PriceStatus::Trading);
+        acc.owner = pyth_program_id;
+        let ai = account_to_account_info(&mut acc, &key);
+
+        assert!(PythLegacyPriceFeed::check_ais(&ai, &pyth_program_id).is_ok());
+        assert!(PythLegacyPriceFeed::check_ais(&ai, &PYTH_ID).is_err());
+    }
+
     #[test]
     fn swb_pull_get_price() {
         // From mainnet: https://solana.fm/address/BSzfJs4d1tAkSDqkepnfzEVcx2WtDVnwwXa2giy9PLeP
         // Actual price $155.59404527
-        // conf/Std_dev ~5%
+        // Std_dev $0.24016179
         let bytes = hex_to_bytes("c41b6cc40ad7db286f5e7566ac000a9530e56b1db49585772719aeaaeeadb4d9bd8c2357b88e9e782e53d81000000000000000000000000000985f538057856308000000000000005cba953f3f15356b17703e554d3983801916531d7976aa424ad64348ec50e4224650d81000000000000000000000000000a0d5a780cc7f580800000000000000a20b742cedab55efd1faf60aef2cb872a092d24dfba8a48c8b953a5e90ac7bbf874ed81000000000000000000000000000c04958360093580800000000000000e7ef024ea756f8beec2eaa40234070da356754a8eeb2ac6a17c32d17c3e99f8ddc50d81000000000000000000000000000bc8739b45d215b0800000000000000e3e5130902c3e9c27917789769f1ae05de15cf504658beafeed2c598a949b3b7bf53d810000000000000000000000000007cec168c94d667080000000000000020e270b743473d87eff321663e267ba1c9a151f7969cef8147f625e9a2af7287ea54d81000000000000000000000000000dc65eccc174d6f0800000000000000ab605484238ac93f225c65f24d7705bb74b00cdb576555c3995e196691a4de5f484ed8100000000000000000000000000088f28dc9271d59080000000000000015196392573dc9043242716f629d4c0fb93bc0cff7a1a10ede24281b0e98fb7d5454d810000000000000000000000000000441a10ca4a268080000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000048ac38271f28ab1b12e49439bddf54871094e4832a56c7a8ec57bd18d357980086807068432f186a147cf0b13a30067d386204ea9d6c8b04743ac2ef010b07524c935636f2523f6aeeb6dc7b7dab0e86a13ff2c794f7895fc78851d69fdb593bdccdb36600000000000000000000000000e40b540200000001000000534f4c2f55534400000000000000000000000000000000000000000000000000000000019e9eb66600000000fca3d11000000000000000000000000000000000000000000000000000000000000000000000000000dc65eccc174d6f0800000000000000006c9225e039550300000000000000000070d3c6ecddf76b080000000000000000d8244bc073aa060000000000000000000441a10ca4a268080000000000000000dc65eccc174d6f08000000000000000200000000000000ea54d810000000005454d81000000000ea54d81000000000fa0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000");
         let mut acc = create_switch_pull_oracle_account_from_bytes(bytes);
         let key = pubkey!("BSzfJs4d1tAkSDqkepnfzEVcx2WtDVnwwXa2giy9PLeP");
@@ -1220,8 +2389,13 @@ mod tests {
 
         let current_timestamp = 42;
         let max_age = 100;
-        let feed: SwitchboardPullPriceFeed =
-            SwitchboardPullPriceFeed::load_checked(&ai, current_timestamp, max_age).unwrap();
+        let feed: SwitchboardPullPriceFeed = SwitchboardPullPriceFeed::load_checked(
+            &ai,
+            current_timestamp,
+            max_age,
+            MAX_CONF_INTERVAL,
+        )
+        .unwrap();
         let price: I80F48 = feed.get_price().unwrap();
         let conf: I80F48 = feed.get_confidence_interval().unwrap();
 
@@ -1230,7 +2404,8 @@ mod tests {
         let target_price: I80F48 = I80F48::from_num(155); // Target price is $155
         let price_tolerance: I80F48 = target_price * I80F48::from_num(0.01);
 
-        let target_conf: I80F48 = target_price * I80F48::from_num(0.05);
+        // Std dev of 0.24016179, well below the 5% cap
+        let target_conf: I80F48 = I80F48::from_num(0.24016179) * STD_DEV_MULTIPLE;
         let conf_tolerance: I80F48 = target_conf * I80F48::from_num(0.005);
 
         let min_price: I80F48 = target_price.checked_sub(price_tolerance).unwrap();
@@ -1262,4 +2437,62 @@ mod tests {
         let max_price: I80F48 = target_price_high.checked_add(price_tolerance).unwrap();
         assert!(price_bias_high >= min_price && price_bias_high <= max_price);
     }
+
+    #[cfg(feature = "mock-oracle")]
+    #[test]
+    fn mock_oracle_load_checked() {
+        let mut mock_oracle = MockOracle {
+            authority: Pubkey::new_unique(),
+            price: I80F48::ZERO.into(),
+            confidence: I80F48::ZERO.into(),
+            last_update: 0,
+            last_update_slot: 0,
+            program_version: crate::constants::PROGRAM_VERSION,
+            _padding: [0; 6],
+        };
+        let clock = Clock {
+            unix_timestamp: 1_000,
+            ..Default::default()
+        };
+        mock_oracle
+            .set_price(I80F48!(150), I80F48!(1.5), &clock)
+            .unwrap();
+
+        let mut data = MockOracle::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&mock_oracle));
+        let mut acc = Account {
+            lamports: 1_000_000,
+            data,
+            owner: crate::ID,
+            executable: false,
+            rent_epoch: 361,
+        };
+        let key = Pubkey::new_unique();
+        let ai = account_to_account_info(&mut acc, &key);
+
+        let feed = MockPriceFeed::load_checked(&ai, 1_030, 60).unwrap();
+        assert_eq!(
+            feed.get_price_of_type(OraclePriceType::RealTime, None)
+                .unwrap(),
+            I80F48!(150)
+        );
+        assert_eq!(
+            feed.get_price_of_type(OraclePriceType::TimeWeighted, Some(PriceBias::Low))
+                .unwrap(),
+            I80F48!(148.5)
+        );
+        assert_eq!(
+            feed.get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::High))
+                .unwrap(),
+            I80F48!(151.5)
+        );
+
+        // Price is older than max age
+        assert!(MockPriceFeed::load_checked(&ai, 1_061, 60).is_err());
+
+        // Price can't be set to zero
+        assert!(mock_oracle
+            .set_price(I80F48::ZERO, I80F48::ZERO, &clock)
+            .is_err());
+    }
 }